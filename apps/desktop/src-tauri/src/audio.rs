@@ -1,31 +1,424 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Sample, SampleFormat, SizedSample, Stream, StreamConfig};
+use cpal::{Device, FromSample, Sample, SampleFormat, SizedSample, Stream, StreamConfig};
 use hound::{WavSpec, WavWriter};
+use nnnoiseless::DenoiseState;
+use rubato::{FftFixedIn, Resampler};
+use serde::{Deserialize, Serialize};
+use base64::Engine;
+use crate::audio_codec::{self, RecordingFormat};
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tempfile::NamedTempFile;
 use uuid::Uuid;
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputDeviceInfo {
+    pub id: String, // cpal has no stable device IDs, so the device name doubles as the ID
+    pub name: String,
+    pub is_default: bool,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+// Sample encoding for the optional archival recording
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Pcm16,
+    Pcm24,
+    Float32,
+}
+
+impl ArchiveFormat {
+    fn wav_spec(self, channels: u16, sample_rate: u32) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            ArchiveFormat::Pcm16 => (16, hound::SampleFormat::Int),
+            ArchiveFormat::Pcm24 => (24, hound::SampleFormat::Int),
+            ArchiveFormat::Float32 => (32, hound::SampleFormat::Float),
+        };
+        WavSpec { channels, sample_rate, bits_per_sample, sample_format }
+    }
+}
+
+// Archival capture keeps the device's native rate and channels alongside the 16kHz mono Whisper file
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RecordingQuality {
+    pub archive_enabled: bool,
+    pub archive_format: ArchiveFormat,
+}
+
+impl Default for RecordingQuality {
+    fn default() -> Self {
+        Self {
+            archive_enabled: false,
+            archive_format: ArchiveFormat::Pcm24,
+        }
+    }
+}
+
+// Which input channel feeds the Whisper file on multi-channel interfaces
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "index", rename_all = "snake_case")]
+pub enum ChannelSelection {
+    Channel(u16), // 0-based, so channel 2 on the interface is Channel(1)
+    Downmix,      // Average of every channel
+}
+
+impl Default for ChannelSelection {
+    fn default() -> Self {
+        ChannelSelection::Channel(0)
+    }
+}
+
+impl ChannelSelection {
+    fn validate(self, channels: u16) -> Result<(), String> {
+        match self {
+            ChannelSelection::Channel(index) if index >= channels => Err(format!(
+                "Input channel {} not available; device has {} channel(s)", index + 1, channels
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn pick(self, frame: &[f32]) -> f32 {
+        match self {
+            ChannelSelection::Channel(index) => frame.get(index as usize).copied().unwrap_or(0.0),
+            ChannelSelection::Downmix => frame.iter().sum::<f32>() / frame.len() as f32,
+        }
+    }
+}
+
+// Voice-activity auto-stop: recording ends once the speaker has been quiet for `silence_ms`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VadSettings {
+    pub enabled: bool,
+    pub silence_ms: u64,
+    pub threshold: f32, // RMS level below which a buffer counts as silence
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            silence_ms: 1500,
+            threshold: 0.01,
+        }
+    }
+}
+
+// Raised by TTS while it speaks so the recorder can mute its input instead of capturing the
+// assistant's own voice. The gate stays closed briefly after speech ends to cover room echo.
+#[derive(Clone, Default)]
+pub struct EchoGate {
+    speaking: Arc<AtomicBool>,
+    ended_at: Arc<Mutex<Option<Instant>>>,
+}
+
+const ECHO_TAIL_MS: u64 = 300;
+
+impl EchoGate {
+    pub fn begin_speaking(&self) {
+        self.speaking.store(true, Ordering::SeqCst);
+    }
+
+    pub fn end_speaking(&self) {
+        self.speaking.store(false, Ordering::SeqCst);
+        if let Ok(mut ended_at) = self.ended_at.lock() {
+            *ended_at = Some(Instant::now());
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        if self.speaking.load(Ordering::SeqCst) {
+            return true;
+        }
+        self.ended_at.lock().ok()
+            .and_then(|ended_at| *ended_at)
+            .map(|ended_at| ended_at.elapsed() < Duration::from_millis(ECHO_TAIL_MS))
+            .unwrap_or(false)
+    }
+}
+
+// Shared between the cpal callback and the recording thread; None until speech is first heard
+#[derive(Clone)]
+struct VoiceActivity {
+    threshold: f32,
+    last_voice_at: Arc<Mutex<Option<Instant>>>,
+}
+
+// Why the recorder stopped itself
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoStopReason {
+    Silence,
+    MaxDuration,
+    DeviceLost,
+}
+
+pub type AutoStopCallback = Box<dyn FnOnce(String, AutoStopReason) + Send>;
+
+// Reported when the input device vanishes mid-recording (e.g. a headset is unplugged)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceLoss {
+    pub file_path: String,
+    pub lost_device: String,
+    pub resumed_on: Option<String>, // Default device the recording carried on with, if resuming
+}
+
+pub type DeviceLostCallback = Box<dyn Fn(DeviceLoss) + Send>;
+
+// 100ms of the 16kHz mono Whisper audio as little-endian i16 PCM, for live waveforms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PcmChunk {
+    pub sequence: u64,
+    pub sample_rate: u32,
+    pub samples_base64: String,
+    pub dropped_samples: u64, // Overwritten before they could be sent because the listener fell behind
+}
+
+pub type PcmChunkCallback = Box<dyn Fn(PcmChunk) + Send>;
+
+// Receives the 16kHz mono Whisper audio as it is written, for live transcription.
+// The sender is dropped when the recording ends, which closes the channel.
+pub type SampleTap = mpsc::Sender<Vec<f32>>;
+
+const LIVE_CHUNK_SAMPLES: usize = 1600; // 100ms at 16kHz
+const LIVE_RING_CAPACITY: usize = 32000; // 2s; older audio is overwritten if chunks aren't drained
+
+// Fixed-size buffer between the audio callback and the thread that emits chunks
+struct PcmRing {
+    samples: VecDeque<i16>,
+    dropped: u64,
+    sequence: u64,
+}
+
+impl PcmRing {
+    fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(LIVE_RING_CAPACITY),
+            dropped: 0,
+            sequence: 0,
+        }
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            if self.samples.len() == LIVE_RING_CAPACITY {
+                self.samples.pop_front();
+                self.dropped += 1;
+            }
+            self.samples.push_back((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+        }
+    }
+
+    // Full chunks only, unless `flush` is set at the end of a recording
+    fn drain_chunks(&mut self, flush: bool) -> Vec<PcmChunk> {
+        let mut chunks = Vec::new();
+        while self.samples.len() >= LIVE_CHUNK_SAMPLES || (flush && !self.samples.is_empty()) {
+            let take = self.samples.len().min(LIVE_CHUNK_SAMPLES);
+            let bytes: Vec<u8> = self.samples.drain(..take).flat_map(|sample| sample.to_le_bytes()).collect();
+            chunks.push(PcmChunk {
+                sequence: self.sequence,
+                sample_rate: 16000,
+                samples_base64: base64::engine::general_purpose::STANDARD.encode(bytes),
+                dropped_samples: std::mem::take(&mut self.dropped),
+            });
+            self.sequence += 1;
+        }
+        chunks
+    }
+}
+
+type SharedWavWriter = Arc<Mutex<Option<WavWriter<BufWriter<File>>>>>;
+
+// An input device as the recorder sees it
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputFormat {
+    pub device_name: String,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+// Receives interleaved f32 samples in [-1.0, 1.0] on the backend's capture thread
+pub type InputDataCallback = Box<dyn FnMut(&[f32]) + Send>;
+
+// Keeps capture running until dropped
+pub type InputGuard = Box<dyn std::any::Any>;
+
+// Where recordings get their audio from: cpal in the app, canned PCM in tests
+pub trait AudioBackend: Send + Sync {
+    // The device with this ID (its name), or the default input when `device_id` is None
+    fn input_format(&self, device_id: Option<&str>) -> Result<InputFormat, String>;
+
+    fn is_connected(&self, device_name: &str) -> bool;
+
+    // Start capturing from the named device; `device_lost` is set if the backend reports it gone
+    fn open_input(&self, device_name: &str, on_data: InputDataCallback, device_lost: Arc<AtomicBool>) -> Result<InputGuard, String>;
+}
+
+pub struct CpalBackend;
+
+impl AudioBackend for CpalBackend {
+    fn input_format(&self, device_id: Option<&str>) -> Result<InputFormat, String> {
+        let device = get_input_device(device_id)?;
+        let config = device.default_input_config().map_err(|e| e.to_string())?;
+        Ok(InputFormat {
+            device_name: device.name().map_err(|e| e.to_string())?,
+            channels: config.channels(),
+            sample_rate: config.sample_rate().0,
+        })
+    }
+
+    fn is_connected(&self, device_name: &str) -> bool {
+        cpal::default_host()
+            .input_devices()
+            .map(|mut devices| devices.any(|candidate| candidate.name().map(|n| n == device_name).unwrap_or(false)))
+            .unwrap_or(true) // Can't enumerate, so don't treat it as unplugged
+    }
+
+    fn open_input(&self, device_name: &str, on_data: InputDataCallback, device_lost: Arc<AtomicBool>) -> Result<InputGuard, String> {
+        let device = get_input_device(Some(device_name))?;
+        let config = device.default_input_config().map_err(|e| e.to_string())?;
+        let stream_config: StreamConfig = config.clone().into();
+
+        // Create audio stream based on sample format
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => build_input_stream::<f32>(&device, &stream_config, on_data, device_lost)?,
+            SampleFormat::I16 => build_input_stream::<i16>(&device, &stream_config, on_data, device_lost)?,
+            SampleFormat::U16 => build_input_stream::<u16>(&device, &stream_config, on_data, device_lost)?,
+            _ => return Err("Unsupported sample format".to_string()),
+        };
+
+        // Start the stream
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(Box::new(stream))
+    }
+}
+
+fn build_input_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut on_data: InputDataCallback,
+    device_lost: Arc<AtomicBool>,
+) -> Result<Stream, String>
+where
+    T: SizedSample + Send + 'static,
+    f32: FromSample<T>,
+{
+    let mut buffer = Vec::new();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                buffer.clear();
+                buffer.extend(data.iter().map(|&sample| sample.to_sample::<f32>()));
+                on_data(&buffer);
+            },
+            move |err| {
+                if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                    device_lost.store(true, Ordering::SeqCst);
+                }
+                eprintln!("Audio stream error: {}", err);
+            },
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
 pub struct AudioRecorder {
     pub is_recording: Arc<Mutex<bool>>,
     pub recording_id: Option<String>,
     pub current_file_path: Option<String>,
+    pub input_device_id: Option<String>, // None records from the system default device
+    pub channel: ChannelSelection,
+    pub quality: RecordingQuality,
+    pub last_archive_path: Option<String>,
+    pub vad: VadSettings,
+    pub noise_suppression: bool, // Run RNNoise on the Whisper file before it is written
+    pub max_duration_secs: u64, // Hard cap so a forgotten recording can't fill the disk; 0 disables it
+    pub resume_on_device_loss: bool, // Carry on with the new default device instead of stopping
+    pub live_streaming: bool, // Emit PCM chunks while recording so the UI can draw a waveform
+    pub live_transcription: bool, // Feed the Whisper audio to a SampleTap for partial transcripts
+    pub output_format: RecordingFormat, // What the Whisper file is converted to once recording stops
+    pub echo_gate: EchoGate, // Shared with TTSState
+    pub echo_suppression: bool, // Record silence while TTS is speaking
+    recording_thread: Option<thread::JoinHandle<()>>,
+    backend: Arc<dyn AudioBackend>,
+    recordings_dir: Option<PathBuf>, // Overrides the cache directory
 }
 
 impl AudioRecorder {
     pub fn new() -> Self {
+        Self::with_backend(Arc::new(CpalBackend))
+    }
+
+    pub fn with_backend(backend: Arc<dyn AudioBackend>) -> Self {
         Self {
             is_recording: Arc::new(Mutex::new(false)),
             recording_id: None,
             current_file_path: None,
+            input_device_id: None,
+            channel: ChannelSelection::default(),
+            quality: RecordingQuality::default(),
+            last_archive_path: None,
+            vad: VadSettings::default(),
+            noise_suppression: false,
+            max_duration_secs: 300,
+            resume_on_device_loss: false,
+            live_streaming: false,
+            live_transcription: false,
+            output_format: RecordingFormat::default(),
+            echo_gate: EchoGate::default(),
+            echo_suppression: true,
+            recording_thread: None,
+            backend,
+            recordings_dir: None,
         }
     }
 
-    pub fn start_recording(&mut self) -> Result<String, String> {
+    pub fn set_input_device(&mut self, device_id: Option<String>) -> Result<(), String> {
+        // Validate the device exists before remembering it
+        if let Some(ref id) = device_id {
+            self.backend.input_format(Some(id))?;
+        }
+        self.input_device_id = device_id;
+
+        // A channel beyond the new device's count falls back to the first channel
+        let channels = self.backend.input_format(self.input_device_id.as_deref())
+            .map(|format| format.channels);
+        if let Ok(channels) = channels {
+            if self.channel.validate(channels).is_err() {
+                self.channel = ChannelSelection::default();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_channel(&mut self, channel: ChannelSelection) -> Result<(), String> {
+        let format = self.backend.input_format(self.input_device_id.as_deref())?;
+        channel.validate(format.channels)?;
+        self.channel = channel;
+        Ok(())
+    }
+
+    // `on_auto_stop` runs with the file path when VAD, the duration cap or a lost device ends the
+    // recording on its own; stop_recording must still be called afterwards to collect the file.
+    // `on_device_lost` runs whenever the input device disappears, whether or not recording resumes.
+    // `on_pcm_chunk` receives live audio every 100ms when live streaming is enabled.
+    // `sample_tap` gets every block of Whisper audio when live transcription is enabled.
+    pub fn start_recording(
+        &mut self,
+        on_auto_stop: Option<AutoStopCallback>,
+        on_device_lost: Option<DeviceLostCallback>,
+        on_pcm_chunk: Option<PcmChunkCallback>,
+        sample_tap: Option<SampleTap>,
+    ) -> Result<String, String> {
         let mut is_recording = self.is_recording.lock().map_err(|e| e.to_string())?;
         
         if *is_recording {
@@ -34,23 +427,57 @@ impl AudioRecorder {
 
         // Generate recording ID and file path
         let recording_id = Uuid::new_v4().to_string();
-        let recordings_dir = get_recordings_dir()?;
+        let recordings_dir = match &self.recordings_dir {
+            Some(dir) => dir.clone(),
+            None => get_recordings_dir()?,
+        };
         let file_path = recordings_dir.join(format!("{}.wav", recording_id));
         
+        let archive = if self.quality.archive_enabled {
+            Some((recordings_dir.join(format!("{}.archive.wav", recording_id)), self.quality.archive_format))
+        } else {
+            None
+        };
+        
         // Update state
         *is_recording = true;
         self.recording_id = Some(recording_id.clone());
         self.current_file_path = Some(file_path.to_string_lossy().to_string());
+        self.last_archive_path = archive.as_ref().map(|(path, _)| path.to_string_lossy().to_string());
 
         // Start recording in a background thread
         let is_recording_clone = self.is_recording.clone();
         let file_path_clone = file_path.clone();
+        let backend = self.backend.clone();
+        let device_id = self.input_device_id.clone();
+        let channel = self.channel;
+        let vad = if self.vad.enabled { Some(self.vad) } else { None };
+        let noise_suppression = self.noise_suppression;
+        let resume_on_device_loss = self.resume_on_device_loss;
+        let on_pcm_chunk = if self.live_streaming { on_pcm_chunk } else { None };
+        let sample_tap = if self.live_transcription { sample_tap } else { None };
+        let echo_gate = if self.echo_suppression { Some(self.echo_gate.clone()) } else { None };
+        let max_duration = if self.max_duration_secs > 0 {
+            Some(Duration::from_secs(self.max_duration_secs))
+        } else {
+            None
+        };
         
-        thread::spawn(move || {
-            if let Err(e) = start_recording_thread(is_recording_clone, file_path_clone) {
-                eprintln!("Recording thread error: {}", e);
+        self.recording_thread = Some(thread::spawn(move || {
+            let device_loss = DeviceLossHandling {
+                resume: resume_on_device_loss,
+                on_lost: on_device_lost,
+            };
+            match start_recording_thread(backend.as_ref(), is_recording_clone, file_path_clone.clone(), device_id, channel, archive, vad, noise_suppression, max_duration, device_loss, on_pcm_chunk, sample_tap, echo_gate) {
+                Ok(Some(reason)) => {
+                    if let Some(callback) = on_auto_stop {
+                        callback(file_path_clone.to_string_lossy().to_string(), reason);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Recording thread error: {}", e),
             }
-        });
+        }));
 
         println!("Started recording with ID: {} at {}", recording_id, file_path.display());
         Ok(recording_id)
@@ -59,12 +486,14 @@ impl AudioRecorder {
     pub fn stop_recording(&mut self) -> Result<String, String> {
         let mut is_recording = self.is_recording.lock().map_err(|e| e.to_string())?;
         
-        if !*is_recording {
+        // A VAD auto-stop clears the flag but leaves the file to be collected here
+        if !*is_recording && self.current_file_path.is_none() {
             return Err("Not recording".to_string());
         }
 
         // Stop recording
         *is_recording = false;
+        drop(is_recording);
 
         let file_path = self.current_file_path.take()
             .ok_or("No recording file path")?;
@@ -72,8 +501,18 @@ impl AudioRecorder {
         let recording_id = self.recording_id.take()
             .ok_or("No recording ID")?;
 
-        // Wait a bit for the recording thread to finish
-        thread::sleep(Duration::from_millis(100));
+        // The WAV is only complete once the recording thread has finalized it
+        if let Some(handle) = self.recording_thread.take() {
+            if handle.join().is_err() {
+                eprintln!("Recording thread panicked");
+            }
+        }
+
+        let file_path = audio_codec::encode_recording(&file_path, self.output_format)
+            .unwrap_or_else(|e| {
+                eprintln!("Keeping WAV recording, encoding failed: {}", e);
+                file_path
+            });
 
         println!("Stopped recording with ID: {}, saved to: {}", recording_id, file_path);
         Ok(file_path)
@@ -84,10 +523,31 @@ impl AudioRecorder {
     }
 }
 
-// Separate function to handle recording in a background thread
-fn start_recording_thread(is_recording: Arc<Mutex<bool>>, file_path: PathBuf) -> Result<(), String> {
-    let device = get_default_input_device()?;
-    let config = device.default_input_config().map_err(|e| e.to_string())?;
+struct DeviceLossHandling {
+    resume: bool,
+    on_lost: Option<DeviceLostCallback>,
+}
+
+// Separate function to handle recording in a background thread; returns why it stopped itself, if it did
+#[allow(clippy::too_many_arguments)]
+fn start_recording_thread(
+    backend: &dyn AudioBackend,
+    is_recording: Arc<Mutex<bool>>,
+    file_path: PathBuf,
+    device_id: Option<String>,
+    channel: ChannelSelection,
+    archive: Option<(PathBuf, ArchiveFormat)>,
+    vad: Option<VadSettings>,
+    noise_suppression: bool,
+    max_duration: Option<Duration>,
+    device_loss: DeviceLossHandling,
+    on_pcm_chunk: Option<PcmChunkCallback>,
+    sample_tap: Option<SampleTap>,
+    echo_gate: Option<EchoGate>,
+) -> Result<Option<AutoStopReason>, String> {
+    let mut format = backend.input_format(device_id.as_deref())?;
+    // The device may have changed since the channel was chosen
+    channel.validate(format.channels)?;
 
     // Create WAV file with proper 16kHz mono format for Whisper
     let spec = WavSpec {
@@ -101,109 +561,290 @@ fn start_recording_thread(is_recording: Arc<Mutex<bool>>, file_path: PathBuf) ->
         .map_err(|e| format!("Failed to create WAV file: {}", e))?;
     let writer = Arc::new(Mutex::new(Some(writer)));
 
-    let input_sample_rate = config.sample_rate().0;
-    let needs_resampling = input_sample_rate != 16000;
-    
-    // Create audio stream based on sample format
-    let stream = match config.sample_format() {
-        SampleFormat::F32 => {
-            create_recording_stream::<f32>(&device, &config.into(), writer.clone(), is_recording.clone(), needs_resampling, input_sample_rate)?
-        }
-        SampleFormat::I16 => {
-            create_recording_stream::<i16>(&device, &config.into(), writer.clone(), is_recording.clone(), needs_resampling, input_sample_rate)?
+    let input_sample_rate = format.sample_rate;
+    let mut whisper_pipeline = WhisperPipeline::new(input_sample_rate, noise_suppression)?;
+    if on_pcm_chunk.is_some() {
+        whisper_pipeline.live_tap = Some(PcmRing::new());
+    }
+    let pipeline = Arc::new(Mutex::new(whisper_pipeline));
+    let emit_live_chunks = |flush: bool| {
+        let Some(callback) = on_pcm_chunk.as_ref() else { return };
+        // Drain under the lock, emit after releasing it so the audio callback isn't held up
+        let chunks = pipeline.lock()
+            .map(|mut pipeline| pipeline.live_tap.as_mut().map(|tap| tap.drain_chunks(flush)).unwrap_or_default())
+            .unwrap_or_default();
+        for chunk in chunks {
+            callback(chunk);
         }
-        SampleFormat::U16 => {
-            create_recording_stream::<u16>(&device, &config.into(), writer.clone(), is_recording.clone(), needs_resampling, input_sample_rate)?
+    };
+
+    // Optional archival file at the device's native rate and channel count
+    let archive_format = archive.as_ref().map(|(_, format)| *format);
+    let archive_writer = match archive {
+        Some((archive_path, archive_format)) => {
+            let spec = archive_format.wav_spec(format.channels, input_sample_rate);
+            let archive_writer = WavWriter::create(&archive_path, spec)
+                .map_err(|e| format!("Failed to create archive WAV file: {}", e))?;
+            Some(archive_writer)
         }
-        _ => return Err("Unsupported sample format".to_string()),
+        None => None,
     };
+    let archive_writer = Arc::new(Mutex::new(archive_writer));
 
-    // Start the stream
-    stream.play().map_err(|e| e.to_string())?;
+    let voice_activity = vad.map(|settings| VoiceActivity {
+        threshold: settings.threshold,
+        last_voice_at: Arc::new(Mutex::new(None)),
+    });
+    
+    // Set by the stream's error callback when the backend reports the device gone
+    let device_lost = Arc::new(AtomicBool::new(false));
+
+    let open_stream = |format: &InputFormat, channel: ChannelSelection, archive_format: Option<ArchiveFormat>| {
+        let sink = RecordingSink {
+            channels: format.channels as usize,
+            channel,
+            writer: writer.clone(),
+            archive_writer: archive_writer.clone(),
+            archive_format,
+            voice_activity: voice_activity.clone(),
+            is_recording: is_recording.clone(),
+            pipeline: pipeline.clone(),
+            echo_gate: echo_gate.clone(),
+            sample_tap: sample_tap.clone(),
+        };
+        backend.open_input(&format.device_name, Box::new(move |data| sink.write(data)), device_lost.clone())
+    };
+    let mut stream = open_stream(&format, channel, archive_format)?;
 
     // Keep the stream alive while recording
+    let started_at = Instant::now();
+    let mut auto_stopped = None;
+    let mut ticks = 0u64;
     while is_recording.lock().map(|guard| *guard).unwrap_or(false) {
         thread::sleep(Duration::from_millis(100));
+        ticks += 1;
+        emit_live_chunks(false);
+
+        // Not every backend reports an unplugged device as a stream error, so also poll once a second
+        let lost = device_lost.load(Ordering::SeqCst) || (ticks % 10 == 0 && !backend.is_connected(&format.device_name));
+        if lost {
+            let lost_device = format.device_name.clone();
+            let resumed = if device_loss.resume {
+                resume_on_default_device(backend, &lost_device, channel, noise_suppression, &writer, &archive_writer, &pipeline, &open_stream)
+            } else {
+                None
+            };
+
+            let resumed_on = resumed.as_ref().map(|(new_format, _)| new_format.device_name.clone());
+            if let Some(callback) = device_loss.on_lost.as_ref() {
+                callback(DeviceLoss {
+                    file_path: file_path.to_string_lossy().to_string(),
+                    lost_device,
+                    resumed_on,
+                });
+            }
+
+            match resumed {
+                Some((new_format, new_stream)) => {
+                    drop(std::mem::replace(&mut stream, new_stream));
+                    format = new_format;
+                    device_lost.store(false, Ordering::SeqCst);
+                    continue;
+                }
+                None => {
+                    // Nothing can be recorded any more, so silence or the time limit mustn't
+                    // replace this reason
+                    auto_stopped = Some(AutoStopReason::DeviceLost);
+                    if let Ok(mut guard) = is_recording.lock() {
+                        *guard = false;
+                    }
+                    break;
+                }
+            }
+        }
+
+        // Only stop after the user has said something, then gone quiet
+        if let (Some(settings), Some(activity)) = (vad, voice_activity.as_ref()) {
+            let last_voice_at = activity.last_voice_at.lock().ok().and_then(|guard| *guard);
+            if let Some(last_voice_at) = last_voice_at {
+                if last_voice_at.elapsed() >= Duration::from_millis(settings.silence_ms) {
+                    auto_stopped = Some(AutoStopReason::Silence);
+                }
+            }
+        }
+
+        if max_duration.map(|max| started_at.elapsed() >= max).unwrap_or(false) {
+            auto_stopped = Some(AutoStopReason::MaxDuration);
+        }
+
+        if auto_stopped.is_some() {
+            if let Ok(mut guard) = is_recording.lock() {
+                *guard = false;
+            }
+        }
     }
 
-    // Finalize the WAV file
+    // Finalize the WAV file, including whatever the pipeline still holds
     if let Ok(mut writer_guard) = writer.lock() {
-        if let Some(writer) = writer_guard.take() {
+        if let Some(mut writer) = writer_guard.take() {
+            if let Ok(mut pipeline) = pipeline.lock() {
+                write_whisper_samples(&mut writer, &pipeline.flush());
+            }
             writer.finalize().map_err(|e| format!("Failed to finalize WAV file: {}", e))?;
         }
     }
+    emit_live_chunks(true);
+
+    if let Ok(mut archive_guard) = archive_writer.lock() {
+        if let Some(archive) = archive_guard.take() {
+            archive.finalize().map_err(|e| format!("Failed to finalize archive WAV file: {}", e))?;
+        }
+    }
 
     drop(stream);
-    Ok(())
+    Ok(auto_stopped)
 }
 
-fn create_recording_stream<T>(
-    device: &Device,
-    config: &StreamConfig,
-    writer: Arc<Mutex<Option<WavWriter<BufWriter<File>>>>>,
-    is_recording: Arc<Mutex<bool>>,
-    needs_resampling: bool,
-    input_sample_rate: u32,
-) -> Result<Stream, String>
+// Switch a running recording to the new default device, continuing the same Whisper file.
+// The archive can't change rate or channel count mid-file, so it is closed at the switch.
+#[allow(clippy::too_many_arguments)]
+fn resume_on_default_device<F>(
+    backend: &dyn AudioBackend,
+    lost_name: &str,
+    channel: ChannelSelection,
+    noise_suppression: bool,
+    writer: &SharedWavWriter,
+    archive_writer: &SharedWavWriter,
+    pipeline: &Arc<Mutex<WhisperPipeline>>,
+    open_stream: &F,
+) -> Option<(InputFormat, InputGuard)>
 where
-    T: Sample + SizedSample + Send + 'static,
-    f32: From<T>,
+    F: Fn(&InputFormat, ChannelSelection, Option<ArchiveFormat>) -> Result<InputGuard, String>,
 {
-    let channels = config.channels as usize;
-    
-    let stream = device
-        .build_input_stream(
-            config,
-            move |data: &[T], _: &cpal::InputCallbackInfo| {
-                // Check if we're still recording
-                let recording = match is_recording.lock() {
-                    Ok(guard) => guard,
-                    Err(_) => return,
-                };
-                if !*recording {
-                    return;
+    let format = backend.input_format(None).ok()?;
+    if format.device_name == lost_name && !backend.is_connected(lost_name) {
+        return None;
+    }
+    let channel = if channel.validate(format.channels).is_ok() { channel } else { ChannelSelection::default() };
+
+    // Flush what the old pipeline holds before the sample rate changes
+    let mut new_pipeline = WhisperPipeline::new(format.sample_rate, noise_suppression).ok()?;
+    if let (Ok(mut pipeline), Ok(mut writer_guard)) = (pipeline.lock(), writer.lock()) {
+        if let Some(writer) = writer_guard.as_mut() {
+            write_whisper_samples(writer, &pipeline.flush());
+        }
+        new_pipeline.live_tap = pipeline.live_tap.take();
+        *pipeline = new_pipeline;
+    }
+    if let Ok(mut archive_guard) = archive_writer.lock() {
+        if let Some(archive) = archive_guard.take() {
+            if let Err(e) = archive.finalize() {
+                eprintln!("Failed to finalize archive WAV file: {}", e);
+            }
+        }
+    }
+
+    match open_stream(&format, channel, None) {
+        Ok(stream) => Some((format, stream)),
+        Err(e) => {
+            eprintln!("Failed to resume recording on {}: {}", lost_name, e);
+            None
+        }
+    }
+}
+
+// Where each buffer of input audio goes; owned by the backend's data callback
+struct RecordingSink {
+    channels: usize,
+    channel: ChannelSelection,
+    writer: SharedWavWriter,
+    archive_writer: SharedWavWriter,
+    archive_format: Option<ArchiveFormat>,
+    voice_activity: Option<VoiceActivity>,
+    is_recording: Arc<Mutex<bool>>,
+    pipeline: Arc<Mutex<WhisperPipeline>>,
+    echo_gate: Option<EchoGate>,
+    sample_tap: Option<SampleTap>,
+}
+
+impl RecordingSink {
+    fn write(&self, data: &[f32]) {
+        // Check if we're still recording
+        let recording = match self.is_recording.lock() {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+        if !*recording {
+            return;
+        }
+
+        let Ok(mut writer_guard) = self.writer.lock() else { return };
+        let Some(writer) = writer_guard.as_mut() else { return };
+
+        // Silence while TTS is speaking keeps the timeline intact
+        let gated = self.echo_gate.as_ref().map(|gate| gate.is_closed()).unwrap_or(false);
+        let samples: Vec<f32> = if gated { vec![0.0; data.len()] } else { data.to_vec() };
+
+        // The archive gets every channel at the native rate, untouched
+        if let (Some(format), Ok(mut archive_guard)) = (self.archive_format, self.archive_writer.lock()) {
+            if let Some(archive) = archive_guard.as_mut() {
+                if write_archive_samples(archive, &samples, format).is_err() {
+                    eprintln!("Failed to write archive audio sample");
                 }
+            }
+        }
 
-                if let Ok(mut writer_guard) = writer.lock() {
-                    if let Some(writer) = writer_guard.as_mut() {
-                        // Convert input samples to f32
-                        let samples_f32: Vec<f32> = data.iter()
-                            .map(|&sample| f32::from(sample))
-                            .collect();
-
-                        // Convert to mono if needed (take left channel)
-                        let mono_samples: Vec<f32> = if channels == 1 {
-                            samples_f32
-                        } else {
-                            samples_f32.chunks_exact(channels)
-                                .map(|frame| frame[0]) // Take left channel
-                                .collect()
-                        };
-
-                        // Simple resampling if needed
-                        let final_samples = if needs_resampling {
-                            resample_to_16khz(&mono_samples, input_sample_rate)
-                        } else {
-                            mono_samples
-                        };
-
-                        // Convert to i16 and write to file
-                        for sample in final_samples {
-                            let sample_i16 = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
-                            if writer.write_sample(sample_i16).is_err() {
-                                eprintln!("Failed to write audio sample");
-                                break;
-                            }
-                        }
+        // Convert to mono if needed (selected channel or downmix)
+        let mono_samples: Vec<f32> = if self.channels == 1 {
+            samples
+        } else {
+            samples.chunks_exact(self.channels)
+                .map(|frame| self.channel.pick(frame))
+                .collect()
+        };
+
+        if let Some(activity) = self.voice_activity.as_ref() {
+            if !mono_samples.is_empty() {
+                let rms = (mono_samples.iter().map(|s| s * s).sum::<f32>() / mono_samples.len() as f32).sqrt();
+                if rms >= activity.threshold {
+                    if let Ok(mut last_voice_at) = activity.last_voice_at.lock() {
+                        *last_voice_at = Some(Instant::now());
                     }
                 }
-            },
-            |err| eprintln!("Audio stream error: {}", err),
-            None,
-        )
-        .map_err(|e| e.to_string())?;
+            }
+        }
+
+        // Optional denoising, then band-limited resampling to 16kHz for Whisper
+        let final_samples = match self.pipeline.lock() {
+            Ok(mut pipeline) => pipeline.push(&mono_samples),
+            Err(_) => return,
+        };
+
+        write_whisper_samples(writer, &final_samples);
+
+        // A closed receiver just means nobody is listening any more
+        if let Some(tap) = self.sample_tap.as_ref() {
+            if !final_samples.is_empty() {
+                let _ = tap.send(final_samples);
+            }
+        }
+    }
+}
 
-    Ok(stream)
+fn write_archive_samples(
+    writer: &mut WavWriter<BufWriter<File>>,
+    samples: &[f32],
+    format: ArchiveFormat,
+) -> Result<(), hound::Error> {
+    for &sample in samples {
+        let sample = sample.clamp(-1.0, 1.0);
+        match format {
+            ArchiveFormat::Pcm16 => writer.write_sample((sample * i16::MAX as f32) as i16)?,
+            ArchiveFormat::Pcm24 => writer.write_sample((sample * 8_388_607.0) as i32)?,
+            ArchiveFormat::Float32 => writer.write_sample(sample)?,
+        }
+    }
+    Ok(())
 }
 
 pub fn get_default_input_device() -> Result<Device, String> {
@@ -212,6 +853,42 @@ pub fn get_default_input_device() -> Result<Device, String> {
         .ok_or_else(|| "No input device available".to_string())
 }
 
+// Find an input device by ID (its name), falling back to the default device when no ID is given
+pub fn get_input_device(device_id: Option<&str>) -> Result<Device, String> {
+    let Some(device_id) = device_id else {
+        return get_default_input_device();
+    };
+
+    let host = cpal::default_host();
+    let mut devices = host.input_devices().map_err(|e| e.to_string())?;
+    devices
+        .find(|device| device.name().map(|name| name == device_id).unwrap_or(false))
+        .ok_or_else(|| format!("Input device not found: {}", device_id))
+}
+
+pub fn list_input_devices() -> Result<Vec<InputDeviceInfo>, String> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|device| device.name().ok());
+    let devices = host.input_devices().map_err(|e| e.to_string())?;
+
+    let mut infos = Vec::new();
+    for device in devices {
+        let Ok(name) = device.name() else { continue };
+        // Skip devices that can't report an input configuration
+        let Ok(config) = device.default_input_config() else { continue };
+
+        infos.push(InputDeviceInfo {
+            id: name.clone(),
+            is_default: default_name.as_deref() == Some(name.as_str()),
+            name,
+            channels: config.channels(),
+            sample_rate: config.sample_rate().0,
+        });
+    }
+
+    Ok(infos)
+}
+
 pub fn record_audio_to_file(duration_secs: u64) -> Result<String, String> {
     let device = get_default_input_device()?;
     let config = device.default_input_config().map_err(|e| e.to_string())?;
@@ -297,6 +974,107 @@ where
     Ok(())
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordingInfo {
+    pub file_path: String,
+    pub file_name: String,
+    pub size_bytes: u64,
+    pub duration_ms: u64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub is_archive: bool, // Native-rate archival copy rather than the Whisper file
+}
+
+// Limits for how much recorded audio is kept; None means no limit on that axis. Both are unset
+// until the user opts in, so upgrading never deletes existing recordings.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_age_days: Option<u32>,
+    pub max_total_mb: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub deleted: Vec<String>,
+    pub freed_bytes: u64,
+}
+
+// Newest first
+pub fn list_recordings() -> Result<Vec<RecordingInfo>, String> {
+    let recordings_dir = get_recordings_dir()?;
+    let entries = std::fs::read_dir(&recordings_dir)
+        .map_err(|e| format!("Failed to read recordings directory: {}", e))?;
+
+    let mut recordings = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if RecordingFormat::from_path(&path).is_none() {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else { continue };
+
+        // A file still being written may have no valid header yet
+        let duration_ms = audio_codec::duration_ms(&path.to_string_lossy()).unwrap_or(0);
+        let created_at = metadata.created()
+            .or_else(|_| metadata.modified())
+            .map(chrono::DateTime::<chrono::Utc>::from)
+            .unwrap_or_else(|_| chrono::Utc::now());
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+
+        recordings.push(RecordingInfo {
+            file_path: path.to_string_lossy().to_string(),
+            is_archive: file_name.ends_with(".archive.wav"),
+            file_name,
+            size_bytes: metadata.len(),
+            duration_ms,
+            created_at,
+        });
+    }
+
+    recordings.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(recordings)
+}
+
+// Delete recordings older than the age limit, then the oldest ones until the total fits the
+// size limit. Files in `protected` (in-progress or awaiting transcription) are never touched.
+pub fn cleanup_recordings(policy: &RetentionPolicy, protected: &[String]) -> Result<CleanupReport, String> {
+    let mut recordings = list_recordings()?;
+    recordings.retain(|recording| !protected.contains(&recording.file_path));
+
+    let mut report = CleanupReport::default();
+    let delete = |recording: &RecordingInfo, report: &mut CleanupReport| {
+        match std::fs::remove_file(&recording.file_path) {
+            Ok(()) => {
+                report.deleted.push(recording.file_path.clone());
+                report.freed_bytes += recording.size_bytes;
+            }
+            Err(e) => eprintln!("Failed to delete recording {}: {}", recording.file_path, e),
+        }
+    };
+
+    if let Some(max_age_days) = policy.max_age_days {
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(max_age_days as i64);
+        for recording in recordings.iter().filter(|recording| recording.created_at < cutoff) {
+            delete(recording, &mut report);
+        }
+        recordings.retain(|recording| recording.created_at >= cutoff);
+    }
+
+    if let Some(max_total_mb) = policy.max_total_mb {
+        let max_total_bytes = max_total_mb * 1024 * 1024;
+        let mut total_bytes: u64 = recordings.iter().map(|recording| recording.size_bytes).sum();
+        // Oldest first
+        for recording in recordings.iter().rev() {
+            if total_bytes <= max_total_bytes {
+                break;
+            }
+            delete(recording, &mut report);
+            total_bytes = total_bytes.saturating_sub(recording.size_bytes);
+        }
+    }
+
+    Ok(report)
+}
+
 // Helper function to get recordings directory
 fn get_recordings_dir() -> Result<PathBuf, String> {
     let recordings_dir = dirs::cache_dir()
@@ -310,24 +1088,196 @@ fn get_recordings_dir() -> Result<PathBuf, String> {
     Ok(recordings_dir)
 }
 
-// Simple linear interpolation resampling to 16kHz
-fn resample_to_16khz(samples: &[f32], input_sample_rate: u32) -> Vec<f32> {
-    if input_sample_rate == 16000 {
-        return samples.to_vec();
+fn write_whisper_samples(writer: &mut WavWriter<BufWriter<File>>, samples: &[f32]) {
+    for &sample in samples {
+        let sample_i16 = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        if writer.write_sample(sample_i16).is_err() {
+            eprintln!("Failed to write audio sample");
+            break;
+        }
     }
-    
-    let ratio = input_sample_rate as f64 / 16000.0;
-    let output_len = (samples.len() as f64 / ratio) as usize;
-    let mut output = Vec::with_capacity(output_len);
-    
-    for i in 0..output_len {
-        let input_index = (i as f64 * ratio) as usize;
-        if input_index < samples.len() {
-            output.push(samples[input_index]);
+}
+
+// Mono processing between the device and the Whisper file: optional RNNoise suppression
+// (which only works at 48kHz) followed by resampling to 16kHz
+struct WhisperPipeline {
+    denoiser: Option<Denoiser>,
+    resampler: Option<StreamResampler>,
+    live_tap: Option<PcmRing>, // Copy of the output for live streaming
+}
+
+impl WhisperPipeline {
+    fn new(input_sample_rate: u32, noise_suppression: bool) -> Result<Self, String> {
+        let denoiser = if noise_suppression {
+            Some(Denoiser::new(input_sample_rate)?)
+        } else {
+            None
+        };
+
+        let resampler_input_rate = if noise_suppression { Denoiser::SAMPLE_RATE } else { input_sample_rate };
+        let resampler = if resampler_input_rate != 16000 {
+            Some(StreamResampler::new(resampler_input_rate, 16000)?)
+        } else {
+            None
+        };
+
+        Ok(Self { denoiser, resampler, live_tap: None })
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Vec<f32> {
+        let samples = match self.denoiser.as_mut() {
+            Some(denoiser) => denoiser.push(samples),
+            None => samples.to_vec(),
+        };
+        let output = match self.resampler.as_mut() {
+            Some(resampler) => resampler.push(&samples),
+            None => samples,
+        };
+        if let Some(tap) = self.live_tap.as_mut() {
+            tap.push(&output);
+        }
+        output
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        let samples = match self.denoiser.as_mut() {
+            Some(denoiser) => denoiser.flush(),
+            None => Vec::new(),
+        };
+        let output = match self.resampler.as_mut() {
+            Some(resampler) => {
+                let mut output = resampler.push(&samples);
+                output.extend(resampler.flush());
+                output
+            }
+            None => samples,
+        };
+        if let Some(tap) = self.live_tap.as_mut() {
+            tap.push(&output);
+        }
+        output
+    }
+}
+
+// RNNoise denoiser working on 480-sample frames at 48kHz
+struct Denoiser {
+    state: Box<DenoiseState<'static>>,
+    upsampler: Option<StreamResampler>, // Devices not running at 48kHz are converted first
+    pending: Vec<f32>,
+    first_frame: bool,
+}
+
+impl Denoiser {
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn new(input_sample_rate: u32) -> Result<Self, String> {
+        let upsampler = if input_sample_rate != Self::SAMPLE_RATE {
+            Some(StreamResampler::new(input_sample_rate, Self::SAMPLE_RATE)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            state: DenoiseState::new(),
+            upsampler,
+            pending: Vec::new(),
+            first_frame: true,
+        })
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Vec<f32> {
+        let samples = match self.upsampler.as_mut() {
+            Some(upsampler) => upsampler.push(samples),
+            None => samples.to_vec(),
+        };
+        self.pending.extend_from_slice(&samples);
+        self.process_pending()
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        if let Some(upsampler) = self.upsampler.as_mut() {
+            let tail = upsampler.flush();
+            self.pending.extend_from_slice(&tail);
+        }
+        // Pad the last partial frame with silence so it still gets denoised
+        if !self.pending.is_empty() {
+            let padded_len = self.pending.len().div_ceil(DenoiseState::FRAME_SIZE) * DenoiseState::FRAME_SIZE;
+            self.pending.resize(padded_len, 0.0);
+        }
+        self.process_pending()
+    }
+
+    fn process_pending(&mut self) -> Vec<f32> {
+        let mut output = Vec::new();
+        let mut frame_out = [0.0f32; DenoiseState::FRAME_SIZE];
+
+        while self.pending.len() >= DenoiseState::FRAME_SIZE {
+            // RNNoise expects samples on the i16 scale
+            let frame_in: Vec<f32> = self.pending.drain(..DenoiseState::FRAME_SIZE)
+                .map(|sample| sample * i16::MAX as f32)
+                .collect();
+            self.state.process_frame(&mut frame_out, &frame_in);
+
+            // The first frame is mostly filter warm-up, so drop it as the RNNoise examples do
+            if self.first_frame {
+                self.first_frame = false;
+                continue;
+            }
+            output.extend(frame_out.iter().map(|sample| sample / i16::MAX as f32));
+        }
+        output
+    }
+}
+
+// Streaming mono resampler. cpal hands us buffers of arbitrary size while rubato
+// works on fixed chunks, so input is buffered until a full chunk is available.
+struct StreamResampler {
+    resampler: FftFixedIn<f32>,
+    pending: Vec<f32>,
+}
+
+impl StreamResampler {
+    const CHUNK_SIZE: usize = 1024;
+
+    fn new(input_sample_rate: u32, output_sample_rate: u32) -> Result<Self, String> {
+        let resampler = FftFixedIn::<f32>::new(input_sample_rate as usize, output_sample_rate as usize, Self::CHUNK_SIZE, 2, 1)
+            .map_err(|e| format!("Failed to create resampler: {}", e))?;
+        Ok(Self { resampler, pending: Vec::new() })
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.pending.extend_from_slice(samples);
+
+        let mut output = Vec::new();
+        while self.pending.len() >= self.resampler.input_frames_next() {
+            let chunk: Vec<f32> = self.pending.drain(..self.resampler.input_frames_next()).collect();
+            match self.resampler.process(&[chunk], None) {
+                Ok(mut resampled) => output.append(&mut resampled[0]),
+                Err(e) => {
+                    eprintln!("Resampling error: {}", e);
+                    break;
+                }
+            }
+        }
+        output
+    }
+
+    // Resample the final partial chunk when recording stops
+    fn flush(&mut self) -> Vec<f32> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+
+        // The partial call pads with silence, which only adds a few milliseconds at the end
+        let chunk = std::mem::take(&mut self.pending);
+        match self.resampler.process_partial(Some(&[chunk]), None) {
+            Ok(mut resampled) => resampled.swap_remove(0),
+            Err(e) => {
+                eprintln!("Resampling error: {}", e);
+                Vec::new()
+            }
         }
     }
-    
-    output
 }
 
 // Test function to verify audio recording works
@@ -335,4 +1285,270 @@ pub fn test_microphone() -> Result<String, String> {
     let device = get_default_input_device()?;
     let device_name = device.name().map_err(|e| e.to_string())?;
     Ok(format!("Microphone detected: {}", device_name))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    const DEVICE: &str = "Fake Mic";
+
+    // Plays canned interleaved PCM through the recorder as fast as it is accepted, then goes quiet.
+    // Unplugging it (`connected` false) makes it stop delivering and report the device gone.
+    struct FakeBackend {
+        format: InputFormat,
+        pcm: Vec<f32>,
+        connected: Arc<AtomicBool>,
+        drained: Arc<AtomicBool>, // Every sample has been delivered
+        unplug_after_frames: Option<usize>,
+    }
+
+    impl FakeBackend {
+        fn new(channels: u16, sample_rate: u32, pcm: Vec<f32>) -> Self {
+            Self {
+                format: InputFormat { device_name: DEVICE.to_string(), channels, sample_rate },
+                pcm,
+                connected: Arc::new(AtomicBool::new(true)),
+                drained: Arc::new(AtomicBool::new(false)),
+                unplug_after_frames: None,
+            }
+        }
+    }
+
+    struct FakeCapture {
+        stop: Arc<AtomicBool>,
+        thread: Option<thread::JoinHandle<()>>,
+    }
+
+    impl Drop for FakeCapture {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    impl AudioBackend for FakeBackend {
+        fn input_format(&self, device_id: Option<&str>) -> Result<InputFormat, String> {
+            match device_id {
+                Some(id) if id != self.format.device_name => Err(format!("Input device not found: {}", id)),
+                _ => Ok(self.format.clone()),
+            }
+        }
+
+        fn is_connected(&self, device_name: &str) -> bool {
+            device_name == self.format.device_name && self.connected.load(Ordering::SeqCst)
+        }
+
+        fn open_input(&self, device_name: &str, mut on_data: InputDataCallback, device_lost: Arc<AtomicBool>) -> Result<InputGuard, String> {
+            if !self.is_connected(device_name) {
+                return Err(format!("Input device not found: {}", device_name));
+            }
+
+            let channels = self.format.channels as usize;
+            let buffer_len = (self.format.sample_rate as usize / 100) * channels; // 10ms buffers
+            let pcm = self.pcm.clone();
+            let connected = self.connected.clone();
+            let drained = self.drained.clone();
+            let unplug_after = self.unplug_after_frames.map(|frames| frames * channels);
+            let stop = Arc::new(AtomicBool::new(false));
+            let stop_clone = stop.clone();
+
+            let thread = thread::spawn(move || {
+                let mut delivered = 0;
+                for buffer in pcm.chunks(buffer_len) {
+                    if stop_clone.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    if unplug_after.map(|limit| delivered >= limit).unwrap_or(false) {
+                        connected.store(false, Ordering::SeqCst);
+                        device_lost.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                    on_data(buffer);
+                    delivered += buffer.len();
+                }
+                drained.store(true, Ordering::SeqCst);
+            });
+
+            Ok(Box::new(FakeCapture { stop, thread: Some(thread) }))
+        }
+    }
+
+    fn sine(frequency: f32, amplitude: f32, sample_rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn interleave(channels: &[Vec<f32>]) -> Vec<f32> {
+        let frames = channels[0].len();
+        (0..frames).flat_map(|i| channels.iter().map(move |channel| channel[i])).collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+    }
+
+    fn wait_for(flag: &AtomicBool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !flag.load(Ordering::SeqCst) {
+            assert!(Instant::now() < deadline, "timed out waiting for the fake backend");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn recorder_for(backend: FakeBackend, dir: &tempfile::TempDir) -> (AudioRecorder, Arc<AtomicBool>) {
+        let drained = backend.drained.clone();
+        let mut recorder = AudioRecorder::with_backend(Arc::new(backend));
+        recorder.recordings_dir = Some(dir.path().to_path_buf());
+        recorder.echo_suppression = false;
+        (recorder, drained)
+    }
+
+    // Record everything the backend has, then collect the file
+    fn record(recorder: &mut AudioRecorder, drained: &AtomicBool) -> String {
+        recorder.start_recording(None, None, None, None).unwrap();
+        wait_for(drained);
+        recorder.stop_recording().unwrap()
+    }
+
+    fn read_whisper_file(path: &str) -> Vec<f32> {
+        let audio = audio_codec::open_audio(path).unwrap();
+        assert_eq!((audio.sample_rate, audio.channels), (16000, 1));
+        audio.samples.collect::<Result<Vec<_>, _>>().unwrap()
+    }
+
+    fn assert_about_one_second(samples: &[f32]) {
+        // The resampler's latency and final padding shift the length by a few milliseconds
+        assert!((15_500..=16_500).contains(&samples.len()), "got {} samples", samples.len());
+    }
+
+    // A 440Hz tone on the left channel of a 48kHz stereo device
+    fn left_tone_backend() -> FakeBackend {
+        let left = sine(440.0, 0.5, 48000, 48000);
+        let right = vec![0.0; 48000];
+        FakeBackend::new(2, 48000, interleave(&[left, right]))
+    }
+
+    #[test]
+    fn resamples_selected_channel_to_16khz_mono() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut recorder, drained) = recorder_for(left_tone_backend(), &dir);
+
+        let samples = read_whisper_file(&record(&mut recorder, &drained));
+        assert_about_one_second(&samples);
+        // RMS of a sine is amplitude / sqrt(2)
+        assert!((rms(&samples) - 0.354).abs() < 0.03, "rms {}", rms(&samples));
+    }
+
+    #[test]
+    fn records_only_the_selected_channel() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut recorder, drained) = recorder_for(left_tone_backend(), &dir);
+        recorder.set_channel(ChannelSelection::Channel(1)).unwrap();
+
+        let samples = read_whisper_file(&record(&mut recorder, &drained));
+        assert_about_one_second(&samples);
+        assert!(rms(&samples) < 0.001, "the right channel is silent");
+    }
+
+    #[test]
+    fn downmix_averages_channels() {
+        let dir = tempfile::tempdir().unwrap();
+        let tone = sine(300.0, 0.4, 44100, 44100);
+        let inverted: Vec<f32> = tone.iter().map(|s| -s).collect();
+        let (mut recorder, drained) = recorder_for(FakeBackend::new(2, 44100, interleave(&[tone, inverted])), &dir);
+        recorder.set_channel(ChannelSelection::Downmix).unwrap();
+
+        let samples = read_whisper_file(&record(&mut recorder, &drained));
+        assert_about_one_second(&samples);
+        assert!(rms(&samples) < 0.001, "opposite channels should cancel out");
+    }
+
+    #[test]
+    fn rejects_channel_the_device_lacks() {
+        let (mut recorder, _) = recorder_for(FakeBackend::new(1, 16000, Vec::new()), &tempfile::tempdir().unwrap());
+        assert!(recorder.set_channel(ChannelSelection::Channel(1)).is_err());
+        assert_eq!(recorder.channel, ChannelSelection::Channel(0));
+    }
+
+    #[test]
+    fn archive_keeps_native_rate_and_channels() {
+        let dir = tempfile::tempdir().unwrap();
+        let tone = sine(440.0, 0.5, 48000, 48000);
+        let (mut recorder, drained) = recorder_for(FakeBackend::new(2, 48000, interleave(&[tone.clone(), tone])), &dir);
+        recorder.quality = RecordingQuality { archive_enabled: true, archive_format: ArchiveFormat::Pcm16 };
+
+        record(&mut recorder, &drained);
+        let archive = hound::WavReader::open(recorder.last_archive_path.as_ref().unwrap()).unwrap();
+        let spec = archive.spec();
+        assert_eq!((spec.channels, spec.sample_rate, spec.bits_per_sample), (2, 48000, 16));
+        assert_eq!(archive.duration(), 48000);
+    }
+
+    #[test]
+    fn stop_finalizes_a_readable_wav() {
+        let dir = tempfile::tempdir().unwrap();
+        // Already at 16kHz mono, so samples pass straight through
+        let tone = sine(200.0, 0.25, 16000, 16000);
+        let (mut recorder, drained) = recorder_for(FakeBackend::new(1, 16000, tone.clone()), &dir);
+
+        let path = record(&mut recorder, &drained);
+        assert!(path.ends_with(".wav"));
+        let samples = read_whisper_file(&path);
+        assert_eq!(samples.len(), tone.len());
+        let max_error = samples.iter().zip(&tone).map(|(a, b)| (a - b).abs()).fold(0.0f32, f32::max);
+        assert!(max_error < 1.0 / 8192.0, "max error {}", max_error);
+        assert!(!recorder.is_recording());
+    }
+
+    #[test]
+    fn flac_output_decodes_to_the_same_audio() {
+        let dir = tempfile::tempdir().unwrap();
+        let tone = sine(200.0, 0.25, 16000, 16000);
+        let (mut recorder, drained) = recorder_for(FakeBackend::new(1, 16000, tone.clone()), &dir);
+        recorder.output_format = RecordingFormat::Flac;
+
+        let path = record(&mut recorder, &drained);
+        assert!(path.ends_with(".flac"));
+        assert!(!std::path::Path::new(&path).with_extension("wav").exists());
+        assert_eq!(read_whisper_file(&path).len(), tone.len());
+    }
+
+    #[test]
+    fn unplugged_device_auto_stops_with_a_valid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut backend = FakeBackend::new(1, 16000, sine(200.0, 0.25, 16000, 32000));
+        backend.unplug_after_frames = Some(8000);
+        let (mut recorder, _) = recorder_for(backend, &dir);
+
+        let (stopped_tx, stopped_rx) = mpsc::channel();
+        let (lost_tx, lost_rx) = mpsc::channel();
+        let on_auto_stop: AutoStopCallback = Box::new(move |_, reason| stopped_tx.send(reason).unwrap());
+        let on_device_lost: DeviceLostCallback = Box::new(move |loss| lost_tx.send(loss).unwrap());
+        recorder.start_recording(Some(on_auto_stop), Some(on_device_lost), None, None).unwrap();
+
+        let reason = stopped_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(reason, AutoStopReason::DeviceLost);
+        let loss = lost_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(loss.lost_device, DEVICE);
+        assert_eq!(loss.resumed_on, None);
+
+        let samples = read_whisper_file(&recorder.stop_recording().unwrap());
+        assert_eq!(samples.len(), 8000);
+    }
+
+    #[test]
+    fn echo_gate_records_silence_while_speaking() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut recorder, drained) = recorder_for(FakeBackend::new(1, 16000, sine(200.0, 0.25, 16000, 16000)), &dir);
+        recorder.echo_suppression = true;
+        recorder.echo_gate.begin_speaking();
+
+        let samples = read_whisper_file(&record(&mut recorder, &drained));
+        assert_eq!(samples.len(), 16000);
+        assert!(rms(&samples) < 0.001);
+    }
+}
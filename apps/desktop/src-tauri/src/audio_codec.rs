@@ -0,0 +1,717 @@
+use audiopus::coder::{Decoder as OpusDecoder, Encoder as OpusEncoder};
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use ogg::{PacketReader, PacketWriteEndInfo, PacketWriter};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Storage format for finished Whisper recordings. Recording always goes to WAV first (it can be
+// finalized after a crash); the file is converted when the recording is collected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    #[default]
+    Wav,
+    Flac, // Lossless, roughly half the size for speech
+    Opus, // Lossy at speech bitrates, around a twentieth of the size
+}
+
+impl RecordingFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordingFormat::Wav => "wav",
+            RecordingFormat::Flac => "flac",
+            RecordingFormat::Opus => "opus",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "wav" => Some(RecordingFormat::Wav),
+            "flac" => Some(RecordingFormat::Flac),
+            "opus" | "ogg" => Some(RecordingFormat::Opus),
+            _ => None,
+        }
+    }
+}
+
+// Decoded audio as interleaved f32 in [-1.0, 1.0], read lazily so long files stay out of memory
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub total_frames: Option<u64>,
+    pub samples: Box<dyn Iterator<Item = Result<f32, String>>>,
+}
+
+pub fn open_audio(path: &str) -> Result<DecodedAudio, String> {
+    match RecordingFormat::from_path(Path::new(path)) {
+        Some(RecordingFormat::Flac) => open_flac(path),
+        Some(RecordingFormat::Opus) => open_opus(path),
+        _ => open_wav(path),
+    }
+}
+
+pub fn duration_ms(path: &str) -> Option<u64> {
+    let audio = match RecordingFormat::from_path(Path::new(path))? {
+        // Opus headers carry no length; the last page's granule position has it
+        RecordingFormat::Opus => return opus_duration_ms(path),
+        _ => open_audio(path).ok()?,
+    };
+    Some(audio.total_frames? * 1000 / audio.sample_rate.max(1) as u64)
+}
+
+// Convert a finished 16kHz mono WAV recording to `format`, replacing the WAV on success
+pub fn encode_recording(wav_path: &str, format: RecordingFormat) -> Result<String, String> {
+    if format == RecordingFormat::Wav {
+        return Ok(wav_path.to_string());
+    }
+
+    let audio = open_wav(wav_path)?;
+    if audio.sample_rate != 16000 || audio.channels != 1 {
+        return Err(format!("Expected 16kHz mono audio, got {}Hz with {} channels", audio.sample_rate, audio.channels));
+    }
+    let samples = audio.samples.collect::<Result<Vec<f32>, String>>()?;
+
+    let output_path: PathBuf = Path::new(wav_path).with_extension(format.extension());
+    let result = match format {
+        RecordingFormat::Flac => write_flac(&output_path, &samples),
+        RecordingFormat::Opus => write_opus(&output_path, &samples),
+        RecordingFormat::Wav => unreachable!(),
+    };
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(e);
+    }
+
+    std::fs::remove_file(wav_path)
+        .map_err(|e| format!("Failed to remove WAV after encoding: {}", e))?;
+    Ok(output_path.to_string_lossy().to_string())
+}
+
+fn open_wav(path: &str) -> Result<DecodedAudio, String> {
+    let reader = hound::WavReader::open(path)
+        .map_err(|e| format!("Failed to open WAV file: {}", e))?;
+    let spec = reader.spec();
+    let total_frames = Some(reader.duration() as u64);
+
+    let samples: Box<dyn Iterator<Item = Result<f32, String>>> = match spec.sample_format {
+        hound::SampleFormat::Float => Box::new(reader.into_samples::<f32>()
+            .map(|sample| sample.map_err(|e| e.to_string()))),
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            Box::new(reader.into_samples::<i32>()
+                .map(move |sample| sample.map(|s| s as f32 / scale).map_err(|e| e.to_string())))
+        }
+    };
+
+    Ok(DecodedAudio { sample_rate: spec.sample_rate, channels: spec.channels, total_frames, samples })
+}
+
+// FLAC
+
+fn open_flac(path: &str) -> Result<DecodedAudio, String> {
+    let reader = claxon::FlacReader::open(path)
+        .map_err(|e| format!("Failed to open FLAC file: {}", e))?;
+    let info = reader.streaminfo();
+    let scale = (1i64 << (info.bits_per_sample - 1)) as f32;
+
+    Ok(DecodedAudio {
+        sample_rate: info.sample_rate,
+        channels: info.channels as u16,
+        total_frames: info.samples,
+        samples: Box::new(FlacSamples { reader, buffer: Vec::new(), block: None, position: 0, scale, finished: false }),
+    })
+}
+
+// Walks FLAC blocks one at a time, yielding interleaved samples
+struct FlacSamples {
+    reader: claxon::FlacReader<File>,
+    buffer: Vec<i32>,
+    block: Option<claxon::Block>,
+    position: u32, // Interleaved index into the current block
+    scale: f32,
+    finished: bool,
+}
+
+impl Iterator for FlacSamples {
+    type Item = Result<f32, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(block) = self.block.as_ref() {
+                let channels = block.channels();
+                if self.position < block.duration() * channels {
+                    let sample = block.sample(self.position % channels, self.position / channels);
+                    self.position += 1;
+                    return Some(Ok(sample as f32 / self.scale));
+                }
+            }
+            if self.finished {
+                return None;
+            }
+
+            let buffer = match self.block.take() {
+                Some(block) => block.into_buffer(),
+                None => std::mem::take(&mut self.buffer),
+            };
+            match self.reader.blocks().read_next_or_eof(buffer) {
+                Ok(Some(block)) => {
+                    self.block = Some(block);
+                    self.position = 0;
+                }
+                Ok(None) => self.finished = true,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(format!("Failed to decode FLAC: {}", e)));
+                }
+            }
+        }
+    }
+}
+
+const FLAC_BLOCK_SIZE: usize = 4096;
+
+// Minimal FLAC encoder: 16-bit mono, fixed-blocksize frames, each subframe using whichever
+// fixed predictor (order 0-4) gives the smallest Rice-coded residual
+fn write_flac(path: &Path, samples: &[f32]) -> Result<(), String> {
+    // open_wav scales 16-bit samples by 1/32768, so this recovers them exactly
+    let pcm: Vec<i32> = samples.iter()
+        .map(|&sample| (sample * 32768.0).round().clamp(i16::MIN as f32, i16::MAX as f32) as i32)
+        .collect();
+
+    let file = File::create(path).map_err(|e| format!("Failed to create FLAC file: {}", e))?;
+    let mut out = BufWriter::new(file);
+
+    let mut header = BitWriter::new();
+    header.write_bits(u64::from_be_bytes(*b"\0\0\0\0fLaC"), 32);
+    header.write_bits(1, 1); // Last metadata block
+    header.write_bits(0, 7); // STREAMINFO
+    header.write_bits(34, 24);
+    header.write_bits(FLAC_BLOCK_SIZE as u64, 16); // Min block size
+    header.write_bits(FLAC_BLOCK_SIZE as u64, 16); // Max block size
+    header.write_bits(0, 24); // Min frame size unknown
+    header.write_bits(0, 24); // Max frame size unknown
+    header.write_bits(16000, 20);
+    header.write_bits(0, 3); // One channel
+    header.write_bits(15, 5); // 16 bits per sample
+    header.write_bits(pcm.len() as u64, 36);
+    header.write_bits(0, 64); // MD5 left unset
+    header.write_bits(0, 64);
+    out.write_all(&header.into_bytes()).map_err(|e| e.to_string())?;
+
+    for (frame_number, block) in pcm.chunks(FLAC_BLOCK_SIZE).enumerate() {
+        out.write_all(&encode_flac_frame(frame_number as u64, block)).map_err(|e| e.to_string())?;
+    }
+    out.flush().map_err(|e| e.to_string())
+}
+
+fn encode_flac_frame(frame_number: u64, block: &[i32]) -> Vec<u8> {
+    let mut frame = BitWriter::new();
+    frame.write_bits(0b11111111111110, 14); // Sync code
+    frame.write_bits(0, 1); // Reserved
+    frame.write_bits(0, 1); // Fixed blocksize
+    frame.write_bits(0b0111, 4); // Block size stored as 16 bits after the header
+    frame.write_bits(0b0000, 4); // Sample rate from STREAMINFO
+    frame.write_bits(0b0000, 4); // Mono
+    frame.write_bits(0b100, 3); // 16 bits per sample
+    frame.write_bits(0, 1); // Reserved
+    frame.write_utf8_number(frame_number);
+    frame.write_bits(block.len() as u64 - 1, 16);
+    let crc = crc8(frame.bytes());
+    frame.write_bits(crc as u64, 8);
+
+    write_flac_subframe(&mut frame, block);
+
+    let mut bytes = frame.into_bytes();
+    let crc = crc16(&bytes);
+    bytes.extend_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+fn write_flac_subframe(frame: &mut BitWriter, block: &[i32]) {
+    // Residuals of fixed predictor `order` (the order-th difference of the signal)
+    let residuals = |order: usize| -> Vec<i64> {
+        let mut signal: Vec<i64> = block.iter().map(|&s| s as i64).collect();
+        for _ in 0..order {
+            signal = signal.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        }
+        signal
+    };
+
+    let mut best: Option<(usize, u32, u64, Vec<u64>)> = None; // (order, rice parameter, bits, folded residuals)
+    for order in 0..=4usize.min(block.len().saturating_sub(1)) {
+        let folded: Vec<u64> = residuals(order).iter()
+            .map(|&r| ((r << 1) ^ (r >> 63)) as u64)
+            .collect();
+        let (parameter, bits) = best_rice_parameter(&folded);
+        if best.as_ref().map(|(_, _, best_bits, _)| bits < *best_bits).unwrap_or(true) {
+            best = Some((order, parameter, bits, folded));
+        }
+    }
+
+    let verbatim_bits = block.len() as u64 * 16;
+    match best {
+        Some((order, parameter, bits, folded)) if bits + order as u64 * 16 < verbatim_bits => {
+            frame.write_bits(0, 1);
+            frame.write_bits(0b001000 | order as u64, 6); // FIXED subframe
+            frame.write_bits(0, 1); // No wasted bits
+            for &warm_up in &block[..order] {
+                frame.write_bits(warm_up as u16 as u64, 16);
+            }
+            frame.write_bits(0b00, 2); // Rice coding with 4-bit parameters
+            frame.write_bits(0, 4); // Partition order 0: one partition
+            frame.write_bits(parameter as u64, 4);
+            for value in folded {
+                frame.write_unary(value >> parameter);
+                frame.write_bits(value & ((1u64 << parameter) - 1), parameter);
+            }
+        }
+        _ => {
+            frame.write_bits(0, 1);
+            frame.write_bits(0b000001, 6); // VERBATIM subframe
+            frame.write_bits(0, 1);
+            for &sample in block {
+                frame.write_bits(sample as u16 as u64, 16);
+            }
+        }
+    }
+    frame.pad_to_byte();
+}
+
+// Rice parameter (0-14) with the fewest total bits
+fn best_rice_parameter(folded: &[u64]) -> (u32, u64) {
+    (0..=14u32)
+        .map(|parameter| {
+            let bits: u64 = folded.iter().map(|value| (value >> parameter) + 1 + parameter as u64).sum();
+            (parameter, bits)
+        })
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap_or((0, u64::MAX))
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    filled: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), current: 0, filled: 0 }
+    }
+
+    fn write_bits(&mut self, value: u64, count: u32) {
+        for bit in (0..count).rev() {
+            self.current = (self.current << 1) | ((value >> bit) & 1) as u8;
+            self.filled += 1;
+            if self.filled == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        for _ in 0..zeros {
+            self.write_bits(0, 1);
+        }
+        self.write_bits(1, 1);
+    }
+
+    // FLAC's UTF-8-like variable length frame number
+    fn write_utf8_number(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write_bits(value, 8);
+            return;
+        }
+        // A lead byte with n-1 continuation bytes holds 5n+1 bits (7 - n in the lead byte)
+        let mut continuation: u32 = 1;
+        while continuation < 6 && value >= 1u64 << (5 * (continuation + 1) + 1) {
+            continuation += 1;
+        }
+        let lead_marker = (0xFF00u64 >> (continuation + 1)) & 0xFF;
+        self.write_bits(lead_marker | (value >> (6 * continuation)), 8);
+        for index in (0..continuation).rev() {
+            self.write_bits(0x80 | ((value >> (6 * index)) & 0x3F), 8);
+        }
+    }
+
+    fn pad_to_byte(&mut self) {
+        if self.filled > 0 {
+            self.write_bits(0, 8 - self.filled);
+        }
+    }
+
+    // Whole bytes written so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.pad_to_byte();
+        self.bytes
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// Ogg Opus (RFC 7845)
+
+const OPUS_FRAME_SAMPLES: usize = 320; // 20ms at 16kHz
+const OPUS_PRE_SKIP: u16 = 312; // In 48kHz samples, as the spec counts them
+const OPUS_BITRATE: i32 = 24000; // Transparent enough for speech recognition
+const OPUS_SERIAL: u32 = 0x5052_4543;
+
+fn write_opus(path: &Path, samples: &[f32]) -> Result<(), String> {
+    let mut encoder = OpusEncoder::new(SampleRate::Hz16000, Channels::Mono, Application::Voip)
+        .map_err(|e| format!("Failed to create Opus encoder: {}", e))?;
+    encoder.set_bitrate(Bitrate::BitsPerSecond(OPUS_BITRATE))
+        .map_err(|e| format!("Failed to set Opus bitrate: {}", e))?;
+
+    let file = File::create(path).map_err(|e| format!("Failed to create Opus file: {}", e))?;
+    let mut writer = PacketWriter::new(BufWriter::new(file));
+
+    let mut head = Vec::with_capacity(19);
+    head.extend_from_slice(b"OpusHead");
+    head.push(1); // Version
+    head.push(1); // Channels
+    head.extend_from_slice(&OPUS_PRE_SKIP.to_le_bytes());
+    head.extend_from_slice(&16000u32.to_le_bytes()); // Original input rate
+    head.extend_from_slice(&0i16.to_le_bytes()); // Output gain
+    head.push(0); // Mapping family
+    writer.write_packet(head.into_boxed_slice(), OPUS_SERIAL, PacketWriteEndInfo::EndPage, 0)
+        .map_err(|e| e.to_string())?;
+
+    let vendor = b"project-r";
+    let mut tags = Vec::new();
+    tags.extend_from_slice(b"OpusTags");
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor);
+    tags.extend_from_slice(&0u32.to_le_bytes()); // No user comments
+    writer.write_packet(tags.into_boxed_slice(), OPUS_SERIAL, PacketWriteEndInfo::EndPage, 0)
+        .map_err(|e| e.to_string())?;
+
+    let frame_count = samples.len().div_ceil(OPUS_FRAME_SAMPLES).max(1);
+    let mut packet = vec![0u8; 4000];
+    for index in 0..frame_count {
+        // Pad the last frame with silence; the final granule position trims it on decode
+        let mut frame = [0.0f32; OPUS_FRAME_SAMPLES];
+        let start = index * OPUS_FRAME_SAMPLES;
+        let end = (start + OPUS_FRAME_SAMPLES).min(samples.len());
+        frame[..end.saturating_sub(start)].copy_from_slice(&samples[start.min(end)..end]);
+
+        let length = encoder.encode_float(&frame, &mut packet)
+            .map_err(|e| format!("Opus encoding failed: {}", e))?;
+
+        let is_last = index + 1 == frame_count;
+        let granule = if is_last {
+            OPUS_PRE_SKIP as u64 + samples.len() as u64 * 3
+        } else {
+            OPUS_PRE_SKIP as u64 + ((index + 1) * OPUS_FRAME_SAMPLES) as u64 * 3
+        };
+        let end_info = if is_last { PacketWriteEndInfo::EndStream } else { PacketWriteEndInfo::NormalPacket };
+        writer.write_packet(packet[..length].to_vec().into_boxed_slice(), OPUS_SERIAL, end_info, granule)
+            .map_err(|e| e.to_string())?;
+    }
+
+    writer.into_inner().flush().map_err(|e| e.to_string())
+}
+
+fn open_opus(path: &str) -> Result<DecodedAudio, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open Opus file: {}", e))?;
+    let mut reader = PacketReader::new(BufReader::new(file));
+
+    let head = reader.read_packet()
+        .map_err(|e| format!("Failed to read Opus header: {}", e))?
+        .ok_or("Empty Opus file")?;
+    if head.data.len() < 19 || &head.data[..8] != b"OpusHead" {
+        return Err("Not an Ogg Opus file".to_string());
+    }
+    let channels = head.data[9] as u16;
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+    let opus_channels = match channels {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        _ => return Err(format!("Unsupported Opus channel count: {}", channels)),
+    };
+
+    // Comment header, not needed for decoding
+    reader.read_packet()
+        .map_err(|e| format!("Failed to read Opus tags: {}", e))?;
+
+    let decoder = OpusDecoder::new(SampleRate::Hz16000, opus_channels)
+        .map_err(|e| format!("Failed to create Opus decoder: {}", e))?;
+
+    Ok(DecodedAudio {
+        sample_rate: 16000,
+        channels,
+        total_frames: None,
+        samples: Box::new(OpusSamples {
+            reader,
+            decoder,
+            channels: channels as usize,
+            pending: Vec::new(),
+            position: 0,
+            skip_frames: pre_skip / 3, // Pre-skip is counted at 48kHz
+            decoded_frames: 0,
+            pre_skip,
+            finished: false,
+        }),
+    })
+}
+
+// Decodes one Ogg packet at a time at 16kHz, trimming the encoder delay and end padding
+struct OpusSamples {
+    reader: PacketReader<BufReader<File>>,
+    decoder: OpusDecoder,
+    channels: usize,
+    pending: Vec<f32>,
+    position: usize,
+    skip_frames: u64,
+    decoded_frames: u64, // Frames produced after the pre-skip
+    pre_skip: u64,
+    finished: bool,
+}
+
+impl Iterator for OpusSamples {
+    type Item = Result<f32, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.position < self.pending.len() {
+                let sample = self.pending[self.position];
+                self.position += 1;
+                return Some(Ok(sample));
+            }
+            if self.finished {
+                return None;
+            }
+
+            let packet = match self.reader.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    self.finished = true;
+                    continue;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(format!("Failed to read Opus packet: {}", e)));
+                }
+            };
+
+            // Up to 120ms per packet
+            let mut output = vec![0.0f32; 1920 * self.channels];
+            let decoded = audiopus::packet::Packet::try_from(packet.data.as_slice())
+                .and_then(|opus_packet| {
+                    let signals = audiopus::MutSignals::try_from(output.as_mut_slice())?;
+                    self.decoder.decode_float(Some(opus_packet), signals, false)
+                });
+            let mut frames = match decoded {
+                Ok(frames) => frames as u64,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(format!("Opus decoding failed: {}", e)));
+                }
+            };
+
+            let skipped = frames.min(self.skip_frames);
+            self.skip_frames -= skipped;
+            frames -= skipped;
+
+            // The last page's granule position marks where the real audio ends
+            if packet.last_in_stream() {
+                let total = packet.absgp_page().saturating_sub(self.pre_skip) / 3;
+                frames = frames.min(total.saturating_sub(self.decoded_frames));
+                self.finished = true;
+            }
+            self.decoded_frames += frames;
+
+            let start = skipped as usize * self.channels;
+            let end = start + frames as usize * self.channels;
+            output.truncate(end);
+            output.drain(..start);
+            self.pending = output;
+            self.position = 0;
+        }
+    }
+}
+
+fn opus_duration_ms(path: &str) -> Option<u64> {
+    let file = File::open(path).ok()?;
+    let mut reader = PacketReader::new(BufReader::new(file));
+    let head = reader.read_packet().ok()??;
+    if head.data.len() < 19 || &head.data[..8] != b"OpusHead" {
+        return None;
+    }
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+
+    let mut last_granule = 0;
+    while let Ok(Some(packet)) = reader.read_packet() {
+        last_granule = packet.absgp_page();
+    }
+    // Granule positions count 48kHz samples
+    Some(last_granule.saturating_sub(pre_skip) / 48)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Encode 16-bit samples with write_flac and decode them with claxon, checking the stream header
+    fn flac_round_trip(pcm: &[i32]) -> Vec<i32> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("round-trip.flac");
+        let samples: Vec<f32> = pcm.iter().map(|&sample| sample as f32 / 32768.0).collect();
+        write_flac(&path, &samples).unwrap();
+
+        let mut reader = claxon::FlacReader::open(&path).unwrap();
+        let info = reader.streaminfo();
+        assert_eq!((info.sample_rate, info.channels, info.bits_per_sample), (16000, 1, 16));
+        assert_eq!(info.samples, Some(pcm.len() as u64));
+        reader.samples().collect::<Result<Vec<i32>, _>>().unwrap()
+    }
+
+    // Deterministic noise, so the residuals don't shrink under any fixed predictor
+    fn noise(count: usize, amplitude: i32) -> Vec<i32> {
+        let mut state: u32 = 0x1234_5678;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 16) as i32 % amplitude
+            })
+            .collect()
+    }
+
+    fn write_wav(path: &Path, samples: &[f32]) {
+        let spec = hound::WavSpec { channels: 1, sample_rate: 16000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for &sample in samples {
+            writer.write_sample((sample * 32768.0).round().clamp(-32768.0, 32767.0) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+    }
+
+    #[test]
+    fn silent_block_round_trips() {
+        let pcm = vec![0; FLAC_BLOCK_SIZE];
+        assert_eq!(flac_round_trip(&pcm), pcm);
+    }
+
+    #[test]
+    fn full_scale_block_round_trips() {
+        // Alternating extremes leave no predictor worth using, so the subframe is verbatim
+        let pcm: Vec<i32> = (0..FLAC_BLOCK_SIZE)
+            .map(|i| if i % 2 == 0 { i16::MAX as i32 } else { i16::MIN as i32 })
+            .collect();
+        assert_eq!(flac_round_trip(&pcm), pcm);
+    }
+
+    #[test]
+    fn partial_last_block_round_trips() {
+        let tone: Vec<i32> = (0..FLAC_BLOCK_SIZE * 2 + 1000)
+            .map(|i| (12000.0 * (i as f32 * 0.05).sin()) as i32)
+            .collect();
+        assert_eq!(flac_round_trip(&tone), tone);
+
+        let mut noisy = noise(FLAC_BLOCK_SIZE + 1, 3000);
+        noisy[FLAC_BLOCK_SIZE] = -1; // A one-sample last block has only the order-0 predictor
+        assert_eq!(flac_round_trip(&noisy), noisy);
+    }
+
+    #[test]
+    fn flac_clamps_samples_outside_full_scale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clipped.flac");
+        write_flac(&path, &[1.5, -1.5, 1.0, -1.0]).unwrap();
+        let decoded = claxon::FlacReader::open(&path).unwrap()
+            .samples()
+            .collect::<Result<Vec<i32>, _>>()
+            .unwrap();
+        assert_eq!(decoded, [32767, -32768, 32767, -32768]);
+    }
+
+    #[test]
+    fn flac_recording_decodes_to_the_wav_samples() {
+        let dir = tempfile::tempdir().unwrap();
+        let wav_path = dir.path().join("take.wav");
+        let samples: Vec<f32> = noise(FLAC_BLOCK_SIZE + 500, 20000).iter().map(|&s| s as f32 / 32768.0).collect();
+        write_wav(&wav_path, &samples);
+
+        let flac_path = encode_recording(wav_path.to_str().unwrap(), RecordingFormat::Flac).unwrap();
+        assert!(flac_path.ends_with(".flac"));
+        assert!(!wav_path.exists());
+        let audio = open_audio(&flac_path).unwrap();
+        assert_eq!(audio.samples.collect::<Result<Vec<f32>, _>>().unwrap(), samples);
+        assert_eq!(duration_ms(&flac_path), Some(((FLAC_BLOCK_SIZE + 500) * 1000 / 16000) as u64));
+    }
+
+    #[test]
+    fn opus_recording_keeps_its_length_and_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let wav_path = dir.path().join("take.wav");
+        // A second of tone plus a partial 20ms frame
+        let tone: Vec<f32> = (0..16_100)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 16000.0).sin())
+            .collect();
+        write_wav(&wav_path, &tone);
+
+        let opus_path = encode_recording(wav_path.to_str().unwrap(), RecordingFormat::Opus).unwrap();
+        assert!(opus_path.ends_with(".opus"));
+        assert!(!wav_path.exists());
+        assert_eq!(duration_ms(&opus_path), Some(1006));
+
+        let audio = open_audio(&opus_path).unwrap();
+        assert_eq!((audio.sample_rate, audio.channels), (16000, 1));
+        let decoded = audio.samples.collect::<Result<Vec<f32>, _>>().unwrap();
+        assert_eq!(decoded.len(), tone.len());
+        let (expected, actual) = (rms(&tone), rms(&decoded));
+        assert!((actual - expected).abs() < expected * 0.1, "rms {} vs {}", actual, expected);
+    }
+
+    #[test]
+    fn empty_opus_recording_decodes_to_no_samples() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.opus");
+        write_opus(&path, &[]).unwrap();
+        let audio = open_audio(path.to_str().unwrap()).unwrap();
+        assert_eq!(audio.samples.count(), 0);
+        assert_eq!(duration_ms(path.to_str().unwrap()), Some(0));
+    }
+
+    #[test]
+    fn wav_recordings_are_left_as_they_are() {
+        let dir = tempfile::tempdir().unwrap();
+        let wav_path = dir.path().join("take.wav");
+        write_wav(&wav_path, &[0.25; 160]);
+        let path = encode_recording(wav_path.to_str().unwrap(), RecordingFormat::Wav).unwrap();
+        assert_eq!(path, wav_path.to_str().unwrap());
+        assert!(wav_path.exists());
+    }
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tauri::Emitter;
+
+// Registry of background LLM work (redo generation, auto-summaries) with heartbeat tracking,
+// so a silent hang turns into a "stalled" event the UI can offer a retry for
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskKind {
+    Redo,    // target_id is a practice sheet ID
+    Summary, // target_id is a session ID
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    Completed,
+    Failed,
+    Stalled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub id: String,
+    pub kind: TaskKind,
+    pub target_id: String,
+    pub state: TaskState,
+    pub progress: String, // Last step reported through the heartbeat
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub started_at: DateTime<Utc>,
+    pub last_heartbeat: DateTime<Utc>,
+}
+
+static TASKS: OnceLock<Mutex<HashMap<String, TaskStatus>>> = OnceLock::new();
+
+fn tasks() -> &'static Mutex<HashMap<String, TaskStatus>> {
+    TASKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Handed to the task body so it can report progress between steps
+#[derive(Clone)]
+pub struct Heartbeat {
+    task_id: String,
+}
+
+impl Heartbeat {
+    pub fn beat(&self, progress: &str) {
+        if let Ok(mut tasks) = tasks().lock() {
+            if let Some(task) = tasks.get_mut(&self.task_id) {
+                task.progress = progress.to_string();
+                task.last_heartbeat = Utc::now();
+            }
+        }
+    }
+}
+
+// Register a task unless the same work is already running; retries reuse the previous entry
+pub fn try_start(kind: TaskKind, target_id: &str) -> Option<Heartbeat> {
+    let mut tasks = tasks().lock().ok()?;
+    let previous = tasks.values()
+        .find(|task| task.kind == kind && task.target_id == target_id)
+        .cloned();
+
+    if let Some(previous) = &previous {
+        if previous.state == TaskState::Running {
+            return None;
+        }
+    }
+
+    let now = Utc::now();
+    let id = previous.as_ref()
+        .map(|task| task.id.clone())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    tasks.insert(id.clone(), TaskStatus {
+        id: id.clone(),
+        kind,
+        target_id: target_id.to_string(),
+        state: TaskState::Running,
+        progress: "Starting".to_string(),
+        error: None,
+        attempts: previous.map(|task| task.attempts + 1).unwrap_or(1),
+        started_at: now,
+        last_heartbeat: now,
+    });
+
+    Some(Heartbeat { task_id: id })
+}
+
+pub fn get_task(task_id: &str) -> Option<TaskStatus> {
+    tasks().lock().ok()?.get(task_id).cloned()
+}
+
+pub fn list_tasks() -> Vec<TaskStatus> {
+    let mut list: Vec<TaskStatus> = tasks().lock()
+        .map(|tasks| tasks.values().cloned().collect())
+        .unwrap_or_default();
+    list.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+    list
+}
+
+fn finish(task_id: &str, state: TaskState, error: Option<String>) -> Option<TaskStatus> {
+    let mut tasks = tasks().lock().ok()?;
+    let task = tasks.get_mut(task_id)?;
+    task.state = state;
+    task.error = error;
+    Some(task.clone())
+}
+
+fn seconds_since_heartbeat(task_id: &str) -> i64 {
+    get_task(task_id)
+        .map(|task| (Utc::now() - task.last_heartbeat).num_seconds())
+        .unwrap_or(0)
+}
+
+// Run a task body while watching its heartbeat. If no beat arrives within `stall_after`
+// the body is dropped and a "background-task-stalled" event is emitted.
+pub async fn run_tracked<Fut>(
+    app: &tauri::AppHandle,
+    heartbeat: Heartbeat,
+    stall_after: Duration,
+    body: Fut,
+) -> Result<(), String>
+where
+    Fut: Future<Output = Result<(), String>>,
+{
+    let task_id = heartbeat.task_id.clone();
+    let watchdog = async {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            if seconds_since_heartbeat(&task_id) >= stall_after.as_secs() as i64 {
+                return;
+            }
+        }
+    };
+
+    let result = tokio::select! {
+        result = body => Some(result),
+        _ = watchdog => None,
+    };
+
+    match result {
+        Some(Ok(())) => {
+            if let Some(task) = finish(&task_id, TaskState::Completed, None) {
+                let _ = app.emit("background-task-finished", &task);
+            }
+            Ok(())
+        }
+        Some(Err(e)) => {
+            if let Some(task) = finish(&task_id, TaskState::Failed, Some(e.clone())) {
+                let _ = app.emit("background-task-finished", &task);
+            }
+            Err(e)
+        }
+        None => {
+            let error = format!("No progress for {} seconds", stall_after.as_secs());
+            if let Some(task) = finish(&task_id, TaskState::Stalled, Some(error.clone())) {
+                let _ = app.emit("background-task-stalled", &task);
+            }
+            Err(error)
+        }
+    }
+}
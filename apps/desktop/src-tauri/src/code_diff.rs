@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+
+// Line diffs between the learner's code and a suggested version, as unified diff hunks, and
+// line-range edits the tutor can make instead of replacing the whole file
+
+// Replace lines start_line..=end_line (1-based) with `replacement`; end_line = start_line - 1
+// inserts before start_line without removing anything
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineEdit {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub replacement: String,
+}
+
+// Apply edits given against the original line numbers. Fails without changing anything if an
+// edit is out of range or overlaps another.
+pub fn apply_edits(code: &str, edits: &[LineEdit]) -> Result<String, String> {
+    let mut lines: Vec<String> = code.lines().map(str::to_string).collect();
+    let mut sorted: Vec<&LineEdit> = edits.iter().collect();
+    sorted.sort_by_key(|edit| (edit.start_line, edit.end_line));
+
+    let mut previous_end = 0;
+    for edit in &sorted {
+        if edit.start_line == 0 || edit.start_line > lines.len() + 1 || edit.end_line + 1 < edit.start_line || edit.end_line > lines.len() {
+            return Err(format!("Edit of lines {}-{} is outside the {}-line file", edit.start_line, edit.end_line, lines.len()));
+        }
+        if edit.start_line <= previous_end {
+            return Err(format!("Edit of lines {}-{} overlaps an earlier edit", edit.start_line, edit.end_line));
+        }
+        previous_end = edit.end_line.max(edit.start_line - 1);
+    }
+
+    // Bottom-up, so earlier line numbers still point at the original lines
+    for edit in sorted.iter().rev() {
+        lines.splice(edit.start_line - 1..edit.end_line, edit.replacement.lines().map(str::to_string));
+    }
+
+    let mut edited = lines.join("\n");
+    if code.ends_with('\n') || code.is_empty() {
+        edited.push('\n');
+    }
+    Ok(edited)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+// Past this many lines on either side the LCS table gets too big, so the diff replaces everything
+const MAX_DIFF_LINES: usize = 2000;
+
+// Unified diff ("@@ -a,b +c,d @@" hunks with `context` unchanged lines around each change);
+// empty when the texts have the same lines
+pub fn unified_diff(before: &str, after: &str, context: usize) -> String {
+    let old: Vec<&str> = before.lines().collect();
+    let new: Vec<&str> = after.lines().collect();
+    let ops = line_ops(&old, &new);
+
+    // Positions in `old` and `new` before each op
+    let mut positions = Vec::with_capacity(ops.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for op in &ops {
+        positions.push((old_pos, new_pos));
+        match op {
+            Op::Equal => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Op::Delete => old_pos += 1,
+            Op::Insert => new_pos += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&index| ops[index] != Op::Equal).collect();
+    let mut diff = String::new();
+    let mut next = 0;
+    while next < changes.len() {
+        // Changes closer than two contexts apart share a hunk
+        let mut last = next;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * context + 1 {
+            last += 1;
+        }
+        let start = changes[next].saturating_sub(context);
+        let end = (changes[last] + context + 1).min(ops.len());
+
+        let old_len = ops[start..end].iter().filter(|op| **op != Op::Insert).count();
+        let new_len = ops[start..end].iter().filter(|op| **op != Op::Delete).count();
+        let (old_start, new_start) = positions[start];
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk_start(old_start, old_len), old_len, hunk_start(new_start, new_len), new_len
+        ));
+        for index in start..end {
+            let (old_index, new_index) = positions[index];
+            match ops[index] {
+                Op::Equal => diff.push_str(&format!(" {}\n", old[old_index])),
+                Op::Delete => diff.push_str(&format!("-{}\n", old[old_index])),
+                Op::Insert => diff.push_str(&format!("+{}\n", new[new_index])),
+            }
+        }
+        next = last + 1;
+    }
+    diff
+}
+
+// 1-based first line of a hunk side; an empty side names the line before it
+fn hunk_start(position: usize, len: usize) -> usize {
+    if len == 0 { position } else { position + 1 }
+}
+
+// Shortest edit script from the longest common subsequence of lines
+fn line_ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    if old.len() > MAX_DIFF_LINES || new.len() > MAX_DIFF_LINES {
+        let mut ops = vec![Op::Delete; old.len()];
+        ops.extend(vec![Op::Insert; new.len()]);
+        return ops;
+    }
+
+    // common[i][j]: length of the LCS of old[i..] and new[j..]
+    let mut common = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push(Op::Equal);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            // Deletions before insertions, as diffs are usually read
+            ops.push(Op::Delete);
+            i += 1;
+        } else {
+            ops.push(Op::Insert);
+            j += 1;
+        }
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(start_line: usize, end_line: usize, replacement: &str) -> LineEdit {
+        LineEdit { start_line, end_line, replacement: replacement.to_string() }
+    }
+
+    #[test]
+    fn edits_apply_against_original_line_numbers() {
+        let code = "a = 1\nb = 2\nprint(a)\nprint(b)\n";
+        let edited = apply_edits(code, &[edit(4, 4, "print(b * 2)"), edit(1, 1, "a = 10\nc = 3"), edit(3, 2, "# show a")]).unwrap();
+        assert_eq!(edited, "a = 10\nc = 3\nb = 2\n# show a\nprint(a)\nprint(b * 2)\n");
+    }
+
+    #[test]
+    fn overlapping_or_out_of_range_edits_are_rejected() {
+        let code = "a\nb\nc\n";
+        assert!(apply_edits(code, &[edit(1, 2, "x"), edit(2, 3, "y")]).unwrap_err().contains("overlaps"));
+        assert!(apply_edits(code, &[edit(3, 5, "x")]).unwrap_err().contains("outside"));
+        assert_eq!(apply_edits(code, &[edit(4, 3, "d")]).unwrap(), "a\nb\nc\nd\n");
+    }
+
+    #[test]
+    fn identical_code_has_no_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb", 3), "");
+    }
+
+    #[test]
+    fn changed_line_keeps_context() {
+        let before = "def total(items):\n    sum = 0\n    for item in items:\n        sum += item\n    return summ\n";
+        let after = "def total(items):\n    sum = 0\n    for item in items:\n        sum += item\n    return sum\n";
+        assert_eq!(
+            unified_diff(before, after, 1),
+            "@@ -4,2 +4,2 @@\n         sum += item\n-    return summ\n+    return sum\n"
+        );
+    }
+
+    #[test]
+    fn distant_changes_get_separate_hunks() {
+        let before = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let after = "A\nb\nc\nd\ne\nf\ng\nh\ni\n";
+        assert_eq!(
+            unified_diff(before, after, 1),
+            "@@ -1,2 +1,2 @@\n-a\n+A\n b\n@@ -8,1 +8,2 @@\n h\n+i\n"
+        );
+    }
+}
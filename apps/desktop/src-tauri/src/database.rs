@@ -1,5 +1,5 @@
 use rusqlite::{Connection, Result, params};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use dirs;
@@ -8,6 +8,9 @@ use dirs;
 pub struct Session {
     pub id: String,
     pub title: String,
+    pub is_archived: bool,
+    pub tags: Vec<String>,
+    pub ended_at: Option<DateTime<Utc>>, // Set when the session is closed out (e.g. after going idle)
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -21,6 +24,24 @@ pub struct Message {
     pub created_at: DateTime<Utc>,
 }
 
+// Where the learner left off in a session; unread messages include ones added by background jobs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionReadState {
+    pub session_id: String,
+    pub last_read_message_id: Option<String>, // None if the session was never marked read
+    pub last_read_at: Option<DateTime<Utc>>, // When that message was created
+    pub unread_count: usize,
+    pub first_unread_message_id: Option<String>, // Where to scroll to
+}
+
+// A message containing the search query; offsets are char indices into `message.content`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageMatch {
+    pub message: Message,
+    pub highlights: Vec<(usize, usize)>, // [start, end) of each occurrence
+    pub snippet: String, // Text around the first occurrence, for the results list
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
@@ -29,6 +50,37 @@ pub struct User {
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscriptionCorrection {
+    pub misheard: String,
+    pub intended: String,
+    pub times_seen: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscriptionJob {
+    pub id: String,
+    pub file_path: String,
+    pub session_id: Option<String>,
+    pub status: String, // "pending", "completed" or "failed"
+    pub transcript: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub id: String,
+    pub file_path: String,
+    pub duration_ms: Option<u64>,
+    pub session_id: Option<String>,
+    pub transcript: Option<String>, // Set once transcription of the file completes
+    pub file_exists: bool, // False when the audio was removed outside the app
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PracticeSheet {
     pub id: String,
@@ -36,6 +88,8 @@ pub struct PracticeSheet {
     pub title: String,
     pub is_completed: bool,
     pub is_redo_ready: bool,
+    pub generation_seed: Option<i64>, // Seed used for the current questions
+    pub generation_backend: Option<String>, // Fallback chain entry that generated the current questions
     pub created_at: DateTime<Utc>,
 }
 
@@ -47,6 +101,103 @@ pub struct PracticeQuestion {
     pub options: Vec<String>,
     pub correct_answer: String,
     pub question_order: i32,
+    pub source_message_ids: Vec<String>, // Session messages the question was derived from
+    pub source_summary_lines: Vec<usize>, // Summary line indices the question was derived from
+}
+
+// Structured memory entry with provenance back to the memory blob it was parsed from
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredMemoryEntry {
+    pub id: String,
+    pub user_id: String,
+    pub category: String,
+    pub content: String,
+    pub source_kind: String,
+    pub source_label: Option<String>,
+    pub source_line: Option<i64>,
+    pub origin: String, // "legacy_migration" for entries recovered from pre-existing memory, "live" otherwise
+    pub created_at: DateTime<Utc>,
+}
+
+// One run of user code, with every line typed into it so the run can be replayed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PythonExecution {
+    pub id: String,
+    pub code: String,
+    pub stdin_inputs: Vec<String>,
+    pub output: String,
+    pub status: String, // "running", "success" or "error"
+    pub replay_of: Option<String>, // Execution this run replayed
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+// Planned review of a practice sheet, joined with the sheet's current state
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScheduledPractice {
+    pub id: String,
+    pub practice_sheet_id: String,
+    pub title: String,
+    pub scheduled_for: DateTime<Utc>,
+    pub is_redo_ready: bool,
+    pub is_completed: bool,
+    pub notified: bool, // Whether the "practice-due" reminder has fired
+}
+
+// Reusable question in the bank; imported from a teacher's file or kept from a generated sheet
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BankQuestion {
+    pub id: String,
+    pub question_text: String,
+    pub options: Vec<String>,
+    pub correct_answer: String,
+    pub source: String, // "imported" or "generated"
+    pub source_path: Option<String>, // File the question was imported from
+    pub created_at: DateTime<Utc>,
+}
+
+// Fields a reviewer may change on a generated question; omitted fields are left as-is
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PracticeQuestionUpdate {
+    pub question_text: Option<String>,
+    pub options: Option<Vec<String>>,
+    pub correct_answer: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PracticeQuestionEdit {
+    pub id: String,
+    pub question_id: String,
+    pub practice_sheet_id: String,
+    pub action: String, // "update" or "delete"
+    pub previous: serde_json::Value,
+    pub updated: Option<serde_json::Value>,
+    pub edited_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MasteryCell {
+    pub topic: String, // practice sheet title
+    pub attempts: i32,
+    pub best_percent: f64,
+    pub latest_percent: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutstandingReview {
+    pub practice_sheet_id: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LearnerOverview {
+    pub user_id: String,
+    pub streak_days: i32,
+    pub last_activity: Option<DateTime<Utc>>,
+    pub session_count: i32,
+    pub mastery: Vec<MasteryCell>,
+    pub outstanding_reviews: Vec<OutstandingReview>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,11 +207,35 @@ pub struct PracticeAttempt {
     pub user_answers: Vec<String>,
     pub score: i32,
     pub total_questions: i32,
+    pub response_times_ms: Vec<i64>, // Time spent on each question, in question order
+    pub confidence_levels: Vec<i32>, // Self-reported confidence per question (1 = guess, 3 = sure)
+    pub questions: Vec<AttemptQuestion>, // The questions as answered; empty for attempts made before snapshots
     pub completed_at: DateTime<Utc>,
 }
 
+// Why an answer in an attempt was wrong, as explained by the quiz model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttemptFeedback {
+    pub attempt_id: String,
+    pub question_index: usize, // Position in the attempt's questions
+    pub question_text: String,
+    pub user_answer: String,
+    pub correct_answer: String,
+    pub explanation: String,
+    pub backend: String, // Label of the backend that wrote the explanation
+    pub created_at: DateTime<Utc>,
+}
+
+// Snapshot of a question at attempt time, since redo generation replaces the sheet's questions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttemptQuestion {
+    pub question_text: String,
+    pub correct_answer: String,
+}
+
 pub struct Database {
     conn: Connection,
+    demo_mode: bool, // Throwaway in-memory database that never touches the learner's record
 }
 
 impl Database {
@@ -78,11 +253,24 @@ impl Database {
         }
 
         let conn = Connection::open(&db_path)?;
-        let database = Database { conn };
+        let database = Database { conn, demo_mode: false };
+        database.initialize_tables()?;
+        database.migrate_legacy_memory()?;
+        Ok(database)
+    }
+
+    // In-memory database for demo/guest mode, discarded when the app closes or demo mode ends
+    pub fn new_demo() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        let database = Database { conn, demo_mode: true };
         database.initialize_tables()?;
         Ok(database)
     }
 
+    pub fn is_demo_mode(&self) -> bool {
+        self.demo_mode
+    }
+
     fn get_db_path() -> PathBuf {
         let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
         path.push("project-r");
@@ -95,13 +283,29 @@ impl Database {
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS sessions (
                 id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL DEFAULT 'default_user',
                 title TEXT NOT NULL,
+                is_archived BOOLEAN NOT NULL DEFAULT 0,
+                summary_seed INTEGER,
+                summary_backend TEXT,
+                ended_at TEXT,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL
             )",
             [],
         )?;
 
+        // Create session_tags table for organizing sessions
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS session_tags (
+                session_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY(session_id, tag),
+                FOREIGN KEY(session_id) REFERENCES sessions(id)
+            )",
+            [],
+        )?;
+
         // Create messages table
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS messages (
@@ -134,6 +338,8 @@ impl Database {
                 title TEXT NOT NULL,
                 is_completed BOOLEAN NOT NULL DEFAULT 0,
                 is_redo_ready BOOLEAN NOT NULL DEFAULT 0,
+                generation_seed INTEGER,
+                generation_backend TEXT,
                 created_at TEXT NOT NULL,
                 FOREIGN KEY(session_id) REFERENCES sessions(id)
             )",
@@ -149,6 +355,8 @@ impl Database {
                 options TEXT NOT NULL,
                 correct_answer TEXT NOT NULL,
                 question_order INTEGER NOT NULL,
+                source_message_ids TEXT NOT NULL DEFAULT '[]',
+                source_summary_lines TEXT NOT NULL DEFAULT '[]',
                 FOREIGN KEY(practice_sheet_id) REFERENCES practice_sheets(id)
             )",
             [],
@@ -162,12 +370,252 @@ impl Database {
                 user_answers TEXT NOT NULL,
                 score INTEGER NOT NULL,
                 total_questions INTEGER NOT NULL,
+                response_times TEXT NOT NULL DEFAULT '[]',
+                confidence_levels TEXT NOT NULL DEFAULT '[]',
+                questions TEXT NOT NULL DEFAULT '[]',
                 completed_at TEXT NOT NULL,
                 FOREIGN KEY(practice_sheet_id) REFERENCES practice_sheets(id)
             )",
             [],
         )?;
 
+        // Create attempt_feedback table (LLM explanations of the questions an attempt got wrong)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS attempt_feedback (
+                id TEXT PRIMARY KEY,
+                attempt_id TEXT NOT NULL,
+                question_index INTEGER NOT NULL,
+                question_text TEXT NOT NULL,
+                user_answer TEXT NOT NULL,
+                correct_answer TEXT NOT NULL,
+                explanation TEXT NOT NULL,
+                backend TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                UNIQUE(attempt_id, question_index),
+                FOREIGN KEY(attempt_id) REFERENCES practice_attempts(id)
+            )",
+            [],
+        )?;
+
+        // Create practice_question_edits table (audit trail of manual question edits)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS practice_question_edits (
+                id TEXT PRIMARY KEY,
+                question_id TEXT NOT NULL,
+                practice_sheet_id TEXT NOT NULL,
+                action TEXT NOT NULL,
+                previous TEXT NOT NULL,
+                updated TEXT,
+                edited_at TEXT NOT NULL,
+                FOREIGN KEY(practice_sheet_id) REFERENCES practice_sheets(id)
+            )",
+            [],
+        )?;
+
+        // Create memory_entries table (structured memory alongside the legacy memory_content blob)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS memory_entries (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                category TEXT NOT NULL,
+                content TEXT NOT NULL,
+                source_kind TEXT NOT NULL,
+                source_label TEXT,
+                source_line INTEGER,
+                origin TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        // Create python_executions table (execution history with stdin for replay)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS python_executions (
+                id TEXT PRIMARY KEY,
+                code TEXT NOT NULL,
+                stdin_inputs TEXT NOT NULL DEFAULT '[]',
+                output TEXT NOT NULL DEFAULT '',
+                status TEXT NOT NULL,
+                replay_of TEXT,
+                created_at TEXT NOT NULL,
+                finished_at TEXT
+            )",
+            [],
+        )?;
+
+        // Create pronunciation_lexicon table (word -> how TTS should say it)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS pronunciation_lexicon (
+                word TEXT PRIMARY KEY,
+                spoken TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create practice_schedule table (planned review dates for practice sheets)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS practice_schedule (
+                id TEXT PRIMARY KEY,
+                practice_sheet_id TEXT NOT NULL,
+                scheduled_for TEXT NOT NULL,
+                notified BOOLEAN NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY(practice_sheet_id) REFERENCES practice_sheets(id)
+            )",
+            [],
+        )?;
+
+        // Create question_bank table for imported and generated questions that sheets can reuse
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS question_bank (
+                id TEXT PRIMARY KEY,
+                question_text TEXT NOT NULL,
+                options TEXT NOT NULL,
+                correct_answer TEXT NOT NULL,
+                source TEXT NOT NULL,
+                source_path TEXT,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create transcription_jobs table so recordings survive a crash before transcription
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS transcription_jobs (
+                id TEXT PRIMARY KEY,
+                file_path TEXT NOT NULL,
+                session_id TEXT,
+                status TEXT NOT NULL DEFAULT 'pending',
+                transcript TEXT,
+                error TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create recordings table linking each recording to its session and transcript
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS recordings (
+                id TEXT PRIMARY KEY,
+                file_path TEXT NOT NULL,
+                duration_ms INTEGER,
+                session_id TEXT,
+                transcript TEXT,
+                created_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create settings table for app-wide key/value preferences
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create model_cache table (Ollama model metadata with a TTL)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS model_cache (
+                name TEXT PRIMARY KEY,
+                info TEXT NOT NULL,
+                cached_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create response_cache table (summary and quiz generations by request hash)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS response_cache (
+                key TEXT PRIMARY KEY,
+                feature TEXT NOT NULL,
+                backend TEXT NOT NULL,
+                response TEXT NOT NULL,
+                seed INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                last_used_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create transcription_corrections table (per-profile misheard -> intended terms)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS transcription_corrections (
+                user_id TEXT NOT NULL,
+                misheard TEXT NOT NULL,
+                intended TEXT NOT NULL,
+                times_seen INTEGER NOT NULL DEFAULT 1,
+                updated_at TEXT NOT NULL,
+                PRIMARY KEY(user_id, misheard)
+            )",
+            [],
+        )?;
+
+        // Create activity_log table (one row per active minute, local time, for usage limits)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS activity_log (
+                user_id TEXT NOT NULL,
+                minute TEXT NOT NULL,
+                PRIMARY KEY(user_id, minute)
+            )",
+            [],
+        )?;
+
+        // Create project_plans table (multi-session projects the tutor guides the learner through)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS project_plans (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                session_id TEXT,
+                title TEXT NOT NULL,
+                goal TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'active',
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create project_milestones table (ordered steps of a plan and their latest check)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS project_milestones (
+                id TEXT PRIMARY KEY,
+                plan_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL,
+                acceptance_criteria TEXT NOT NULL DEFAULT '[]',
+                check_code TEXT NOT NULL DEFAULT '',
+                completed_at TEXT,
+                last_check TEXT,
+                FOREIGN KEY(plan_id) REFERENCES project_plans(id)
+            )",
+            [],
+        )?;
+
+        // Create embeddings table (chunks of memory entries and messages with their vectors, per model)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS embeddings (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                source_kind TEXT NOT NULL,
+                source_id TEXT NOT NULL,
+                session_id TEXT,
+                chunk_index INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                model TEXT NOT NULL,
+                vector BLOB NOT NULL,
+                created_at TEXT NOT NULL,
+                UNIQUE(source_id, chunk_index, model)
+            )",
+            [],
+        )?;
+
         // Create index for better query performance
         self.conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_messages_session_id ON messages(session_id)",
@@ -184,6 +632,16 @@ impl Database {
             [],
         )?;
 
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_attempt_feedback_attempt_id ON attempt_feedback(attempt_id)",
+            [],
+        )?;
+
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_embeddings_user_model ON embeddings(user_id, model)",
+            [],
+        )?;
+
         // Handle schema migrations for existing databases
         self.migrate_database_schema()?;
         self.fix_user_datetime_data()?;
@@ -228,84 +686,200 @@ impl Database {
                 [],
             )?;
         }
-        
-        Ok(())
-    }
 
-    fn fix_user_datetime_data(&self) -> Result<()> {
-        // Check if users table exists and has data that needs fixing
-        let mut stmt = self.conn.prepare("SELECT id, created_at, updated_at FROM users")?;
-        let user_rows: Vec<(String, String, String)> = stmt.query_map([], |row| {
-            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-        })?.collect::<Result<Vec<_>, _>>()?;
-        
-        let now = Utc::now().to_rfc3339();
-        
-        for (user_id, created_at_str, updated_at_str) in user_rows {
-            let mut needs_update = false;
-            let mut new_created_at = created_at_str.clone();
-            let mut new_updated_at = updated_at_str.clone();
-            
-            // Check if created_at is valid RFC3339
-            if DateTime::parse_from_rfc3339(&created_at_str).is_err() {
-                new_created_at = now.clone();
-                needs_update = true;
-            }
-            
-            // Check if updated_at is valid RFC3339
-            if DateTime::parse_from_rfc3339(&updated_at_str).is_err() {
-                new_updated_at = now.clone();
-                needs_update = true;
-            }
-            
-            if needs_update {
-                self.conn.execute(
-                    "UPDATE users SET created_at = ?1, updated_at = ?2 WHERE id = ?3",
-                    params![new_created_at, new_updated_at, user_id],
-                )?;
-            }
-        }
+        if !self.has_column("sessions", "is_archived")? {
+            self.conn.execute(
+                "ALTER TABLE sessions ADD COLUMN is_archived BOOLEAN NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+
+        // Sessions belong to a learner profile so several learners can share one database
+        if !self.has_column("sessions", "user_id")? {
+            self.conn.execute(
+                "ALTER TABLE sessions ADD COLUMN user_id TEXT NOT NULL DEFAULT 'default_user'",
+                [],
+            )?;
+        }
+
+        if !self.has_column("sessions", "ended_at")? {
+            self.conn.execute("ALTER TABLE sessions ADD COLUMN ended_at TEXT", [])?;
+        }
+
+        if !self.has_column("sessions", "summary_seed")? {
+            self.conn.execute("ALTER TABLE sessions ADD COLUMN summary_seed INTEGER", [])?;
+        }
+
+        if !self.has_column("practice_sheets", "generation_seed")? {
+            self.conn.execute("ALTER TABLE practice_sheets ADD COLUMN generation_seed INTEGER", [])?;
+        }
+
+        // Which fallback chain entry produced summaries and sheets
+        if !self.has_column("sessions", "summary_backend")? {
+            self.conn.execute("ALTER TABLE sessions ADD COLUMN summary_backend TEXT", [])?;
+        }
+
+        // Class rules for the tutor (JSON SessionContext)
+        if !self.has_column("sessions", "context")? {
+            self.conn.execute("ALTER TABLE sessions ADD COLUMN context TEXT", [])?;
+        }
+
+        // Read marker: the last message the learner has seen
+        if !self.has_column("sessions", "last_read_message_id")? {
+            self.conn.execute("ALTER TABLE sessions ADD COLUMN last_read_message_id TEXT", [])?;
+            self.conn.execute("ALTER TABLE sessions ADD COLUMN last_read_at TEXT", [])?;
+        }
+
+        if !self.has_column("practice_sheets", "generation_backend")? {
+            self.conn.execute("ALTER TABLE practice_sheets ADD COLUMN generation_backend TEXT", [])?;
+        }
+
+        // Links from generated questions back to their source material
+        if !self.has_column("practice_questions", "source_message_ids")? {
+            self.conn.execute(
+                "ALTER TABLE practice_questions ADD COLUMN source_message_ids TEXT NOT NULL DEFAULT '[]'",
+                [],
+            )?;
+        }
+
+        if !self.has_column("practice_questions", "source_summary_lines")? {
+            self.conn.execute(
+                "ALTER TABLE practice_questions ADD COLUMN source_summary_lines TEXT NOT NULL DEFAULT '[]'",
+                [],
+            )?;
+        }
+
+        // Per-question timing and confidence for practice attempts
+        if !self.has_column("practice_attempts", "response_times")? {
+            self.conn.execute(
+                "ALTER TABLE practice_attempts ADD COLUMN response_times TEXT NOT NULL DEFAULT '[]'",
+                [],
+            )?;
+        }
+
+        if !self.has_column("practice_attempts", "confidence_levels")? {
+            self.conn.execute(
+                "ALTER TABLE practice_attempts ADD COLUMN confidence_levels TEXT NOT NULL DEFAULT '[]'",
+                [],
+            )?;
+        }
+
+        if !self.has_column("practice_attempts", "questions")? {
+            self.conn.execute(
+                "ALTER TABLE practice_attempts ADD COLUMN questions TEXT NOT NULL DEFAULT '[]'",
+                [],
+            )?;
+        }
+        
+        Ok(())
+    }
+
+    fn has_column(&self, table: &str, column: &str) -> Result<bool> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let column_names = stmt.query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(column_names.iter().any(|name| name == column))
+    }
+
+    fn fix_user_datetime_data(&self) -> Result<()> {
+        // Check if users table exists and has data that needs fixing
+        let mut stmt = self.conn.prepare("SELECT id, created_at, updated_at FROM users")?;
+        let user_rows: Vec<(String, String, String)> = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?.collect::<Result<Vec<_>, _>>()?;
+        
+        let now = Utc::now().to_rfc3339();
+        
+        for (user_id, created_at_str, updated_at_str) in user_rows {
+            let mut needs_update = false;
+            let mut new_created_at = created_at_str.clone();
+            let mut new_updated_at = updated_at_str.clone();
+            
+            // Check if created_at is valid RFC3339
+            if DateTime::parse_from_rfc3339(&created_at_str).is_err() {
+                new_created_at = now.clone();
+                needs_update = true;
+            }
+            
+            // Check if updated_at is valid RFC3339
+            if DateTime::parse_from_rfc3339(&updated_at_str).is_err() {
+                new_updated_at = now.clone();
+                needs_update = true;
+            }
+            
+            if needs_update {
+                self.conn.execute(
+                    "UPDATE users SET created_at = ?1, updated_at = ?2 WHERE id = ?3",
+                    params![new_created_at, new_updated_at, user_id],
+                )?;
+            }
+        }
         
         Ok(())
     }
 
+    // New sessions belong to the active learner profile
     pub fn create_session(&self, id: &str, title: &str) -> Result<()> {
         let now = Utc::now();
+        let user_id = self.get_active_user_id()?;
         self.conn.execute(
-            "INSERT INTO sessions (id, title, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
-            params![id, title, now.to_rfc3339(), now.to_rfc3339()],
+            "INSERT INTO sessions (id, user_id, title, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, user_id, title, now.to_rfc3339(), now.to_rfc3339()],
         )?;
         Ok(())
     }
 
+    // The learner profile a session belongs to
+    pub fn get_session_user_id(&self, session_id: &str) -> Result<String> {
+        let mut stmt = self.conn.prepare("SELECT user_id FROM sessions WHERE id = ?1")?;
+        stmt.query_row([session_id], |row| row.get(0))
+    }
+
     pub fn get_all_sessions(&self) -> Result<Vec<Session>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, title, created_at, updated_at FROM sessions ORDER BY updated_at DESC"
+            "SELECT id, title, is_archived, ended_at, created_at, updated_at FROM sessions ORDER BY updated_at DESC"
         )?;
 
         let session_iter = stmt.query_map([], |row| {
-            let created_at_str: String = row.get(2)?;
-            let updated_at_str: String = row.get(3)?;
+            let ended_at_str: Option<String> = row.get(3)?;
+            let created_at_str: String = row.get(4)?;
+            let updated_at_str: String = row.get(5)?;
             
             Ok(Session {
                 id: row.get(0)?,
                 title: row.get(1)?,
+                is_archived: row.get(2)?,
+                tags: Vec::new(),
+                ended_at: ended_at_str
+                    .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                    .map(|dt| dt.with_timezone(&Utc)),
                 created_at: DateTime::parse_from_rfc3339(&created_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(2, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "created_at".to_string(), rusqlite::types::Type::Text))?
                     .with_timezone(&Utc),
                 updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(3, "updated_at".to_string(), rusqlite::types::Type::Text))?
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(5, "updated_at".to_string(), rusqlite::types::Type::Text))?
                     .with_timezone(&Utc),
             })
         })?;
 
         let mut sessions = Vec::new();
         for session in session_iter {
-            sessions.push(session?);
+            let mut session = session?;
+            session.tags = self.get_session_tags(&session.id)?;
+            sessions.push(session);
         }
         Ok(sessions)
     }
 
+    pub fn get_session_tags(&self, session_id: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tag FROM session_tags WHERE session_id = ?1 ORDER BY tag ASC"
+        )?;
+        let tags = stmt.query_map([session_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(tags)
+    }
+
     pub fn get_session_messages(&self, session_id: &str) -> Result<Vec<Message>> {
         let mut stmt = self.conn.prepare(
             "SELECT id, session_id, role, content, created_at FROM messages 
@@ -333,6 +907,46 @@ impl Database {
         Ok(messages)
     }
 
+    // Case-insensitive substring search over one session's messages, oldest first
+    pub fn search_session_messages(&self, session_id: &str, query: &str) -> Result<Vec<MessageMatch>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // LIKE narrows the candidates; the exact offsets are worked out below
+        let pattern = format!("%{}%", query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        let mut stmt = self.conn.prepare(
+            "SELECT id, session_id, role, content, created_at FROM messages 
+             WHERE session_id = ?1 AND content LIKE ?2 ESCAPE '\\' ORDER BY created_at ASC"
+        )?;
+
+        let message_iter = stmt.query_map(params![session_id, pattern], |row| {
+            let created_at_str: String = row.get(4)?;
+            
+            Ok(Message {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                role: row.get(2)?,
+                content: row.get(3)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut matches = Vec::new();
+        for message in message_iter {
+            let message = message?;
+            let highlights = find_highlights(&message.content, query);
+            if let Some(&(start, end)) = highlights.first() {
+                let snippet = make_snippet(&message.content, start, end);
+                matches.push(MessageMatch { message, highlights, snippet });
+            }
+        }
+        Ok(matches)
+    }
+
     pub fn add_message(&self, session_id: &str, role: &str, content: &str) -> Result<String> {
         let id = uuid::Uuid::new_v4().to_string();
         let now = Utc::now();
@@ -342,37 +956,210 @@ impl Database {
             params![id, session_id, role, content, now.to_rfc3339()],
         )?;
 
-        // Update session's updated_at timestamp
+        // Update session's updated_at timestamp (new activity also reopens an ended session)
         self.conn.execute(
-            "UPDATE sessions SET updated_at = ?1 WHERE id = ?2",
+            "UPDATE sessions SET updated_at = ?1, ended_at = NULL WHERE id = ?2",
             params![now.to_rfc3339(), session_id],
         )?;
 
         Ok(id)
     }
 
-    pub fn update_session_title(&self, session_id: &str, title: &str) -> Result<()> {
+    // Open sessions with messages whose last activity is older than the cutoff
+    pub fn get_idle_session_ids(&self, cutoff: DateTime<Utc>) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM sessions
+             WHERE ended_at IS NULL AND updated_at < ?1
+             AND EXISTS (SELECT 1 FROM messages WHERE messages.session_id = sessions.id)"
+        )?;
+        let ids = stmt.query_map([cutoff.to_rfc3339()], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(ids)
+    }
+
+    pub fn mark_session_ended(&self, session_id: &str) -> Result<()> {
         let now = Utc::now();
         self.conn.execute(
-            "UPDATE sessions SET title = ?1, updated_at = ?2 WHERE id = ?3",
-            params![title, now.to_rfc3339(), session_id],
+            "UPDATE sessions SET ended_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), session_id],
         )?;
         Ok(())
     }
 
-    pub fn delete_session(&self, session_id: &str) -> Result<()> {
-        // Delete messages first (foreign key constraint)
+    pub fn set_session_summary_seed(&self, session_id: &str, seed: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE sessions SET summary_seed = ?1 WHERE id = ?2",
+            params![seed, session_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_session_summary_backend(&self, session_id: &str, backend: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE sessions SET summary_backend = ?1 WHERE id = ?2",
+            params![backend, session_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_session_context(&self, session_id: &str) -> Result<crate::session_context::SessionContext> {
+        let value: Option<String> = match self.conn.query_row(
+            "SELECT context FROM sessions WHERE id = ?1",
+            [session_id],
+            |row| row.get(0),
+        ) {
+            Ok(value) => value,
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(value
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_session_context(&self, session_id: &str, context: &crate::session_context::SessionContext) -> Result<()> {
+        let value = serde_json::to_string(context)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let updated = self.conn.execute(
+            "UPDATE sessions SET context = ?1 WHERE id = ?2",
+            params![value, session_id],
+        )?;
+        if updated == 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    }
+
+    // Move the read marker to `message_id`; a message older than the current marker leaves it alone
+    pub fn mark_session_read(&self, session_id: &str, message_id: &str) -> Result<()> {
+        let created_at: String = self.conn.query_row(
+            "SELECT created_at FROM messages WHERE id = ?1 AND session_id = ?2",
+            params![message_id, session_id],
+            |row| row.get(0),
+        )?;
         self.conn.execute(
-            "DELETE FROM messages WHERE session_id = ?1",
-            params![session_id],
+            "UPDATE sessions SET last_read_message_id = ?1, last_read_at = ?2
+             WHERE id = ?3 AND (last_read_at IS NULL OR last_read_at < ?2)",
+            params![message_id, created_at, session_id],
         )?;
+        Ok(())
+    }
+
+    pub fn get_session_read_state(&self, session_id: &str) -> Result<SessionReadState> {
+        let (last_read_message_id, last_read_at): (Option<String>, Option<String>) = self.conn.query_row(
+            "SELECT last_read_message_id, last_read_at FROM sessions WHERE id = ?1",
+            [session_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM messages WHERE session_id = ?1 AND (?2 IS NULL OR created_at > ?2)
+             ORDER BY created_at ASC"
+        )?;
+        let unread_ids = stmt.query_map(params![session_id, last_read_at], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let last_read_at = match last_read_at {
+            Some(value) => Some(DateTime::parse_from_rfc3339(&value)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(1, "last_read_at".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc)),
+            None => None,
+        };
 
-        // Delete session
+        Ok(SessionReadState {
+            session_id: session_id.to_string(),
+            last_read_message_id,
+            last_read_at,
+            unread_count: unread_ids.len(),
+            first_unread_message_id: unread_ids.into_iter().next(),
+        })
+    }
+
+    pub fn get_session_title(&self, session_id: &str) -> Result<Option<String>> {
+        match self.conn.query_row("SELECT title FROM sessions WHERE id = ?1", [session_id], |row| row.get(0)) {
+            Ok(title) => Ok(Some(title)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn update_session_title(&self, session_id: &str, title: &str) -> Result<()> {
+        let now = Utc::now();
         self.conn.execute(
-            "DELETE FROM sessions WHERE id = ?1",
-            params![session_id],
+            "UPDATE sessions SET title = ?1, updated_at = ?2 WHERE id = ?3",
+            params![title, now.to_rfc3339(), session_id],
         )?;
+        Ok(())
+    }
+
+    pub fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.delete_sessions(&[session_id.to_string()])
+    }
+
+    // Bulk session operations, each applied in a single transaction
+    pub fn delete_sessions(&self, session_ids: &[String]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        
+        for session_id in session_ids {
+            // Delete dependent rows first (foreign key constraint)
+            tx.execute(
+                "DELETE FROM messages WHERE session_id = ?1",
+                params![session_id],
+            )?;
+            tx.execute(
+                "DELETE FROM session_tags WHERE session_id = ?1",
+                params![session_id],
+            )?;
+            tx.execute(
+                "DELETE FROM embeddings WHERE session_id = ?1",
+                params![session_id],
+            )?;
+            // Recordings and project plans outlive the session; only the link goes
+            tx.execute(
+                "UPDATE recordings SET session_id = NULL WHERE session_id = ?1",
+                params![session_id],
+            )?;
+            tx.execute(
+                "UPDATE project_plans SET session_id = NULL WHERE session_id = ?1",
+                params![session_id],
+            )?;
+
+            // Delete session
+            tx.execute(
+                "DELETE FROM sessions WHERE id = ?1",
+                params![session_id],
+            )?;
+        }
+        
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn archive_sessions(&self, session_ids: &[String], archived: bool) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        
+        for session_id in session_ids {
+            tx.execute(
+                "UPDATE sessions SET is_archived = ?1 WHERE id = ?2",
+                params![archived, session_id],
+            )?;
+        }
+        
+        tx.commit()?;
+        Ok(())
+    }
 
+    pub fn tag_sessions(&self, session_ids: &[String], tag: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        
+        for session_id in session_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO session_tags (session_id, tag) VALUES (?1, ?2)",
+                params![session_id, tag],
+            )?;
+        }
+        
+        tx.commit()?;
         Ok(())
     }
 
@@ -423,111 +1210,1586 @@ impl Database {
         Ok(user)
     }
 
-    pub fn append_to_memory(&self, user_id: &str, content: &str) -> Result<()> {
-        let now = Utc::now();
-        
-        // Get current memory content
-        let current_user = self.get_or_create_user(user_id)?;
-        
-        // Append new content with proper formatting
-        let new_memory_content = if current_user.memory_content.is_empty() {
-            format!("{}\n", content)
-        } else {
-            format!("{}\n{}\n", current_user.memory_content, content)
-        };
-        
+    pub fn append_to_memory(&self, user_id: &str, content: &str) -> Result<()> {
+        // Memory is never written in demo mode
+        if self.demo_mode {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        
+        // Get current memory content
+        let current_user = self.get_or_create_user(user_id)?;
+        
+        // Append new content with proper formatting
+        let new_memory_content = if current_user.memory_content.is_empty() {
+            format!("{}\n", content)
+        } else {
+            format!("{}\n{}\n", current_user.memory_content, content)
+        };
+        
+        self.conn.execute(
+            "UPDATE users SET memory_content = ?1, updated_at = ?2 WHERE id = ?3",
+            params![new_memory_content, now.to_rfc3339(), user_id],
+        )?;
+
+        // Keep the structured store in step with the blob, numbering lines from where the
+        // appended segment starts in it
+        let first_line = if current_user.memory_content.is_empty() {
+            0
+        } else {
+            current_user.memory_content.matches('\n').count() + 1
+        };
+        let entries = crate::memory::parse_memory_entries(content);
+        self.insert_memory_entries(user_id, &entries, "live", first_line)?;
+
+        Ok(())
+    }
+
+    // One-time copy of every user's legacy memory blob into memory_entries, keeping the
+    // line each entry came from so nothing accumulated before the structured store is lost
+    fn migrate_legacy_memory(&self) -> Result<()> {
+        if self.get_bool_setting("structured_memory_migrated", false)? {
+            return Ok(());
+        }
+
+        let mut stmt = self.conn.prepare("SELECT id, memory_content FROM users")?;
+        let users = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (user_id, memory_content) in users {
+            let entries = crate::memory::parse_memory_entries(&memory_content);
+            self.insert_memory_entries(&user_id, &entries, "legacy_migration", 0)?;
+        }
+        self.set_bool_setting("structured_memory_migrated", true)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    // Entries parsed from a segment of the blob starting at `first_line`, stored with their line in the whole blob
+    fn insert_memory_entries(&self, user_id: &str, entries: &[crate::memory::MemoryEntry], origin: &str, first_line: usize) -> Result<()> {
+        let now = Utc::now();
+        for entry in entries {
+            self.conn.execute(
+                "INSERT INTO memory_entries (id, user_id, category, content, source_kind, source_label, source_line, origin, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    uuid::Uuid::new_v4().to_string(),
+                    user_id,
+                    entry.category,
+                    entry.content,
+                    entry.source_kind,
+                    entry.source_label,
+                    entry.source_line.map(|line| (first_line + line) as i64),
+                    origin,
+                    now.to_rfc3339(),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_memory_entries(&self, user_id: &str, category: Option<&str>) -> Result<Vec<StoredMemoryEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, user_id, category, content, source_kind, source_label, source_line, origin, created_at
+             FROM memory_entries WHERE user_id = ?1 AND (?2 IS NULL OR category = ?2)
+             ORDER BY created_at ASC, source_line ASC"
+        )?;
+
+        let entry_iter = stmt.query_map(params![user_id, category], |row| {
+            let created_at_str: String = row.get(8)?;
+            
+            Ok(StoredMemoryEntry {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                category: row.get(2)?,
+                content: row.get(3)?,
+                source_kind: row.get(4)?,
+                source_label: row.get(5)?,
+                source_line: row.get(6)?,
+                origin: row.get(7)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(8, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut entries = Vec::new();
+        for entry in entry_iter {
+            entries.push(entry?);
+        }
+        Ok(entries)
+    }
+
+    pub fn get_memory_content(&self, user_id: &str) -> Result<String> {
+        let user = self.get_or_create_user(user_id)?;
+        Ok(user.memory_content)
+    }
+
+    // Transcription job queue methods
+    pub fn enqueue_transcription_job(&self, file_path: &str, session_id: Option<&str>) -> Result<String> {
+        // Reuse an existing pending job for the same recording
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM transcription_jobs WHERE file_path = ?1 AND status = 'pending'"
+        )?;
+        match stmt.query_row([file_path], |row| row.get::<_, String>(0)) {
+            Ok(id) => return Ok(id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => return Err(e),
+        }
+        
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now();
+        self.conn.execute(
+            "INSERT INTO transcription_jobs (id, file_path, session_id, status, created_at, updated_at)
+             VALUES (?1, ?2, ?3, 'pending', ?4, ?5)",
+            params![id, file_path, session_id, now.to_rfc3339(), now.to_rfc3339()],
+        )?;
+        Ok(id)
+    }
+
+    // Mark pending jobs for a recording as finished; returns how many jobs were updated
+    pub fn finish_transcription_jobs_for_file(&self, file_path: &str, outcome: std::result::Result<&str, &str>) -> Result<usize> {
+        let now = Utc::now();
+        let (status, transcript, error) = match outcome {
+            Ok(transcript) => ("completed", Some(transcript), None),
+            Err(error) => ("failed", None, Some(error)),
+        };
+        self.conn.execute(
+            "UPDATE transcription_jobs SET status = ?1, transcript = ?2, error = ?3, updated_at = ?4
+             WHERE file_path = ?5 AND status = 'pending'",
+            params![status, transcript, error, now.to_rfc3339(), file_path],
+        )
+    }
+
+    pub fn get_transcription_jobs(&self, status: Option<&str>) -> Result<Vec<TranscriptionJob>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, file_path, session_id, status, transcript, error, created_at, updated_at
+             FROM transcription_jobs WHERE (?1 IS NULL OR status = ?1) ORDER BY created_at ASC"
+        )?;
+
+        let job_iter = stmt.query_map([status], |row| {
+            let created_at_str: String = row.get(6)?;
+            let updated_at_str: String = row.get(7)?;
+            
+            Ok(TranscriptionJob {
+                id: row.get(0)?,
+                file_path: row.get(1)?,
+                session_id: row.get(2)?,
+                status: row.get(3)?,
+                transcript: row.get(4)?,
+                error: row.get(5)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(6, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+                updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(7, "updated_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut jobs = Vec::new();
+        for job in job_iter {
+            jobs.push(job?);
+        }
+        Ok(jobs)
+    }
+
+    // Recording methods
+    pub fn add_recording(&self, id: &str, file_path: &str, duration_ms: Option<u64>, session_id: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO recordings (id, file_path, duration_ms, session_id, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, file_path, duration_ms.map(|ms| ms as i64), session_id, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_recording_transcript(&self, file_path: &str, transcript: &str) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE recordings SET transcript = ?1 WHERE file_path = ?2",
+            params![transcript, file_path],
+        )
+    }
+
+    // Forget recordings whose audio retention cleanup deleted
+    pub fn delete_recordings_by_path(&self, file_paths: &[String]) -> Result<usize> {
+        let mut stmt = self.conn.prepare("DELETE FROM recordings WHERE file_path = ?1")?;
+        let mut deleted = 0;
+        for file_path in file_paths {
+            deleted += stmt.execute([file_path])?;
+        }
+        Ok(deleted)
+    }
+
+    // Newest first; all recordings when no session is given
+    pub fn get_recordings(&self, session_id: Option<&str>) -> Result<Vec<Recording>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, file_path, duration_ms, session_id, transcript, created_at
+             FROM recordings WHERE (?1 IS NULL OR session_id = ?1) ORDER BY created_at DESC"
+        )?;
+        let recordings = stmt.query_map([session_id], Self::row_to_recording)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(recordings)
+    }
+
+    pub fn get_recording(&self, id: &str) -> Result<Option<Recording>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, file_path, duration_ms, session_id, transcript, created_at
+             FROM recordings WHERE id = ?1"
+        )?;
+        match stmt.query_row([id], Self::row_to_recording) {
+            Ok(recording) => Ok(Some(recording)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn row_to_recording(row: &rusqlite::Row) -> rusqlite::Result<Recording> {
+        let file_path: String = row.get(1)?;
+        let duration_ms: Option<i64> = row.get(2)?;
+        let created_at_str: String = row.get(5)?;
+
+        Ok(Recording {
+            id: row.get(0)?,
+            file_exists: std::path::Path::new(&file_path).exists(),
+            file_path,
+            duration_ms: duration_ms.map(|ms| ms as u64),
+            session_id: row.get(3)?,
+            transcript: row.get(4)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(5, "created_at".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+        })
+    }
+
+    // Project plan methods
+    pub fn create_project_plan(
+        &self,
+        user_id: &str,
+        session_id: Option<&str>,
+        goal: &str,
+        draft: &crate::project_plan::PlanDraft,
+    ) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        let tx = self.conn.unchecked_transaction()?;
+
+        // The new plan takes over from whichever one was active
+        tx.execute(
+            "UPDATE project_plans SET status = 'paused', updated_at = ?1 WHERE user_id = ?2 AND status = 'active'",
+            params![now, user_id],
+        )?;
+        tx.execute(
+            "INSERT INTO project_plans (id, user_id, session_id, title, goal, status, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, 'active', ?6, ?6)",
+            params![id, user_id, session_id, draft.title, goal, now],
+        )?;
+        for (index, milestone) in draft.milestones.iter().enumerate() {
+            let criteria_json = serde_json::to_string(&milestone.acceptance_criteria)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            tx.execute(
+                "INSERT INTO project_milestones (id, plan_id, position, title, description, acceptance_criteria, check_code)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    uuid::Uuid::new_v4().to_string(),
+                    id,
+                    (index + 1) as i32,
+                    milestone.title.trim(),
+                    milestone.description,
+                    criteria_json,
+                    milestone.check_code,
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn get_project_plan(&self, plan_id: &str) -> Result<Option<crate::project_plan::ProjectPlan>> {
+        let plan = self.conn.query_row(
+            "SELECT id, user_id, session_id, title, goal, status, created_at, updated_at FROM project_plans WHERE id = ?1",
+            [plan_id],
+            Self::row_to_project_plan,
+        );
+        let mut plan = match plan {
+            Ok(plan) => plan,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        plan.milestones = self.get_project_milestones(plan_id)?;
+        Ok(Some(plan))
+    }
+
+    // Every plan for the user, most recently worked on first
+    pub fn get_project_plans(&self, user_id: &str) -> Result<Vec<crate::project_plan::ProjectPlan>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, user_id, session_id, title, goal, status, created_at, updated_at
+             FROM project_plans WHERE user_id = ?1 ORDER BY updated_at DESC"
+        )?;
+        let mut plans = stmt.query_map([user_id], Self::row_to_project_plan)?
+            .collect::<Result<Vec<_>, _>>()?;
+        for plan in &mut plans {
+            plan.milestones = self.get_project_milestones(&plan.id)?;
+        }
+        Ok(plans)
+    }
+
+    pub fn get_active_project_plan(&self, user_id: &str) -> Result<Option<crate::project_plan::ProjectPlan>> {
+        let plan_id = self.conn.query_row(
+            "SELECT id FROM project_plans WHERE user_id = ?1 AND status = 'active' ORDER BY updated_at DESC LIMIT 1",
+            [user_id],
+            |row| row.get::<_, String>(0),
+        );
+        match plan_id {
+            Ok(plan_id) => self.get_project_plan(&plan_id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_project_plan_status(&self, plan_id: &str, status: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let tx = self.conn.unchecked_transaction()?;
+        if status == "active" {
+            tx.execute(
+                "UPDATE project_plans SET status = 'paused', updated_at = ?1
+                 WHERE status = 'active' AND id != ?2 AND user_id = (SELECT user_id FROM project_plans WHERE id = ?2)",
+                params![now, plan_id],
+            )?;
+        }
+        tx.execute(
+            "UPDATE project_plans SET status = ?1, updated_at = ?2 WHERE id = ?3",
+            params![status, now, plan_id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Store a check result; a passing check completes the milestone, and the last one completes the plan
+    pub fn record_milestone_check(&self, milestone_id: &str, check: &crate::project_plan::MilestoneCheck) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let check_json = serde_json::to_string(check)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let tx = self.conn.unchecked_transaction()?;
+
+        tx.execute(
+            "UPDATE project_milestones SET last_check = ?1 WHERE id = ?2",
+            params![check_json, milestone_id],
+        )?;
+        if check.passed {
+            tx.execute(
+                "UPDATE project_milestones SET completed_at = ?1 WHERE id = ?2 AND completed_at IS NULL",
+                params![check.checked_at.to_rfc3339(), milestone_id],
+            )?;
+        }
+        tx.execute(
+            "UPDATE project_plans SET updated_at = ?1 WHERE id = (SELECT plan_id FROM project_milestones WHERE id = ?2)",
+            params![now, milestone_id],
+        )?;
+        tx.execute(
+            "UPDATE project_plans SET status = 'completed'
+             WHERE id = (SELECT plan_id FROM project_milestones WHERE id = ?1)
+               AND NOT EXISTS (
+                   SELECT 1 FROM project_milestones
+                   WHERE plan_id = project_plans.id AND completed_at IS NULL
+               )",
+            params![milestone_id],
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_project_milestones(&self, plan_id: &str) -> Result<Vec<crate::project_plan::Milestone>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, plan_id, position, title, description, acceptance_criteria, check_code, completed_at, last_check
+             FROM project_milestones WHERE plan_id = ?1 ORDER BY position ASC"
+        )?;
+        let milestones = stmt.query_map([plan_id], |row| {
+            let criteria_json: String = row.get(5)?;
+            let acceptance_criteria: Vec<String> = serde_json::from_str(&criteria_json)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(5, "acceptance_criteria".to_string(), rusqlite::types::Type::Text))?;
+            let completed_at_str: Option<String> = row.get(7)?;
+            let last_check_json: Option<String> = row.get(8)?;
+
+            Ok(crate::project_plan::Milestone {
+                id: row.get(0)?,
+                plan_id: row.get(1)?,
+                position: row.get(2)?,
+                title: row.get(3)?,
+                description: row.get(4)?,
+                acceptance_criteria,
+                check_code: row.get(6)?,
+                completed_at: completed_at_str
+                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                    .map(|dt| dt.with_timezone(&Utc)),
+                last_check: last_check_json.and_then(|json| serde_json::from_str(&json).ok()),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+        Ok(milestones)
+    }
+
+    fn row_to_project_plan(row: &rusqlite::Row) -> rusqlite::Result<crate::project_plan::ProjectPlan> {
+        let created_at_str: String = row.get(6)?;
+        let updated_at_str: String = row.get(7)?;
+
+        Ok(crate::project_plan::ProjectPlan {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            session_id: row.get(2)?,
+            title: row.get(3)?,
+            goal: row.get(4)?,
+            status: row.get(5)?,
+            milestones: Vec::new(),
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(6, "created_at".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(7, "updated_at".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+        })
+    }
+
+    // Class overview methods (aggregates across learner profiles)
+    pub fn get_class_overview(&self) -> Result<Vec<LearnerOverview>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM users
+             UNION SELECT DISTINCT user_id FROM sessions
+             ORDER BY 1 ASC"
+        )?;
+        let user_ids = stmt.query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut overview = Vec::new();
+        for user_id in user_ids {
+            overview.push(self.get_learner_overview(&user_id)?);
+        }
+        Ok(overview)
+    }
+
+    fn get_learner_overview(&self, user_id: &str) -> Result<LearnerOverview> {
+        let session_count: i32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM sessions WHERE user_id = ?1",
+            [user_id],
+            |row| row.get(0),
+        )?;
+
+        // Every message and completed attempt counts as activity
+        let mut stmt = self.conn.prepare(
+            "SELECT m.created_at FROM messages m JOIN sessions s ON s.id = m.session_id WHERE s.user_id = ?1
+             UNION ALL
+             SELECT a.completed_at FROM practice_attempts a
+             JOIN practice_sheets p ON p.id = a.practice_sheet_id
+             JOIN sessions s ON s.id = p.session_id WHERE s.user_id = ?1"
+        )?;
+        let activity: Vec<DateTime<Utc>> = stmt.query_map([user_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .filter_map(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .collect();
+
+        let mut stmt = self.conn.prepare(
+            "SELECT p.title, a.score, a.total_questions FROM practice_attempts a
+             JOIN practice_sheets p ON p.id = a.practice_sheet_id
+             JOIN sessions s ON s.id = p.session_id
+             WHERE s.user_id = ?1 ORDER BY a.completed_at ASC"
+        )?;
+        let attempts = stmt.query_map([user_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i32>(1)?, row.get::<_, i32>(2)?))
+        })?.collect::<Result<Vec<_>, _>>()?;
+
+        let mut mastery: Vec<MasteryCell> = Vec::new();
+        for (topic, score, total) in attempts {
+            let percent = if total > 0 { score as f64 * 100.0 / total as f64 } else { 0.0 };
+            match mastery.iter_mut().find(|cell| cell.topic == topic) {
+                Some(cell) => {
+                    cell.attempts += 1;
+                    cell.best_percent = cell.best_percent.max(percent);
+                    cell.latest_percent = percent;
+                }
+                None => mastery.push(MasteryCell {
+                    topic,
+                    attempts: 1,
+                    best_percent: percent,
+                    latest_percent: percent,
+                }),
+            }
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT p.id, p.title, p.created_at FROM practice_sheets p
+             JOIN sessions s ON s.id = p.session_id
+             WHERE s.user_id = ?1 AND p.is_completed = 0 ORDER BY p.created_at ASC"
+        )?;
+        let review_iter = stmt.query_map([user_id], |row| {
+            let created_at_str: String = row.get(2)?;
+            Ok(OutstandingReview {
+                practice_sheet_id: row.get(0)?,
+                title: row.get(1)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(2, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+        let mut outstanding_reviews = Vec::new();
+        for review in review_iter {
+            outstanding_reviews.push(review?);
+        }
+
+        Ok(LearnerOverview {
+            user_id: user_id.to_string(),
+            streak_days: Self::activity_streak(&activity, Utc::now()),
+            last_activity: activity.iter().max().copied(),
+            session_count,
+            mastery,
+            outstanding_reviews,
+        })
+    }
+
+    // Consecutive days with activity, ending today (or yesterday if today has none yet)
+    fn activity_streak(activity: &[DateTime<Utc>], now: DateTime<Utc>) -> i32 {
+        let days: std::collections::HashSet<chrono::NaiveDate> = activity.iter()
+            .map(|dt| dt.date_naive())
+            .collect();
+
+        let mut day = now.date_naive();
+        if !days.contains(&day) {
+            day = day - chrono::Duration::days(1);
+        }
+
+        let mut streak = 0;
+        while days.contains(&day) {
+            streak += 1;
+            day = day - chrono::Duration::days(1);
+        }
+        streak
+    }
+
+    // Settings methods
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare("SELECT value FROM settings WHERE key = ?1")?;
+        match stmt.query_row([key], |row| row.get(0)) {
+            Ok(value) => Ok(Some(value)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        let now = Utc::now();
+        self.conn.execute(
+            "INSERT INTO settings (key, value, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+            params![key, value, now.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_setting(&self, key: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM settings WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+
+    pub fn get_reproducibility_settings(&self) -> Result<crate::llm::ReproducibilitySettings> {
+        let seed = self.get_setting("generation_seed")?
+            .and_then(|value| value.parse::<i64>().ok());
+        let deterministic = self.get_setting("deterministic_mode")?
+            .map(|value| value == "true")
+            .unwrap_or(false);
+        Ok(crate::llm::ReproducibilitySettings { seed, deterministic })
+    }
+
+    pub fn set_reproducibility_settings(&self, settings: &crate::llm::ReproducibilitySettings) -> Result<()> {
+        match settings.seed {
+            Some(seed) => self.set_setting("generation_seed", &seed.to_string())?,
+            None => self.delete_setting("generation_seed")?,
+        }
+        self.set_setting("deterministic_mode", if settings.deterministic { "true" } else { "false" })
+    }
+
+    pub fn get_idle_timeout_minutes(&self) -> Result<i64> {
+        Ok(self.get_setting("idle_timeout_minutes")?
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(30))
+    }
+
+    pub fn get_vocabulary_hints(&self) -> Result<crate::whisper::VocabularyHints> {
+        Ok(self.get_setting("vocabulary_hints")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_vocabulary_hints(&self, hints: &crate::whisper::VocabularyHints) -> Result<()> {
+        let value = serde_json::to_string(hints)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("vocabulary_hints", &value)
+    }
+
+    // Whisper language code, or "auto" to detect it per recording
+    pub fn get_transcription_language(&self) -> Result<String> {
+        Ok(self.get_setting("transcription_language")?.unwrap_or_else(|| "en".to_string()))
+    }
+
+    pub fn get_recording_retention(&self) -> Result<crate::audio::RetentionPolicy> {
+        Ok(self.get_setting("recording_retention")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_recording_retention(&self, policy: &crate::audio::RetentionPolicy) -> Result<()> {
+        let value = serde_json::to_string(policy)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("recording_retention", &value)
+    }
+
+    pub fn get_results_hook(&self) -> Result<Option<crate::results_hook::ResultsHook>> {
+        Ok(self.get_setting("results_hook")?
+            .and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    pub fn set_results_hook(&self, hook: Option<&crate::results_hook::ResultsHook>) -> Result<()> {
+        match hook {
+            Some(hook) => {
+                let value = serde_json::to_string(hook)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                self.set_setting("results_hook", &value)
+            }
+            None => self.delete_setting("results_hook"),
+        }
+    }
+
+    // Seconds a background task may go without a heartbeat before it counts as stalled. Unset,
+    // a generation gets its whole timeout plus a little to report the failure, so a slow one
+    // isn't killed while it's still allowed to run.
+    pub fn get_task_stall_seconds(&self) -> Result<u64> {
+        let configured = self.get_setting("task_stall_seconds")?
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|seconds| *seconds > 0);
+        match configured {
+            Some(seconds) => Ok(seconds),
+            None => Ok(self.get_ollama_client_config()?.generation_timeout_secs + 30),
+        }
+    }
+
+    // Model metadata cache methods
+    pub fn replace_model_cache(&self, models: &[crate::llm::ModelInfo]) -> Result<()> {
+        let now = Utc::now();
+        let tx = self.conn.unchecked_transaction()?;
+        
+        tx.execute("DELETE FROM model_cache", [])?;
+        for model in models {
+            let info_json = serde_json::to_string(model)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            tx.execute(
+                "INSERT INTO model_cache (name, info, cached_at) VALUES (?1, ?2, ?3)",
+                params![model.name, info_json, now.to_rfc3339()],
+            )?;
+        }
+        
+        tx.commit()?;
+        Ok(())
+    }
+
+    // All cached models with the time of the oldest entry, or None if the cache is empty
+    pub fn get_cached_models(&self) -> Result<Option<(Vec<crate::llm::ModelInfo>, DateTime<Utc>)>> {
+        let mut stmt = self.conn.prepare("SELECT info, cached_at FROM model_cache ORDER BY name ASC")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?.collect::<Result<Vec<_>, _>>()?;
+        
+        let mut models = Vec::new();
+        let mut oldest: Option<DateTime<Utc>> = None;
+        for (info_json, cached_at_str) in rows {
+            let model: crate::llm::ModelInfo = serde_json::from_str(&info_json)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(0, "info".to_string(), rusqlite::types::Type::Text))?;
+            let cached_at = DateTime::parse_from_rfc3339(&cached_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(1, "cached_at".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc);
+            oldest = Some(oldest.map_or(cached_at, |o| o.min(cached_at)));
+            models.push(model);
+        }
+        
+        Ok(oldest.map(|cached_at| (models, cached_at)))
+    }
+
+    // A cached generation, marked as just used so pruning keeps it
+    pub fn get_cached_response(&self, key: &str) -> Result<Option<crate::response_cache::CachedResponse>> {
+        let mut stmt = self.conn.prepare("SELECT response, seed, backend FROM response_cache WHERE key = ?1")?;
+        let cached = match stmt.query_row([key], |row| {
+            Ok(crate::response_cache::CachedResponse {
+                response: row.get(0)?,
+                seed: row.get(1)?,
+                backend: row.get(2)?,
+            })
+        }) {
+            Ok(cached) => cached,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        self.conn.execute(
+            "UPDATE response_cache SET last_used_at = ?1 WHERE key = ?2",
+            params![Utc::now().to_rfc3339(), key],
+        )?;
+        Ok(Some(cached))
+    }
+
+    pub fn put_cached_response(&self, key: &str, feature: &str, cached: &crate::response_cache::CachedResponse) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO response_cache (key, feature, backend, response, seed, created_at, last_used_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+            params![key, feature, cached.backend, cached.response, cached.seed, now],
+        )?;
+        tx.execute(
+            "DELETE FROM response_cache WHERE key NOT IN
+                (SELECT key FROM response_cache ORDER BY last_used_at DESC LIMIT ?1)",
+            params![crate::response_cache::MAX_CACHED_RESPONSES as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn clear_response_cache(&self) -> Result<usize> {
+        Ok(self.conn.execute("DELETE FROM response_cache", [])?)
+    }
+
+    pub fn get_model_cache_ttl_minutes(&self) -> Result<i64> {
+        Ok(self.get_setting("model_cache_ttl_minutes")?
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(60))
+    }
+
+    // Latency budget per tutoring turn in milliseconds (0 disables) and the model to fall back to
+    pub fn get_latency_budget(&self) -> Result<(u64, String)> {
+        let budget_ms = self.get_setting("turn_latency_budget_ms")?
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0);
+        let fallback_model = self.get_setting("fallback_model")?
+            .unwrap_or_else(|| "gemma3:1b".to_string());
+        Ok((budget_ms, fallback_model))
+    }
+
+    // Ordered models/providers each generation walks on failure
+    pub fn get_model_chain(&self) -> Result<Vec<crate::llm::ModelBackend>> {
+        Ok(self.get_setting("model_fallback_chain")?
+            .and_then(|value| serde_json::from_str::<Vec<crate::llm::ModelBackend>>(&value).ok())
+            .filter(|chain| !chain.is_empty())
+            .unwrap_or_else(|| vec![crate::llm::ModelBackend::local(crate::llm::DEFAULT_CHAT_MODEL)]))
+    }
+
+    // The chat model is the head of the fallback chain
+    pub fn get_chat_model(&self) -> Result<String> {
+        Ok(self.get_model_chain()?
+            .into_iter()
+            .next()
+            .map(|backend| backend.model)
+            .unwrap_or_else(|| crate::llm::DEFAULT_CHAT_MODEL.to_string()))
+    }
+
+    // Make a local model the head of the chain, keeping the remaining backends as fallbacks
+    pub fn set_chat_model(&self, model: &str) -> Result<()> {
+        let primary = crate::llm::ModelBackend::local(model);
+        let mut chain = self.get_model_chain()?;
+        chain.retain(|backend| *backend != primary);
+        chain.insert(0, primary);
+        self.set_model_chain(&chain)
+    }
+
+    pub fn set_model_chain(&self, chain: &[crate::llm::ModelBackend]) -> Result<()> {
+        let value = serde_json::to_string(chain)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("model_fallback_chain", &value)
+    }
+
+    // The feature's own chain, or the shared one when it has none
+    pub fn get_feature_model_chain(&self, feature: crate::llm::Feature) -> Result<Vec<crate::llm::ModelBackend>> {
+        let own = self.get_setting(feature.setting_key())?
+            .and_then(|value| serde_json::from_str::<Vec<crate::llm::ModelBackend>>(&value).ok())
+            .filter(|chain| !chain.is_empty());
+        match own {
+            Some(chain) => Ok(chain),
+            None => self.get_model_chain(),
+        }
+    }
+
+    // None goes back to the shared chain
+    pub fn set_feature_model_chain(&self, feature: crate::llm::Feature, chain: Option<&[crate::llm::ModelBackend]>) -> Result<()> {
+        match chain {
+            Some(chain) => {
+                let value = serde_json::to_string(chain)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                self.set_setting(feature.setting_key(), &value)
+            }
+            None => self.delete_setting(feature.setting_key()),
+        }
+    }
+
+    pub fn get_ollama_client_config(&self) -> Result<crate::ollama::ClientConfig> {
+        Ok(self.get_setting("ollama_client")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_ollama_client_config(&self, config: &crate::ollama::ClientConfig) -> Result<()> {
+        let value = serde_json::to_string(config)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("ollama_client", &value)
+    }
+
+    // The learner profile the app is being used as; memory and corrections are kept per
+    // profile, while the usage limit covers all of them
+    pub fn get_active_user_id(&self) -> Result<String> {
+        Ok(self.get_setting("active_user_id")?
+            .filter(|user_id| !user_id.is_empty())
+            .unwrap_or_else(|| "default_user".to_string()))
+    }
+
+    pub fn set_active_user_id(&self, user_id: &str) -> Result<()> {
+        self.get_or_create_user(user_id)?;
+        self.set_setting("active_user_id", user_id)
+    }
+
+    pub fn get_bool_setting(&self, key: &str, default: bool) -> Result<bool> {
+        Ok(self.get_setting(key)?
+            .map(|value| value == "true")
+            .unwrap_or(default))
+    }
+
+    pub fn set_bool_setting(&self, key: &str, value: bool) -> Result<()> {
+        self.set_setting(key, if value { "true" } else { "false" })
+    }
+
+    pub fn get_memory_injection_settings(&self) -> Result<crate::memory::MemoryInjectionSettings> {
+        Ok(self.get_setting("memory_injection")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn get_usage_limit(&self) -> Result<Option<crate::usage_limits::UsageLimit>> {
+        Ok(self.get_setting("usage_limit")?
+            .and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    pub fn set_usage_limit(&self, limit: Option<&crate::usage_limits::UsageLimit>) -> Result<()> {
+        match limit {
+            Some(limit) => {
+                let value = serde_json::to_string(limit)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                self.set_setting("usage_limit", &value)
+            }
+            None => self.delete_setting("usage_limit"),
+        }
+    }
+
+    // Mark the current minute as active; repeated calls within a minute count once
+    pub fn log_activity(&self, user_id: &str, at: DateTime<Local>) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO activity_log (user_id, minute) VALUES (?1, ?2)",
+            params![user_id, at.format("%Y-%m-%dT%H:%M").to_string()],
+        )?;
+        Ok(())
+    }
+
+    // The limit is for the device, so minutes count once whichever learner profiles were active
+    pub fn get_usage_minutes(&self, day: NaiveDate) -> Result<u32> {
+        self.conn.query_row(
+            "SELECT COUNT(DISTINCT minute) FROM activity_log WHERE minute LIKE ?1",
+            params![format!("{}T%", day.format("%Y-%m-%d"))],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn get_usage_status(&self) -> Result<crate::usage_limits::UsageStatus> {
+        let used_minutes = self.get_usage_minutes(Local::now().date_naive())?;
+        Ok(crate::usage_limits::UsageStatus::new(self.get_usage_limit()?.as_ref(), used_minutes))
+    }
+
+    pub fn set_memory_injection_settings(&self, settings: &crate::memory::MemoryInjectionSettings) -> Result<()> {
+        let value = serde_json::to_string(settings)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("memory_injection", &value)
+    }
+
+    pub fn get_generation_options(&self) -> Result<crate::llm::GenerationOptions> {
+        Ok(self.get_setting("generation_options")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_generation_options(&self, options: &crate::llm::GenerationOptions) -> Result<()> {
+        let value = serde_json::to_string(options)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("generation_options", &value)
+    }
+
+    pub fn get_tts_settings(&self) -> Result<crate::tts::TtsSettings> {
+        Ok(self.get_setting("tts")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_tts_settings(&self, settings: &crate::tts::TtsSettings) -> Result<()> {
+        let value = serde_json::to_string(settings)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("tts", &value)
+    }
+
+    pub fn get_pedagogy_mode(&self) -> Result<crate::llm::PedagogyMode> {
+        Ok(self.get_setting("pedagogy_mode")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_pedagogy_mode(&self, mode: crate::llm::PedagogyMode) -> Result<()> {
+        let value = serde_json::to_string(&mode)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("pedagogy_mode", &value)
+    }
+
+    pub fn get_retrieval_settings(&self) -> Result<crate::embeddings::RetrievalSettings> {
+        Ok(self.get_setting("retrieval")?
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_retrieval_settings(&self, settings: &crate::embeddings::RetrievalSettings) -> Result<()> {
+        let value = serde_json::to_string(settings)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting("retrieval", &value)
+    }
+
+    // Embedding index methods
+
+    // Memory entries and messages with no chunks embedded by `model` yet, memory first. Messages
+    // shorter than a sentence carry nothing worth retrieving.
+    pub fn get_unindexed_sources(&self, user_id: &str, model: &str, limit: usize) -> Result<Vec<crate::embeddings::PendingSource>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT CASE WHEN m.category = 'session_summaries' THEN 'summary' ELSE 'memory' END, m.id, NULL, m.content
+             FROM memory_entries m
+             WHERE m.user_id = ?1 AND trim(m.content) != ''
+               AND NOT EXISTS (SELECT 1 FROM embeddings e WHERE e.source_id = m.id AND e.model = ?2)
+             UNION ALL
+             SELECT * FROM (
+                SELECT 'message', msg.id, msg.session_id, msg.content
+                FROM messages msg JOIN sessions s ON s.id = msg.session_id
+                WHERE s.user_id = ?1 AND length(trim(msg.content)) >= 20
+                  AND NOT EXISTS (SELECT 1 FROM embeddings e WHERE e.source_id = msg.id AND e.model = ?2)
+                ORDER BY msg.created_at ASC
+             )
+             LIMIT ?3"
+        )?;
+
+        let source_iter = stmt.query_map(params![user_id, model, limit as i64], |row| {
+            Ok(crate::embeddings::PendingSource {
+                source_kind: row.get(0)?,
+                source_id: row.get(1)?,
+                session_id: row.get(2)?,
+                text: row.get(3)?,
+            })
+        })?;
+
+        let mut sources = Vec::new();
+        for source in source_iter {
+            sources.push(source?);
+        }
+        Ok(sources)
+    }
+
+    pub fn save_embeddings(
+        &self,
+        user_id: &str,
+        model: &str,
+        source: &crate::embeddings::PendingSource,
+        chunks: &[(String, Vec<f32>)],
+    ) -> Result<()> {
+        let now = Utc::now();
+        let tx = self.conn.unchecked_transaction()?;
+        for (index, (content, vector)) in chunks.iter().enumerate() {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO embeddings (id, user_id, source_kind, source_id, session_id, chunk_index, content, model, vector, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    uuid::Uuid::new_v4().to_string(),
+                    user_id,
+                    source.source_kind,
+                    source.source_id,
+                    source.session_id,
+                    index as i64,
+                    content,
+                    model,
+                    crate::embeddings::vector_to_blob(vector),
+                    now.to_rfc3339(),
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Every chunk embedded by `model`, leaving out the messages of `exclude_session` (already in
+    // the prompt as history)
+    pub fn get_embeddings(&self, user_id: &str, model: &str, exclude_session: Option<&str>) -> Result<Vec<crate::embeddings::StoredEmbedding>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT source_kind, source_id, content, vector FROM embeddings
+             WHERE user_id = ?1 AND model = ?2 AND (?3 IS NULL OR session_id IS NULL OR session_id != ?3)"
+        )?;
+
+        let embedding_iter = stmt.query_map(params![user_id, model, exclude_session], |row| {
+            let vector: Vec<u8> = row.get(3)?;
+            Ok(crate::embeddings::StoredEmbedding {
+                source_kind: row.get(0)?,
+                source_id: row.get(1)?,
+                content: row.get(2)?,
+                vector: crate::embeddings::blob_to_vector(&vector),
+            })
+        })?;
+
+        let mut embeddings = Vec::new();
+        for embedding in embedding_iter {
+            embeddings.push(embedding?);
+        }
+        Ok(embeddings)
+    }
+
+    // Drop chunks whose memory entry or message no longer exists
+    pub fn prune_embeddings(&self) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM embeddings WHERE
+                (source_kind = 'message' AND source_id NOT IN (SELECT id FROM messages))
+                OR (source_kind != 'message' AND source_id NOT IN (SELECT id FROM memory_entries))",
+            [],
+        )?)
+    }
+
+    pub fn clear_embeddings(&self, user_id: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM embeddings WHERE user_id = ?1", params![user_id])?;
+        Ok(())
+    }
+
+    // Memory text the tutor prompt includes, filtered by the injection settings
+    pub fn get_tutor_memory_context(&self, user_id: &str) -> Result<String> {
+        let memory = crate::memory::parse_memory(&self.get_memory_content(user_id)?);
+        let settings = self.get_memory_injection_settings()?;
+        Ok(crate::memory::render_for_prompt(&memory, &settings))
+    }
+
+    // Transcription correction dictionary methods
+    pub fn upsert_transcription_correction(&self, user_id: &str, misheard: &str, intended: &str) -> Result<()> {
+        let now = Utc::now();
+        self.conn.execute(
+            "INSERT INTO transcription_corrections (user_id, misheard, intended, times_seen, updated_at)
+             VALUES (?1, ?2, ?3, 1, ?4)
+             ON CONFLICT(user_id, misheard) DO UPDATE SET
+                times_seen = CASE WHEN intended = excluded.intended THEN times_seen + 1 ELSE 1 END,
+                intended = excluded.intended,
+                updated_at = excluded.updated_at",
+            params![user_id, misheard.to_lowercase(), intended, now.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_transcription_corrections(&self, user_id: &str) -> Result<Vec<TranscriptionCorrection>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT misheard, intended, times_seen, updated_at FROM transcription_corrections
+             WHERE user_id = ?1 ORDER BY times_seen DESC, updated_at DESC"
+        )?;
+
+        let correction_iter = stmt.query_map([user_id], |row| {
+            let updated_at_str: String = row.get(3)?;
+            
+            Ok(TranscriptionCorrection {
+                misheard: row.get(0)?,
+                intended: row.get(1)?,
+                times_seen: row.get(2)?,
+                updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(3, "updated_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut corrections = Vec::new();
+        for correction in correction_iter {
+            corrections.push(correction?);
+        }
+        Ok(corrections)
+    }
+
+    pub fn delete_transcription_correction(&self, user_id: &str, misheard: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM transcription_corrections WHERE user_id = ?1 AND misheard = ?2",
+            params![user_id, misheard.to_lowercase()],
+        )?;
+        Ok(())
+    }
+
+    // Practice sheet management methods
+    pub fn create_practice_sheet(&self, session_id: &str, title: &str, generation_seed: Option<i64>) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now();
+        
+        self.conn.execute(
+            "INSERT INTO practice_sheets (id, session_id, title, is_completed, is_redo_ready, generation_seed, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![id, session_id, title, false, false, generation_seed, now.to_rfc3339()],
+        )?;
+        
+        Ok(id)
+    }
+
+    pub fn add_practice_question(
+        &self,
+        practice_sheet_id: &str,
+        question: &crate::practice_sheet::QuizQuestion,
+        question_order: i32,
+    ) -> Result<String> {
+        Self::insert_practice_question(&self.conn, practice_sheet_id, question, question_order)
+    }
+
+    fn insert_practice_question(
+        conn: &Connection,
+        practice_sheet_id: &str,
+        question: &crate::practice_sheet::QuizQuestion,
+        question_order: i32,
+    ) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let options_json = serde_json::to_string(&question.options)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let source_messages_json = serde_json::to_string(&question.source_message_ids)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let source_lines_json = serde_json::to_string(&question.source_summary_lines)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        
+        conn.execute(
+            "INSERT INTO practice_questions (id, practice_sheet_id, question_text, options, correct_answer, question_order, source_message_ids, source_summary_lines) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![id, practice_sheet_id, question.question_text, options_json, question.correct_answer, question_order, source_messages_json, source_lines_json],
+        )?;
+        
+        Ok(id)
+    }
+
+    pub fn get_all_practice_sheets(&self) -> Result<Vec<PracticeSheet>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, session_id, title, is_completed, is_redo_ready, generation_seed, generation_backend, created_at FROM practice_sheets ORDER BY created_at DESC"
+        )?;
+
+        let sheet_iter = stmt.query_map([], |row| {
+            let created_at_str: String = row.get(7)?;
+            
+            Ok(PracticeSheet {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                title: row.get(2)?,
+                is_completed: row.get(3)?,
+                is_redo_ready: row.get(4)?,
+                generation_seed: row.get(5)?,
+                generation_backend: row.get(6)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(7, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut sheets = Vec::new();
+        for sheet in sheet_iter {
+            sheets.push(sheet?);
+        }
+        Ok(sheets)
+    }
+
+    pub fn get_practice_sheet_questions(&self, practice_sheet_id: &str) -> Result<Vec<PracticeQuestion>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, practice_sheet_id, question_text, options, correct_answer, question_order, source_message_ids, source_summary_lines 
+             FROM practice_questions WHERE practice_sheet_id = ?1 ORDER BY question_order ASC"
+        )?;
+
+        let question_iter = stmt.query_map([practice_sheet_id], Self::map_practice_question)?;
+
+        let mut questions = Vec::new();
+        for question in question_iter {
+            questions.push(question?);
+        }
+        Ok(questions)
+    }
+
+    pub fn get_practice_question(&self, question_id: &str) -> Result<PracticeQuestion> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, practice_sheet_id, question_text, options, correct_answer, question_order, source_message_ids, source_summary_lines 
+             FROM practice_questions WHERE id = ?1"
+        )?;
+        stmt.query_row([question_id], Self::map_practice_question)
+    }
+
+    fn map_practice_question(row: &rusqlite::Row) -> Result<PracticeQuestion> {
+        let options_json: String = row.get(3)?;
+        let options: Vec<String> = serde_json::from_str(&options_json)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(3, "options".to_string(), rusqlite::types::Type::Text))?;
+        let source_messages_json: String = row.get(6)?;
+        let source_message_ids: Vec<String> = serde_json::from_str(&source_messages_json)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(6, "source_message_ids".to_string(), rusqlite::types::Type::Text))?;
+        let source_lines_json: String = row.get(7)?;
+        let source_summary_lines: Vec<usize> = serde_json::from_str(&source_lines_json)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(7, "source_summary_lines".to_string(), rusqlite::types::Type::Text))?;
+        
+        Ok(PracticeQuestion {
+            id: row.get(0)?,
+            practice_sheet_id: row.get(1)?,
+            question_text: row.get(2)?,
+            options,
+            correct_answer: row.get(4)?,
+            question_order: row.get(5)?,
+            source_message_ids,
+            source_summary_lines,
+        })
+    }
+
+    pub fn update_practice_question(&self, question_id: &str, update: &PracticeQuestionUpdate) -> Result<PracticeQuestion> {
+        let previous = self.get_practice_question(question_id)?;
+        
+        let question_text = update.question_text.clone().unwrap_or_else(|| previous.question_text.clone());
+        let options = update.options.clone().unwrap_or_else(|| previous.options.clone());
+        let correct_answer = update.correct_answer.clone().unwrap_or_else(|| previous.correct_answer.clone());
+        
+        if question_text.trim().is_empty() {
+            return Err(Self::invalid_edit("Question text cannot be empty"));
+        }
+        if options.len() < 2 {
+            return Err(Self::invalid_edit("A question needs at least two options"));
+        }
+        if !options.contains(&correct_answer) {
+            return Err(Self::invalid_edit("The correct answer must be one of the options"));
+        }
+        
+        let options_json = serde_json::to_string(&options)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE practice_questions SET question_text = ?1, options = ?2, correct_answer = ?3 WHERE id = ?4",
+            params![question_text, options_json, correct_answer, question_id],
+        )?;
+        
+        let updated = serde_json::json!({
+            "question_text": question_text,
+            "options": options,
+            "correct_answer": correct_answer,
+        });
+        Self::record_question_edit(&tx, &previous, "update", Some(&updated))?;
+        tx.commit()?;
+        
+        self.get_practice_question(question_id)
+    }
+
+    pub fn delete_practice_question(&self, question_id: &str) -> Result<()> {
+        let previous = self.get_practice_question(question_id)?;
+        
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM practice_questions WHERE id = ?1", params![question_id])?;
+        
+        // Close the gap in question ordering
+        tx.execute(
+            "UPDATE practice_questions SET question_order = question_order - 1
+             WHERE practice_sheet_id = ?1 AND question_order > ?2",
+            params![previous.practice_sheet_id, previous.question_order],
+        )?;
+        
+        Self::record_question_edit(&tx, &previous, "delete", None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_question_edit(
+        conn: &Connection,
+        previous: &PracticeQuestion,
+        action: &str,
+        updated: Option<&serde_json::Value>,
+    ) -> Result<()> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now();
+        let previous_json = serde_json::json!({
+            "question_text": previous.question_text,
+            "options": previous.options,
+            "correct_answer": previous.correct_answer,
+        }).to_string();
+        
+        conn.execute(
+            "INSERT INTO practice_question_edits (id, question_id, practice_sheet_id, action, previous, updated, edited_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![id, previous.id, previous.practice_sheet_id, action, previous_json, updated.map(|u| u.to_string()), now.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_practice_question_edits(&self, practice_sheet_id: &str) -> Result<Vec<PracticeQuestionEdit>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, question_id, practice_sheet_id, action, previous, updated, edited_at
+             FROM practice_question_edits WHERE practice_sheet_id = ?1 ORDER BY edited_at ASC"
+        )?;
+
+        let edit_iter = stmt.query_map([practice_sheet_id], |row| {
+            let previous_json: String = row.get(4)?;
+            let updated_json: Option<String> = row.get(5)?;
+            let edited_at_str: String = row.get(6)?;
+            
+            Ok(PracticeQuestionEdit {
+                id: row.get(0)?,
+                question_id: row.get(1)?,
+                practice_sheet_id: row.get(2)?,
+                action: row.get(3)?,
+                previous: serde_json::from_str(&previous_json)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "previous".to_string(), rusqlite::types::Type::Text))?,
+                updated: updated_json.and_then(|json| serde_json::from_str(&json).ok()),
+                edited_at: DateTime::parse_from_rfc3339(&edited_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(6, "edited_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut edits = Vec::new();
+        for edit in edit_iter {
+            edits.push(edit?);
+        }
+        Ok(edits)
+    }
+
+    // Python execution history methods
+    pub fn create_python_execution(&self, id: &str, code: &str, replay_of: Option<&str>) -> Result<()> {
+        let now = Utc::now();
+        self.conn.execute(
+            "INSERT INTO python_executions (id, code, status, replay_of, created_at) VALUES (?1, ?2, 'running', ?3, ?4)",
+            params![id, code, replay_of, now.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_python_input(&self, id: &str, input: &str) -> Result<()> {
+        let execution = self.get_python_execution(id)?;
+        let mut inputs = execution.stdin_inputs;
+        inputs.push(input.to_string());
+        let inputs_json = serde_json::to_string(&inputs)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
         self.conn.execute(
-            "UPDATE users SET memory_content = ?1, updated_at = ?2 WHERE id = ?3",
-            params![new_memory_content, now.to_rfc3339(), user_id],
+            "UPDATE python_executions SET stdin_inputs = ?1 WHERE id = ?2",
+            params![inputs_json, id],
         )?;
-
         Ok(())
     }
 
-    pub fn get_memory_content(&self, user_id: &str) -> Result<String> {
-        let user = self.get_or_create_user(user_id)?;
-        Ok(user.memory_content)
+    pub fn append_python_output(&self, id: &str, output: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE python_executions SET output = output || ?1 WHERE id = ?2",
+            params![output, id],
+        )?;
+        Ok(())
     }
 
-    // Practice sheet management methods
-    pub fn create_practice_sheet(&self, session_id: &str, title: &str) -> Result<String> {
-        let id = uuid::Uuid::new_v4().to_string();
+    pub fn finish_python_execution(&self, id: &str, status: &str) -> Result<()> {
         let now = Utc::now();
-        
         self.conn.execute(
-            "INSERT INTO practice_sheets (id, session_id, title, is_completed, is_redo_ready, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![id, session_id, title, false, false, now.to_rfc3339()],
+            "UPDATE python_executions SET status = ?1, finished_at = ?2 WHERE id = ?3 AND status = 'running'",
+            params![status, now.to_rfc3339(), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_python_execution(&self, id: &str) -> Result<PythonExecution> {
+        self.conn.query_row(
+            "SELECT id, code, stdin_inputs, output, status, replay_of, created_at, finished_at FROM python_executions WHERE id = ?1",
+            [id],
+            Self::map_python_execution,
+        )
+    }
+
+    pub fn get_python_executions(&self, limit: i64) -> Result<Vec<PythonExecution>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, code, stdin_inputs, output, status, replay_of, created_at, finished_at
+             FROM python_executions ORDER BY created_at DESC LIMIT ?1"
         )?;
+        let executions = stmt.query_map([limit], Self::map_python_execution)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(executions)
+    }
+
+    // Most recent earlier replay of an execution, to compare replays against each other
+    pub fn get_latest_replay(&self, original_id: &str, exclude_id: &str) -> Result<Option<PythonExecution>> {
+        match self.conn.query_row(
+            "SELECT id, code, stdin_inputs, output, status, replay_of, created_at, finished_at
+             FROM python_executions WHERE replay_of = ?1 AND id != ?2 ORDER BY created_at DESC LIMIT 1",
+            params![original_id, exclude_id],
+            Self::map_python_execution,
+        ) {
+            Ok(execution) => Ok(Some(execution)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn map_python_execution(row: &rusqlite::Row) -> Result<PythonExecution> {
+        let inputs_json: String = row.get(2)?;
+        let created_at_str: String = row.get(6)?;
+        let finished_at_str: Option<String> = row.get(7)?;
         
-        Ok(id)
+        Ok(PythonExecution {
+            id: row.get(0)?,
+            code: row.get(1)?,
+            stdin_inputs: serde_json::from_str(&inputs_json).unwrap_or_default(),
+            output: row.get(3)?,
+            status: row.get(4)?,
+            replay_of: row.get(5)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(6, "created_at".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            finished_at: finished_at_str
+                .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                .map(|dt| dt.with_timezone(&Utc)),
+        })
     }
 
-    pub fn add_practice_question(
-        &self,
-        practice_sheet_id: &str,
-        question_text: &str,
-        options: &Vec<String>,
-        correct_answer: &str,
-        question_order: i32,
-    ) -> Result<String> {
-        let id = uuid::Uuid::new_v4().to_string();
-        let options_json = serde_json::to_string(options)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    // Practice schedule methods
+    pub fn schedule_practice(&self, practice_sheet_id: &str, scheduled_for: DateTime<Utc>) -> Result<String> {
+        // Make sure the sheet exists before planning a review of it
+        self.get_practice_sheet_title(practice_sheet_id)?;
         
+        let id = uuid::Uuid::new_v4().to_string();
         self.conn.execute(
-            "INSERT INTO practice_questions (id, practice_sheet_id, question_text, options, correct_answer, question_order) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![id, practice_sheet_id, question_text, options_json, correct_answer, question_order],
+            "INSERT INTO practice_schedule (id, practice_sheet_id, scheduled_for, notified, created_at)
+             VALUES (?1, ?2, ?3, 0, ?4)",
+            params![id, practice_sheet_id, scheduled_for.to_rfc3339(), Utc::now().to_rfc3339()],
         )?;
-        
         Ok(id)
     }
 
-    pub fn get_all_practice_sheets(&self) -> Result<Vec<PracticeSheet>> {
+    // Reviews from `since` onwards plus any earlier ones that are still unnotified, soonest first
+    pub fn get_upcoming_schedule(&self, since: DateTime<Utc>) -> Result<Vec<ScheduledPractice>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, session_id, title, is_completed, is_redo_ready, created_at FROM practice_sheets ORDER BY created_at DESC"
+            "SELECT s.id, s.practice_sheet_id, p.title, s.scheduled_for, p.is_redo_ready, p.is_completed, s.notified
+             FROM practice_schedule s JOIN practice_sheets p ON p.id = s.practice_sheet_id
+             WHERE s.scheduled_for >= ?1 OR s.notified = 0
+             ORDER BY s.scheduled_for ASC"
         )?;
+        let schedule = stmt.query_map([since.to_rfc3339()], Self::map_scheduled_practice)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(schedule)
+    }
 
-        let sheet_iter = stmt.query_map([], |row| {
-            let created_at_str: String = row.get(5)?;
-            
-            Ok(PracticeSheet {
-                id: row.get(0)?,
-                session_id: row.get(1)?,
-                title: row.get(2)?,
-                is_completed: row.get(3)?,
-                is_redo_ready: row.get(4)?,
-                created_at: DateTime::parse_from_rfc3339(&created_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(5, "created_at".to_string(), rusqlite::types::Type::Text))?
-                    .with_timezone(&Utc),
-            })
-        })?;
+    // Reviews whose time has come but whose reminder hasn't fired yet
+    pub fn get_due_practice(&self, now: DateTime<Utc>) -> Result<Vec<ScheduledPractice>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.id, s.practice_sheet_id, p.title, s.scheduled_for, p.is_redo_ready, p.is_completed, s.notified
+             FROM practice_schedule s JOIN practice_sheets p ON p.id = s.practice_sheet_id
+             WHERE s.scheduled_for <= ?1 AND s.notified = 0
+             ORDER BY s.scheduled_for ASC"
+        )?;
+        let due = stmt.query_map([now.to_rfc3339()], Self::map_scheduled_practice)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(due)
+    }
 
-        let mut sheets = Vec::new();
-        for sheet in sheet_iter {
-            sheets.push(sheet?);
+    pub fn mark_practice_notified(&self, schedule_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE practice_schedule SET notified = 1 WHERE id = ?1",
+            params![schedule_id],
+        )?;
+        Ok(())
+    }
+
+    fn map_scheduled_practice(row: &rusqlite::Row) -> Result<ScheduledPractice> {
+        let scheduled_for_str: String = row.get(3)?;
+        
+        Ok(ScheduledPractice {
+            id: row.get(0)?,
+            practice_sheet_id: row.get(1)?,
+            title: row.get(2)?,
+            scheduled_for: DateTime::parse_from_rfc3339(&scheduled_for_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(3, "scheduled_for".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+            is_redo_ready: row.get(4)?,
+            is_completed: row.get(5)?,
+            notified: row.get(6)?,
+        })
+    }
+
+    // Pronunciation lexicon methods
+    pub fn get_pronunciation_lexicon(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare("SELECT word, spoken FROM pronunciation_lexicon ORDER BY word ASC")?;
+        let entries = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    pub fn set_pronunciation(&self, word: &str, spoken: &str) -> Result<()> {
+        let now = Utc::now();
+        self.conn.execute(
+            "INSERT INTO pronunciation_lexicon (word, spoken, updated_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(word) DO UPDATE SET spoken = excluded.spoken, updated_at = excluded.updated_at",
+            params![word.to_lowercase(), spoken, now.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_pronunciation(&self, word: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM pronunciation_lexicon WHERE word = ?1",
+            [word.to_lowercase()],
+        )?;
+        Ok(())
+    }
+
+    // Question bank methods
+    pub fn add_bank_questions(
+        &self,
+        questions: &[crate::practice_sheet::QuizQuestion],
+        source: &str,
+        source_path: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let now = Utc::now();
+        let tx = self.conn.unchecked_transaction()?;
+        let mut ids = Vec::new();
+        
+        for question in questions {
+            let id = uuid::Uuid::new_v4().to_string();
+            let options_json = serde_json::to_string(&question.options)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            tx.execute(
+                "INSERT INTO question_bank (id, question_text, options, correct_answer, source, source_path, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![id, question.question_text, options_json, question.correct_answer, source, source_path, now.to_rfc3339()],
+            )?;
+            ids.push(id);
         }
-        Ok(sheets)
+        
+        tx.commit()?;
+        Ok(ids)
     }
 
-    pub fn get_practice_sheet_questions(&self, practice_sheet_id: &str) -> Result<Vec<PracticeQuestion>> {
+    pub fn get_bank_questions(&self, source: Option<&str>) -> Result<Vec<BankQuestion>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, practice_sheet_id, question_text, options, correct_answer, question_order 
-             FROM practice_questions WHERE practice_sheet_id = ?1 ORDER BY question_order ASC"
+            "SELECT id, question_text, options, correct_answer, source, source_path, created_at
+             FROM question_bank WHERE (?1 IS NULL OR source = ?1) ORDER BY created_at DESC"
         )?;
 
-        let question_iter = stmt.query_map([practice_sheet_id], |row| {
-            let options_json: String = row.get(3)?;
-            let options: Vec<String> = serde_json::from_str(&options_json)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(3, "options".to_string(), rusqlite::types::Type::Text))?;
+        let question_iter = stmt.query_map([source], |row| {
+            let options_json: String = row.get(2)?;
+            let created_at_str: String = row.get(6)?;
             
-            Ok(PracticeQuestion {
+            Ok(BankQuestion {
                 id: row.get(0)?,
-                practice_sheet_id: row.get(1)?,
-                question_text: row.get(2)?,
-                options,
-                correct_answer: row.get(4)?,
-                question_order: row.get(5)?,
+                question_text: row.get(1)?,
+                options: serde_json::from_str(&options_json)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(2, "options".to_string(), rusqlite::types::Type::Text))?,
+                correct_answer: row.get(3)?,
+                source: row.get(4)?,
+                source_path: row.get(5)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(6, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
             })
         })?;
 
@@ -538,6 +2800,69 @@ impl Database {
         Ok(questions)
     }
 
+    // Build a new practice sheet from bank questions, in the order given
+    pub fn assemble_practice_sheet(&self, session_id: &str, title: &str, bank_question_ids: &[String]) -> Result<String> {
+        let bank = self.get_bank_questions(None)?;
+        let mut questions = Vec::new();
+        for id in bank_question_ids {
+            let question = bank.iter()
+                .find(|question| &question.id == id)
+                .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+            questions.push(crate::practice_sheet::QuizQuestion {
+                question_text: question.question_text.clone(),
+                options: question.options.clone(),
+                correct_answer: question.correct_answer.clone(),
+                source_message_ids: Vec::new(),
+                source_summary_lines: Vec::new(),
+            });
+        }
+        
+        let practice_sheet_id = self.create_practice_sheet(session_id, title, None)?;
+        for (index, question) in questions.iter().enumerate() {
+            self.add_practice_question(&practice_sheet_id, question, (index + 1) as i32)?;
+        }
+        Ok(practice_sheet_id)
+    }
+
+    fn invalid_edit(message: &str) -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+            Some(message.to_string()),
+        )
+    }
+
+    pub fn get_messages_by_ids(&self, message_ids: &[String]) -> Result<Vec<Message>> {
+        let mut messages = Vec::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, session_id, role, content, created_at FROM messages WHERE id = ?1"
+        )?;
+        
+        for message_id in message_ids {
+            let message = stmt.query_row([message_id], |row| {
+                let created_at_str: String = row.get(4)?;
+                
+                Ok(Message {
+                    id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    role: row.get(2)?,
+                    content: row.get(3)?,
+                    created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                        .map_err(|_| rusqlite::Error::InvalidColumnType(4, "created_at".to_string(), rusqlite::types::Type::Text))?
+                        .with_timezone(&Utc),
+                })
+            });
+            
+            // Source messages may have been deleted since the question was generated
+            match message {
+                Ok(message) => messages.push(message),
+                Err(rusqlite::Error::QueryReturnedNoRows) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(messages)
+    }
+
     // Practice attempt management methods
     pub fn create_practice_attempt(
         &self,
@@ -545,21 +2870,123 @@ impl Database {
         user_answers: &Vec<String>,
         score: i32,
         total_questions: i32,
+        response_times_ms: &Vec<i64>,
+        confidence_levels: &Vec<i32>,
     ) -> Result<String> {
         let id = uuid::Uuid::new_v4().to_string();
         let now = Utc::now();
         let answers_json = serde_json::to_string(user_answers)
             .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let response_times_json = serde_json::to_string(response_times_ms)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let confidence_json = serde_json::to_string(confidence_levels)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let questions: Vec<AttemptQuestion> = self.get_practice_sheet_questions(practice_sheet_id)?
+            .into_iter()
+            .map(|question| AttemptQuestion {
+                question_text: question.question_text,
+                correct_answer: question.correct_answer,
+            })
+            .collect();
+        let questions_json = serde_json::to_string(&questions)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
         
         self.conn.execute(
-            "INSERT INTO practice_attempts (id, practice_sheet_id, user_answers, score, total_questions, completed_at) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![id, practice_sheet_id, answers_json, score, total_questions, now.to_rfc3339()],
+            "INSERT INTO practice_attempts (id, practice_sheet_id, user_answers, score, total_questions, response_times, confidence_levels, questions, completed_at) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![id, practice_sheet_id, answers_json, score, total_questions, response_times_json, confidence_json, questions_json, now.to_rfc3339()],
         )?;
         
         Ok(id)
     }
 
+    // Every attempt at the sheet, oldest first
+    pub fn get_practice_attempts(&self, practice_sheet_id: &str) -> Result<Vec<PracticeAttempt>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, practice_sheet_id, user_answers, score, total_questions, response_times, confidence_levels, completed_at, questions 
+             FROM practice_attempts WHERE practice_sheet_id = ?1 ORDER BY completed_at ASC"
+        )?;
+        let attempts = stmt.query_map([practice_sheet_id], Self::row_to_practice_attempt)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attempts)
+    }
+
+    fn row_to_practice_attempt(row: &rusqlite::Row) -> rusqlite::Result<PracticeAttempt> {
+        let completed_at_str: String = row.get(7)?;
+        let answers_json: String = row.get(2)?;
+        let user_answers: Vec<String> = serde_json::from_str(&answers_json)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(2, "user_answers".to_string(), rusqlite::types::Type::Text))?;
+        let response_times_json: String = row.get(5)?;
+        let response_times_ms: Vec<i64> = serde_json::from_str(&response_times_json)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(5, "response_times".to_string(), rusqlite::types::Type::Text))?;
+        let confidence_json: String = row.get(6)?;
+        let confidence_levels: Vec<i32> = serde_json::from_str(&confidence_json)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(6, "confidence_levels".to_string(), rusqlite::types::Type::Text))?;
+        let questions_json: String = row.get(8)?;
+        let questions: Vec<AttemptQuestion> = serde_json::from_str(&questions_json)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(8, "questions".to_string(), rusqlite::types::Type::Text))?;
+        
+        Ok(PracticeAttempt {
+            id: row.get(0)?,
+            practice_sheet_id: row.get(1)?,
+            user_answers,
+            score: row.get(3)?,
+            total_questions: row.get(4)?,
+            response_times_ms,
+            confidence_levels,
+            questions,
+            completed_at: DateTime::parse_from_rfc3339(&completed_at_str)
+                .map_err(|_| rusqlite::Error::InvalidColumnType(7, "completed_at".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc),
+        })
+    }
+
+    // Attempt feedback methods
+    pub fn save_attempt_feedback(&self, feedback: &AttemptFeedback) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO attempt_feedback (id, attempt_id, question_index, question_text, user_answer, correct_answer, explanation, backend, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                uuid::Uuid::new_v4().to_string(),
+                feedback.attempt_id,
+                feedback.question_index as i64,
+                feedback.question_text,
+                feedback.user_answer,
+                feedback.correct_answer,
+                feedback.explanation,
+                feedback.backend,
+                feedback.created_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Explanations stored for an attempt, in question order
+    pub fn get_attempt_feedback(&self, attempt_id: &str) -> Result<Vec<AttemptFeedback>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT attempt_id, question_index, question_text, user_answer, correct_answer, explanation, backend, created_at
+             FROM attempt_feedback WHERE attempt_id = ?1 ORDER BY question_index ASC"
+        )?;
+        let feedback = stmt.query_map([attempt_id], |row| {
+            let question_index: i64 = row.get(1)?;
+            let created_at_str: String = row.get(7)?;
+            Ok(AttemptFeedback {
+                attempt_id: row.get(0)?,
+                question_index: question_index as usize,
+                question_text: row.get(2)?,
+                user_answer: row.get(3)?,
+                correct_answer: row.get(4)?,
+                explanation: row.get(5)?,
+                backend: row.get(6)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(7, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+        Ok(feedback)
+    }
+
     pub fn mark_practice_sheet_completed(&self, practice_sheet_id: &str) -> Result<()> {
         self.conn.execute(
             "UPDATE practice_sheets SET is_completed = ?1 WHERE id = ?2",
@@ -568,6 +2995,22 @@ impl Database {
         Ok(())
     }
 
+    pub fn set_practice_sheet_backend(&self, practice_sheet_id: &str, backend: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE practice_sheets SET generation_backend = ?1 WHERE id = ?2",
+            params![backend, practice_sheet_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_practice_sheet_seed(&self, practice_sheet_id: &str, generation_seed: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE practice_sheets SET generation_seed = ?1 WHERE id = ?2",
+            params![generation_seed, practice_sheet_id],
+        )?;
+        Ok(())
+    }
+
     pub fn mark_practice_sheet_redo_ready(&self, practice_sheet_id: &str) -> Result<()> {
         self.conn.execute(
             "UPDATE practice_sheets SET is_redo_ready = ?1 WHERE id = ?2",
@@ -578,27 +3021,11 @@ impl Database {
 
     pub fn get_practice_attempt(&self, practice_sheet_id: &str) -> Result<Option<PracticeAttempt>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, practice_sheet_id, user_answers, score, total_questions, completed_at 
+            "SELECT id, practice_sheet_id, user_answers, score, total_questions, response_times, confidence_levels, completed_at, questions 
              FROM practice_attempts WHERE practice_sheet_id = ?1 ORDER BY completed_at DESC LIMIT 1"
         )?;
 
-        let attempt = stmt.query_row([practice_sheet_id], |row| {
-            let completed_at_str: String = row.get(5)?;
-            let answers_json: String = row.get(2)?;
-            let user_answers: Vec<String> = serde_json::from_str(&answers_json)
-                .map_err(|_| rusqlite::Error::InvalidColumnType(2, "user_answers".to_string(), rusqlite::types::Type::Text))?;
-            
-            Ok(PracticeAttempt {
-                id: row.get(0)?,
-                practice_sheet_id: row.get(1)?,
-                user_answers,
-                score: row.get(3)?,
-                total_questions: row.get(4)?,
-                completed_at: DateTime::parse_from_rfc3339(&completed_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(5, "completed_at".to_string(), rusqlite::types::Type::Text))?
-                    .with_timezone(&Utc),
-            })
-        });
+        let attempt = stmt.query_row([practice_sheet_id], Self::row_to_practice_attempt);
 
         match attempt {
             Ok(a) => Ok(Some(a)),
@@ -623,15 +3050,7 @@ impl Database {
         
         // Add new questions
         for (index, question) in new_questions.iter().enumerate() {
-            let id = uuid::Uuid::new_v4().to_string();
-            let options_json = serde_json::to_string(&question.options)
-                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-            
-            tx.execute(
-                "INSERT INTO practice_questions (id, practice_sheet_id, question_text, options, correct_answer, question_order) 
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                params![id, practice_sheet_id, question.question_text, options_json, question.correct_answer, (index + 1) as i32],
-            )?;
+            Self::insert_practice_question(&tx, practice_sheet_id, question, (index + 1) as i32)?;
         }
         
         // Commit transaction
@@ -641,6 +3060,10 @@ impl Database {
 
     // Helper function to update or insert practice sheet results in memory
     fn update_practice_sheet_in_memory(&self, user_id: &str, sheet_title: &str, new_content: &str) -> Result<()> {
+        if self.demo_mode {
+            return Ok(());
+        }
+
         let current_user = self.get_or_create_user(user_id)?;
         let full_memory = current_user.memory_content;
         
@@ -677,6 +3100,17 @@ impl Database {
                 "UPDATE users SET memory_content = ?1, updated_at = ?2 WHERE id = ?3",
                 params![updated_memory.trim(), now.to_rfc3339(), user_id],
             )?;
+
+            // Swap the sheet's structured entries for the ones parsed from its new block
+            self.conn.execute(
+                "DELETE FROM memory_entries WHERE user_id = ?1 AND source_kind = 'practice_sheet' AND source_label = ?2",
+                params![user_id, sheet_title],
+            )?;
+            // The stored blob is trimmed, so lines are counted from its first non-blank character
+            let leading = updated_memory.len() - updated_memory.trim_start().len();
+            let first_line = full_memory[leading.min(start_pos)..start_pos].matches('\n').count();
+            let entries = crate::memory::parse_memory_entries(new_content);
+            self.insert_memory_entries(user_id, &entries, "live", first_line)?;
         } else {
             // Practice sheet doesn't exist in memory, append it
             self.append_to_memory(user_id, new_content)?;
@@ -707,6 +3141,21 @@ impl Database {
         let questions = self.get_practice_sheet_questions(practice_sheet_id)?;
 
         // Format the results for memory
+        let mut memory_content = Self::format_practice_results(&sheet_title, &attempt, &questions);
+        memory_content.push_str("Redo Available: Yes\n");
+
+        // Update or insert practice sheet results in memory
+        self.update_practice_sheet_in_memory(user_id, &sheet_title, &memory_content)?;
+
+        Ok(())
+    }
+
+    // Shared formatting of a practice attempt for the memory profile
+    fn format_practice_results(
+        sheet_title: &str,
+        attempt: &PracticeAttempt,
+        questions: &[PracticeQuestion],
+    ) -> String {
         let mut memory_content = format!(
             "Practice Sheet: {}\nDate: {}\nScore: {}/{}\n",
             sheet_title,
@@ -715,6 +3164,19 @@ impl Database {
             attempt.total_questions
         );
 
+        // Longest run of consecutive correct answers
+        let mut current_streak = 0;
+        let mut best_streak = 0;
+        for (index, question) in questions.iter().enumerate() {
+            if attempt.user_answers.get(index) == Some(&question.correct_answer) {
+                current_streak += 1;
+                best_streak = best_streak.max(current_streak);
+            } else {
+                current_streak = 0;
+            }
+        }
+        memory_content.push_str(&format!("Best Streak: {}\n", best_streak));
+
         // Add incorrect answers details
         let mut has_incorrect = false;
         for (index, question) in questions.iter().enumerate() {
@@ -731,6 +3193,9 @@ impl Database {
                         user_answer,
                         question.correct_answer
                     ));
+                    if let Some(detail) = Self::format_response_detail(attempt, index) {
+                        memory_content.push_str(&format!("  {}\n", detail));
+                    }
                 }
             }
         }
@@ -739,12 +3204,53 @@ impl Database {
             memory_content.push_str("Perfect score! All answers correct.\n");
         }
 
-        memory_content.push_str("Redo Available: Yes\n");
+        // Correct answers the user was unsure about still need review
+        let mut has_unsure = false;
+        for (index, question) in questions.iter().enumerate() {
+            let is_correct = attempt.user_answers.get(index) == Some(&question.correct_answer);
+            let is_unsure = attempt.confidence_levels.get(index).map_or(false, |&level| level <= 1);
+            if is_correct && is_unsure {
+                if !has_unsure {
+                    memory_content.push_str("Correct But Unsure:\n");
+                    has_unsure = true;
+                }
+                memory_content.push_str(&format!("- Question: {}\n", question.question_text));
+                if let Some(detail) = Self::format_response_detail(attempt, index) {
+                    memory_content.push_str(&format!("  {}\n", detail));
+                }
+            }
+        }
 
-        // Update or insert practice sheet results in memory
-        self.update_practice_sheet_in_memory(user_id, &sheet_title, &memory_content)?;
+        memory_content
+    }
 
-        Ok(())
+    fn format_response_detail(attempt: &PracticeAttempt, index: usize) -> Option<String> {
+        let confidence = attempt.confidence_levels.get(index).map(|&level| match level {
+            i32::MIN..=1 => "guessing",
+            2 => "somewhat sure",
+            _ => "confident",
+        });
+        let seconds = attempt.response_times_ms.get(index).map(|&ms| ms as f64 / 1000.0);
+
+        match (confidence, seconds) {
+            (Some(confidence), Some(seconds)) => Some(format!("Confidence: {}, Time: {:.1}s", confidence, seconds)),
+            (Some(confidence), None) => Some(format!("Confidence: {}", confidence)),
+            (None, Some(seconds)) => Some(format!("Time: {:.1}s", seconds)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn get_practice_sheet_session_id(&self, practice_sheet_id: &str) -> Result<String> {
+        let mut stmt = self.conn.prepare("SELECT session_id FROM practice_sheets WHERE id = ?1")?;
+        stmt.query_row([practice_sheet_id], |row| row.get(0))
+    }
+
+    // The learner profile of the session a practice sheet was made in
+    pub fn get_practice_sheet_user_id(&self, practice_sheet_id: &str) -> Result<String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.user_id FROM practice_sheets p JOIN sessions s ON s.id = p.session_id WHERE p.id = ?1"
+        )?;
+        stmt.query_row([practice_sheet_id], |row| row.get(0))
     }
 
     pub fn get_practice_sheet_title(&self, practice_sheet_id: &str) -> Result<String> {
@@ -818,41 +3324,47 @@ impl Database {
         let questions = self.get_practice_sheet_questions(practice_sheet_id)?;
 
         // Format the results for memory (same logic as store_practice_results_to_memory)
-        let mut memory_content = format!(
-            "Practice Sheet: {}\nDate: {}\nScore: {}/{}\n",
-            sheet_title,
-            attempt.completed_at.format("%Y-%m-%d %H:%M:%S"),
-            attempt.score,
-            attempt.total_questions
-        );
-
-        // Add incorrect answers details
-        let mut has_incorrect = false;
-        for (index, question) in questions.iter().enumerate() {
-            if index < attempt.user_answers.len() {
-                let user_answer = &attempt.user_answers[index];
-                if user_answer != &question.correct_answer {
-                    if !has_incorrect {
-                        memory_content.push_str("Incorrect Answers:\n");
-                        has_incorrect = true;
-                    }
-                    memory_content.push_str(&format!(
-                        "- Question: {}\n  Your answer: {}\n  Correct answer: {}\n",
-                        question.question_text,
-                        user_answer,
-                        question.correct_answer
-                    ));
-                }
-            }
-        }
-
-        if !has_incorrect {
-            memory_content.push_str("Perfect score! All answers correct.\n");
-        }
-
+        let mut memory_content = Self::format_practice_results(&sheet_title, &attempt, &questions);
         memory_content.push_str("Redo Available: Yes");
 
         Ok(memory_content)
     }
 
-}
\ No newline at end of file
+}
+
+// Char ranges of every non-overlapping, case-insensitive occurrence of `query` in `text`
+fn find_highlights(text: &str, query: &str) -> Vec<(usize, usize)> {
+    let text: Vec<char> = text.chars().collect();
+    let query: Vec<char> = query.chars().collect();
+    let same = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+
+    let mut highlights = Vec::new();
+    let mut start = 0;
+    while start + query.len() <= text.len() {
+        if text[start..start + query.len()].iter().zip(&query).all(|(&a, &b)| same(a, b)) {
+            highlights.push((start, start + query.len()));
+            start += query.len();
+        } else {
+            start += 1;
+        }
+    }
+    highlights
+}
+
+// About 40 chars either side of a match, with ellipses where the text was cut
+fn make_snippet(text: &str, start: usize, end: usize) -> String {
+    const CONTEXT_CHARS: usize = 40;
+    let chars: Vec<char> = text.chars().collect();
+    let from = start.saturating_sub(CONTEXT_CHARS);
+    let to = (end + CONTEXT_CHARS).min(chars.len());
+
+    let mut snippet: String = chars[from..to].iter().collect();
+    snippet = snippet.replace('\n', " ");
+    if from > 0 {
+        snippet.insert_str(0, "...");
+    }
+    if to < chars.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
@@ -0,0 +1,263 @@
+use crate::provider::{Completion, CompletionRequest, LlmProvider, TextSink};
+use std::path::PathBuf;
+
+// A GGUF model run in-process on llama.cpp, for machines where Ollama can't be installed or
+// started. The model is the file name of a GGUF bundled next to the executable or placed in the
+// models directory. Builds without the "embedded-llm" feature report that they can't run it, and
+// the fallback chain moves on to the next backend.
+pub struct EmbeddedProvider {
+    model: String,
+    model_path: Option<PathBuf>,
+}
+
+impl EmbeddedProvider {
+    pub fn new(model: &str) -> Self {
+        Self { model: model.to_string(), model_path: find_gguf_model(model) }
+    }
+}
+
+impl LlmProvider for EmbeddedProvider {
+    async fn complete(&self, request: &CompletionRequest<'_>) -> Result<Completion, String> {
+        self.complete_streaming(request, &mut |_| {}).await
+    }
+
+    async fn complete_streaming(&self, request: &CompletionRequest<'_>, on_text: &mut TextSink<'_>) -> Result<Completion, String> {
+        let Some(path) = &self.model_path else {
+            return Err(format!("Embedded model {} not found; put the GGUF file next to the app or in the models folder", self.model));
+        };
+        llama::complete(path, request, on_text).await
+    }
+}
+
+// Bare GGUF file names only, looked up next to the executable, then in the models directory
+fn find_gguf_model(name: &str) -> Option<PathBuf> {
+    if name.contains(['/', '\\']) || name.starts_with('.') || !name.ends_with(".gguf") {
+        return None;
+    }
+
+    let bundled = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(name)));
+    let downloaded = dirs::config_dir()
+        .map(|dir| dir.join("project-r").join("models").join(name));
+    [bundled, downloaded].into_iter().flatten().find(|path| path.is_file())
+}
+
+#[cfg(not(feature = "embedded-llm"))]
+mod llama {
+    use crate::provider::{Completion, CompletionRequest, TextSink};
+    use std::path::Path;
+
+    pub async fn complete(path: &Path, _request: &CompletionRequest<'_>, _on_text: &mut TextSink<'_>) -> Result<Completion, String> {
+        Err(format!("Embedded inference isn't included in this build, so {} can't be loaded", path.display()))
+    }
+}
+
+#[cfg(feature = "embedded-llm")]
+mod llama {
+    use crate::provider::{Completion, CompletionRequest, ResponseFormat, TextSink, DEFAULT_CONTEXT_TOKENS};
+    use llama_cpp_2::context::params::LlamaContextParams;
+    use llama_cpp_2::llama_backend::LlamaBackend;
+    use llama_cpp_2::llama_batch::LlamaBatch;
+    use llama_cpp_2::model::params::LlamaModelParams;
+    use llama_cpp_2::model::{LlamaChatMessage, LlamaModel};
+    use llama_cpp_2::sampling::LlamaSampler;
+    use std::num::NonZeroU32;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Mutex, OnceLock};
+
+    // llama.cpp picks a new seed on every request given this one
+    const RANDOM_SEED: u32 = u32::MAX;
+
+    // llama.cpp's backend can be started only once per process
+    static BACKEND: OnceLock<Result<LlamaBackend, String>> = OnceLock::new();
+
+    // The last model loaded, kept so each request doesn't read the file again. The lock is held
+    // for the whole generation, so requests run one at a time.
+    static LOADED: Mutex<Option<(PathBuf, LlamaModel)>> = Mutex::new(None);
+
+    // An owned copy of the request for the blocking thread
+    struct Generation {
+        messages: Vec<(String, String)>, // Role and content; embedded models take text only
+        schema: Option<String>, // JSON schema the reply is held to
+        context_tokens: usize,
+        max_tokens: Option<usize>,
+        temperature: f32,
+        top_p: f32,
+        seed: u32,
+    }
+
+    impl Generation {
+        fn new(request: &CompletionRequest<'_>) -> Self {
+            Self {
+                messages: request.messages.iter().map(|message| (message.role.clone(), message.content.clone())).collect(),
+                schema: match request.format {
+                    ResponseFormat::Text => None,
+                    ResponseFormat::Json => Some("{}".to_string()),
+                    ResponseFormat::Schema(schema) => Some(schema.to_string()),
+                },
+                context_tokens: request.context_tokens.unwrap_or(DEFAULT_CONTEXT_TOKENS),
+                max_tokens: usize::try_from(request.max_tokens).ok().filter(|&tokens| tokens > 0),
+                temperature: request.temperature,
+                top_p: request.top_p,
+                seed: request.seed.map_or(RANDOM_SEED, |seed| seed as u32),
+            }
+        }
+    }
+
+    // Generation runs on a blocking thread and hands its text back over a channel as tokens are
+    // sampled. Dropping the returned future stops it at the next token.
+    pub async fn complete(path: &Path, request: &CompletionRequest<'_>, on_text: &mut TextSink<'_>) -> Result<Completion, String> {
+        let path = path.to_path_buf();
+        let generation = Generation::new(request);
+        let (pieces, mut received) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let running = tokio::task::spawn_blocking(move || {
+            generate(&path, &generation, &mut |piece| pieces.send(piece.to_string()).is_ok())
+        });
+
+        while let Some(piece) = received.recv().await {
+            on_text(&piece);
+        }
+        running.await.map_err(|e| format!("Embedded model stopped unexpectedly: {}", e))?
+    }
+
+    fn backend() -> Result<&'static LlamaBackend, String> {
+        BACKEND.get_or_init(|| {
+            let mut backend = LlamaBackend::init().map_err(|e| e.to_string())?;
+            backend.void_logs();
+            Ok(backend)
+        })
+        .as_ref()
+        .map_err(|e| format!("Failed to start llama.cpp: {}", e))
+    }
+
+    // `on_piece` returns false once nobody is listening, which ends the generation
+    fn generate(path: &Path, generation: &Generation, on_piece: &mut dyn FnMut(&str) -> bool) -> Result<Completion, String> {
+        let backend = backend()?;
+        let mut loaded = LOADED.lock().map_err(|e| e.to_string())?;
+        let model = match &mut *loaded {
+            Some((loaded_path, model)) if loaded_path == path => model,
+            slot => {
+                // Free the previous model before reading the next
+                *slot = None;
+                let model = LlamaModel::load_from_file(backend, path, &LlamaModelParams::default())
+                    .map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+                &mut slot.insert((path.to_path_buf(), model)).1
+            }
+        };
+
+        let template = model.chat_template(None)
+            .map_err(|e| format!("{} has no usable chat template: {}", path.display(), e))?;
+        let messages = generation.messages.iter()
+            .map(|(role, content)| LlamaChatMessage::new(role.clone(), content.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to build the prompt: {}", e))?;
+        let prompt = model.apply_chat_template(&template, &messages, true)
+            .map_err(|e| format!("Failed to build the prompt: {}", e))?;
+
+        let vocab = model.vocab();
+        let prompt_tokens = vocab.tokenize(prompt.as_bytes(), true, true);
+        let window = generation.context_tokens;
+        if prompt_tokens.is_empty() || prompt_tokens.len() >= window {
+            return Err(format!("Prompt of {} tokens doesn't fit the {}-token window", prompt_tokens.len(), window));
+        }
+
+        // The whole prompt goes in as one batch, so the batch is as large as the window
+        let window_u32 = u32::try_from(window).map_err(|e| e.to_string())?;
+        let params = LlamaContextParams::default()
+            .with_n_ctx(NonZeroU32::new(window_u32))
+            .with_n_batch(window_u32);
+        let mut context = model.new_context(backend, params)
+            .map_err(|e| format!("Failed to create a llama.cpp context: {}", e))?;
+
+        let mut batch = LlamaBatch::new(window, 1);
+        let last = prompt_tokens.len() - 1;
+        for (position, token) in prompt_tokens.iter().enumerate() {
+            batch.add(*token, position as i32, &[0], position == last).map_err(|e| e.to_string())?;
+        }
+        context.decode(&mut batch).map_err(|e| format!("llama.cpp failed on the prompt: {}", e))?;
+
+        let mut sampler = sampler(model, generation)?;
+        let budget = (window - prompt_tokens.len()).min(generation.max_tokens.unwrap_or(usize::MAX));
+        let mut text = String::new();
+        let mut pending = Vec::new();
+        for position in prompt_tokens.len()..prompt_tokens.len() + budget {
+            let token = sampler.sample(&context, batch.n_tokens() - 1);
+            if vocab.is_eog(token) {
+                return Ok(Completion { text, truncated: false });
+            }
+
+            pending.extend(vocab.token_to_piece(token, false, None));
+            let piece = take_utf8(&mut pending);
+            if !piece.is_empty() {
+                if !on_piece(&piece) {
+                    return Err("Embedded generation was cancelled".to_string());
+                }
+                text.push_str(&piece);
+            }
+
+            batch.clear();
+            batch.add(token, position as i32, &[0], true).map_err(|e| e.to_string())?;
+            context.decode(&mut batch).map_err(|e| format!("llama.cpp failed mid-reply: {}", e))?;
+        }
+        Ok(Completion { text, truncated: true })
+    }
+
+    // A schema is enforced with a grammar ahead of the usual top-p and temperature; temperature 0
+    // takes the likeliest token every time
+    fn sampler(model: &LlamaModel, generation: &Generation) -> Result<LlamaSampler, String> {
+        let mut samplers = Vec::new();
+        if let Some(schema) = &generation.schema {
+            let grammar = llama_cpp_2::json_schema_to_grammar(schema)
+                .map_err(|e| format!("Failed to turn the JSON schema into a grammar: {}", e))?;
+            samplers.push(LlamaSampler::grammar(model, &grammar, "root")
+                .map_err(|e| format!("llama.cpp rejected the JSON grammar: {}", e))?);
+        }
+        if generation.temperature <= 0.0 {
+            samplers.push(LlamaSampler::greedy());
+        } else {
+            samplers.push(LlamaSampler::top_p(generation.top_p, 1));
+            samplers.push(LlamaSampler::temp(generation.temperature));
+            samplers.push(LlamaSampler::dist(generation.seed));
+        }
+        Ok(LlamaSampler::chain_simple(samplers))
+    }
+
+    // Take the text decoded so far, keeping back the start of a character whose bytes are split
+    // across tokens
+    fn take_utf8(bytes: &mut Vec<u8>) -> String {
+        let complete = match std::str::from_utf8(bytes) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => bytes.len(),
+        };
+        let rest = bytes.split_off(complete);
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        *bytes = rest;
+        text
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn characters_split_across_tokens_wait_for_their_last_byte() {
+            let mut pending = "né".as_bytes()[..2].to_vec();
+            assert_eq!(take_utf8(&mut pending), "n");
+            pending.push("é".as_bytes()[1]);
+            assert_eq!(take_utf8(&mut pending), "é");
+            assert!(pending.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_bare_gguf_names_are_looked_up() {
+        assert_eq!(find_gguf_model("../secrets.gguf"), None);
+        assert_eq!(find_gguf_model("models/tutor.gguf"), None);
+        assert_eq!(find_gguf_model("tutor.bin"), None);
+    }
+}
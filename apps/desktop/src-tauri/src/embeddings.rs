@@ -0,0 +1,264 @@
+use crate::ollama::{CallKind, OllamaHttp};
+use serde::{Deserialize, Serialize};
+
+// Retrieval over memory and past sessions: memory entries, session summaries and messages are
+// split into chunks, embedded with an Ollama embedding model and stored in SQLite; prompts get the
+// chunks closest to the question instead of the whole memory blob
+
+pub const DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+
+// Sources embedded per retrieval, so indexing a long history doesn't stall one answer;
+// reindex_embeddings catches up in one go
+pub const INDEX_BATCH: usize = 16;
+
+// Chunks longer than this are split; short enough for small embedding models' context
+const MAX_CHUNK_CHARS: usize = 1000;
+
+// Stored as the "retrieval" setting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrievalSettings {
+    pub enabled: bool,
+    pub model: String,
+    pub base_url: Option<String>, // Ollama server for embeddings; None for the default
+    pub max_chunks: usize,        // Chunks added to a prompt
+    pub min_score: f32,           // Cosine similarity below which a chunk isn't relevant
+}
+
+impl Default for RetrievalSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            base_url: None,
+            max_chunks: 8,
+            min_score: 0.3,
+        }
+    }
+}
+
+impl RetrievalSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.model.trim().is_empty() {
+            return Err("Embedding model can't be empty".to_string());
+        }
+        if self.max_chunks == 0 || self.max_chunks > 50 {
+            return Err("Retrieved chunks must be between 1 and 50".to_string());
+        }
+        if !(-1.0..=1.0).contains(&self.min_score) {
+            return Err("Minimum similarity must be between -1 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Text not embedded yet with the current model
+#[derive(Debug, Clone)]
+pub struct PendingSource {
+    pub source_kind: String, // "summary", "memory" or "message"
+    pub source_id: String,   // memory_entries or messages id
+    pub session_id: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredEmbedding {
+    pub source_kind: String,
+    pub source_id: String,
+    pub content: String,
+    pub vector: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrievedChunk {
+    pub source_kind: String,
+    pub source_id: String,
+    pub content: String,
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    #[serde(default)]
+    embedding: Vec<f32>,
+}
+
+pub struct EmbeddingClient {
+    http: OllamaHttp,
+}
+
+impl EmbeddingClient {
+    pub fn new(base_url: Option<String>) -> Self {
+        Self { http: OllamaHttp::new(base_url) }
+    }
+
+    pub async fn embed(&self, model: &str, text: &str) -> Result<Vec<f32>, String> {
+        let request = EmbeddingRequest { model, prompt: text };
+        let response: EmbeddingResponse = self.http.post_json("/api/embeddings", &request, CallKind::Quick).await
+            .map_err(|e| format!("Failed to embed text with {}: {}", model, e))?;
+        if response.embedding.is_empty() {
+            return Err(format!("{} returned no embedding; is it an embedding model?", model));
+        }
+        Ok(response.embedding)
+    }
+
+    // Each source's chunks with their vectors. Stops at the first failure, keeping what was done.
+    pub async fn embed_sources(&self, model: &str, sources: Vec<PendingSource>) -> (Vec<(PendingSource, Vec<(String, Vec<f32>)>)>, Option<String>) {
+        let mut embedded = Vec::with_capacity(sources.len());
+        for source in sources {
+            let mut chunks = Vec::new();
+            for chunk in chunk_text(&source.text) {
+                match self.embed(model, &chunk).await {
+                    Ok(vector) => chunks.push((chunk, vector)),
+                    Err(e) => return (embedded, Some(e)),
+                }
+            }
+            embedded.push((source, chunks));
+        }
+        (embedded, None)
+    }
+}
+
+// Paragraphs packed into chunks of at most MAX_CHUNK_CHARS; longer lines are cut at char boundaries
+pub fn chunk_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for line in text.lines().map(str::trim_end).filter(|line| !line.trim().is_empty()) {
+        let pieces: Vec<String> = line.chars()
+            .collect::<Vec<_>>()
+            .chunks(MAX_CHUNK_CHARS)
+            .map(|piece| piece.iter().collect())
+            .collect();
+        for piece in pieces {
+            if !current.is_empty() && current.chars().count() + 1 + piece.chars().count() > MAX_CHUNK_CHARS {
+                chunks.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(&piece);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+// The closest chunks above min_score, best first, with repeated text kept once
+pub fn rank(query: &[f32], candidates: &[StoredEmbedding], settings: &RetrievalSettings) -> Vec<RetrievedChunk> {
+    let mut scored: Vec<(f32, &StoredEmbedding)> = candidates.iter()
+        .map(|candidate| (cosine_similarity(query, &candidate.vector), candidate))
+        .filter(|(score, _)| *score >= settings.min_score)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut chunks: Vec<RetrievedChunk> = Vec::new();
+    for (score, candidate) in scored {
+        if chunks.len() >= settings.max_chunks {
+            break;
+        }
+        if chunks.iter().any(|chunk| chunk.content == candidate.content) {
+            continue;
+        }
+        chunks.push(RetrievedChunk {
+            source_kind: candidate.source_kind.clone(),
+            source_id: candidate.source_id.clone(),
+            content: candidate.content.clone(),
+            score,
+        });
+    }
+    chunks
+}
+
+// One line per chunk, most relevant first, so trimming the prompt from the end drops the
+// least relevant
+pub fn render_for_prompt(chunks: &[RetrievedChunk]) -> String {
+    chunks.iter()
+        .map(|chunk| {
+            let label = match chunk.source_kind.as_str() {
+                "summary" => "Earlier session",
+                "message" => "Earlier conversation",
+                _ => "Note",
+            };
+            format!("- {}: {}", label, chunk.content.replace('\n', " "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Vectors are stored as little-endian f32 blobs
+pub fn vector_to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+pub fn blob_to_vector(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(content: &str, vector: Vec<f32>) -> StoredEmbedding {
+        StoredEmbedding {
+            source_kind: "memory".to_string(),
+            source_id: content.to_string(),
+            content: content.to_string(),
+            vector,
+        }
+    }
+
+    #[test]
+    fn long_text_is_split_into_bounded_chunks() {
+        let text = format!("{}\n\nshort line\n{}", "a".repeat(1500), "b".repeat(600));
+        let chunks = chunk_text(&text);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= MAX_CHUNK_CHARS));
+        assert_eq!(chunks[1], format!("{}\nshort line", "a".repeat(500)));
+        assert!(chunk_text("  \n\n").is_empty());
+    }
+
+    #[test]
+    fn ranking_keeps_the_closest_distinct_chunks() {
+        let candidates = vec![
+            stored("loops", vec![1.0, 0.0]),
+            stored("loops", vec![0.9, 0.1]),
+            stored("strings", vec![0.0, 1.0]),
+            stored("lists", vec![0.7, 0.7]),
+        ];
+        let settings = RetrievalSettings { max_chunks: 2, min_score: 0.3, ..Default::default() };
+        let chunks = rank(&[1.0, 0.0], &candidates, &settings);
+        let contents: Vec<&str> = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(contents, ["loops", "lists"]);
+    }
+
+    #[test]
+    fn vectors_round_trip_through_blobs() {
+        let vector = vec![0.25, -1.5, 3.0];
+        assert_eq!(blob_to_vector(&vector_to_blob(&vector)), vector);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::llm::{matches_model_name, ModelBackend, OllamaClient};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Mutex, OnceLock};
+
+// Snapshot of the subsystems commands depend on, refreshed by the health watchdog
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    pub ollama_reachable: bool,
+    pub installed_models: Vec<String>,
+    pub whisper_ready: bool,
+    pub tts_ready: bool,
+    pub checked_at: DateTime<Utc>,
+}
+
+// Why a command can't run, with the action the UI can offer to fix it.
+// Serialized as the command's error string, e.g. {"kind":"ModelMissing","action":"pull","model":"gemma3n"}
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
+pub enum HealthIssue {
+    OllamaUnreachable { action: &'static str, url: String },
+    ModelMissing { action: &'static str, model: String },
+    WhisperNotReady { action: &'static str },
+    TtsNotReady { action: &'static str },
+}
+
+impl HealthIssue {
+    pub fn ollama_unreachable(url: &str) -> Self {
+        HealthIssue::OllamaUnreachable { action: "start_ollama", url: url.to_string() }
+    }
+
+    pub fn model_missing(model: &str) -> Self {
+        HealthIssue::ModelMissing { action: "pull", model: model.to_string() }
+    }
+
+    pub fn whisper_not_ready() -> Self {
+        HealthIssue::WhisperNotReady { action: "initialize_whisper" }
+    }
+
+    pub fn tts_not_ready() -> Self {
+        HealthIssue::TtsNotReady { action: "initialize_tts" }
+    }
+
+    pub fn into_error(self) -> String {
+        serde_json::to_string(&self).unwrap_or_else(|_| format!("{:?}", self))
+    }
+}
+
+// Whether the chat model can be reached, emitted to the UI as "llm-status" when it changes,
+// e.g. {"status":"model_missing","model":"gemma3n"}
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LlmStatus {
+    Connected { model: String },
+    Disconnected { url: String },
+    ModelMissing { model: String },
+}
+
+impl LlmStatus {
+    pub fn from_report(report: &HealthReport, model: &str, local_url: &str) -> Self {
+        if !report.ollama_reachable {
+            LlmStatus::Disconnected { url: local_url.to_string() }
+        } else if is_installed(&report.installed_models, model) {
+            LlmStatus::Connected { model: model.to_string() }
+        } else {
+            LlmStatus::ModelMissing { model: model.to_string() }
+        }
+    }
+}
+
+static LATEST: OnceLock<Mutex<Option<HealthReport>>> = OnceLock::new();
+static LLM_STATUS: OnceLock<Mutex<Option<LlmStatus>>> = OnceLock::new();
+
+fn latest_slot() -> &'static Mutex<Option<HealthReport>> {
+    LATEST.get_or_init(|| Mutex::new(None))
+}
+
+pub fn latest() -> Option<HealthReport> {
+    latest_slot().lock().ok()?.clone()
+}
+
+// Store a new report; returns true when it differs from the previous one (ignoring the timestamp)
+pub fn record(report: HealthReport) -> bool {
+    let Ok(mut slot) = latest_slot().lock() else { return false };
+    let changed = match slot.as_ref() {
+        Some(previous) => HealthReport { checked_at: report.checked_at, ..previous.clone() } != report,
+        None => true,
+    };
+    *slot = Some(report);
+    changed
+}
+
+fn llm_status_slot() -> &'static Mutex<Option<LlmStatus>> {
+    LLM_STATUS.get_or_init(|| Mutex::new(None))
+}
+
+pub fn latest_llm_status() -> Option<LlmStatus> {
+    llm_status_slot().lock().ok()?.clone()
+}
+
+// Store the chat model's status; returns the previous one
+pub fn record_llm_status(status: LlmStatus) -> Option<LlmStatus> {
+    let Ok(mut slot) = llm_status_slot().lock() else { return None };
+    slot.replace(status)
+}
+
+pub async fn probe_ollama(client: &OllamaClient) -> (bool, Vec<String>) {
+    match client.list_models().await {
+        Ok(models) => (true, models.into_iter().map(|model| model.name).collect()),
+        Err(_) => (false, Vec::new()),
+    }
+}
+
+fn is_installed(installed_models: &[String], model: &str) -> bool {
+    installed_models.iter().any(|name| matches_model_name(name, model))
+}
+
+// Whether the fallback chain has something that can answer. Remote backends can't be probed
+// from here, so any of them lets the request through to the chain walk.
+pub fn check_chain(report: &HealthReport, chain: &[ModelBackend], local_url: &str) -> Result<(), HealthIssue> {
+    if chain.iter().any(|backend| backend.is_remote()) {
+        return Ok(());
+    }
+    if !report.ollama_reachable {
+        return Err(HealthIssue::ollama_unreachable(local_url));
+    }
+    if chain.iter().any(|backend| is_installed(&report.installed_models, &backend.model)) {
+        return Ok(());
+    }
+    let model = chain.first().map(|backend| backend.model.as_str()).unwrap_or(crate::llm::DEFAULT_CHAT_MODEL);
+    Err(HealthIssue::model_missing(model))
+}
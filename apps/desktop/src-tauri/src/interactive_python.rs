@@ -1,4 +1,5 @@
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -6,9 +7,196 @@ use std::time::Duration;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
+// Outcome of test-running AI-suggested code before the user applies it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DryRunResult {
+    pub success: bool,
+    pub output: String,
+    pub error: String,       // Final traceback line, e.g. "NameError: name 'x' is not defined"
+    pub timed_out: bool,
+    pub needs_input: bool,   // Program reads stdin, so it could only run up to the first input()
+}
+
+// Memory a test run may map before allocations fail
+const DRY_RUN_MEMORY_BYTES: u64 = 2 << 30;
+
+// Sets the CPU and memory limits where the OS has them (the resource module is Unix-only), then
+// runs the code from argv[1] in a fresh namespace. The limits are hard, so the code can't raise
+// them again.
+const DRY_RUN_PRELUDE: &str = r#"
+import sys
+try:
+    import resource
+    for name, value in (("RLIMIT_CPU", int(sys.argv[2])), ("RLIMIT_AS", int(sys.argv[3]))):
+        try:
+            resource.setrlimit(getattr(resource, name), (value, value))
+        except (AttributeError, ValueError, OSError):
+            pass
+except ImportError:
+    pass
+source = sys.argv[1]
+sys.argv = ["-c"]
+exec(compile(source, "<string>", "exec"), {"__name__": "__main__", "__builtins__": __builtins__})
+"#;
+
+// Test-run code the user hasn't accepted yet (a suggested answer, a milestone check) with no
+// stdin and a time limit. It's kept away from the user's setup as far as plain Python allows:
+// isolated mode (-I: no PYTHON* variables, user site-packages or current directory on sys.path),
+// an empty temporary working directory, an environment with only what's needed to find Python,
+// and CPU time and memory limits. This is not a sandbox; the code can still reach any file the
+// user can.
+pub async fn dry_run_python(code: &str, timeout: Duration) -> DryRunResult {
+    let workdir = match tempfile::tempdir() {
+        Ok(workdir) => workdir,
+        Err(e) => {
+            return DryRunResult {
+                error: format!("Failed to create a working directory: {}", e),
+                ..Default::default()
+            }
+        }
+    };
+
+    let mut command = tokio::process::Command::new(crate::venv::python_program());
+    command.env_clear();
+    for name in ["PATH", "SYSTEMROOT", "TEMP", "TMP"] {
+        if let Some(value) = std::env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+    command
+        .envs(crate::venv::activation_env())
+        .current_dir(workdir.path())
+        .args(["-I", "-c", DRY_RUN_PRELUDE])
+        .arg(code)
+        .arg((timeout.as_secs() + 1).to_string())
+        .arg(DRY_RUN_MEMORY_BYTES.to_string());
+    run_to_completion(command, "", timeout).await
+}
+
+// Run code non-interactively, feeding `stdin` up front and then closing it
+pub async fn run_python_with_stdin(code: &str, stdin: &str, timeout: Duration) -> DryRunResult {
+    let mut command = tokio::process::Command::new(crate::venv::python_program());
+    command.envs(crate::venv::activation_env()).arg("-c").arg(code);
+    run_to_completion(command, stdin, timeout).await
+}
+
+async fn run_to_completion(mut command: tokio::process::Command, stdin: &str, timeout: Duration) -> DryRunResult {
+    use tokio::io::AsyncWriteExt;
+
+    let child = command
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            return DryRunResult {
+                error: format!("Failed to start Python: {}", e),
+                ..Default::default()
+            }
+        }
+    };
+
+    // Dropping the handle closes stdin, so reads past the provided input hit EOF
+    if let Some(mut child_stdin) = child.stdin.take() {
+        if !stdin.is_empty() {
+            let _ = child_stdin.write_all(stdin.as_bytes()).await;
+        }
+    }
+
+    match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(Ok(output)) => {
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            let error = stderr.lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+
+            DryRunResult {
+                success: output.status.success(),
+                output: stdout,
+                needs_input: error.starts_with("EOFError"),
+                error: if output.status.success() { String::new() } else { error },
+                timed_out: false,
+            }
+        }
+        Ok(Err(e)) => DryRunResult {
+            error: format!("Failed to run Python: {}", e),
+            ..Default::default()
+        },
+        Err(_) => DryRunResult {
+            error: format!("Timed out after {} seconds", timeout.as_secs()),
+            timed_out: true,
+            ..Default::default()
+        },
+    }
+}
+
+// Runs in a REPL session's interpreter. Each request is a line with the code's length in bytes,
+// then the code; it runs in one namespace kept for the whole session, and the reply is a JSON
+// line with what it printed. Like the interactive prompt, a final expression's value is printed.
+// stdin carries the requests, so input() in the code hits EOF.
+const REPL_DRIVER: &str = r#"
+import ast, contextlib, io, json, linecache, sys, traceback
+
+namespace = {"__name__": "__main__", "__builtins__": __builtins__}
+requests, replies = sys.stdin.buffer, sys.stdout
+sys.stdin = io.StringIO()
+while True:
+    header = requests.readline()
+    if not header:
+        break
+    source = requests.read(int(header)).decode()
+    # So tracebacks can quote the failing line
+    linecache.cache["<repl>"] = (len(source), None, source.splitlines(True), "<repl>")
+    output = io.StringIO()
+    success = True
+    with contextlib.redirect_stdout(output), contextlib.redirect_stderr(output):
+        try:
+            tree = ast.parse(source, "<repl>")
+            last = tree.body.pop() if tree.body and isinstance(tree.body[-1], ast.Expr) else None
+            exec(compile(tree, "<repl>", "exec"), namespace)
+            if last is not None:
+                value = eval(compile(ast.Expression(last.value), "<repl>", "eval"), namespace)
+                if value is not None:
+                    print(repr(value))
+        except BaseException:
+            success = False
+            kind, error, trace = sys.exc_info()
+            # Skip this driver's frame; a syntax error has no frames of the code's own
+            traceback.print_exception(kind, error, None if issubclass(kind, SyntaxError) else trace.tb_next)
+    replies.write(json.dumps({"output": output.getvalue(), "success": success}) + "\n")
+    replies.flush()
+"#;
+
+// Longest a single evaluation may take; the session is closed when it's exceeded, as the code
+// is still running in it
+const REPL_EVAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct ReplReply {
+    output: String,
+    success: bool,
+}
+
+// An interpreter kept alive between runs, so variables and functions defined by one evaluation
+// are there for the next
+struct PythonRepl {
+    stdin: tokio::process::ChildStdin,
+    stdout: tokio::io::BufReader<tokio::process::ChildStdout>,
+    _child: tokio::process::Child, // Killed when the session is dropped
+}
+
 // Session manager to handle multiple Python sessions
 pub struct PythonSessionManager {
     sessions: Arc<Mutex<HashMap<String, PythonSession>>>,
+    repls: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<PythonRepl>>>>>,
 }
 
 struct PythonSession {
@@ -23,6 +211,7 @@ impl PythonSessionManager {
     pub fn new() -> Self {
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
+            repls: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -40,8 +229,11 @@ impl PythonSessionManager {
             })
             .map_err(|e| format!("Failed to create PTY: {}", e))?;
 
-        // Create Python command
-        let mut cmd = CommandBuilder::new("python3");
+        // Create Python command, in the selected virtual environment if there is one
+        let mut cmd = CommandBuilder::new(crate::venv::python_program());
+        for (key, value) in crate::venv::activation_env() {
+            cmd.env(key, value);
+        }
         cmd.arg("-c");
         cmd.arg(&code);
 
@@ -180,4 +372,83 @@ impl PythonSessionManager {
         sessions.remove(&session_id);
         Ok(())
     }
-}
\ No newline at end of file
+
+    // Start a REPL session; its ID is what evaluate_in_repl takes
+    pub async fn start_repl(&self) -> Result<String, String> {
+        let mut child = tokio::process::Command::new(crate::venv::python_program())
+            .envs(crate::venv::activation_env())
+            .arg("-c")
+            .arg(REPL_DRIVER)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to start Python: {}", e))?;
+        let stdin = child.stdin.take().ok_or("Failed to open the Python session's input")?;
+        let stdout = child.stdout.take().ok_or("Failed to open the Python session's output")?;
+
+        let repl_id = Uuid::new_v4().to_string();
+        let repl = PythonRepl {
+            stdin,
+            stdout: tokio::io::BufReader::new(stdout),
+            _child: child,
+        };
+        self.repls.lock().unwrap().insert(repl_id.clone(), Arc::new(tokio::sync::Mutex::new(repl)));
+        Ok(repl_id)
+    }
+
+    // Run code in a REPL session, after whatever it ran before. Evaluations in one session run
+    // one at a time.
+    pub async fn evaluate_in_repl(&self, repl_id: &str, code: &str) -> Result<DryRunResult, String> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        let repl = self.repls.lock().unwrap().get(repl_id).cloned().ok_or("REPL session not found")?;
+        let mut repl = repl.lock().await;
+        let request = format!("{}\n{}", code.len(), code);
+        if let Err(e) = repl.stdin.write_all(request.as_bytes()).await {
+            self.repls.lock().unwrap().remove(repl_id);
+            return Err(format!("The Python session has exited: {}", e));
+        }
+
+        let mut line = String::new();
+        let reply = match tokio::time::timeout(REPL_EVAL_TIMEOUT, repl.stdout.read_line(&mut line)).await {
+            Ok(Ok(0)) | Ok(Err(_)) => {
+                self.repls.lock().unwrap().remove(repl_id);
+                return Err("The Python session has exited".to_string());
+            }
+            Ok(Ok(_)) => serde_json::from_str::<ReplReply>(&line).map_err(|e| format!("Unreadable reply from the Python session: {}", e))?,
+            Err(_) => {
+                self.repls.lock().unwrap().remove(repl_id);
+                return Ok(DryRunResult {
+                    error: format!("Timed out after {} seconds; the session was closed", REPL_EVAL_TIMEOUT.as_secs()),
+                    timed_out: true,
+                    ..Default::default()
+                });
+            }
+        };
+
+        let error = if reply.success {
+            String::new()
+        } else {
+            reply.output.lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+        Ok(DryRunResult {
+            success: reply.success,
+            output: reply.output,
+            needs_input: error.starts_with("EOFError"),
+            error,
+            timed_out: false,
+        })
+    }
+
+    pub async fn close_repl(&self, repl_id: &str) -> Result<(), String> {
+        self.repls.lock().unwrap().remove(repl_id);
+        Ok(())
+    }
+}
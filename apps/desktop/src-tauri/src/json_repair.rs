@@ -0,0 +1,431 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+// How a model's JSON output had to be handled before it could be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseOutcome {
+    Clean,     // Parsed as returned
+    Repaired,  // Needed surrounding text stripped or truncated output closed
+    Failed,
+}
+
+pub struct Parsed<T> {
+    pub value: T,
+    pub outcome: ParseOutcome,
+}
+
+// Parse LLM output as `T`, salvaging what it can from fenced, chatty or truncated JSON.
+// Truncated output is first closed where it stopped, then cut back to earlier commas
+// until something deserializes, so a cut-off list keeps its complete items.
+pub fn parse<T: DeserializeOwned>(raw: &str) -> Result<Parsed<T>, String> {
+    parse_accepting(raw, |text| serde_json::from_str::<T>(text).map_err(|e| e.to_string()))
+}
+
+// Like `parse`, but a candidate must also match `schema` before it is deserialized, so
+// repair keeps cutting back until what is left has the shape that was asked for
+pub fn parse_with_schema<T: DeserializeOwned>(raw: &str, schema: &Value) -> Result<Parsed<T>, String> {
+    parse_accepting(raw, |text| {
+        let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        validate_schema(&value, schema)?;
+        serde_json::from_value(value).map_err(|e| e.to_string())
+    })
+}
+
+fn parse_accepting<T>(raw: &str, accept: impl Fn(&str) -> Result<T, String>) -> Result<Parsed<T>, String> {
+    let error = match accept(raw) {
+        Ok(value) => return Ok(Parsed { value, outcome: ParseOutcome::Clean }),
+        Err(e) => e,
+    };
+
+    // The model sometimes mentions a bracket in prose before the real value starts
+    const MAX_START_ATTEMPTS: usize = 8;
+    let starts = raw.char_indices()
+        .filter(|(_, c)| *c == '{' || *c == '[')
+        .map(|(index, _)| index)
+        .take(MAX_START_ATTEMPTS);
+    for start in starts {
+        for candidate in repair_candidates(&raw[start..]) {
+            if let Ok(value) = accept(&candidate) {
+                return Ok(Parsed { value, outcome: ParseOutcome::Repaired });
+            }
+        }
+    }
+
+    Err(format!("Failed to parse JSON response: {}. Raw response: {}", error, raw))
+}
+
+// Like `parse`, recording the outcome against the model
+pub fn parse_for_model<T: DeserializeOwned>(model: &str, raw: &str) -> Result<T, String> {
+    record_result(model, parse(raw))
+}
+
+pub fn parse_for_model_with_schema<T: DeserializeOwned>(model: &str, raw: &str, schema: &Value) -> Result<T, String> {
+    record_result(model, parse_with_schema(raw, schema))
+}
+
+fn record_result<T>(model: &str, result: Result<Parsed<T>, String>) -> Result<T, String> {
+    match result {
+        Ok(parsed) => {
+            record(model, parsed.outcome);
+            Ok(parsed.value)
+        }
+        Err(e) => {
+            record(model, ParseOutcome::Failed);
+            Err(e)
+        }
+    }
+}
+
+// Check `value` against the subset of JSON Schema sent as a response format: type, enum,
+// required, properties, items, minItems, maxItems and minLength. Errors name the failing path.
+pub fn validate_schema(value: &Value, schema: &Value) -> Result<(), String> {
+    check_schema(value, schema, "$")
+}
+
+fn check_schema(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !matches {
+            return Err(format!("{} should be of type {}", path, expected));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!("{} is not one of the allowed values", path));
+        }
+    }
+
+    if let (Some(text), Some(min)) = (value.as_str(), schema.get("minLength").and_then(Value::as_u64)) {
+        if (text.chars().count() as u64) < min {
+            return Err(format!("{} should have at least {} characters", path, min));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let required = schema.get("required").and_then(Value::as_array).into_iter().flatten();
+        for key in required.filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                return Err(format!("{} is missing \"{}\"", path, key));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, property) in properties {
+                if let Some(field) = object.get(key) {
+                    check_schema(field, property, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        let count = items.len() as u64;
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64).filter(|min| count < *min) {
+            return Err(format!("{} should have at least {} items, got {}", path, min, count));
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64).filter(|max| count > *max) {
+            return Err(format!("{} should have at most {} items, got {}", path, max, count));
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                check_schema(item, item_schema, &format!("{}[{}]", path, index))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Scan {
+    end: Option<usize>, // Byte index just past the first complete value
+    stack: Vec<char>,   // Containers still open where the text stopped
+    in_string: bool,
+    pending_escape: Option<usize>, // Start of an escape sequence the text stopped inside
+    commas: Vec<(usize, Vec<char>)>, // Each comma outside a string, with the containers open at it
+}
+
+fn scan(text: &str) -> Scan {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut pending_escape = None;
+    let mut unicode_digits = 0;
+    let mut commas = Vec::new();
+
+    for (index, c) in text.char_indices() {
+        if in_string {
+            if pending_escape.is_some() {
+                if unicode_digits > 0 {
+                    unicode_digits -= 1;
+                    if unicode_digits == 0 {
+                        pending_escape = None;
+                    }
+                } else if c == 'u' {
+                    unicode_digits = 4;
+                } else {
+                    pending_escape = None;
+                }
+                continue;
+            }
+            match c {
+                '\\' => pending_escape = Some(index),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => stack.push(c),
+            '}' | ']' => {
+                stack.pop();
+                if stack.is_empty() {
+                    return Scan { end: Some(index + 1), stack, in_string, pending_escape, commas };
+                }
+            }
+            ',' => commas.push((index, stack.clone())),
+            _ => {}
+        }
+    }
+
+    Scan { end: None, stack, in_string, pending_escape, commas }
+}
+
+// Texts to try, most complete first; `text` starts at an opening bracket
+fn repair_candidates(text: &str) -> Vec<String> {
+    let scan = scan(text);
+    if let Some(end) = scan.end {
+        // A complete value with prose or a code fence after it
+        return vec![text[..end].to_string()];
+    }
+
+    let mut candidates = Vec::new();
+    let mut stopped_at = text.to_string();
+    if scan.in_string {
+        if let Some(escape_start) = scan.pending_escape {
+            stopped_at.truncate(escape_start);
+        }
+        stopped_at.push('"');
+    }
+    candidates.push(close(&stopped_at, &scan.stack));
+
+    for (comma, stack) in scan.commas.iter().rev() {
+        candidates.push(close(&text[..*comma], stack));
+    }
+    candidates
+}
+
+fn close(text: &str, stack: &[char]) -> String {
+    let mut closed = text.trim_end().to_string();
+    if closed.ends_with(',') {
+        closed.pop();
+    } else if closed.ends_with(':') {
+        closed.push_str("null");
+    }
+    for opener in stack.iter().rev() {
+        closed.push(if *opener == '{' { '}' } else { ']' });
+    }
+    closed
+}
+
+// Per-model counts of how JSON output was handled, reset when the app restarts
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ModelParseStats {
+    pub clean: u64,
+    pub repaired: u64,
+    pub failed: u64,
+    pub continuations: u64, // "continue" follow-ups sent after output hit num_predict
+}
+
+static STATS: OnceLock<Mutex<HashMap<String, ModelParseStats>>> = OnceLock::new();
+
+fn stats_slot() -> &'static Mutex<HashMap<String, ModelParseStats>> {
+    STATS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn update_stats(model: &str, update: impl FnOnce(&mut ModelParseStats)) {
+    if let Ok(mut stats) = stats_slot().lock() {
+        update(stats.entry(model.to_string()).or_default());
+    }
+}
+
+pub fn record(model: &str, outcome: ParseOutcome) {
+    update_stats(model, |stats| match outcome {
+        ParseOutcome::Clean => stats.clean += 1,
+        ParseOutcome::Repaired => stats.repaired += 1,
+        ParseOutcome::Failed => stats.failed += 1,
+    });
+}
+
+pub fn record_continuation(model: &str) {
+    update_stats(model, |stats| stats.continuations += 1);
+}
+
+pub fn stats() -> HashMap<String, ModelParseStats> {
+    stats_slot().lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Reply {
+        conversation_response: String,
+        #[serde(default)]
+        code_to_insert: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Question {
+        question_text: String,
+        options: Vec<String>,
+        correct_answer: String,
+    }
+
+    fn reply(raw: &str) -> Parsed<Reply> {
+        parse::<Reply>(raw).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn valid_json_is_clean() {
+        let parsed = reply(r#"{"conversation_response": "Hi", "code_to_insert": "x = 1"}"#);
+        assert_eq!(parsed.outcome, ParseOutcome::Clean);
+        assert_eq!(parsed.value.code_to_insert, "x = 1");
+    }
+
+    #[test]
+    fn strips_code_fences_and_prose() {
+        let raw = "Sure! Here you go:\n```json\n{\"conversation_response\": \"Hi {there}\"}\n```\nHope that helps.";
+        let parsed = reply(raw);
+        assert_eq!(parsed.outcome, ParseOutcome::Repaired);
+        assert_eq!(parsed.value.conversation_response, "Hi {there}");
+    }
+
+    #[test]
+    fn skips_brackets_mentioned_in_prose() {
+        let parsed = reply(r#"Use a list [like this] then: {"conversation_response": "ok"}"#);
+        assert_eq!(parsed.value.conversation_response, "ok");
+    }
+
+    #[test]
+    fn closes_string_cut_mid_value() {
+        let parsed = reply(r#"{"conversation_response": "Loops repeat code until"#);
+        assert_eq!(parsed.outcome, ParseOutcome::Repaired);
+        assert_eq!(parsed.value.conversation_response, "Loops repeat code until");
+    }
+
+    #[test]
+    fn drops_dangling_key() {
+        let parsed = reply(r#"{"conversation_response": "Done", "code_to_ins"#);
+        assert_eq!(parsed.value.conversation_response, "Done");
+        assert_eq!(parsed.value.code_to_insert, "");
+    }
+
+    #[test]
+    fn handles_cut_after_colon_and_comma() {
+        assert_eq!(reply(r#"{"conversation_response": "A", "code_to_insert":"#).value.code_to_insert, "");
+        assert_eq!(reply(r#"{"conversation_response": "A","#).value.conversation_response, "A");
+    }
+
+    #[test]
+    fn drops_incomplete_escape_sequence() {
+        assert_eq!(reply(r#"{"conversation_response": "say \"hi\" \"#).value.conversation_response, "say \"hi\" ");
+        assert_eq!(reply(r#"{"conversation_response": "caf\u00"#).value.conversation_response, "caf");
+    }
+
+    #[test]
+    fn keeps_complete_items_of_truncated_array() {
+        let raw = r#"[
+            {"question_text": "Q1", "options": ["a", "b"], "correct_answer": "a"},
+            {"question_text": "Q2", "options": ["c", "d"], "correct_answer": "d"},
+            {"question_text": "Q3", "options": ["e", "#;
+        let parsed = parse::<Vec<Question>>(raw).unwrap();
+        assert_eq!(parsed.outcome, ParseOutcome::Repaired);
+        assert_eq!(parsed.value.len(), 2);
+        assert_eq!(parsed.value[1].correct_answer, "d");
+    }
+
+    #[test]
+    fn ignores_brackets_inside_strings() {
+        let raw = r#"{"conversation_response": "Use ] and } freely", "code_to_insert": "print([1, {2: 3}])"#;
+        let parsed = reply(raw);
+        assert_eq!(parsed.value.conversation_response, "Use ] and } freely");
+        assert_eq!(parsed.value.code_to_insert, "print([1, {2: 3}])");
+    }
+
+    #[test]
+    fn rejects_output_without_json() {
+        let error = parse::<Reply>("I can't help with that.").err().unwrap();
+        assert!(error.contains("Raw response: I can't help with that."));
+    }
+
+    fn question_schema() -> Value {
+        serde_json::json!({
+            "type": "array",
+            "minItems": 2,
+            "items": {
+                "type": "object",
+                "properties": {
+                    "question_text": { "type": "string", "minLength": 1 },
+                    "options": { "type": "array", "items": { "type": "string" } },
+                    "correct_answer": { "type": "string" }
+                },
+                "required": ["question_text", "options", "correct_answer"]
+            }
+        })
+    }
+
+    #[test]
+    fn schema_errors_name_the_path() {
+        let schema = question_schema();
+        let missing = serde_json::json!([{"question_text": "Q", "options": []}, {"question_text": "Q", "options": [], "correct_answer": "a"}]);
+        assert_eq!(validate_schema(&missing, &schema).unwrap_err(), "$[0] is missing \"correct_answer\"");
+
+        let wrong_type = serde_json::json!([{"question_text": "Q", "options": [1], "correct_answer": "a"}, {"question_text": "Q", "options": [], "correct_answer": "a"}]);
+        assert_eq!(validate_schema(&wrong_type, &schema).unwrap_err(), "$[0].options[0] should be of type string");
+
+        let too_few = serde_json::json!([{"question_text": "Q", "options": [], "correct_answer": "a"}]);
+        assert_eq!(validate_schema(&too_few, &schema).unwrap_err(), "$ should have at least 2 items, got 1");
+    }
+
+    #[test]
+    fn schema_parse_rejects_json_of_the_wrong_shape() {
+        let error = parse_with_schema::<Vec<Question>>(r#"[{"question_text": "", "options": [], "correct_answer": "a"}]"#, &question_schema()).err().unwrap();
+        assert!(error.contains("$ should have at least 2 items"));
+    }
+
+    #[test]
+    fn schema_parse_cuts_truncated_array_back_to_valid_items() {
+        let raw = r#"[
+            {"question_text": "Q1", "options": ["a", "b"], "correct_answer": "a"},
+            {"question_text": "Q2", "options": ["c", "d"], "correct_answer": "d"},
+            {"question_text": "Q3", "options": ["e", "#;
+        let parsed = parse_with_schema::<Vec<Question>>(raw, &question_schema()).unwrap();
+        assert_eq!(parsed.outcome, ParseOutcome::Repaired);
+        assert_eq!(parsed.value.len(), 2);
+    }
+
+    #[test]
+    fn records_outcomes_per_model() {
+        let _ = parse_for_model::<Reply>("test-model-a", r#"{"conversation_response": "x"}"#);
+        let _ = parse_for_model::<Reply>("test-model-a", r#"{"conversation_response": "x"#);
+        let _ = parse_for_model::<Reply>("test-model-a", "nope");
+        record_continuation("test-model-a");
+
+        let stats = stats().remove("test-model-a").unwrap();
+        assert_eq!((stats.clean, stats.repaired, stats.failed, stats.continuations), (1, 1, 1, 1));
+    }
+}
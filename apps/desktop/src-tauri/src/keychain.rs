@@ -0,0 +1,53 @@
+use crate::llm::ModelBackend;
+use crate::provider::{ProviderKind, OPENAI_DEFAULT_BASE_URL};
+
+// API keys for remote model servers live in the OS keychain (Keychain on macOS, Credential
+// Manager on Windows, the Secret Service on Linux), never in the settings table or a command
+// response. There's one key per server, so backends for several models on it share it.
+
+const SERVICE: &str = "project-r";
+
+fn account(backend: &ModelBackend) -> String {
+    match backend.provider {
+        ProviderKind::Ollama => format!("ollama:{}", backend.base_url.as_deref().unwrap_or("local")),
+        ProviderKind::OpenAi => format!("openai:{}", backend.base_url.as_deref().unwrap_or(OPENAI_DEFAULT_BASE_URL)),
+        ProviderKind::Embedded => format!("embedded:{}", backend.model),
+    }
+}
+
+fn entry(backend: &ModelBackend) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(SERVICE, &account(backend)).map_err(|e| format!("Failed to open the keychain: {}", e))
+}
+
+// The stored key for the backend's server; None when there is none or the keychain is unavailable
+pub fn api_key(backend: &ModelBackend) -> Option<String> {
+    entry(backend).ok()?.get_password().ok().filter(|key| !key.is_empty())
+}
+
+// Store each key the settings UI sent, an empty one removing the stored key. Backends sent
+// without one keep theirs.
+pub fn save_api_keys(chain: &[ModelBackend]) -> Result<(), String> {
+    for backend in chain {
+        let Some(key) = &backend.api_key else { continue };
+        let entry = entry(backend)?;
+        let key = key.trim();
+        let saved = if key.is_empty() {
+            match entry.delete_credential() {
+                Err(keyring::Error::NoEntry) => Ok(()),
+                result => result,
+            }
+        } else {
+            entry.set_password(key)
+        };
+        saved.map_err(|e| format!("Failed to save the API key for {}: {}", backend.label(), e))?;
+    }
+    Ok(())
+}
+
+// Mark which backends have a stored key, for a chain about to be sent to the UI. Only
+// OpenAI-compatible servers are sent one.
+pub fn mark_api_keys(chain: &mut [ModelBackend]) {
+    for backend in chain {
+        backend.has_api_key = backend.provider == ProviderKind::OpenAi && api_key(backend).is_some();
+    }
+}
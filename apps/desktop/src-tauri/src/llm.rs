@@ -1,327 +1,1196 @@
-use reqwest;
+use crate::ollama::{CallKind, OllamaHttp};
+use crate::prompts::{self, PromptKind};
+use crate::provider::{complete_with_continuation, stream_with_continuation, CompletionRequest, LlmProvider, Provider, ProviderKind, ResponseFormat};
 use serde::{Deserialize, Serialize};
 use serde_json;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SessionLLMRequest {
-    pub model: String,
-    pub prompt: String,
-    pub stream: bool,
-    pub format: String, // "json" for structured responses
-    pub options: RequestOptions,
+// One message of a chat conversation: "system", "user" or "assistant". Tutor turns send earlier
+// messages as real history; one-off prompts are a single user message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>, // Base64 PNG, JPEG, GIF or WebP images for vision models, as Ollama takes them
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self { role: role.to_string(), content: content.into(), images: Vec::new() }
+    }
+}
+
+// Decoded size allowed per attached image; a screenshot or plot is well under it
+const MAX_IMAGE_BYTES: usize = 8 * 1024 * 1024;
+
+// Images attached to one question
+pub const MAX_IMAGES: usize = 4;
+
+// Check an attached image (base64, optionally as a data URL) and return the bare base64 that
+// goes in a message's images
+pub fn image_attachment(data: &str) -> Result<String, String> {
+    use base64::Engine;
+
+    let data = data.trim();
+    let encoded = match data.strip_prefix("data:") {
+        Some(url) => url.split_once(";base64,").map(|(_, encoded)| encoded).ok_or("Image data URLs must be base64")?,
+        None => data,
+    };
+    let encoded: String = encoded.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let bytes = base64::engine::general_purpose::STANDARD.decode(&encoded)
+        .map_err(|e| format!("Attached image isn't valid base64: {}", e))?;
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(format!("Attached image is {} MB; images can be at most {} MB", bytes.len() / (1024 * 1024), MAX_IMAGE_BYTES / (1024 * 1024)));
+    }
+    if image_mime_type(&encoded).is_none() {
+        return Err("Attached images must be PNG, JPEG, GIF or WebP".to_string());
+    }
+    Ok(encoded)
+}
+
+// MIME type of a base64 image from its first bytes
+pub fn image_mime_type(encoded: &str) -> Option<&'static str> {
+    [("iVBORw0KGgo", "image/png"), ("/9j/", "image/jpeg"), ("R0lGOD", "image/gif"), ("UklGR", "image/webp")]
+        .into_iter()
+        .find(|(prefix, _)| encoded.starts_with(prefix))
+        .map(|(_, mime)| mime)
+}
+
+// Seed and determinism settings for reproducible generations (quizzes, summaries)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReproducibilitySettings {
+    pub seed: Option<i64>,    // Fixed seed; a fresh random seed is used per generation when unset
+    pub deterministic: bool,  // Greedy sampling with a stable seed
+}
+
+impl ReproducibilitySettings {
+    const DETERMINISTIC_DEFAULT_SEED: i64 = 42;
+
+    // The seed to send to Ollama and record alongside the generated artifact
+    pub fn resolve_seed(&self) -> i64 {
+        match (self.seed, self.deterministic) {
+            (Some(seed), _) => seed,
+            (None, true) => Self::DETERMINISTIC_DEFAULT_SEED,
+            (None, false) => (uuid::Uuid::new_v4().as_u128() as u32) as i64,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RequestOptions {
-    pub num_predict: i32,    // Maximum tokens to generate
-    pub temperature: f32,    // Randomness (0.0 to 1.0)
-    pub top_p: f32,         // Nucleus sampling
+// Sampling overrides for a generation; unset fields keep each feature's own defaults. The
+// "generation_options" setting holds the user's defaults and command parameters override them.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub num_predict: Option<i32>, // Most tokens to generate
+    pub seed: Option<i64>,
+}
+
+impl GenerationOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.temperature.is_some_and(|temperature| !(0.0..=2.0).contains(&temperature)) {
+            return Err("Temperature must be between 0 and 2".to_string());
+        }
+        if self.top_p.is_some_and(|top_p| top_p <= 0.0 || top_p > 1.0) {
+            return Err("top_p must be above 0 and at most 1".to_string());
+        }
+        if self.num_predict.is_some_and(|num_predict| !(1..=8192).contains(&num_predict)) {
+            return Err("num_predict must be between 1 and 8192".to_string());
+        }
+        Ok(())
+    }
+
+    // These options, with unset fields taken from `defaults`
+    pub fn or(self, defaults: GenerationOptions) -> Self {
+        Self {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            num_predict: self.num_predict.or(defaults.num_predict),
+            seed: self.seed.or(defaults.seed),
+        }
+    }
+
+    // Deterministic mode keeps greedy sampling whatever the overrides say
+    pub fn without_sampling(self) -> Self {
+        Self { temperature: None, top_p: None, ..self }
+    }
+
+    pub fn apply<'a>(&self, request: CompletionRequest<'a>) -> CompletionRequest<'a> {
+        CompletionRequest {
+            temperature: self.temperature.unwrap_or(request.temperature),
+            top_p: self.top_p.unwrap_or(request.top_p),
+            max_tokens: self.num_predict.unwrap_or(request.max_tokens),
+            seed: self.seed.or(request.seed),
+            ..request
+        }
+    }
+}
+
+// 1-based, inclusive line range selected in the editor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelectionRange {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+// Token budget for tutor responses
+const SESSION_NUM_PREDICT: i32 = 2000;
+
+// How much of the conversation goes along with each tutor request
+const HISTORY_MAX_MESSAGES: usize = 12;
+const HISTORY_MAX_TOKENS: usize = 1500;
+
+// The session's latest messages that fit the history budget, oldest first. Assistant messages
+// are stored as plain text, so they're re-wrapped in the reply format to keep the model on JSON.
+pub fn chat_history(messages: &[crate::database::Message]) -> Vec<ChatMessage> {
+    let mut history = Vec::new();
+    let mut tokens = 0;
+    for message in messages.iter().rev().take(HISTORY_MAX_MESSAGES) {
+        let content = if message.role == "assistant" {
+            serde_json::json!({ "conversation_response": message.content, "code_to_insert": "", "edits": [] }).to_string()
+        } else {
+            message.content.clone()
+        };
+        tokens += estimate_tokens(&content);
+        if tokens > HISTORY_MAX_TOKENS {
+            break;
+        }
+        history.push(ChatMessage::new(&message.role, content));
+    }
+    history.reverse();
+    history
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SessionLLMResponse {
+// The tutor prompt exactly as it would be sent, for the prompt preview
+#[derive(Debug, Serialize)]
+pub struct PromptPreview {
     pub model: String,
-    pub created_at: String,
-    pub response: String,
-    pub done: bool,
+    pub prompt: String, // The messages rendered one after another, for reading
+    pub messages: Vec<ChatMessage>,
+    pub estimated_tokens: usize,
+    pub sections: Vec<PromptSection>, // What the prompt was assembled from, largest first
+    pub max_response_tokens: i32,
+    pub context_tokens: usize, // The model's context window the prompt was fitted to
+    pub trimmed: PromptTrim,
+}
+
+// What was left out of a prompt so it and the reply fit the model's context window
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PromptTrim {
+    pub dropped_history: usize,      // Oldest conversation messages
+    pub dropped_memory_lines: usize, // Memory entries, the least relevant or the oldest
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptSection {
+    pub name: &'static str, // "system_template", "memory", "session_context", "history", "code" or "user_input"
+    pub estimated_tokens: usize,
+}
+
+// Rough token count without the model's tokenizer: about 4 characters per token for
+// English, but code and short words run closer to one token per word piece
+pub fn estimate_tokens(text: &str) -> usize {
+    let by_chars = text.chars().count().div_ceil(4);
+    let by_words = (text.split_whitespace().count() * 4).div_ceil(3);
+    by_chars.max(by_words)
+}
+
+// The end of `text` that fits in `max_tokens`, cut at a line break, with a marker when anything
+// was left out
+pub fn keep_latest_tokens(text: &str, max_tokens: usize) -> String {
+    if estimate_tokens(text) <= max_tokens {
+        return text.to_string();
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let mut tokens = 0;
+    let mut start = lines.len();
+    while start > 0 {
+        tokens += estimate_tokens(lines[start - 1]) + 1;
+        if tokens > max_tokens {
+            break;
+        }
+        start -= 1;
+    }
+    if start < lines.len() {
+        return format!("(earlier part left out)\n{}", lines[start..].join("\n"));
+    }
+
+    // Even the last line doesn't fit, so keep as much of its end as does
+    let chars: Vec<char> = lines.last().copied().unwrap_or_default().chars().collect();
+    let tail = |count: usize| chars[chars.len() - count..].iter().collect::<String>();
+    let (mut low, mut high) = (0, chars.len());
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if estimate_tokens(&tail(middle)) < max_tokens {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    format!("(earlier part left out)\n{}", tail(low))
+}
+
+// How the lines of the memory block are ordered, which decides the ones left out first when the
+// tutor prompt doesn't fit
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MemoryOrder {
+    #[default]
+    Chronological, // The whole memory, in sections whose entries run oldest first
+    Ranked,        // Retrieved chunks, most relevant first
+}
+
+// How the tutor answers, stored as the "pedagogy_mode" setting. In hint mode it guides the student
+// with questions and scaffolded hints and never writes the solution for them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PedagogyMode {
+    #[default]
+    Direct,
+    Hints,
+}
+
+// Highest hint level: an outline of the solution with the key parts left blank
+pub const MAX_HINT_LEVEL: u8 = 3;
+
+// Sent as the response format of tutor requests and checked again on our side, since
+// servers without schema support only get the prompt's description
+pub fn session_response_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "conversation_response": { "type": "string", "minLength": 1 },
+            "code_to_insert": { "type": "string" },
+            "hint_level": { "type": "integer", "minimum": 1, "maximum": 3 },
+            "edits": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "start_line": { "type": "integer" },
+                        "end_line": { "type": "integer" },
+                        "replacement": { "type": "string" }
+                    },
+                    "required": ["start_line", "end_line", "replacement"]
+                }
+            }
+        },
+        "required": ["conversation_response", "code_to_insert"]
+    })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Editor contents with "  12 | " line numbers, for prompts whose answers refer to lines
+fn numbered_code(code: &str) -> String {
+    code.lines()
+        .enumerate()
+        .map(|(index, line)| format!("{:>4} | {}", index + 1, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SessionResponse {
     pub conversation_response: String,
+    #[serde(default)]
     pub code_to_insert: String,
+    #[serde(default)]
+    pub edits: Vec<crate::code_diff::LineEdit>, // Line-range changes to the editor code; once applied, code_to_insert holds the edited file
+    #[serde(default)]
+    pub hint_level: Option<u8>, // In hint mode, 1 (a guiding question) to 3 (an outline with blanks); None otherwise
+    #[serde(default)]
+    pub metadata: ResponseMetadata, // Filled in by the backend, not the LLM
+    #[serde(default)]
+    pub dry_run: Option<crate::interactive_python::DryRunResult>, // Result of test-running code_to_insert
+    #[serde(default)]
+    pub self_check: Option<SelfCheckResult>, // Verification pass over the explanation and code
+}
+
+// What explain_python_error returns: the model's explanation and fix, plus the fix as a diff
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ErrorExplanation {
+    pub summary: String, // What went wrong, in one or two sentences
+    pub cause: String,   // Why, pointing at the code
+    #[serde(default)]
+    pub line: usize,     // Line of the learner's code the error comes from; 0 when unclear
+    #[serde(default)]
+    pub fixed_code: String, // The whole file with the fix applied
+    #[serde(default)]
+    pub fix_diff: String,   // Filled in by the backend from fixed_code
+    #[serde(default)]
+    pub metadata: ResponseMetadata,
+}
+
+pub fn error_explanation_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "summary": { "type": "string", "minLength": 1 },
+            "cause": { "type": "string" },
+            "line": { "type": "integer" },
+            "fixed_code": { "type": "string" }
+        },
+        "required": ["summary", "cause", "line", "fixed_code"]
+    })
 }
 
+// Traceback lines sent with an error explanation; the end of a traceback is what matters
+const MAX_TRACEBACK_LINES: usize = 40;
+
+// Outcome of the optional verification pass; a failed check flags the response with a caution
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SelfCheckResult {
+    pub passed: bool,
+    pub issues: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SelfCheckVerdict {
+    #[serde(default = "default_accurate")]
+    accurate: bool,
+    #[serde(default)]
+    issues: Vec<String>,
+}
+
+fn default_accurate() -> bool {
+    true
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ResponseMetadata {
+    pub model: String,
+    pub latency_ms: u64,
+    pub used_fallback: bool, // The primary model blew the latency budget for this turn
+    #[serde(default)]
+    pub served_by: String, // Fallback chain entry that produced the response
+    #[serde(default)]
+    pub failed_backends: Vec<String>, // Earlier chain entries that errored, with their errors
+    #[serde(default)]
+    pub utterance_ids: Vec<String>, // Sentences queued for TTS while the answer streamed, in order
+}
+
+// Chat model used until the learner picks another
+pub const DEFAULT_CHAT_MODEL: &str = "gemma3n";
+
+// One entry in the model fallback chain. An Ollama backend with no base_url is the local
+// Ollama; an "openai" backend without one goes to the OpenAI API. An "embedded" backend's
+// model is a GGUF file name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelBackend {
+    pub model: String,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    // Only ever sent by the settings UI; it's moved to the OS keychain and never written back
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+    #[serde(default, skip_deserializing)]
+    pub has_api_key: bool, // Whether the keychain holds a key for this backend's server
+}
+
+impl ModelBackend {
+    pub fn local(model: &str) -> Self {
+        Self { model: model.to_string(), base_url: None, provider: ProviderKind::Ollama, api_key: None, has_api_key: false }
+    }
+
+    // Served by something other than the local Ollama, so it can't be probed or listed
+    pub fn is_remote(&self) -> bool {
+        self.base_url.is_some() || self.provider != ProviderKind::Ollama
+    }
+
+    pub fn label(&self) -> String {
+        match (&self.base_url, self.provider) {
+            (Some(base_url), _) => format!("{} @ {}", self.model, base_url),
+            (None, ProviderKind::OpenAi) => format!("{} @ {}", self.model, crate::provider::OPENAI_DEFAULT_BASE_URL),
+            (None, ProviderKind::Embedded) => format!("{} (embedded)", self.model),
+            (None, ProviderKind::Ollama) => self.model.clone(),
+        }
+    }
+}
+
+// Features that can be given their own fallback chain instead of the shared one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Feature {
+    Tutor,   // Tutor answers, questions about a selection and project plans
+    Summary, // Session summaries
+    Quiz,    // Practice sheets and their redos
+}
+
+impl Feature {
+    pub fn setting_key(self) -> &'static str {
+        match self {
+            Feature::Tutor => "model_chain_tutor",
+            Feature::Summary => "model_chain_summary",
+            Feature::Quiz => "model_chain_quiz",
+        }
+    }
+}
+
+// Ollama reports tags ("gemma3n:latest"), while settings usually name the bare model
+pub fn matches_model_name(installed: &str, model: &str) -> bool {
+    installed == model || installed.strip_prefix(model).map(|rest| rest.starts_with(':')).unwrap_or(false)
+}
+
+// "name", "name:tag" or "namespace/name:tag", as Ollama's registry accepts them
+pub fn validate_model_tag(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Model name can't be empty".to_string());
+    }
+    let (model, tag) = match name.split_once(':') {
+        Some((model, tag)) => (model, Some(tag)),
+        None => (name, None),
+    };
+    let valid_part = |part: &str| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    };
+    if !model.split('/').all(valid_part) || !tag.map_or(true, valid_part) {
+        return Err(format!("{} isn't a valid model name; use a name like llama3.2 or qwen2.5-coder:7b", name));
+    }
+    Ok(())
+}
+
+pub const GENERATION_CANCELLED: &str = "Generation cancelled";
+
+// Generations the user can stop, keyed by a request id the frontend picks. Stopping drops the
+// generation's future, which closes the HTTP request and makes Ollama stop generating.
+#[derive(Default)]
+pub struct GenerationRegistry {
+    running: std::sync::Mutex<std::collections::HashMap<String, tokio::sync::oneshot::Sender<()>>>,
+}
+
+impl GenerationRegistry {
+    // Run `generation` to completion unless cancel(request_id) is called first; without an id it
+    // can't be cancelled
+    pub async fn run<T>(
+        &self,
+        request_id: Option<&str>,
+        generation: impl std::future::Future<Output = Result<T, String>>,
+    ) -> Result<T, String> {
+        let Some(request_id) = request_id else {
+            return generation.await;
+        };
+        let (stop, stopped) = tokio::sync::oneshot::channel();
+        self.running.lock().map_err(|e| e.to_string())?.insert(request_id.to_string(), stop);
+
+        let result = tokio::select! {
+            result = generation => result,
+            _ = stopped => Err(GENERATION_CANCELLED.to_string()),
+        };
+        if let Ok(mut running) = self.running.lock() {
+            running.remove(request_id);
+        }
+        result
+    }
+
+    // Ids of the generations still running, sorted
+    pub fn running_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.running.lock()
+            .map(|running| running.keys().cloned().collect())
+            .unwrap_or_default();
+        ids.sort();
+        ids
+    }
+
+    // Returns whether a generation with this id was running
+    pub fn cancel(&self, request_id: &str) -> bool {
+        let stop = self.running.lock().ok().and_then(|mut running| running.remove(request_id));
+        match stop {
+            Some(stop) => stop.send(()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+pub struct ChainOutcome<T> {
+    pub value: T,
+    pub served_by: ModelBackend,
+    pub failures: Vec<String>,
+}
+
+// Try each backend in order until one succeeds
+pub async fn walk_model_chain<T, F, Fut>(chain: &[ModelBackend], mut call: F) -> Result<ChainOutcome<T>, String>
+where
+    F: FnMut(ModelBackend) -> Fut,
+    Fut: std::future::Future<Output = Result<T, String>>,
+{
+    let mut failures = Vec::new();
+    for backend in chain {
+        match call(backend.clone()).await {
+            Ok(value) => {
+                return Ok(ChainOutcome { value, served_by: backend.clone(), failures });
+            }
+            Err(e) => failures.push(format!("{}: {}", backend.label(), e)),
+        }
+    }
+    Err(format!("All models in the fallback chain failed. {}", failures.join("; ")))
+}
+
+// Model entry from Ollama's /api/tags, flattened for the settings UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    pub size: u64,             // Bytes on disk
+    pub modified_at: String,
+    pub digest: String,
+    pub family: String,
+    pub parameter_size: String,     // e.g. "7B"
+    pub quantization_level: String, // e.g. "Q4_K_M"
+}
+
+// An installed model for the model management page
+#[derive(Debug, Clone, Serialize)]
+pub struct InstalledModel {
+    #[serde(flatten)]
+    pub info: ModelInfo,
+    pub in_use: bool, // Named by a model chain, the latency fallback or retrieval; can't be deleted
+}
+
+// One line of a streamed /api/pull, emitted as "model-pull-progress"
+#[derive(Debug, Clone, Serialize)]
+pub struct PullProgress {
+    pub model: String,
+    pub status: String, // e.g. "pulling manifest", "pulling <digest>", "verifying sha256 digest", "success"
+    pub completed: Option<u64>, // Bytes of the current layer
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullLine {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    completed: Option<u64>,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+// Model management talks to Ollama directly; generation goes through the backend's provider
 pub struct OllamaClient {
-    base_url: String,
-    client: reqwest::Client,
+    http: OllamaHttp,
+    provider: Provider,
+    options: GenerationOptions, // Applied to tutor, selection, error and plan requests
+    pedagogy: PedagogyMode,     // Applied to tutor answers
+    memory_order: MemoryOrder,  // Of the memory block given to tutor answers
+    text_stream: Option<tokio::sync::mpsc::UnboundedSender<String>>, // Gets tutor answers' raw text as it's generated
 }
 
 impl OllamaClient {
     pub fn new(base_url: Option<String>) -> Self {
+        let http = OllamaHttp::new(base_url);
         Self {
-            base_url: base_url.unwrap_or_else(|| "http://localhost:11434".to_string()),
-            client: reqwest::Client::new(),
+            provider: Provider::Ollama(http.clone()),
+            http,
+            options: GenerationOptions::default(),
+            pedagogy: PedagogyMode::default(),
+            memory_order: MemoryOrder::default(),
+            text_stream: None,
         }
     }
 
-    pub async fn check_connection(&self) -> Result<(), String> {
-        let url = format!("{}/api/tags", self.base_url);
-        
-        let response = self.client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to connect to Ollama: {}. Make sure Ollama is running.", e))?;
-
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            Err(format!("Ollama returned status: {}", response.status()))
+    pub fn for_backend(backend: &ModelBackend) -> Self {
+        Self {
+            http: OllamaHttp::new(backend.base_url.clone()),
+            provider: Provider::for_backend(backend),
+            options: GenerationOptions::default(),
+            pedagogy: PedagogyMode::default(),
+            memory_order: MemoryOrder::default(),
+            text_stream: None,
         }
     }
 
-    pub async fn ensure_model(&self, model_name: &str) -> Result<(), String> {
-        // Check if model exists by listing models
-        let url = format!("{}/api/tags", self.base_url);
-        
-        let response = self.client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to check models: {}", e))?;
-
-        if !response.status().is_success() {
-            return Err(format!("Failed to list models: {}", response.status()));
-        }
+    pub fn with_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-        let models_response: serde_json::Value = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse models response: {}", e))?;
-
-        // Check if our model is in the list
-        if let Some(models) = models_response.get("models").and_then(|m| m.as_array()) {
-            let model_exists = models.iter().any(|model| {
-                model.get("name")
-                    .and_then(|name| name.as_str())
-                    .map(|name| name.contains(model_name))
-                    .unwrap_or(false)
-            });
-
-            if model_exists {
-                println!("Model {} is already available", model_name);
-                return Ok(());
-            }
+    pub fn with_pedagogy(mut self, pedagogy: PedagogyMode) -> Self {
+        self.pedagogy = pedagogy;
+        self
+    }
+
+    pub fn with_memory_order(mut self, memory_order: MemoryOrder) -> Self {
+        self.memory_order = memory_order;
+        self
+    }
+
+    // Stream tutor answers, sending each piece of the reply (the JSON, not just the explanation)
+    // as it arrives, e.g. so speech can start before the answer is finished
+    pub fn with_text_stream(mut self, text_stream: Option<tokio::sync::mpsc::UnboundedSender<String>>) -> Self {
+        self.text_stream = text_stream;
+        self
+    }
+
+    // Tokens kept free for the tutor's reply when fitting the prompt
+    fn session_response_tokens(&self) -> i32 {
+        self.options.num_predict.unwrap_or(SESSION_NUM_PREDICT)
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.http.base_url()
+    }
+
+    pub async fn check_connection(&self) -> Result<(), String> {
+        self.http.get_json::<serde_json::Value>("/api/tags", CallKind::Quick).await?;
+        Ok(())
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, String> {
+        let models_response: serde_json::Value = self.http.get_json("/api/tags", CallKind::Quick).await
+            .map_err(|e| format!("Failed to list models: {}", e))?;
+
+        let text = |value: &serde_json::Value, key: &str| {
+            value.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string()
+        };
+
+        let models = models_response.get("models")
+            .and_then(|m| m.as_array())
+            .map(|models| {
+                models.iter().map(|model| {
+                    let details = model.get("details").cloned().unwrap_or_default();
+                    ModelInfo {
+                        name: text(model, "name"),
+                        size: model.get("size").and_then(|v| v.as_u64()).unwrap_or(0),
+                        modified_at: text(model, "modified_at"),
+                        digest: text(model, "digest"),
+                        family: text(&details, "family"),
+                        parameter_size: text(&details, "parameter_size"),
+                        quantization_level: text(&details, "quantization_level"),
+                    }
+                }).collect()
+            })
+            .unwrap_or_default();
+
+        Ok(models)
+    }
+
+    pub async fn ensure_model(&self, model_name: &str, on_progress: impl FnMut(PullProgress)) -> Result<(), String> {
+        // Check if model exists by listing models
+        let models = self.list_models().await?;
+        if models.iter().any(|model| model.name.contains(model_name)) {
+            println!("Model {} is already available", model_name);
+            return Ok(());
         }
 
         // Model doesn't exist, try to pull it
         println!("Model {} not found. Attempting to pull...", model_name);
-        self.pull_model(model_name).await
+        self.pull_model(model_name, on_progress).await
     }
 
-    pub async fn pull_model(&self, model_name: &str) -> Result<(), String> {
-        let url = format!("{}/api/pull", self.base_url);
-        
+    // Pull with Ollama's streamed progress. Reported when the status changes or a layer
+    // advances by a percent; dropping the future stops the pull, and Ollama resumes it next time.
+    pub async fn pull_model(&self, model_name: &str, mut on_progress: impl FnMut(PullProgress)) -> Result<(), String> {
         let request_body = serde_json::json!({
-            "name": model_name
+            "name": model_name,
+            "stream": true,
         });
 
-        let response = self.client
-            .post(&url)
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to pull model: {}", e))?;
+        let mut response = self.http.post_stream("/api/pull", &request_body, CallKind::Download).await
+            .map_err(|e| format!("Failed to pull model {}: {}", model_name, e))?;
 
-        if response.status().is_success() {
-            println!("Successfully pulled model: {}", model_name);
-            Ok(())
-        } else {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            Err(format!("Failed to pull model {}: {}", model_name, error_text))
+        let mut buffer = Vec::new();
+        let mut last: Option<(String, u64)> = None;
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("Pull of {} was interrupted: {}", model_name, e))? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let Ok(line) = serde_json::from_slice::<PullLine>(&line) else { continue };
+                if let Some(error) = line.error {
+                    return Err(format!("Failed to pull model {}: {}", model_name, error));
+                }
+
+                let percent = match (line.completed, line.total) {
+                    (Some(completed), Some(total)) if total > 0 => completed * 100 / total,
+                    _ => 0,
+                };
+                if last.as_ref() == Some(&(line.status.clone(), percent)) {
+                    continue;
+                }
+                last = Some((line.status.clone(), percent));
+                on_progress(PullProgress {
+                    model: model_name.to_string(),
+                    status: line.status,
+                    completed: line.completed,
+                    total: line.total,
+                });
+            }
+        }
+
+        if last.as_ref().map(|(status, _)| status.as_str()) != Some("success") {
+            return Err(format!("Pull of {} ended before it finished", model_name));
         }
+        println!("Successfully pulled model: {}", model_name);
+        Ok(())
     }
 
+    pub async fn delete_model(&self, model_name: &str) -> Result<(), String> {
+        let request_body = serde_json::json!({
+            "name": model_name
+        });
+
+        self.http.send_json(reqwest::Method::DELETE, "/api/delete", &request_body, CallKind::Quick).await
+            .map_err(|e| format!("Failed to delete model {}: {}", model_name, e))?;
+        Ok(())
+    }
+
+    // `history` is the conversation so far, from chat_history; `images` (from image_attachment)
+    // go with the question, for a plot or a screenshot of an error
+    #[allow(clippy::too_many_arguments)]
     pub async fn generate_session_response(
         &self,
         user_input: &str,
         current_code: &str,
+        memory_context: &str,
+        session_context: &str,
+        history: &[ChatMessage],
+        images: &[String],
         model_name: &str,
     ) -> Result<SessionResponse, String> {
-        let prompt = self.create_session_prompt(user_input, current_code);
-        
-        let request = SessionLLMRequest {
+        if !images.is_empty() && self.provider.supports_images(model_name).await == Some(false) {
+            return Err(format!("{} can't read images; pick a vision model such as llava or gemma3", model_name));
+        }
+        let context_tokens = self.provider.context_window(model_name).await;
+        let (mut messages, _) = self.create_session_messages(user_input, current_code, memory_context, session_context, history, context_tokens);
+        if let Some(turn) = messages.last_mut() {
+            turn.images = images.to_vec();
+        }
+        let mut response = self.request_session_response(messages, model_name, Some(context_tokens)).await?;
+        match self.pedagogy {
+            PedagogyMode::Direct => apply_response_edits(&mut response, current_code),
+            PedagogyMode::Hints => enforce_hint_mode(&mut response),
+        }
+        Ok(response)
+    }
+
+    // Assemble the tutor messages without sending them, with a token estimate per part
+    pub async fn preview_session_prompt(
+        &self,
+        user_input: &str,
+        current_code: &str,
+        memory_context: &str,
+        session_context: &str,
+        history: &[ChatMessage],
+        model_name: &str,
+    ) -> PromptPreview {
+        let context_tokens = self.provider.context_window(model_name).await;
+        let (messages, trimmed) = self.create_session_messages(user_input, current_code, memory_context, session_context, history, context_tokens);
+        let memory_context = kept_memory(memory_context, trimmed.dropped_memory_lines, self.memory_order);
+        let history = &history[trimmed.dropped_history..];
+        let prompt = messages.iter()
+            .map(|message| format!("[{}]\n{}", message.role, message.content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let estimated_tokens = estimate_tokens(&prompt);
+
+        let mut sections = vec![
+            PromptSection { name: "memory", estimated_tokens: estimate_tokens(&memory_context) },
+            PromptSection { name: "session_context", estimated_tokens: estimate_tokens(session_context) },
+            PromptSection { name: "history", estimated_tokens: history.iter().map(|message| estimate_tokens(&message.content)).sum() },
+            PromptSection { name: "code", estimated_tokens: estimate_tokens(current_code) },
+            PromptSection { name: "user_input", estimated_tokens: estimate_tokens(user_input) },
+        ];
+        let parts: usize = sections.iter().map(|section| section.estimated_tokens).sum();
+        sections.push(PromptSection { name: "system_template", estimated_tokens: estimated_tokens.saturating_sub(parts) });
+        sections.sort_by(|a, b| b.estimated_tokens.cmp(&a.estimated_tokens));
+
+        PromptPreview {
             model: model_name.to_string(),
             prompt,
-            stream: false,
-            format: "json".to_string(),
-            options: RequestOptions {
-                num_predict: 2000,    // Increase token limit to prevent truncation
-                temperature: 0.7,     
-                top_p: 0.9,          
-            },
-        };
+            messages,
+            estimated_tokens,
+            sections,
+            max_response_tokens: self.session_response_tokens(),
+            context_tokens,
+            trimmed,
+        }
+    }
 
-        let url = format!("{}/api/generate", self.base_url);
-        
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send request to Ollama: {}", e))?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("Ollama request failed: {}", error_text));
+    // Answer a question about a selected range of lines, sending only that excerpt plus a little context
+    pub async fn generate_selection_response(
+        &self,
+        question: &str,
+        code: &str,
+        selection: &SelectionRange,
+        session_context: &str,
+        model_name: &str,
+    ) -> Result<SessionResponse, String> {
+        let prompt = self.create_selection_prompt(question, code, selection, session_context)?;
+        let mut response = self.request_session_response(vec![ChatMessage::new("user", prompt)], model_name, None).await?;
+        apply_response_edits(&mut response, code);
+        Ok(response)
+    }
+
+    // Explain a failed run from its traceback and suggest the smallest fix
+    pub async fn explain_error(
+        &self,
+        code: &str,
+        traceback: &str,
+        session_context: &str,
+        model_name: &str,
+    ) -> Result<ErrorExplanation, String> {
+        let traceback_lines: Vec<&str> = traceback.trim_end().lines().collect();
+        let traceback = traceback_lines[traceback_lines.len().saturating_sub(MAX_TRACEBACK_LINES)..].join("\n");
+        let prompt = prompts::render(PromptKind::ExplainError, &[
+            ("session_context", session_context),
+            ("code", &numbered_code(code)),
+            ("traceback", &traceback),
+        ]);
+
+        let messages = [ChatMessage::new("user", prompt)];
+        let schema = error_explanation_schema();
+        let request = self.options.apply(CompletionRequest {
+            model: model_name,
+            messages: &messages,
+            format: ResponseFormat::Schema(&schema),
+            context_tokens: None,
+            max_tokens: SESSION_NUM_PREDICT,
+            temperature: 0.2,
+            top_p: 0.9,
+            seed: None,
+        });
+
+        let output = complete_with_continuation(&self.provider, request).await
+            .map_err(|e| format!("Error explanation request failed: {}", e))?;
+        let mut explanation: ErrorExplanation = crate::json_repair::parse_for_model_with_schema(model_name, &output, &schema)?;
+        if explanation.fixed_code.trim().is_empty() {
+            explanation.fixed_code = code.to_string();
         }
+        explanation.fix_diff = crate::code_diff::unified_diff(code, &explanation.fixed_code, 3);
+        Ok(explanation)
+    }
 
-        let llm_response: SessionLLMResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse LLM response: {}", e))?;
-
-        // Parse the JSON response from the LLM with error handling for truncation
-        let session_response = self.parse_json_response(&llm_response.response)?;
-        Ok(session_response)
-    }
-
-    fn parse_json_response(&self, response: &str) -> Result<SessionResponse, String> {
-        // First try normal JSON parsing
-        match serde_json::from_str::<SessionResponse>(response) {
-            Ok(parsed) => return Ok(parsed),
-            Err(_) => {
-                // If JSON parsing fails, try to fix common issues
-                
-                // Fix common typos first
-                let mut fixed_response = response.to_string();
-                fixed_response = fixed_response.replace("\"conversaation_response\"", "\"conversation_response\"");
-                
-                // Try parsing the typo-fixed version
-                match serde_json::from_str::<SessionResponse>(&fixed_response) {
-                    Ok(parsed) => return Ok(parsed),
-                    Err(_) => {
-                        // Continue with truncation handling
-                    }
-                }
-                
-                // Handle truncated JSON by attempting to complete it
-                
-                // If response ends abruptly, try to close the JSON properly
-                if !fixed_response.trim().ends_with('}') {
-                    // Count opening and closing braces to see if we need to close
-                    let open_braces = fixed_response.matches('{').count();
-                    let close_braces = fixed_response.matches('}').count();
-                    
-                    if open_braces > close_braces {
-                        // Try to find where conversation_response field ends
-                        if fixed_response.contains("\"conversation_response\"") && !fixed_response.contains("\"code_to_insert\"") {
-                            // Add empty code field and close JSON
-                            fixed_response.push_str("\", \"code_to_insert\": \"\"}");
-                        } else if !fixed_response.trim().ends_with('"') {
-                            // Close the current string and JSON
-                            fixed_response.push_str("\"}");
-                        } else {
-                            // Just close the JSON
-                            fixed_response.push('}');
-                        }
-                    }
-                }
-                
-                // Try parsing the fixed JSON
-                match serde_json::from_str::<SessionResponse>(&fixed_response) {
-                    Ok(parsed) => Ok(parsed),
-                    Err(e) => {
-                        // If still failing, try extracting manually
-                        self.manual_json_extraction(response)
-                            .or_else(|_| Err(format!("Failed to parse JSON response: {}. Raw response: {}", e, response)))
-                    }
-                }
-            }
+    // Second, short LLM call that reviews the tutor's answer for factual or code mistakes
+    // (and, when the session has class rules, for anything that breaks them)
+    pub async fn verify_session_response(
+        &self,
+        user_input: &str,
+        response: &SessionResponse,
+        session_context: &str,
+        model_name: &str,
+    ) -> Result<SelfCheckResult, String> {
+        let prompt = prompts::render(PromptKind::SelfCheck, &[
+            ("session_context", session_context),
+            ("question", user_input),
+            ("explanation", &response.conversation_response),
+            ("code", &response.code_to_insert),
+        ]);
+
+        let messages = [ChatMessage::new("user", prompt)];
+        let request = CompletionRequest {
+            model: model_name,
+            messages: &messages,
+            format: ResponseFormat::Json,
+            context_tokens: None,
+            max_tokens: 300,
+            temperature: 0.0, // Reviews should be stable, not creative
+            top_p: 0.9,
+            seed: None,
+        };
+
+        let completion = self.provider.complete(&request).await
+            .map_err(|e| format!("Self-check request failed: {}", e))?;
+
+        let verdict: SelfCheckVerdict = crate::json_repair::parse_for_model(model_name, &completion.text)
+            .map_err(|e| format!("Self-check returned invalid JSON: {}", e))?;
+
+        Ok(SelfCheckResult {
+            passed: verdict.accurate && verdict.issues.is_empty(),
+            issues: verdict.issues,
+        })
+    }
+
+    // A few words naming what a session is about, from its first question and answer
+    pub async fn generate_session_title(&self, question: &str, answer: &str, model_name: &str) -> Result<String, String> {
+        // The start of each is enough to tell the topic
+        let excerpt = |text: &str| text.chars().take(1000).collect::<String>();
+        let prompt = prompts::render(PromptKind::SessionTitle, &[
+            ("question", &excerpt(question)),
+            ("answer", &excerpt(answer)),
+        ]);
+
+        let messages = [ChatMessage::new("user", prompt)];
+        let request = CompletionRequest {
+            model: model_name,
+            messages: &messages,
+            format: ResponseFormat::Text,
+            context_tokens: None,
+            max_tokens: 24,
+            temperature: 0.3,
+            top_p: 0.9,
+            seed: None,
+        };
+
+        let completion = self.provider.complete(&request).await
+            .map_err(|e| format!("Session title request failed: {}", e))?;
+        clean_session_title(&completion.text).ok_or_else(|| format!("{} returned an empty session title", model_name))
+    }
+
+    // Break a project goal into milestones, each with acceptance criteria and a Python check
+    pub async fn generate_project_plan(
+        &self,
+        goal: &str,
+        memory_context: &str,
+        session_context: &str,
+        model_name: &str,
+    ) -> Result<crate::project_plan::PlanDraft, String> {
+        let memory_section = if memory_context.trim().is_empty() {
+            String::new()
+        } else {
+            format!("What you know about this student from earlier sessions:\n{}\n\n", memory_context)
+        };
+        let prompt = prompts::render(PromptKind::ProjectPlan, &[
+            ("memory_section", &memory_section),
+            ("session_context", session_context),
+            ("goal", goal),
+        ]);
+
+        let messages = [ChatMessage::new("user", prompt)];
+        let request = self.options.apply(CompletionRequest {
+            model: model_name,
+            messages: &messages,
+            format: ResponseFormat::Json,
+            context_tokens: None,
+            max_tokens: SESSION_NUM_PREDICT,
+            temperature: 0.3,
+            top_p: 0.9,
+            seed: None,
+        });
+
+        let output = complete_with_continuation(&self.provider, request).await
+            .map_err(|e| format!("Project plan request failed: {}", e))?;
+        let draft: crate::project_plan::PlanDraft = crate::json_repair::parse_for_model(model_name, &output)?;
+        draft.validate()
+    }
+
+    // Send the tutor messages, let a cut-off reply finish, then parse with truncation repair
+    async fn request_session_response(&self, messages: Vec<ChatMessage>, model_name: &str, context_tokens: Option<usize>) -> Result<SessionResponse, String> {
+        let schema = session_response_schema();
+        let request = self.options.apply(CompletionRequest {
+            model: model_name,
+            messages: &messages,
+            format: ResponseFormat::Schema(&schema),
+            context_tokens,
+            max_tokens: SESSION_NUM_PREDICT,
+            temperature: 0.7,
+            top_p: 0.9,
+            seed: None,
+        });
+
+        let output = match &self.text_stream {
+            Some(text_stream) => stream_with_continuation(&self.provider, request, &mut |text: &str| {
+                let _ = text_stream.send(text.to_string());
+            }).await?,
+            None => complete_with_continuation(&self.provider, request).await?,
+        };
+        self.parse_json_response(&output, model_name)
+    }
+
+    fn parse_json_response(&self, response: &str, model_name: &str) -> Result<SessionResponse, String> {
+        crate::json_repair::parse_for_model_with_schema(model_name, response, &session_response_schema())
+    }
+
+    fn create_selection_prompt(&self, question: &str, code: &str, selection: &SelectionRange, session_context: &str) -> Result<String, String> {
+        const CONTEXT_LINES: usize = 3;
+
+        let lines: Vec<&str> = code.lines().collect();
+        if selection.start_line == 0 || selection.start_line > selection.end_line || selection.start_line > lines.len() {
+            return Err(format!(
+                "Invalid selection: lines {}-{} in a {}-line file",
+                selection.start_line, selection.end_line, lines.len()
+            ));
         }
+        let end_line = selection.end_line.min(lines.len());
+
+        let number_lines = |from: usize, to: usize| -> String {
+            (from..=to)
+                .map(|line| format!("{:>4} | {}", line, lines[line - 1]))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let before_start = selection.start_line.saturating_sub(CONTEXT_LINES).max(1);
+        let after_end = (end_line + CONTEXT_LINES).min(lines.len());
+        let context_before = if before_start < selection.start_line {
+            number_lines(before_start, selection.start_line - 1)
+        } else {
+            "(start of file)".to_string()
+        };
+        let context_after = if end_line < after_end {
+            number_lines(end_line + 1, after_end)
+        } else {
+            "(end of file)".to_string()
+        };
+
+        Ok(prompts::render(PromptKind::Selection, &[
+            ("session_context", session_context),
+            ("context_before", &context_before),
+            ("start_line", &selection.start_line.to_string()),
+            ("end_line", &end_line.to_string()),
+            ("selected_lines", &number_lines(selection.start_line, end_line)),
+            ("context_after", &context_after),
+            ("question", question),
+        ]))
     }
-    
-    fn manual_json_extraction(&self, response: &str) -> Result<SessionResponse, String> {
-        // Manual extraction for severely malformed JSON
-        let mut conversation_response = String::new();
-        let mut code_to_insert = String::new();
-        
-        // Try to extract conversation_response (handle typos like "conversaation_response")
-        let conversation_patterns = ["\"conversation_response\"", "\"conversaation_response\""];
-        for pattern in &conversation_patterns {
-            if let Some(start) = response.find(pattern) {
-                if let Some(colon_pos) = response[start..].find(':') {
-                    let after_colon = start + colon_pos + 1;
-                    if let Some(quote_start) = response[after_colon..].find('"') {
-                        let content_start = after_colon + quote_start + 1;
-                        // Find the end quote, handling escaped quotes
-                        let mut end_pos = content_start;
-                        let chars: Vec<char> = response.chars().collect();
-                        while end_pos < chars.len() {
-                            if chars[end_pos] == '"' && (end_pos == 0 || chars[end_pos - 1] != '\\') {
-                                break;
-                            }
-                            end_pos += 1;
-                        }
-                        if end_pos < chars.len() {
-                            conversation_response = response[content_start..end_pos].to_string();
-                            break;
-                        }
-                    }
-                }
+
+    // The tutor messages, fitted so they leave room for the reply in the context window: the
+    // oldest history goes first, then memory entries (see kept_memory). The instructions, code
+    // and question are always sent, even if they alone don't fit.
+    fn create_session_messages(
+        &self,
+        user_input: &str,
+        current_code: &str,
+        memory_context: &str,
+        session_context: &str,
+        history: &[ChatMessage],
+        context_tokens: usize,
+    ) -> (Vec<ChatMessage>, PromptTrim) {
+        let budget = context_tokens.saturating_sub(self.session_response_tokens() as usize);
+        let mut trim = PromptTrim::default();
+        loop {
+            let memory = kept_memory(memory_context, trim.dropped_memory_lines, self.memory_order);
+            let messages = self.assemble_session_messages(
+                user_input, current_code, &memory, session_context, &history[trim.dropped_history..], trim.dropped_history,
+            );
+            let tokens: usize = messages.iter().map(|message| estimate_tokens(&message.content)).sum();
+            if tokens <= budget {
+                return (messages, trim);
             }
-        }
-        
-        // Try to extract code_to_insert
-        if let Some(start) = response.find("\"code_to_insert\"") {
-            if let Some(colon_pos) = response[start..].find(':') {
-                let after_colon = start + colon_pos + 1;
-                if let Some(quote_start) = response[after_colon..].find('"') {
-                    let content_start = after_colon + quote_start + 1;
-                    let mut end_pos = content_start;
-                    let chars: Vec<char> = response.chars().collect();
-                    while end_pos < chars.len() {
-                        if chars[end_pos] == '"' && (end_pos == 0 || chars[end_pos - 1] != '\\') {
-                            break;
-                        }
-                        end_pos += 1;
-                    }
-                    if end_pos < chars.len() {
-                        code_to_insert = response[content_start..end_pos].to_string();
-                    }
-                }
+            if trim.dropped_history < history.len() {
+                trim.dropped_history += 1;
+            } else if !memory.is_empty() {
+                trim.dropped_memory_lines += 1;
+            } else {
+                return (messages, trim);
             }
         }
-        
-        if !conversation_response.is_empty() {
-            Ok(SessionResponse {
-                conversation_response,
-                code_to_insert,
-            })
+    }
+
+    // System prompt with the tutor's instructions and what is known about the student, then the
+    // conversation so far, then this turn with the editor contents
+    fn assemble_session_messages(
+        &self,
+        user_input: &str,
+        current_code: &str,
+        memory_context: &str,
+        session_context: &str,
+        history: &[ChatMessage],
+        dropped_history: usize,
+    ) -> Vec<ChatMessage> {
+        // Only mention the student profile when there is something to show
+        let memory_section = if memory_context.trim().is_empty() {
+            String::new()
+        } else {
+            format!(
+                "What you know about this student from earlier sessions (use it to adapt, don't recite it):\n{}\n\n",
+                memory_context
+            )
+        };
+
+        let history_note = if dropped_history > 0 {
+            format!("The {} oldest messages of this conversation were left out to fit your context.\n\n", dropped_history)
         } else {
-            Err("Could not extract conversation_response".to_string())
+            String::new()
+        };
+
+        let kind = match self.pedagogy {
+            PedagogyMode::Direct => PromptKind::Tutor,
+            PedagogyMode::Hints => PromptKind::HintTutor,
+        };
+        let system = prompts::render(kind, &[
+            ("memory_section", &memory_section),
+            ("session_context", session_context),
+            ("history_note", &history_note),
+        ]);
+
+        let turn = format!(
+            "Current Python code in the editor (line numbers are for edits, not part of the code):\n```python\n{}\n```\n\nUser said: \"{}\"",
+            numbered_code(current_code),
+            user_input
+        );
+
+        let mut messages = Vec::with_capacity(history.len() + 2);
+        messages.push(ChatMessage::new("system", system));
+        messages.extend(history.iter().cloned());
+        messages.push(ChatMessage::new("user", turn));
+        messages
+    }
+}
+
+// Apply the reply's edits to the code they were made against so code_to_insert holds the whole
+// edited file. Edits that don't apply are dropped, leaving the editor as it was.
+fn apply_response_edits(response: &mut SessionResponse, code: &str) {
+    if response.edits.is_empty() {
+        return;
+    }
+    match crate::code_diff::apply_edits(code, &response.edits) {
+        Ok(edited) => response.code_to_insert = edited,
+        Err(e) => {
+            eprintln!("Discarding the tutor's code edits: {}", e);
+            response.edits.clear();
         }
     }
+}
 
-    fn create_session_prompt(&self, user_input: &str, current_code: &str) -> String {
-        format!(
-            r#"You are an AI Python tutor for Project-R. You help students learn Python through conversation and code assistance.
-
-Current Python code in the editor:
-```python
-{}
-```
-
-User said: "{}"
-
-CRITICAL: You must respond with valid JSON in EXACTLY this format:
-{{
-  "conversation_response": "Your helpful response to the user as their Python tutor. Keep this conversational and friendly. Avoid code blocks in this field.",
-  "code_to_insert": "Any Python code to insert/replace in the editor, or empty string if no code changes needed"
-}}
-
-IMPORTANT JSON RULES:
-- Field names must be EXACTLY: "conversation_response" and "code_to_insert" (check spelling carefully!)
-- Valid JSON syntax only
-- No additional text outside the JSON
-- Keep conversation_response concise to avoid truncation
-- Escape quotes properly with \"
-- DO NOT misspell field names (NOT "conversaation_response")
-
-Guidelines:
-- Be encouraging and educational in conversation_response
-- Explain concepts clearly but keep responses reasonably short
-- Provide working Python code in code_to_insert when requested
-- If the user asks to fix code, provide the corrected version in code_to_insert
-- If user asks to add features, provide the enhanced code in code_to_insert
-- Only include runnable Python code in code_to_insert
-
-Remember: Respond ONLY with valid JSON, no additional text."#,
-            current_code,
-            user_input
-        )
+// Hint mode is enforced here as well as in the prompt: whatever the model wrote, no code reaches
+// the editor, so the student writes the solution themselves
+fn enforce_hint_mode(response: &mut SessionResponse) {
+    if !response.code_to_insert.trim().is_empty() || !response.edits.is_empty() {
+        eprintln!("Dropping code from a hint-mode answer");
+    }
+    response.code_to_insert.clear();
+    response.edits.clear();
+    response.hint_level = Some(response.hint_level.unwrap_or(1).clamp(1, MAX_HINT_LEVEL));
+}
+
+// Longest session title kept; the session list shows about this much
+const MAX_SESSION_TITLE_CHARS: usize = 60;
+
+// The first line of a title reply, without a "Title:" label, quotes, markdown or closing punctuation
+fn clean_session_title(reply: &str) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line.strip_prefix("Title:").or_else(|| line.strip_prefix("title:")).unwrap_or(line);
+    let title = line.trim()
+        .trim_matches(|c: char| matches!(c, '"' | '\'' | '*' | '#' | '`'))
+        .trim_end_matches(['.', '!', ':'])
+        .trim();
+    if title.is_empty() {
+        return None;
     }
+    Some(title.chars().take(MAX_SESSION_TITLE_CHARS).collect::<String>().trim_end().to_string())
+}
+
+// The memory block without `dropped_lines` of its entries. Ranked memory loses its last lines.
+// Whole memory has no order across sections, so each time the oldest entry of the longest
+// section goes, and a heading goes with its section's last entry.
+fn kept_memory(memory_context: &str, dropped_lines: usize, order: MemoryOrder) -> String {
+    let lines: Vec<&str> = memory_context.lines().collect();
+    if order == MemoryOrder::Ranked {
+        return lines[..lines.len().saturating_sub(dropped_lines)].join("\n");
+    }
+
+    let mut sections: Vec<(Option<&str>, std::collections::VecDeque<&str>)> = Vec::new();
+    for line in lines {
+        match sections.last_mut() {
+            Some((_, entries)) if line.starts_with("- ") => entries.push_back(line),
+            _ if line.starts_with("- ") => sections.push((None, [line].into())),
+            _ => sections.push((Some(line), Default::default())),
+        }
+    }
+    for _ in 0..dropped_lines {
+        // max_by_key returns the last of equals; searching from the end gives ties to the first section
+        let Some((_, entries)) = sections.iter_mut().rev().max_by_key(|(_, entries)| entries.len()) else { break };
+        if entries.pop_front().is_none() {
+            break;
+        }
+    }
+    sections.iter()
+        .filter(|(_, entries)| !entries.is_empty())
+        .flat_map(|(heading, entries)| heading.iter().chain(entries.iter()))
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 // Test function to verify Ollama connection
@@ -329,4 +1198,76 @@ pub async fn test_ollama_connection() -> Result<String, String> {
     let client = OllamaClient::new(None);
     client.check_connection().await?;
     Ok("Successfully connected to Ollama".to_string())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMORY: &str = "Strengths:\n- loops (5/5)\nWeak spots to reinforce:\n- slicing\n- dict.get\n- f-strings\nPrevious sessions:\n- Lists\n- Dicts";
+
+    #[test]
+    fn short_text_is_kept_whole() {
+        assert_eq!(keep_latest_tokens("first\nsecond", 100), "first\nsecond");
+    }
+
+    #[test]
+    fn latest_lines_are_kept_with_a_marker() {
+        let text = format!("{}\nthe last line", "an early line that won't fit\n".repeat(20));
+        let kept = keep_latest_tokens(&text, 10);
+        assert!(kept.starts_with("(earlier part left out)\n"), "{}", kept);
+        assert!(kept.ends_with("the last line"));
+        assert!(estimate_tokens(&kept) < estimate_tokens(&text));
+    }
+
+    #[test]
+    fn a_last_line_over_the_budget_keeps_its_end() {
+        let line = (0..400).map(|i| format!("word{}", i)).collect::<Vec<_>>().join(" ");
+        let kept = keep_latest_tokens(&format!("intro\n{}", line), 20);
+        let tail = kept.strip_prefix("(earlier part left out)\n").unwrap();
+        assert!(!tail.is_empty());
+        assert!(line.ends_with(tail));
+        assert!(estimate_tokens(tail) < 20, "{}", tail);
+    }
+
+    #[test]
+    fn ranked_memory_loses_its_least_relevant_lines() {
+        let ranked = "- Note: most relevant\n- Note: related\n- Note: least relevant";
+        assert_eq!(kept_memory(ranked, 1, MemoryOrder::Ranked), "- Note: most relevant\n- Note: related");
+        assert_eq!(kept_memory(ranked, 5, MemoryOrder::Ranked), "");
+    }
+
+    #[test]
+    fn whole_memory_loses_the_oldest_entries_of_its_longest_section() {
+        assert_eq!(kept_memory(MEMORY, 0, MemoryOrder::Chronological), MEMORY);
+        assert_eq!(
+            kept_memory(MEMORY, 2, MemoryOrder::Chronological),
+            "Strengths:\n- loops (5/5)\nWeak spots to reinforce:\n- f-strings\nPrevious sessions:\n- Lists\n- Dicts",
+        );
+        // With one entry left in each section the first goes, taking its heading with it
+        assert_eq!(
+            kept_memory(MEMORY, 4, MemoryOrder::Chronological),
+            "Weak spots to reinforce:\n- f-strings\nPrevious sessions:\n- Dicts",
+        );
+        assert_eq!(kept_memory(MEMORY, 9, MemoryOrder::Chronological), "");
+    }
+
+    #[test]
+    fn history_is_trimmed_before_memory() {
+        let client = OllamaClient::new(None).with_options(GenerationOptions { num_predict: Some(0), ..Default::default() });
+        let history: Vec<ChatMessage> = (0..6).map(|i| ChatMessage::new("user", format!("message {} ", i).repeat(50))).collect();
+        let (full, trim) = client.create_session_messages("why?", "", MEMORY, "", &history, usize::MAX);
+        assert_eq!((trim.dropped_history, trim.dropped_memory_lines), (0, 0));
+        let full_tokens: usize = full.iter().map(|message| estimate_tokens(&message.content)).sum();
+
+        let (messages, trim) = client.create_session_messages("why?", "", MEMORY, "", &history, full_tokens - 1);
+        assert_eq!((trim.dropped_history, trim.dropped_memory_lines), (1, 0));
+        assert_eq!(messages.len(), history.len() + 1);
+
+        let (messages, trim) = client.create_session_messages("why?", "", MEMORY, "", &history, 0);
+        assert_eq!(trim.dropped_history, history.len());
+        assert!(trim.dropped_memory_lines > 0);
+        assert!(!messages[0].content.contains("(5/5)"));
+        assert_eq!(messages.len(), 2);
+    }
+}
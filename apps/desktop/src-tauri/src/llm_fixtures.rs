@@ -0,0 +1,368 @@
+use crate::llm::{GenerationOptions, ModelBackend, OllamaClient};
+use crate::practice_sheet::PracticeSheetLLMClient;
+use crate::session_summary::SummaryLLMClient;
+use crate::speech_stream::SpokenTextStream;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Test fixture standing in for Ollama: an HTTP server on a free local port that answers each path
+// with queued replies, in order, and records the requests it got. Clients under test
+// are pointed at it through `backend`, so LLM-dependent parsing and validation run in CI without
+// a model. Paths with nothing queued get a 404, which clients treat like an old Ollama (e.g.
+// /api/show, so the default context window is used).
+
+type Replies = Arc<Mutex<HashMap<String, VecDeque<Reply>>>>;
+
+struct Reply {
+    status: u16,
+    body: String,
+    delay: Duration, // Before the reply is sent, e.g. to outlast a timeout
+}
+type Requests = Arc<Mutex<Vec<Request>>>;
+
+struct Request {
+    path: String,
+    authorization: Option<String>,
+    body: serde_json::Value,
+}
+
+pub struct MockOllama {
+    base_url: String,
+    replies: Replies,
+    requests: Requests,
+}
+
+impl MockOllama {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind the mock Ollama server");
+        let base_url = format!("http://{}", listener.local_addr().expect("Mock Ollama has no address"));
+        let replies: Replies = Arc::default();
+        let requests: Requests = Arc::default();
+
+        let (server_replies, server_requests) = (replies.clone(), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (replies, requests) = (server_replies.clone(), server_requests.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, replies, requests).await {
+                        eprintln!("Mock Ollama connection failed: {}", e);
+                    }
+                });
+            }
+        });
+        Self { base_url, replies, requests }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn backend(&self, model: &str) -> ModelBackend {
+        ModelBackend { base_url: Some(self.base_url.clone()), ..ModelBackend::local(model) }
+    }
+
+    // Queue the body of the next reply to `path`
+    pub fn reply(&self, path: &str, body: serde_json::Value) {
+        self.raw_reply(path, body.to_string());
+    }
+
+    // Queue an error reply to `path`, e.g. 503 while Ollama is busy loading a model
+    pub fn status_reply(&self, path: &str, status: u16) {
+        self.push_reply(path, Reply { status, body: serde_json::json!({ "error": "fixture" }).to_string(), delay: Duration::ZERO });
+    }
+
+    // Queue a reply to `path` that's only sent after `delay`
+    pub fn slow_reply(&self, path: &str, body: serde_json::Value, delay: Duration) {
+        self.push_reply(path, Reply { status: 200, body: body.to_string(), delay });
+    }
+
+    fn raw_reply(&self, path: &str, body: String) {
+        self.push_reply(path, Reply { status: 200, body, delay: Duration::ZERO });
+    }
+
+    fn push_reply(&self, path: &str, reply: Reply) {
+        self.replies.lock().unwrap().entry(path.to_string()).or_default().push_back(reply);
+    }
+
+    // Queue an /api/chat reply whose message is `content`
+    pub fn chat_reply(&self, content: &str) {
+        self.reply("/api/chat", serde_json::json!({
+            "message": { "role": "assistant", "content": content },
+            "done_reason": "stop",
+        }));
+    }
+
+    // Queue a streamed /api/chat reply: one line per piece of the message, then the final line
+    pub fn chat_stream_reply(&self, pieces: &[&str]) {
+        let mut lines: Vec<String> = pieces.iter()
+            .map(|piece| serde_json::json!({ "message": { "role": "assistant", "content": piece }, "done": false }).to_string())
+            .collect();
+        lines.push(serde_json::json!({ "message": { "role": "assistant", "content": "" }, "done": true, "done_reason": "stop" }).to_string());
+        self.raw_reply("/api/chat", lines.join("\n"));
+    }
+
+    // Bodies of the requests made to `path`, oldest first
+    pub fn requests(&self, path: &str) -> Vec<serde_json::Value> {
+        self.requests.lock().unwrap().iter()
+            .filter(|request| request.path == path)
+            .map(|request| request.body.clone())
+            .collect()
+    }
+
+    // Authorization headers of the requests made to `path`, oldest first
+    pub fn authorizations(&self, path: &str) -> Vec<Option<String>> {
+        self.requests.lock().unwrap().iter()
+            .filter(|request| request.path == path)
+            .map(|request| request.authorization.clone())
+            .collect()
+    }
+}
+
+// One request per connection: headers, then a Content-Length body
+async fn serve(mut stream: TcpStream, replies: Replies, requests: Requests) -> std::io::Result<()> {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 4096];
+    let header_end = loop {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        data.extend_from_slice(&buffer[..read]);
+        if let Some(position) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+    let header = |wanted: &str| head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case(wanted))
+        .map(|(_, value)| value.trim().to_string());
+    let length = header("content-length").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+    let authorization = header("authorization");
+    while data.len() < header_end + length {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..read]);
+    }
+
+    let body = serde_json::from_slice(&data[header_end..]).unwrap_or(serde_json::Value::Null);
+    requests.lock().unwrap().push(Request { path: path.clone(), authorization, body });
+    let reply = replies.lock().unwrap().get_mut(&path).and_then(VecDeque::pop_front);
+    let reply = reply.unwrap_or_else(|| Reply {
+        status: 404,
+        body: serde_json::json!({ "error": format!("no fixture for {}", path) }).to_string(),
+        delay: Duration::ZERO,
+    });
+    tokio::time::sleep(reply.delay).await;
+    let response = format!(
+        "HTTP/1.1 {} Fixture\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status, reply.body.len(), reply.body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::ChatMessage;
+    use crate::ollama::{CallKind, ClientConfig, OllamaError, OllamaHttp};
+    use crate::provider::{CompletionRequest, LlmProvider, Provider, ProviderKind, ResponseFormat};
+
+    fn quiz_reply(correct_answer: &str) -> String {
+        let questions: Vec<serde_json::Value> = (1..=5)
+            .map(|number| serde_json::json!({
+                "question_text": format!("What does len([1, 2, {}]) return?", number),
+                "options": ["1", "2", "3", "4"],
+                "correct_answer": correct_answer,
+            }))
+            .collect();
+        serde_json::Value::from(questions).to_string()
+    }
+
+    // The retry settings are global, so every test that changes them sets the same values. A
+    // one-second quick timeout doesn't affect the other fixtures, which answer straight away.
+    async fn list_models(mock: &MockOllama) -> Result<serde_json::Value, OllamaError> {
+        crate::ollama::set_config(ClientConfig { request_timeout_secs: 1, max_retries: 3, initial_backoff_ms: 1, ..Default::default() });
+        OllamaHttp::new(Some(mock.base_url().to_string())).get_json("/api/tags", CallKind::Quick).await
+    }
+
+    #[tokio::test]
+    async fn busy_ollama_is_retried_until_it_answers() {
+        let mock = MockOllama::start().await;
+        mock.status_reply("/api/tags", 503);
+        mock.reply("/api/tags", serde_json::json!({ "models": [] }));
+
+        assert_eq!(list_models(&mock).await.unwrap(), serde_json::json!({ "models": [] }));
+        assert_eq!(mock.requests("/api/tags").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let mock = MockOllama::start().await;
+        mock.status_reply("/api/tags", 400);
+        mock.reply("/api/tags", serde_json::json!({ "models": [] }));
+
+        let error = list_models(&mock).await.unwrap_err();
+        assert!(matches!(error, OllamaError::Status { status: 400, .. }), "{}", error);
+        assert_eq!(mock.requests("/api/tags").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_max_retries() {
+        let mock = MockOllama::start().await;
+        for _ in 0..6 {
+            mock.status_reply("/api/tags", 503);
+        }
+
+        let error = list_models(&mock).await.unwrap_err();
+        assert!(matches!(error, OllamaError::Status { status: 503, .. }), "{}", error);
+        assert_eq!(mock.requests("/api/tags").len(), 4);
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_not_retried() {
+        let mock = MockOllama::start().await;
+        mock.slow_reply("/api/tags", serde_json::json!({ "models": [] }), Duration::from_secs(3));
+        mock.reply("/api/tags", serde_json::json!({ "models": [] }));
+
+        let error = list_models(&mock).await.unwrap_err();
+        assert!(matches!(error, OllamaError::Timeout), "{}", error);
+        assert_eq!(mock.requests("/api/tags").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn quiz_seed_wins_over_the_options_and_sampling_is_greedy_when_deterministic() {
+        let mock = MockOllama::start().await;
+        mock.chat_reply(&quiz_reply("3"));
+        mock.chat_reply(&quiz_reply("3"));
+        let client = PracticeSheetLLMClient::for_backend(&mock.backend("test-model"))
+            .with_options(GenerationOptions { seed: Some(1), temperature: Some(0.9), ..Default::default() });
+
+        client.generate_practice_sheet("Lists and len()", "test-model", 42, true).await.unwrap();
+        client.generate_practice_sheet("Lists and len()", "test-model", 42, true).await.unwrap();
+
+        let requests = mock.requests("/api/chat");
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            assert_eq!(request["options"]["seed"], 42);
+            assert_eq!(request["options"]["temperature"], 0.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn quiz_with_an_answer_outside_its_options_is_rejected() {
+        let mock = MockOllama::start().await;
+        mock.chat_reply(&quiz_reply("five"));
+        let error = PracticeSheetLLMClient::for_backend(&mock.backend("test-model"))
+            .generate_practice_sheet("Lists and len()", "test-model", 7, false)
+            .await
+            .unwrap_err();
+        assert!(error.contains("is not in options"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn tutor_reply_in_a_code_fence_is_parsed_and_its_edits_applied() {
+        let mock = MockOllama::start().await;
+        mock.chat_reply("Sure!\n```json\n{\"conversation_response\": \"Use a plus sign.\", \"code_to_insert\": \"\", \"edits\": [{\"start_line\": 2, \"end_line\": 2, \"replacement\": \"print(a + b)\"}]}\n```");
+        let response = OllamaClient::for_backend(&mock.backend("test-model"))
+            .with_options(GenerationOptions { seed: Some(11), ..Default::default() })
+            .generate_session_response("How do I add them?", "a, b = 1, 2\nprint(a, b)\n", "", "", &[], &[], "test-model")
+            .await
+            .unwrap();
+
+        assert_eq!(response.conversation_response, "Use a plus sign.");
+        assert_eq!(response.code_to_insert, "a, b = 1, 2\nprint(a + b)\n");
+        assert_eq!(mock.requests("/api/chat")[0]["options"]["seed"], 11);
+    }
+
+    #[tokio::test]
+    async fn streamed_tutor_reply_can_be_spoken_sentence_by_sentence() {
+        let mock = MockOllama::start().await;
+        mock.chat_stream_reply(&[
+            "{\"conversation_response\": \"Lists keep items in ",
+            "order. Use append to add one",
+            " at the end.\", \"code_to_insert\": \"items.append(3)\"}",
+        ]);
+        let (text_stream, mut pieces) = tokio::sync::mpsc::unbounded_channel();
+        let response = OllamaClient::for_backend(&mock.backend("test-model"))
+            .with_text_stream(Some(text_stream))
+            .generate_session_response("How do I add to a list?", "", "", "", &[], &[], "test-model")
+            .await
+            .unwrap();
+
+        assert_eq!(response.conversation_response, "Lists keep items in order. Use append to add one at the end.");
+        assert_eq!(response.code_to_insert, "items.append(3)");
+        assert_eq!(mock.requests("/api/chat")[0]["stream"], true);
+
+        let mut spoken = SpokenTextStream::default();
+        let mut sentences = Vec::new();
+        while let Ok(piece) = pieces.try_recv() {
+            sentences.extend(spoken.push(&piece));
+        }
+        assert_eq!(sentences, ["Lists keep items in order.", "Use append to add one at the end."]);
+    }
+
+    #[tokio::test]
+    async fn openai_backend_is_sent_chat_completions_with_its_key() {
+        let mock = MockOllama::start().await;
+        mock.reply("/chat/completions", serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": "{\"answer\": 4}" }, "finish_reason": "length" }],
+        }));
+        let backend = ModelBackend {
+            provider: ProviderKind::OpenAi,
+            api_key: Some("sk-test".to_string()),
+            ..mock.backend("gpt-test")
+        };
+        let schema = serde_json::json!({ "type": "object", "properties": { "answer": { "type": "integer" } } });
+        let messages = [
+            ChatMessage::new("system", "Answer in JSON."),
+            ChatMessage { images: vec!["iVBORw0KGgo=".to_string()], ..ChatMessage::new("user", "What is 2 + 2?") },
+        ];
+        let completion = Provider::for_backend(&backend)
+            .complete(&CompletionRequest {
+                model: "gpt-test",
+                messages: &messages,
+                format: ResponseFormat::Schema(&schema),
+                context_tokens: None,
+                max_tokens: 64,
+                temperature: 0.2,
+                top_p: 0.9,
+                seed: Some(3),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(completion.text, "{\"answer\": 4}");
+        assert!(completion.truncated);
+        assert_eq!(mock.authorizations("/chat/completions"), [Some("Bearer sk-test".to_string())]);
+        let request = &mock.requests("/chat/completions")[0];
+        assert_eq!(request["model"], "gpt-test");
+        assert_eq!(request["stream"], false);
+        assert_eq!(request["max_tokens"], 64);
+        assert_eq!(request["seed"], 3);
+        assert_eq!(request["messages"][0], serde_json::json!({ "role": "system", "content": "Answer in JSON." }));
+        assert_eq!(request["messages"][1]["content"][1]["image_url"]["url"], "data:image/png;base64,iVBORw0KGgo=");
+        assert_eq!(request["response_format"]["json_schema"]["schema"], schema);
+    }
+
+    #[tokio::test]
+    async fn summary_is_sent_with_its_seed() {
+        let mock = MockOllama::start().await;
+        mock.chat_reply("  Session name: Adding numbers\n");
+        let summary = SummaryLLMClient::for_backend(&mock.backend("test-model"))
+            .generate_session_summary("Student: how do I add?\n\nAI Tutor: Use +.", "test-model", 5, false)
+            .await
+            .unwrap();
+
+        assert_eq!(summary, "Session name: Adding numbers");
+        assert_eq!(mock.requests("/api/chat")[0]["options"]["seed"], 5);
+    }
+}
@@ -1,10 +1,9 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::Mutex;
-use std::collections::HashSet;
-use tauri::{command, State};
-use std::sync::OnceLock;
+use std::sync::{Arc, Mutex};
+use tauri::{command, Emitter, Manager, State};
+use prompts::PromptKind;
 
 mod audio;
 mod whisper;
@@ -14,25 +13,62 @@ mod interactive_python;
 mod database;
 mod session_summary;
 mod practice_sheet;
+mod memory;
+mod question_import;
+mod background_tasks;
+mod playback;
+mod health;
+mod audio_codec;
+mod usage_limits;
+mod session_context;
+mod results_hook;
+mod json_repair;
+mod project_plan;
+mod transcription_worker;
+mod ollama;
+mod provider;
+mod embedded_llm;
+mod keychain;
+mod code_diff;
+mod embeddings;
+mod response_cache;
+mod prompts;
+mod speech_queue;
+mod speech_stream;
+mod venv;
+#[cfg(test)]
+mod llm_fixtures;
 
 // Global state for audio recorder
 struct AudioState {
     recorder: Mutex<audio::AudioRecorder>,
 }
 
+// Global state for recording playback
+struct PlaybackState {
+    player: Mutex<playback::AudioPlayer>,
+}
+
 // Global state for Whisper transcriber
 struct WhisperState {
     transcriber: Mutex<whisper::WhisperTranscriber>,
+    cancel: Arc<whisper::TranscriptionCancel>, // Reachable while a transcription holds the lock
+    worker: transcription_worker::TranscriptionWorker, // File transcriptions and live passes run here, one at a time
 }
 
 // Global state for LLM client
 struct LLMState {
     client: llm::OllamaClient,
+    generations: llm::GenerationRegistry, // Tutor answers that cancel_generation can stop
+    pulls: llm::GenerationRegistry,       // Model pulls by model name, for cancel_model_pull
 }
 
 // Global state for TTS engine
 struct TTSState {
-    engine: Mutex<tts::SystemTTSEngine>,
+    engine: Mutex<Box<dyn tts::TtsBackend>>, // Replaced when the TTS settings change
+    speech: tts::SpeechControl, // Shared with the engine, for stop_speech
+    queue: speech_queue::SpeechQueue, // Utterances waiting to be spoken
+    echo_gate: audio::EchoGate, // Mutes the recorder while speech plays
 }
 
 // Global state for Python session manager
@@ -45,32 +81,212 @@ struct DatabaseState {
     db: Mutex<database::Database>,
 }
 
-// Global state for Summary LLM client
-struct SummaryState {
-    client: session_summary::SummaryLLMClient,
+// Rejects destructive or exporting commands while demo mode is active
+fn ensure_not_demo_mode(db: &database::Database) -> Result<(), String> {
+    if db.is_demo_mode() {
+        return Err("This action is disabled in demo mode".to_string());
+    }
+    Ok(())
 }
 
-// Global state for Practice Sheet LLM client
-struct PracticeSheetState {
-    client: practice_sheet::PracticeSheetLLMClient,
+// A fresh connection to the profile database for a background job, or None in demo mode: the
+// demo's sessions aren't in it, and the job would act on the real profile behind the demo
+fn background_database(app: &tauri::AppHandle) -> Result<Option<database::Database>, String> {
+    if app.state::<DatabaseState>().db.lock().map_err(|e| e.to_string())?.is_demo_mode() {
+        return Ok(None);
+    }
+    database::Database::new().map(Some).map_err(|e| e.to_string())
 }
 
-// Global static to track running redo generation tasks
-static RUNNING_REDO_TASKS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+// Tracked tasks fail rather than run in demo mode, so they can be retried once it's off
+const DEMO_MODE_TASK_ERROR: &str = "Background tasks don't run in demo mode";
+
+// Refuses new activity once today's guardian limit is used up; otherwise logs the minute as active.
+// Reading and exporting past material don't go through this check.
+fn ensure_within_usage_limit(db: &database::Database) -> Result<(), String> {
+    let status = db.get_usage_status().map_err(|e| e.to_string())?;
+    if status.locked {
+        return Err(status.into_error());
+    }
+    let user_id = db.get_active_user_id().map_err(|e| e.to_string())?;
+    db.log_activity(&user_id, chrono::Local::now()).map_err(|e| e.to_string())
+}
 
+// Every run lands in the execution history; interactive runs use their session ID as execution ID
 #[command]
-async fn execute_python_code(code: String, state: State<'_, PythonState>) -> Result<String, String> {
-    state.session_manager.start_python_session(code).await
+async fn execute_python_code(
+    code: String,
+    state: State<'_, PythonState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+    }
+    
+    let result = state.session_manager.start_python_session(code.clone()).await;
+    
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    let history = match &result {
+        Ok(output) if output.starts_with("INTERACTIVE_SESSION:") => {
+            let session_id = output.trim_start_matches("INTERACTIVE_SESSION:");
+            db.create_python_execution(session_id, &code, None)
+        }
+        Ok(output) | Err(output) => {
+            let execution_id = uuid::Uuid::new_v4().to_string();
+            db.create_python_execution(&execution_id, &code, None)
+                .and_then(|_| db.append_python_output(&execution_id, output))
+                .and_then(|_| db.finish_python_execution(&execution_id, if result.is_ok() { "success" } else { "error" }))
+        }
+    };
+    if let Err(e) = history {
+        eprintln!("Failed to record Python execution: {}", e);
+    }
+    
+    result
 }
 
 #[command] 
-async fn send_python_input(sessionId: String, input: String, state: State<'_, PythonState>) -> Result<(), String> {
-    state.session_manager.send_input(sessionId, input).await
+async fn send_python_input(
+    sessionId: String,
+    input: String,
+    state: State<'_, PythonState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    state.session_manager.send_input(sessionId.clone(), input.clone()).await?;
+    
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    if let Err(e) = db.record_python_input(&sessionId, &input) {
+        eprintln!("Failed to record Python input: {}", e);
+    }
+    Ok(())
+}
+
+#[command]
+async fn get_python_output(
+    sessionId: String,
+    state: State<'_, PythonState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<Vec<String>, String> {
+    let outputs = state.session_manager.get_output(sessionId.clone()).await?;
+    
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    for output in &outputs {
+        // The manager appends a status line once the program exits
+        let status = match output.as_str() {
+            "\n[Program finished successfully]" => Some("success"),
+            "\n[Program exited with error]" | "\n[Program terminated unexpectedly]" => Some("error"),
+            _ => None,
+        };
+        let recorded = match status {
+            Some(status) => db.finish_python_execution(&sessionId, status),
+            None => db.append_python_output(&sessionId, output),
+        };
+        if let Err(e) = recorded {
+            eprintln!("Failed to record Python output: {}", e);
+        }
+    }
+    Ok(outputs)
+}
+
+#[command]
+async fn get_python_executions(limit: Option<i64>, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let executions = db.get_python_executions(limit.unwrap_or(50)).map_err(|e| e.to_string())?;
+    serde_json::to_string(&executions).map_err(|e| e.to_string())
+}
+
+// Rerun recorded code with the same stdin. Replays use a pipe rather than a terminal, so they are
+// compared with earlier replays of the same execution rather than the original interactive run.
+#[command]
+async fn replay_execution(executionId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let original = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_python_execution(&executionId)
+            .map_err(|e| format!("Execution not found: {}", e))?
+    };
+    
+    // A replay of a replay replays the original
+    let original_id = original.replay_of.clone().unwrap_or(original.id.clone());
+    let stdin: String = original.stdin_inputs.concat();
+    let result = interactive_python::run_python_with_stdin(&original.code, &stdin, std::time::Duration::from_secs(10)).await;
+    
+    let replay_id = uuid::Uuid::new_v4().to_string();
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.create_python_execution(&replay_id, &original.code, Some(&original_id)).map_err(|e| e.to_string())?;
+    for input in &original.stdin_inputs {
+        db.record_python_input(&replay_id, input).map_err(|e| e.to_string())?;
+    }
+    db.append_python_output(&replay_id, &result.output).map_err(|e| e.to_string())?;
+    db.finish_python_execution(&replay_id, if result.success { "success" } else { "error" }).map_err(|e| e.to_string())?;
+    
+    let previous = db.get_latest_replay(&original_id, &replay_id).map_err(|e| e.to_string())?;
+    let replay = serde_json::json!({
+        "execution_id": replay_id,
+        "replay_of": original_id,
+        "result": result,
+        "matches_previous_replay": previous.map(|previous| previous.output == result.output),
+    });
+    Ok(replay.to_string())
+}
+
+// A REPL session keeps one interpreter alive across runs, so what one run defines the next can use
+#[command]
+async fn start_python_repl(state: State<'_, PythonState>) -> Result<String, String> {
+    state.session_manager.start_repl().await
+}
+
+// Run more code in a REPL session; like every run it lands in the execution history
+#[command]
+async fn evaluate_python_repl(
+    replId: String,
+    code: String,
+    state: State<'_, PythonState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+    }
+    
+    let result = state.session_manager.evaluate_in_repl(&replId, &code).await?;
+    
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    let execution_id = uuid::Uuid::new_v4().to_string();
+    let history = db.create_python_execution(&execution_id, &code, None)
+        .and_then(|_| db.append_python_output(&execution_id, &result.output))
+        .and_then(|_| db.finish_python_execution(&execution_id, if result.success { "success" } else { "error" }));
+    if let Err(e) = history {
+        eprintln!("Failed to record Python execution: {}", e);
+    }
+    
+    serde_json::to_string(&result).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn close_python_repl(replId: String, state: State<'_, PythonState>) -> Result<(), String> {
+    state.session_manager.close_repl(&replId).await
+}
+
+// Project-R's own virtual environments, with the one Python sessions run in marked as selected
+#[command]
+async fn list_venvs() -> Result<String, String> {
+    serde_json::to_string(&venv::list_venvs()?).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn get_python_output(sessionId: String, state: State<'_, PythonState>) -> Result<Vec<String>, String> {
-    state.session_manager.get_output(sessionId).await
+async fn create_venv(name: String) -> Result<String, String> {
+    let venv = venv::create_venv(&name).await?;
+    serde_json::to_string(&venv).map_err(|e| e.to_string())
+}
+
+// Run Python sessions started from now on in `name`, or on the system Python without one
+#[command]
+async fn select_venv(name: Option<String>, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let name = name.filter(|name| !name.is_empty());
+    venv::select(name.as_deref())?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("python_venv", name.as_deref().unwrap_or_default()).map_err(|e| e.to_string())
 }
 
 #[command]
@@ -89,263 +305,3089 @@ async fn test_microphone() -> Result<String, String> {
 }
 
 #[command]
-async fn start_recording(state: State<'_, AudioState>) -> Result<String, String> {
-    let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
-    recorder.start_recording()
+async fn list_input_devices() -> Result<String, String> {
+    let devices = audio::list_input_devices()?;
+    serde_json::to_string(&devices).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn stop_recording(state: State<'_, AudioState>) -> Result<String, String> {
-    let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
-    recorder.stop_recording()
+async fn set_input_device(
+    deviceId: Option<String>,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.set_input_device(deviceId.clone())?;
+    }
+    
+    // Remember the choice across restarts
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    match deviceId {
+        Some(id) => db.set_setting("input_device", &id),
+        None => db.delete_setting("input_device"),
+    }.map_err(|e| e.to_string())
 }
 
 #[command]
-async fn is_recording(state: State<'_, AudioState>) -> Result<bool, String> {
+async fn get_input_device(state: State<'_, AudioState>) -> Result<Option<String>, String> {
     let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
-    Ok(recorder.is_recording())
+    Ok(recorder.input_device_id.clone())
 }
 
 #[command]
-async fn record_audio_sample(duration_secs: u64) -> Result<String, String> {
-    // Recording audio for {} seconds...
-    audio::record_audio_to_file(duration_secs)
+async fn get_input_channel(state: State<'_, AudioState>) -> Result<String, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    serde_json::to_string(&recorder.channel).map_err(|e| e.to_string())
 }
 
+// Pick the interface channel the mic is on, or downmix all of them
 #[command]
-async fn initialize_whisper(state: State<'_, WhisperState>) -> Result<String, String> {
-    // Initializing Whisper model...
-    
-    // Download model if needed
-    let model_path = whisper::ensure_whisper_model().await?;
-    
-    // Initialize transcriber
-    let mut transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
-    transcriber.initialize(&model_path)?;
+async fn set_input_channel(
+    channel: audio::ChannelSelection,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.set_channel(channel)?;
+    }
     
-    Ok("Whisper model initialized successfully".to_string())
+    let value = serde_json::to_string(&channel).map_err(|e| e.to_string())?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("input_channel", &value).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn transcribe_audio(
-    audio_file_path: String,
-    state: State<'_, WhisperState>
-) -> Result<String, String> {
-    // Transcribing audio file: {}
+async fn get_recording_quality(state: State<'_, AudioState>) -> Result<String, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    serde_json::to_string(&recorder.quality).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_recording_quality(
+    quality: audio::RecordingQuality,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.quality = quality;
+    }
     
-    let transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
-    let transcription = transcriber.transcribe_audio_file(&audio_file_path)?;
+    let value = serde_json::to_string(&quality).map_err(|e| e.to_string())?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("recording_quality", &value).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_recording_format(state: State<'_, AudioState>) -> Result<String, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    serde_json::to_string(&recorder.output_format).map_err(|e| e.to_string())
+}
+
+// "wav", "flac" or "opus"; applies to recordings stopped from now on
+#[command]
+async fn set_recording_format(
+    format: audio_codec::RecordingFormat,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.output_format = format;
+    }
     
-    // Transcription result: {}
-    Ok(transcription)
+    let value = serde_json::to_string(&format).map_err(|e| e.to_string())?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("recording_format", &value).map_err(|e| e.to_string())
 }
 
+// Path of the archival recording from the most recent take, if archiving was on
 #[command]
-async fn test_ollama_connection() -> Result<String, String> {
-    llm::test_ollama_connection().await
+async fn get_last_archive_recording(state: State<'_, AudioState>) -> Result<Option<String>, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.last_archive_path.clone())
 }
 
 #[command]
-async fn initialize_llm(state: State<'_, LLMState>) -> Result<String, String> {
-    // Initializing LLM connection...
+async fn get_vad_settings(state: State<'_, AudioState>) -> Result<String, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    serde_json::to_string(&recorder.vad).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_vad_settings(
+    settings: audio::VadSettings,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    if settings.silence_ms == 0 {
+        return Err("Silence duration must be greater than zero".to_string());
+    }
     
-    // Test connection to Ollama
-    state.client.check_connection().await?;
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.vad = settings;
+    }
     
-    // Ensure Gemma 3n model is available
-    state.client.ensure_model("gemma3n").await?;
+    let value = serde_json::to_string(&settings).map_err(|e| e.to_string())?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("recording_vad", &value).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_noise_suppression(state: State<'_, AudioState>) -> Result<bool, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.noise_suppression)
+}
+
+#[command]
+async fn set_noise_suppression(
+    enabled: bool,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.noise_suppression = enabled;
+    }
     
-    Ok("LLM initialized successfully with Gemma 3n model".to_string())
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("noise_suppression", enabled).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn generate_ai_response(
-    userInput: String,
-    currentCode: String,
-    sessionId: Option<String>,
-    llm_state: State<'_, LLMState>,
+async fn get_max_recording_duration(state: State<'_, AudioState>) -> Result<u64, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.max_duration_secs)
+}
+
+#[command]
+async fn set_max_recording_duration(
+    seconds: u64,
+    state: State<'_, AudioState>,
     db_state: State<'_, DatabaseState>
-) -> Result<String, String> {
-    // Generating AI response for input: {}
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.max_duration_secs = seconds;
+    }
     
-    let response = llm_state.client
-        .generate_session_response(&userInput, &currentCode, "gemma3n")
-        .await?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("max_recording_seconds", &seconds.to_string()).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_resume_on_device_loss(state: State<'_, AudioState>) -> Result<bool, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.resume_on_device_loss)
+}
+
+// Whether an unplugged mic hands the recording over to the new default device instead of stopping
+#[command]
+async fn set_resume_on_device_loss(
+    enabled: bool,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.resume_on_device_loss = enabled;
+    }
     
-    // Save conversation history if sessionId is provided
-    if let Some(ref sessionId) = sessionId {
-        let db = db_state.db.lock().map_err(|e| e.to_string())?;
-        
-        // Save user message
-        db.add_message(sessionId, "user", &userInput)
-            .map_err(|e| format!("Failed to save user message: {}", e))?;
-        
-        // Save AI conversation response (not the code part)
-        db.add_message(sessionId, "assistant", &response.conversation_response)
-            .map_err(|e| format!("Failed to save assistant message: {}", e))?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("resume_on_device_loss", enabled).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_live_audio_streaming(state: State<'_, AudioState>) -> Result<bool, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.live_streaming)
+}
+
+// Stream "recording-pcm-chunk" events during recording for a live waveform
+#[command]
+async fn set_live_audio_streaming(
+    enabled: bool,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.live_streaming = enabled;
     }
     
-    // Convert the response back to JSON string for the frontend
-    let json_response = serde_json::to_string(&response)
-        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("live_audio_streaming", enabled).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_live_transcription(state: State<'_, AudioState>) -> Result<bool, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.live_transcription)
+}
+
+// Emit "transcription-partial" events with the words so far while recording
+#[command]
+async fn set_live_transcription(
+    enabled: bool,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.live_transcription = enabled;
+    }
     
-    Ok(json_response)
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("live_transcription", enabled).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn test_tts() -> Result<String, String> {
-    tts::test_tts()
+async fn get_echo_suppression(state: State<'_, AudioState>) -> Result<bool, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.echo_suppression)
 }
 
+// Record silence while TTS is speaking so the tutor's voice doesn't end up in the transcript
 #[command]
-async fn initialize_tts(state: State<'_, TTSState>) -> Result<String, String> {
-    // Initializing TTS engine...
+async fn set_echo_suppression(
+    enabled: bool,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.echo_suppression = enabled;
+    }
     
-    let mut engine = state.engine.lock().map_err(|e| e.to_string())?;
-    engine.initialize()?;
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("echo_suppression", enabled).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn start_recording(
+    sessionId: Option<String>,
+    app: tauri::AppHandle,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (corrections, initial_prompt, language) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+        let (corrections, initial_prompt) = load_transcription_hints(&db, sessionId.as_deref())?;
+        (corrections, initial_prompt, db.get_transcription_language().map_err(|e| e.to_string())?)
+    };
     
-    Ok("TTS engine initialized successfully".to_string())
+    let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    
+    // Partial transcripts while recording, when live transcription is on
+    let (sample_tap, live_samples) = if recorder.live_transcription {
+        let (sample_tap, live_samples) = std::sync::mpsc::channel();
+        (Some(sample_tap), Some(live_samples))
+    } else {
+        (None, None)
+    };
+    let live_app = app.clone();
+    
+    // On a VAD, duration-cap or lost-device stop the frontend hears about it and then calls stop_recording as usual
+    let auto_stop_app = app.clone();
+    let on_auto_stop: audio::AutoStopCallback = Box::new(move |file_path, reason| {
+        let _ = auto_stop_app.emit("recording-auto-stopped", serde_json::json!({
+            "file_path": file_path,
+            "reason": reason,
+        }));
+    });
+    let device_lost_app = app.clone();
+    let on_device_lost: audio::DeviceLostCallback = Box::new(move |loss| {
+        let _ = device_lost_app.emit("recording-device-lost", &loss);
+    });
+    let on_pcm_chunk: audio::PcmChunkCallback = Box::new(move |chunk| {
+        let _ = app.emit("recording-pcm-chunk", &chunk);
+    });
+    let recording_id = recorder.start_recording(Some(on_auto_stop), Some(on_device_lost), Some(on_pcm_chunk), sample_tap)?;
+    
+    if let Some(live_samples) = live_samples {
+        let live_recording_id = recording_id.clone();
+        std::thread::spawn(move || {
+            run_live_transcription(live_app, live_recording_id, live_samples, corrections, initial_prompt, language);
+        });
+    }
+    
+    Ok(recording_id)
+}
+
+// Drains the recorder's sample tap, emitting "transcription-partial" after each Whisper pass
+// and a final event once the recording stops and the channel closes
+fn run_live_transcription(
+    app: tauri::AppHandle,
+    recording_id: String,
+    live_samples: std::sync::mpsc::Receiver<Vec<f32>>,
+    corrections: Vec<(String, String)>,
+    initial_prompt: Option<String>,
+    language: String
+) {
+    // Shared with the passes, which run on the Whisper worker so they queue behind file transcriptions
+    let live = Arc::new(Mutex::new(whisper::LiveTranscription::new()));
+    let emit = |is_final: bool| {
+        let Ok(live) = live.lock() else { return };
+        let mut partial = live.snapshot(&recording_id, is_final);
+        partial.committed_text = whisper::apply_corrections(&partial.committed_text, &corrections);
+        partial.tentative_text = whisper::apply_corrections(&partial.tentative_text, &corrections);
+        let _ = app.emit("transcription-partial", &partial);
+    };
+    let run_pass = |finish: bool| -> Result<(), String> {
+        let live = Arc::clone(&live);
+        let initial_prompt = initial_prompt.clone();
+        let language = language.clone();
+        tauri::async_runtime::block_on(with_transcriber(&app, move |transcriber| {
+            let mut live = live.lock().map_err(|e| e.to_string())?;
+            live.step(transcriber, initial_prompt.as_deref(), &language, finish)
+        }))
+    };
+    let push = |block: &[f32]| live.lock().map(|mut live| live.push(block)).unwrap_or(false);
+    
+    for block in live_samples.iter() {
+        if !push(&block) {
+            continue;
+        }
+        // Take in whatever arrived during the previous pass so Whisper doesn't fall behind
+        while let Ok(block) = live_samples.try_recv() {
+            push(&block);
+        }
+        match run_pass(false) {
+            Ok(()) => emit(false),
+            Err(e) => eprintln!("Live transcription pass failed: {}", e),
+        }
+    }
+    
+    if let Err(e) = run_pass(true) {
+        eprintln!("Live transcription pass failed: {}", e);
+    }
+    emit(true);
 }
 
 #[command]
-async fn generate_and_play_speech(
-    text: String,
-    state: State<'_, TTSState>
+async fn stop_recording(
+    sessionId: Option<String>,
+    state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
 ) -> Result<String, String> {
-    // Generating and playing speech for: {}
+    let file_path = {
+        let mut recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.stop_recording()?
+    };
     
-    // The text is already clean conversation text from structured output
-    let engine = state.engine.lock().map_err(|e| e.to_string())?;
-    engine.generate_speech(&text)?;
+    // Queue the recording so it still gets transcribed if the app dies before transcribe_audio runs
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.enqueue_transcription_job(&file_path, sessionId.as_deref())
+        .map_err(|e| format!("Failed to queue transcription: {}", e))?;
+    
+    // Keep the recording's metadata so it can be replayed from the session later
+    let recording_id = std::path::Path::new(&file_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let duration_ms = audio_codec::duration_ms(&file_path);
+    db.add_recording(&recording_id, &file_path, duration_ms, sessionId.as_deref())
+        .map_err(|e| format!("Failed to save recording: {}", e))?;
     
-    Ok("Speech completed successfully".to_string())
+    Ok(file_path)
 }
 
-// Database commands
 #[command]
-async fn create_session(sessionId: String, title: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+async fn list_recordings() -> Result<String, String> {
+    let recordings = audio::list_recordings()?;
+    serde_json::to_string(&recordings).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_session_recordings(sessionId: Option<String>, state: State<'_, DatabaseState>) -> Result<String, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.create_session(&sessionId, &title).map_err(|e| e.to_string())
+    let recordings = db.get_recordings(sessionId.as_deref()).map_err(|e| e.to_string())?;
+    serde_json::to_string(&recordings).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn get_all_sessions(state: State<'_, DatabaseState>) -> Result<String, String> {
+async fn get_recording(recordingId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    let sessions = db.get_all_sessions().map_err(|e| e.to_string())?;
-    serde_json::to_string(&sessions).map_err(|e| e.to_string())
+    let recording = db.get_recording(&recordingId)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Recording {} not found", recordingId))?;
+    serde_json::to_string(&recording).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn get_session_messages(sessionId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+async fn get_recording_retention(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let policy = db.get_recording_retention().map_err(|e| e.to_string())?;
+    serde_json::to_string(&policy).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_recording_retention(policy: audio::RetentionPolicy, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_recording_retention(&policy).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn cleanup_recordings(
+    audio_state: State<'_, AudioState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let in_progress = {
+        let recorder = audio_state.recorder.lock().map_err(|e| e.to_string())?;
+        recorder.current_file_path.clone()
+    };
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    // Demo mode has no transcription jobs, so pending recordings wouldn't be protected
+    ensure_not_demo_mode(&db)?;
+    let report = run_recording_cleanup(&db, in_progress)?;
+    serde_json::to_string(&report).map_err(|e| e.to_string())
+}
+
+// Apply the retention policy, sparing the recording in progress and anything awaiting
+// transcription, and drop the rows of the recordings it deleted
+fn run_recording_cleanup(db: &database::Database, in_progress: Option<String>) -> Result<audio::CleanupReport, String> {
+    let policy = db.get_recording_retention().map_err(|e| e.to_string())?;
+    let mut protected: Vec<String> = db.get_transcription_jobs(Some("pending"))
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|job| job.file_path)
+        .collect();
+    protected.extend(in_progress);
+    let report = audio::cleanup_recordings(&policy, &protected)?;
+    db.delete_recordings_by_path(&report.deleted).map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+// Play back a recording so the user can review it before transcribing
+#[command]
+async fn play_recording(
+    filePath: String,
+    startMs: Option<u64>,
+    app: tauri::AppHandle,
+    state: State<'_, PlaybackState>
+) -> Result<String, String> {
+    let on_finished: playback::PlaybackFinishedCallback = Box::new(move |status| {
+        let _ = app.emit("recording-playback-finished", status);
+    });
+    
+    let mut player = state.player.lock().map_err(|e| e.to_string())?;
+    let status = player.play(&filePath, startMs.unwrap_or(0), on_finished)?;
+    serde_json::to_string(&status).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn stop_playback(state: State<'_, PlaybackState>) -> Result<(), String> {
+    let mut player = state.player.lock().map_err(|e| e.to_string())?;
+    player.stop();
+    Ok(())
+}
+
+#[command]
+async fn seek_playback(positionMs: u64, state: State<'_, PlaybackState>) -> Result<String, String> {
+    let mut player = state.player.lock().map_err(|e| e.to_string())?;
+    let status = player.seek(positionMs)?;
+    serde_json::to_string(&status).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_playback_status(state: State<'_, PlaybackState>) -> Result<String, String> {
+    let player = state.player.lock().map_err(|e| e.to_string())?;
+    serde_json::to_string(&player.status()).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn is_recording(state: State<'_, AudioState>) -> Result<bool, String> {
+    let recorder = state.recorder.lock().map_err(|e| e.to_string())?;
+    Ok(recorder.is_recording())
+}
+
+#[command]
+async fn record_audio_sample(duration_secs: u64) -> Result<String, String> {
+    // Recording audio for {} seconds...
+    audio::record_audio_to_file(duration_secs)
+}
+
+#[command]
+async fn initialize_whisper(
+    app: tauri::AppHandle,
+    state: State<'_, WhisperState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    // Initializing Whisper model...
+    let (use_gpu, language) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        (
+            db.get_bool_setting("whisper_use_gpu", true).map_err(|e| e.to_string())?,
+            db.get_transcription_language().map_err(|e| e.to_string())?,
+        )
+    };
+    
+    // Download model if needed, reporting progress so the UI isn't left waiting silently
+    let progress_app = app.clone();
+    let multilingual = whisper::needs_multilingual_model(&language);
+    let model_path = whisper::ensure_whisper_model(multilingual, move |progress| {
+        let _ = progress_app.emit("whisper-download-progress", progress);
+    }).await?;
+    
+    // Initialize transcriber
+    {
+        let mut transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
+        transcriber.initialize(&model_path, use_gpu)?;
+    }
+    
+    // Deliver transcriptions left over from a previous run
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = process_pending_transcriptions(&app).await {
+            eprintln!("Failed to process pending transcriptions: {}", e);
+        }
+    });
+    
+    Ok("Whisper model initialized successfully".to_string())
+}
+
+// Run `job` against the loaded model on the Whisper worker, behind any transcriptions already queued
+async fn with_transcriber<T, F>(app: &tauri::AppHandle, job: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&whisper::WhisperTranscriber) -> Result<T, String> + Send + 'static,
+{
+    let worker_app = app.clone();
+    app.state::<WhisperState>().worker.run(move || {
+        let state = worker_app.state::<WhisperState>();
+        let transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
+        if !transcriber.is_initialized() {
+            return Err(health::HealthIssue::whisper_not_ready().into_error());
+        }
+        job(&transcriber)
+    }).await
+}
+
+async fn process_pending_transcriptions(app: &tauri::AppHandle) -> Result<(), String> {
+    let db_state = app.state::<DatabaseState>();
+    
+    let (jobs, language) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let jobs = db.get_transcription_jobs(Some("pending")).map_err(|e| e.to_string())?;
+        (jobs, db.get_transcription_language().map_err(|e| e.to_string())?)
+    };
+    
+    for job in jobs {
+        let (corrections, initial_prompt) = {
+            let db = db_state.db.lock().map_err(|e| e.to_string())?;
+            load_transcription_hints(&db, job.session_id.as_deref())?
+        };
+        let file_path = job.file_path.clone();
+        let language = language.clone();
+        let result = with_transcriber(app, move |transcriber| {
+            transcriber.transcribe_audio_file(&file_path, initial_prompt.as_deref(), &language)
+        }).await
+            .map(|mut transcription| {
+                transcription.text = whisper::apply_corrections(&transcription.text, &corrections);
+                transcription
+            });
+        
+        {
+            let db = db_state.db.lock().map_err(|e| e.to_string())?;
+            let outcome = match &result {
+                Ok(transcription) => Ok(transcription.text.as_str()),
+                Err(e) => Err(e.as_str()),
+            };
+            db.finish_transcription_jobs_for_file(&job.file_path, outcome)
+                .map_err(|e| e.to_string())?;
+            if let Ok(transcription) = &result {
+                db.set_recording_transcript(&job.file_path, &transcription.text).map_err(|e| e.to_string())?;
+            }
+        }
+        
+        let _ = app.emit("transcription-job-finished", serde_json::json!({
+            "job_id": job.id,
+            "file_path": job.file_path,
+            "session_id": job.session_id,
+            "transcript": result.as_ref().ok().map(|transcription| &transcription.text),
+            "confidence": result.as_ref().ok().map(|transcription| transcription.confidence),
+            "low_confidence": result.as_ref().ok().map(|transcription| transcription.low_confidence),
+            "error": result.as_ref().err(),
+        }));
+    }
+    
+    Ok(())
+}
+
+#[command]
+async fn get_transcription_jobs(status: Option<String>, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let jobs = db.get_transcription_jobs(status.as_deref()).map_err(|e| e.to_string())?;
+    serde_json::to_string(&jobs).map_err(|e| e.to_string())
+}
+
+// Returns the text, the language it was transcribed in and how confident Whisper was;
+// `language` overrides the transcription language setting for this call ("auto" detects it).
+// `low_confidence` results are still stored but shouldn't be sent to the tutor as-is.
+#[command]
+async fn transcribe_audio(
+    audio_file_path: String,
+    language: Option<String>,
+    sessionId: Option<String>,
+    app: tauri::AppHandle,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    // Transcribing audio file: {}
+    
+    let (corrections, initial_prompt, language) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let (corrections, initial_prompt) = load_transcription_hints(&db, sessionId.as_deref())?;
+        let language = match language {
+            Some(language) => language,
+            None => db.get_transcription_language().map_err(|e| e.to_string())?,
+        };
+        (corrections, initial_prompt, language)
+    };
+    whisper::validate_language(&language)?;
+    
+    let file_path = audio_file_path.clone();
+    let result = with_transcriber(&app, move |transcriber| {
+        transcriber.transcribe_audio_file(&file_path, initial_prompt.as_deref(), &language)
+    }).await
+        .map(|mut transcription| {
+            transcription.text = whisper::apply_corrections(&transcription.text, &corrections);
+            transcription
+        });
+    
+    // Close out the queued job for this recording, if any
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let outcome = match &result {
+            Ok(transcription) => Ok(transcription.text.as_str()),
+            Err(e) => Err(e.as_str()),
+        };
+        db.finish_transcription_jobs_for_file(&audio_file_path, outcome)
+            .map_err(|e| e.to_string())?;
+        if let Ok(transcription) = &result {
+            db.set_recording_transcript(&audio_file_path, &transcription.text).map_err(|e| e.to_string())?;
+        }
+    }
+    
+    // Transcription result: {}
+    serde_json::to_string(&result?).map_err(|e| e.to_string())
+}
+
+// Transcribe several files one after another on the Whisper worker, e.g. lecture recordings
+// being imported into a session. Emits "transcription-batch-progress" as each file starts and
+// finishes; one file failing doesn't stop the rest, but cancel_transcription stops the batch.
+#[command]
+async fn transcribe_audio_batch(
+    paths: Vec<String>,
+    language: Option<String>,
+    sessionId: Option<String>,
+    app: tauri::AppHandle,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (corrections, initial_prompt, language) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let (corrections, initial_prompt) = load_transcription_hints(&db, sessionId.as_deref())?;
+        let language = match language {
+            Some(language) => language,
+            None => db.get_transcription_language().map_err(|e| e.to_string())?,
+        };
+        (corrections, initial_prompt, language)
+    };
+    whisper::validate_language(&language)?;
+    
+    let total = paths.len();
+    let mut results = Vec::with_capacity(total);
+    let mut cancelled = false;
+    
+    // One worker job per file, so single transcriptions requested meanwhile aren't stuck behind the whole batch
+    for (index, file_path) in paths.into_iter().enumerate() {
+        let result = if cancelled {
+            Err("Skipped after the batch was cancelled".to_string())
+        } else {
+            let job_app = app.clone();
+            let job_path = file_path.clone();
+            let initial_prompt = initial_prompt.clone();
+            let language = language.clone();
+            with_transcriber(&app, move |transcriber| {
+                let _ = job_app.emit("transcription-batch-progress", serde_json::json!({
+                    "index": index,
+                    "total": total,
+                    "file_path": &job_path,
+                    "status": "transcribing",
+                }));
+                transcriber.transcribe_audio_file(&job_path, initial_prompt.as_deref(), &language)
+            }).await
+                .map(|mut transcription| {
+                    transcription.text = whisper::apply_corrections(&transcription.text, &corrections);
+                    transcription
+                })
+        };
+        cancelled = cancelled || result.as_ref().err().is_some_and(|e| whisper::is_cancellation(e));
+        
+        if let Ok(transcription) = &result {
+            let db = db_state.db.lock().map_err(|e| e.to_string())?;
+            db.set_recording_transcript(&file_path, &transcription.text).map_err(|e| e.to_string())?;
+        }
+        
+        let file_result = whisper::BatchFileResult {
+            file_path,
+            error: result.as_ref().err().cloned(),
+            transcription: result.ok(),
+        };
+        let _ = app.emit("transcription-batch-progress", serde_json::json!({
+            "index": index,
+            "total": total,
+            "file_path": &file_result.file_path,
+            "status": if file_result.error.is_some() { "failed" } else { "done" },
+            "result": &file_result,
+        }));
+        results.push(file_result);
+    }
+    
+    serde_json::to_string(&results).map_err(|e| e.to_string())
+}
+
+// How many file transcriptions are waiting on the Whisper worker, and whether one is running
+#[command]
+async fn get_transcription_queue(state: State<'_, WhisperState>) -> Result<String, String> {
+    serde_json::to_string(&state.worker.status()).map_err(|e| e.to_string())
+}
+
+// Stop the file transcription in progress; it fails with "Transcription cancelled" and releases
+// the model. Returns false when nothing was being transcribed.
+#[command]
+async fn cancel_transcription(state: State<'_, WhisperState>) -> Result<bool, String> {
+    Ok(state.cancel.cancel())
+}
+
+// Like transcribe_audio, but with segment and word timings for highlighting during playback.
+// Doesn't touch the transcription queue; the recording's stored transcript is still updated.
+#[command]
+async fn transcribe_audio_detailed(
+    audioFilePath: String,
+    language: Option<String>,
+    sessionId: Option<String>,
+    app: tauri::AppHandle,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (corrections, initial_prompt, language) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let (corrections, initial_prompt) = load_transcription_hints(&db, sessionId.as_deref())?;
+        let language = match language {
+            Some(language) => language,
+            None => db.get_transcription_language().map_err(|e| e.to_string())?,
+        };
+        (corrections, initial_prompt, language)
+    };
+    whisper::validate_language(&language)?;
+    
+    let file_path = audioFilePath.clone();
+    let mut transcription = with_transcriber(&app, move |transcriber| {
+        transcriber.transcribe_detailed(&file_path, initial_prompt.as_deref(), &language)
+    }).await?;
+    
+    // Multi-word corrections only show up in the segment text; single words are fixed in place
+    for segment in transcription.segments.iter_mut() {
+        segment.text = whisper::apply_corrections(&segment.text, &corrections);
+        for word in segment.words.iter_mut() {
+            word.text = whisper::apply_corrections(&word.text, &corrections);
+        }
+    }
+    transcription.text = whisper::apply_corrections(&transcription.text, &corrections);
+    
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.set_recording_transcript(&audioFilePath, &transcription.text).map_err(|e| e.to_string())?;
+    }
+    
+    serde_json::to_string(&transcription).map_err(|e| e.to_string())
+}
+
+// Transcribe an imported recording into timestamped segments, optionally labeling speakers
+// as Teacher/Student, or as Speaker 1/2 with `numberSpeakers` (study groups, parent and child).
+// Long files are split on silence and emit "transcription-progress" with each chunk's segments.
+// With `sessionId` each speaker turn is also stored in that session as a user message.
+#[command]
+async fn transcribe_imported_recording(
+    audioFilePath: String,
+    labelSpeakers: Option<bool>,
+    numberSpeakers: Option<bool>,
+    sessionId: Option<String>,
+    app: tauri::AppHandle,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (corrections, initial_prompt, language) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let (corrections, initial_prompt) = load_transcription_hints(&db, None)?;
+        (corrections, initial_prompt, db.get_transcription_language().map_err(|e| e.to_string())?)
+    };
+    
+    let speaker_labels = if numberSpeakers.unwrap_or(false) {
+        Some(whisper::NUMBERED_SPEAKERS)
+    } else if labelSpeakers.unwrap_or(false) {
+        Some(["Teacher", "Student"])
+    } else {
+        None
+    };
+    let progress_app = app.clone();
+    let (segments, language) = with_transcriber(&app, move |transcriber| {
+        transcriber.transcribe_chunked(&audioFilePath, initial_prompt.as_deref(), &language, speaker_labels, |chunk| {
+            for segment in chunk.segments.iter_mut() {
+                segment.text = whisper::apply_corrections(&segment.text, &corrections);
+            }
+            let _ = progress_app.emit("transcription-progress", serde_json::json!({
+                "file_path": &audioFilePath,
+                "chunk": &*chunk,
+            }));
+        })
+    }).await?;
+    
+    let transcript = whisper::format_labeled_transcript(&segments);
+    let confidence = whisper::transcript_confidence(segments.iter().map(|segment| (segment.start_ms, segment.end_ms, segment.confidence)));
+    
+    let mut message_ids = Vec::new();
+    if let Some(session_id) = sessionId.as_deref() {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        for (speaker, text) in whisper::speaker_turns(&segments) {
+            let content = match speaker {
+                Some(speaker) => format!("{}: {}", speaker, text),
+                None => text,
+            };
+            message_ids.push(db.add_message(session_id, "user", &content).map_err(|e| e.to_string())?);
+        }
+    }
+    
+    let result = serde_json::json!({
+        "low_confidence": whisper::is_low_confidence(&transcript, confidence),
+        "transcript": transcript,
+        "segments": segments,
+        "language": language,
+        "confidence": confidence,
+        "message_ids": message_ids,
+    });
+    Ok(result.to_string())
+}
+
+// Loads the learner's correction dictionary and a Whisper prompt biased toward the intended
+// spellings, the learner's own terms, the session's code words and Python vocabulary, in that order
+fn load_transcription_hints(db: &database::Database, session_id: Option<&str>) -> Result<(Vec<(String, String)>, Option<String>), String> {
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    let corrections: Vec<(String, String)> = db.get_transcription_corrections(user_id)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|c| (c.misheard, c.intended))
+        .collect();
+    let hints = db.get_vocabulary_hints().map_err(|e| e.to_string())?;
+    
+    let mut terms: Vec<String> = corrections.iter().map(|(_, intended)| intended.clone()).collect();
+    terms.extend(hints.custom_terms.iter().cloned());
+    if let (true, Some(session_id)) = (hints.session_terms, session_id) {
+        let messages = db.get_session_messages(session_id).map_err(|e| e.to_string())?;
+        let recent: Vec<&str> = messages.iter()
+            .rev()
+            .take(20)
+            .rev()
+            .map(|message| message.content.as_str())
+            .collect();
+        terms.extend(whisper::session_vocabulary(&recent));
+    }
+    if hints.python_terms {
+        terms.extend(whisper::PYTHON_VOCABULARY.iter().map(|term| term.to_string()));
+    }
+    
+    Ok((corrections, whisper::build_vocabulary_prompt(&terms)))
+}
+
+// Transcription correction dictionary commands
+#[command]
+async fn record_transcript_edit(original: String, edited: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let corrections = whisper::learn_corrections(&original, &edited);
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    for (misheard, intended) in &corrections {
+        db.upsert_transcription_correction(user_id, misheard, intended)
+            .map_err(|e| e.to_string())?;
+    }
+    
+    serde_json::to_string(&corrections).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_transcription_corrections(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    let corrections = db.get_transcription_corrections(user_id).map_err(|e| e.to_string())?;
+    serde_json::to_string(&corrections).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn add_transcription_correction(misheard: String, intended: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    if misheard.trim().is_empty() || intended.trim().is_empty() {
+        return Err("Both the misheard and intended terms are required".to_string());
+    }
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    db.upsert_transcription_correction(user_id, misheard.trim(), intended.trim()).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn delete_transcription_correction(misheard: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    db.delete_transcription_correction(user_id, &misheard).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn test_ollama_connection() -> Result<String, String> {
+    llm::test_ollama_connection().await
+}
+
+#[command]
+async fn initialize_llm(
+    app: tauri::AppHandle,
+    state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    // Initializing LLM connection...
+    let model = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.get_chat_model().map_err(|e| e.to_string())?
+    };
+    
+    // Test connection to Ollama
+    state.client.check_connection().await?;
+    
+    // Ensure the chosen chat model is available, pulling it with progress if it isn't
+    let pull = state.client.ensure_model(&model, |progress| {
+        let _ = app.emit("model-pull-progress", progress);
+    });
+    run_pull(&state, &model, pull).await?;
+    
+    Ok(format!("LLM initialized successfully with {} model", model))
+}
+
+// Probe every subsystem and store the result for command gating
+async fn refresh_health(app: &tauri::AppHandle) -> Result<health::HealthReport, String> {
+    let (ollama_reachable, installed_models) = health::probe_ollama(&app.state::<LLMState>().client).await;
+    let whisper_ready = app.state::<WhisperState>().transcriber.lock()
+        .map(|transcriber| transcriber.is_initialized())
+        .map_err(|e| e.to_string())?;
+    let tts_ready = app.state::<TTSState>().engine.lock()
+        .map(|engine| engine.is_initialized())
+        .map_err(|e| e.to_string())?;
+    
+    let report = health::HealthReport {
+        ollama_reachable,
+        installed_models,
+        whisper_ready,
+        tts_ready,
+        checked_at: chrono::Utc::now(),
+    };
+    if health::record(report.clone()) {
+        let _ = app.emit("health-changed", &report);
+    }
+    update_llm_status(app, &report)?;
+    Ok(report)
+}
+
+// Emit "llm-status" when the chat model's status changes. When Ollama comes back after being
+// unreachable, the chat model is ensured again in the background, as initialize_llm does, so a
+// model lost while it was down is pulled without the user retrying.
+fn update_llm_status(app: &tauri::AppHandle, report: &health::HealthReport) -> Result<(), String> {
+    let db_state = app.state::<DatabaseState>();
+    let model = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.get_chat_model().map_err(|e| e.to_string())?
+    };
+    let llm_state = app.state::<LLMState>();
+    let status = health::LlmStatus::from_report(report, &model, llm_state.client.base_url());
+    let previous = health::record_llm_status(status.clone());
+    if previous.as_ref() == Some(&status) {
+        return Ok(());
+    }
+    let _ = app.emit("llm-status", &status);
+    
+    let reconnected = matches!(previous, Some(health::LlmStatus::Disconnected { .. }));
+    let pulling = llm_state.pulls.running_ids().iter().any(|id| id == &model);
+    if reconnected && matches!(status, health::LlmStatus::ModelMissing { .. }) && !pulling {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let llm_state = app.state::<LLMState>();
+            let pull = llm_state.client.ensure_model(&model, |progress| {
+                let _ = app.emit("model-pull-progress", progress);
+            });
+            match run_pull(&llm_state, &model, pull).await {
+                Ok(()) => {
+                    let status = health::LlmStatus::Connected { model: model.clone() };
+                    health::record_llm_status(status.clone());
+                    let _ = app.emit("llm-status", &status);
+                }
+                Err(e) => eprintln!("Couldn't restore {} after Ollama reconnected: {}", model, e),
+            }
+        });
+    }
+    Ok(())
+}
+
+// Health watchdog: re-checks subsystems in the background so gating rarely has to wait on a probe.
+// While Ollama is unreachable it checks more often, so reconnecting is noticed quickly.
+async fn watch_health(app: tauri::AppHandle) {
+    loop {
+        if let Err(e) = refresh_health(&app).await {
+            eprintln!("Health check failed: {}", e);
+        }
+        let seconds = match health::latest_llm_status() {
+            Some(health::LlmStatus::Disconnected { .. }) => 5,
+            _ => 30,
+        };
+        tokio::time::sleep(std::time::Duration::from_secs(seconds)).await;
+    }
+}
+
+// Fail fast with an actionable error when no backend in the chain can answer. A bad cached
+// report is confirmed with a fresh probe first, since Ollama may have come back since.
+async fn ensure_generation_ready(llm_state: &LLMState, chain: &[llm::ModelBackend]) -> Result<(), String> {
+    let local_url = llm_state.client.base_url();
+    if let Some(report) = health::latest() {
+        if health::check_chain(&report, chain, local_url).is_ok() {
+            return Ok(());
+        }
+    }
+    
+    let (ollama_reachable, installed_models) = health::probe_ollama(&llm_state.client).await;
+    let report = match health::latest() {
+        Some(previous) => health::HealthReport { ollama_reachable, installed_models, checked_at: chrono::Utc::now(), ..previous },
+        None => health::HealthReport {
+            ollama_reachable,
+            installed_models,
+            whisper_ready: false,
+            tts_ready: false,
+            checked_at: chrono::Utc::now(),
+        },
+    };
+    let result = health::check_chain(&report, chain, local_url);
+    health::record(report);
+    result.map_err(|issue| issue.into_error())
+}
+
+#[command]
+async fn get_health_status(app: tauri::AppHandle) -> Result<String, String> {
+    let report = refresh_health(&app).await?;
+    serde_json::to_string(&report).map_err(|e| e.to_string())
+}
+
+// The chat model's current status, in the shape of the "llm-status" event
+#[command]
+async fn get_llm_status(app: tauri::AppHandle) -> Result<String, String> {
+    refresh_health(&app).await?;
+    let status = health::latest_llm_status().ok_or("LLM status isn't available yet")?;
+    serde_json::to_string(&status).map_err(|e| e.to_string())
+}
+
+// Cached model listing; refreshes from Ollama when the cache is older than its TTL
+async fn get_models_cached(
+    llm_state: &LLMState,
+    db_state: &DatabaseState,
+    force_refresh: bool,
+) -> Result<Vec<llm::ModelInfo>, String> {
+    let (cached, ttl_minutes) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        (
+            db.get_cached_models().map_err(|e| e.to_string())?,
+            db.get_model_cache_ttl_minutes().map_err(|e| e.to_string())?,
+        )
+    };
+    
+    if let Some((models, cached_at)) = &cached {
+        let is_fresh = chrono::Utc::now() - *cached_at < chrono::Duration::minutes(ttl_minutes);
+        if is_fresh && !force_refresh {
+            return Ok(models.clone());
+        }
+    }
+    
+    match llm_state.client.list_models().await {
+        Ok(models) => {
+            let db = db_state.db.lock().map_err(|e| e.to_string())?;
+            db.replace_model_cache(&models).map_err(|e| e.to_string())?;
+            Ok(models)
+        }
+        // Serve stale metadata rather than nothing when Ollama is unreachable
+        Err(e) => cached.map(|(models, _)| models).ok_or(e),
+    }
+}
+
+// Models installed in the local Ollama (/api/tags), for picking the chat model
+#[command]
+async fn list_models(
+    forceRefresh: Option<bool>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let models = get_models_cached(&llm_state, &db_state, forceRefresh.unwrap_or(false)).await?;
+    serde_json::to_string(&models).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_chat_model(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_chat_model().map_err(|e| e.to_string())
+}
+
+// Used by tutoring replies, summaries, practice sheets and plans; must be installed locally
+#[command]
+async fn set_chat_model(
+    model: String,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    let model = model.trim();
+    if model.is_empty() {
+        return Err("Model name can't be empty".to_string());
+    }
+    let installed = get_models_cached(&llm_state, &db_state, true).await?;
+    if !installed.iter().any(|info| llm::matches_model_name(&info.name, model)) {
+        return Err(format!("Model {} is not installed; pull it with Ollama first", model));
+    }
+    
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.set_chat_model(model).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_ollama_client_config(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let config = db.get_ollama_client_config().map_err(|e| e.to_string())?;
+    serde_json::to_string(&config).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_ollama_client_config(config: ollama::ClientConfig, state: State<'_, DatabaseState>) -> Result<(), String> {
+    config.validate()?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_ollama_client_config(&config).map_err(|e| e.to_string())?;
+    ollama::set_config(config);
+    Ok(())
+}
+
+#[command]
+async fn get_model_details(
+    name: String,
+    forceRefresh: Option<bool>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let models = get_models_cached(&llm_state, &db_state, forceRefresh.unwrap_or(false)).await?;
+    let model = models.iter()
+        .find(|m| m.name == name || m.name == format!("{}:latest", name))
+        .ok_or_else(|| format!("Model {} is not installed", name))?;
+    serde_json::to_string(model).map_err(|e| e.to_string())
+}
+
+// Disk advisor: installed Ollama and Whisper models with sizes and whether the app still uses them
+#[command]
+async fn get_model_disk_usage(
+    llm_state: State<'_, LLMState>,
+    whisper_state: State<'_, WhisperState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let ollama_models = get_models_cached(&llm_state, &db_state, true).await?;
+    let models_in_use = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ollama_models_in_use(&db)?
+    };
+    let loaded_whisper = {
+        let transcriber = whisper_state.transcriber.lock().map_err(|e| e.to_string())?;
+        transcriber.model_path().map(|path| path.to_string())
+    };
+    let whisper_models = whisper::list_whisper_models().await?;
+    
+    let ollama: Vec<serde_json::Value> = ollama_models.iter().map(|model| serde_json::json!({
+        "model": model,
+        "in_use": models_in_use.iter().any(|name| llm::matches_model_name(&model.name, name)),
+    })).collect();
+    let whisper: Vec<serde_json::Value> = whisper_models.iter().map(|model| serde_json::json!({
+        "model": model,
+        "in_use": loaded_whisper.as_deref() == Some(model.path.as_str()),
+    })).collect();
+    
+    let result = serde_json::json!({
+        "ollama": ollama,
+        "whisper": whisper,
+        "ollama_bytes": ollama_models.iter().map(|model| model.size).sum::<u64>(),
+        "whisper_bytes": whisper_models.iter().map(|model| model.size_bytes).sum::<u64>(),
+    });
+    Ok(result.to_string())
+}
+
+// Models the fallback chains or the latency-budget fallback would call locally
+fn ollama_models_in_use(db: &database::Database) -> Result<Vec<String>, String> {
+    let mut models = Vec::new();
+    for feature in [llm::Feature::Tutor, llm::Feature::Summary, llm::Feature::Quiz] {
+        let chain = db.get_feature_model_chain(feature).map_err(|e| e.to_string())?;
+        models.extend(chain.into_iter().filter(|backend| !backend.is_remote()).map(|backend| backend.model));
+    }
+    let (_, fallback_model) = db.get_latency_budget().map_err(|e| e.to_string())?;
+    models.push(fallback_model);
+    let retrieval = db.get_retrieval_settings().map_err(|e| e.to_string())?;
+    if retrieval.enabled && retrieval.base_url.is_none() {
+        models.push(retrieval.model);
+    }
+    Ok(models)
+}
+
+// Installed models with size and modified date, each marked if settings still use it
+#[command]
+async fn list_installed_models(
+    forceRefresh: Option<bool>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let models = get_models_cached(&llm_state, &db_state, forceRefresh.unwrap_or(false)).await?;
+    let in_use = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ollama_models_in_use(&db)?
+    };
+    let installed: Vec<llm::InstalledModel> = models.into_iter()
+        .map(|info| {
+            let in_use = in_use.iter().any(|model| llm::matches_model_name(&info.name, model));
+            llm::InstalledModel { info, in_use }
+        })
+        .collect();
+    serde_json::to_string(&installed).map_err(|e| e.to_string())
+}
+
+// Models being pulled right now, so a reopened settings page can show their progress again
+#[command]
+async fn list_model_pulls(state: State<'_, LLMState>) -> Result<String, String> {
+    serde_json::to_string(&state.pulls.running_ids()).map_err(|e| e.to_string())
+}
+
+// Pull a model into the local Ollama, emitting "model-pull-progress" until it finishes
+#[command]
+async fn pull_ollama_model(
+    name: String,
+    app: tauri::AppHandle,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    let name = name.trim();
+    llm::validate_model_tag(name)?;
+    
+    let pull = llm_state.client.pull_model(name, |progress| {
+        let _ = app.emit("model-pull-progress", progress);
+    });
+    run_pull(&llm_state, name, pull).await?;
+    // Refresh the cached listing so the new model shows up right away
+    get_models_cached(&llm_state, &db_state, true).await?;
+    Ok(())
+}
+
+// Run a pull so cancel_model_pull can stop it
+async fn run_pull(
+    llm_state: &LLMState,
+    model: &str,
+    pull: impl std::future::Future<Output = Result<(), String>>
+) -> Result<(), String> {
+    llm_state.pulls.run(Some(model), pull).await.map_err(|e| {
+        if e == llm::GENERATION_CANCELLED { format!("Pull of {} cancelled", model) } else { e }
+    })
+}
+
+// Stop a running pull; Ollama keeps the downloaded layers, so pulling again resumes it.
+// Returns false if no pull of that model was running.
+#[command]
+async fn cancel_model_pull(name: String, state: State<'_, LLMState>) -> Result<bool, String> {
+    Ok(state.pulls.cancel(name.trim()))
+}
+
+#[command]
+async fn delete_ollama_model(
+    name: String,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        if ollama_models_in_use(&db)?.iter().any(|model| llm::matches_model_name(&name, model)) {
+            return Err(format!("Model {} is still used by the model chain or latency fallback; change those settings first", name));
+        }
+    }
+    
+    llm_state.client.delete_model(&name).await?;
+    // Refresh the cached listing so the deleted model disappears right away
+    get_models_cached(&llm_state, &db_state, true).await?;
+    Ok(())
+}
+
+#[command]
+async fn get_whisper_acceleration(
+    state: State<'_, WhisperState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let gpu_enabled = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.get_bool_setting("whisper_use_gpu", true).map_err(|e| e.to_string())?
+    };
+    let transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
+    serde_json::to_string(&transcriber.acceleration(gpu_enabled)).map_err(|e| e.to_string())
+}
+
+// Run Whisper on the GPU when the build supports it; a loaded model is reloaded right away
+#[command]
+async fn set_whisper_gpu(
+    enabled: bool,
+    state: State<'_, WhisperState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.set_bool_setting("whisper_use_gpu", enabled).map_err(|e| e.to_string())?;
+    }
+    
+    let mut transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
+    if let Some(model_path) = transcriber.model_path().map(|path| path.to_string()) {
+        transcriber.initialize(&model_path, enabled)?;
+    }
+    serde_json::to_string(&transcriber.acceleration(enabled)).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_vocabulary_hints(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let hints = db.get_vocabulary_hints().map_err(|e| e.to_string())?;
+    serde_json::to_string(&hints).map_err(|e| e.to_string())
+}
+
+// Terms Whisper is nudged toward; applies from the next transcription
+#[command]
+async fn set_vocabulary_hints(hints: whisper::VocabularyHints, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_vocabulary_hints(&hints).map_err(|e| e.to_string())
+}
+
+// The initial prompt Whisper would get right now, for checking which terms make the cut
+#[command]
+async fn preview_vocabulary_prompt(sessionId: Option<String>, state: State<'_, DatabaseState>) -> Result<Option<String>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let (_, initial_prompt) = load_transcription_hints(&db, sessionId.as_deref())?;
+    Ok(initial_prompt)
+}
+
+#[command]
+async fn get_transcription_language(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_transcription_language().map_err(|e| e.to_string())
+}
+
+// A Whisper language code or "auto"; a loaded model that can't handle it is swapped for one that can
+#[command]
+async fn set_transcription_language(
+    language: String,
+    app: tauri::AppHandle,
+    state: State<'_, WhisperState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    whisper::validate_language(&language)?;
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.set_setting("transcription_language", &language).map_err(|e| e.to_string())?;
+    }
+    
+    let needs_reload = {
+        let transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
+        transcriber.is_initialized() && transcriber.is_multilingual() != whisper::needs_multilingual_model(&language)
+    };
+    if needs_reload {
+        initialize_whisper(app, state, db_state).await?;
+    }
+    Ok(())
+}
+
+// Whisper models on disk with their sizes, plus the models the app can download
+#[command]
+async fn list_whisper_models(state: State<'_, WhisperState>) -> Result<String, String> {
+    let loaded = {
+        let transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
+        transcriber.model_path().map(|path| path.to_string())
+    };
+    let installed: Vec<serde_json::Value> = whisper::list_whisper_models().await?.iter().map(|model| serde_json::json!({
+        "model": model,
+        "in_use": loaded.as_deref() == Some(model.path.as_str()),
+    })).collect();
+    
+    let result = serde_json::json!({
+        "installed": installed,
+        "available": whisper::downloadable_whisper_models().await?,
+    });
+    Ok(result.to_string())
+}
+
+// Download a model ahead of time, emitting "whisper-download-progress"; returns its path.
+// Doesn't load it: initialize_whisper picks the model for the transcription language.
+#[command]
+async fn download_whisper_model(name: String, app: tauri::AppHandle) -> Result<String, String> {
+    whisper::download_whisper_model(&name, move |progress| {
+        let _ = app.emit("whisper-download-progress", progress);
+    }).await
+}
+
+// Returns the bytes freed
+#[command]
+async fn delete_whisper_model(name: String, state: State<'_, WhisperState>) -> Result<u64, String> {
+    {
+        let transcriber = state.transcriber.lock().map_err(|e| e.to_string())?;
+        let loaded = transcriber.model_path()
+            .and_then(|path| std::path::Path::new(path).file_name())
+            .map(|file_name| file_name.to_string_lossy() == name)
+            .unwrap_or(false);
+        if loaded {
+            return Err(format!("Whisper model {} is currently loaded", name));
+        }
+    }
+    whisper::delete_whisper_model(&name)
+}
+
+// Unwrap a fallback chain result, noting which backend served it and which failed first
+fn chain_response(outcome: llm::ChainOutcome<llm::SessionResponse>) -> llm::SessionResponse {
+    let mut response = outcome.value;
+    response.metadata.model = outcome.served_by.model.clone();
+    response.metadata.served_by = outcome.served_by.label();
+    response.metadata.failed_backends = outcome.failures;
+    response
+}
+
+// Verify the response before it is shown or spoken; problems downgrade it with a caution
+async fn self_check_response(
+    llm_state: &LLMState,
+    user_input: &str,
+    context: &session_context::SessionContext,
+    response: &mut llm::SessionResponse,
+) {
+    let mut check = llm::SelfCheckResult { passed: true, issues: Vec::new() };
+    
+    // Imports are checked here rather than trusting the reviewer to spot them
+    let disallowed = session_context::disallowed_imports(&response.code_to_insert, context);
+    if !disallowed.is_empty() {
+        check.passed = false;
+        check.issues.push(format!("Imports libraries the class hasn't covered: {}", disallowed.join(", ")));
+    }
+    
+    // Code that crashes is a discrepancy no matter what the reviewer says
+    if let Some(dry_run) = &response.dry_run {
+        if !dry_run.success && !dry_run.needs_input && !dry_run.timed_out {
+            check.passed = false;
+            check.issues.push(format!("Suggested code fails when run: {}", dry_run.error));
+        }
+    }
+    
+    match llm_state.client.verify_session_response(user_input, response, &context.render_for_prompt(), &response.metadata.model).await {
+        Ok(verdict) => {
+            check.passed &= verdict.passed;
+            check.issues.extend(verdict.issues);
+        }
+        // A failed review leaves the answer as-is rather than blocking the turn
+        Err(e) => eprintln!("Self-check failed: {}", e),
+    }
+    
+    if !check.passed {
+        response.conversation_response = format!(
+            "Heads up, I may have made a mistake here, so double-check this. {}",
+            response.conversation_response
+        );
+    }
+    response.self_check = Some(check);
+}
+
+// Class rules plus the active project plan, so answers stay within both
+fn tutor_context_section(context: &session_context::SessionContext, plan: Option<&project_plan::ProjectPlan>) -> String {
+    let mut section = context.render_for_prompt();
+    if let Some(plan) = plan {
+        section.push_str(&plan.render_for_prompt());
+    }
+    section
+}
+
+#[command]
+async fn generate_ai_response(
+    userInput: String,
+    currentCode: String,
+    sessionId: Option<String>,
+    requestId: Option<String>,
+    options: Option<llm::GenerationOptions>,
+    images: Option<Vec<String>>,
+    speak: Option<bool>,
+    language: Option<String>,
+    app: tauri::AppHandle,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    // Generating AI response for input: {}
+    let images = images.unwrap_or_default();
+    if images.len() > llm::MAX_IMAGES {
+        return Err(format!("At most {} images can be attached to a question", llm::MAX_IMAGES));
+    }
+    let images = images.iter().map(|image| llm::image_attachment(image)).collect::<Result<Vec<_>, _>>()?;
+    
+    let ((budget_ms, fallback_model), dry_run_enabled, self_check_enabled, memory_context, chain, session_context, active_plan, history, options, pedagogy) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+        let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+        (
+            db.get_latency_budget().map_err(|e| e.to_string())?,
+            db.get_bool_setting("dry_run_code", false).map_err(|e| e.to_string())?,
+            db.get_bool_setting("self_check_responses", false).map_err(|e| e.to_string())?,
+            db.get_tutor_memory_context(user_id).map_err(|e| e.to_string())?,
+            db.get_feature_model_chain(llm::Feature::Tutor).map_err(|e| e.to_string())?,
+            match &sessionId {
+                Some(session_id) => db.get_session_context(session_id).map_err(|e| e.to_string())?,
+                None => session_context::SessionContext::default(),
+            },
+            db.get_active_project_plan(user_id).map_err(|e| e.to_string())?,
+            load_chat_history(&db, sessionId.as_deref())?,
+            resolve_generation_options(&db, options)?,
+            db.get_pedagogy_mode().map_err(|e| e.to_string())?,
+        )
+    };
+    let (memory_context, memory_order) = retrieve_memory_context(&db_state.db, &userInput, sessionId.as_deref(), memory_context).await;
+    let context_section = tutor_context_section(&session_context, active_plan.as_ref());
+    ensure_generation_ready(&llm_state, &chain).await?;
+    
+    // With `speak`, the answer's sentences are queued for TTS as they're generated, so the tutor
+    // starts talking before the answer is finished; their ids end up in the response metadata.
+    // `language` is the Whisper code the question was asked in, which the answer is spoken in.
+    let speech_lexicon = if speak.unwrap_or(false) {
+        ensure_tts_ready(&app.state::<TTSState>())?;
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        Some(db.get_pronunciation_lexicon().map_err(|e| e.to_string())?)
+    } else {
+        None
+    };
+    // A checked answer is only spoken once the check is done, so a failed check's caution is
+    // the first thing heard
+    let stream_lexicon = speech_lexicon.as_ref().filter(|_| !self_check_enabled);
+    
+    // A cancelled answer is dropped before anything is saved to the session
+    let generation = async {
+        let started = std::time::Instant::now();
+        // Each backend speaks through a stream of its own. One that fails after speaking part of
+        // its answer has that speech stopped before the next backend is tried.
+        let primary = llm::walk_model_chain(&chain, |backend| {
+            let (text_stream, speaker) = stream_lexicon
+                .map(|lexicon| stream_speech(&app, lexicon.clone(), language.clone()))
+                .unzip();
+            let client = llm::OllamaClient::for_backend(&backend)
+                .with_options(options)
+                .with_pedagogy(pedagogy)
+                .with_memory_order(memory_order)
+                .with_text_stream(text_stream);
+            let (app, user_input, current_code, memory_context, context_section, history, images) = (&app, &userInput, &currentCode, &memory_context, &context_section, &history, &images);
+            async move {
+                let result = client.generate_session_response(user_input, current_code, memory_context, context_section, history, images, &backend.model).await;
+                drop(client);
+                match result {
+                    Ok(response) => Ok((response, speaker)),
+                    Err(e) => {
+                        if let Some(speaker) = speaker {
+                            if !speaker.await.unwrap_or_default().is_empty() {
+                                app.state::<TTSState>().speech.stop();
+                            }
+                        }
+                        Err(e)
+                    }
+                }
+            }
+        });
+        
+        // Within budget the fallback chain answers; past it, the fast fallback model takes this turn
+        let outcome = if budget_ms > 0 {
+            tokio::time::timeout(std::time::Duration::from_millis(budget_ms), primary).await.ok().transpose()?
+        } else {
+            Some(primary.await?)
+        };
+        let (mut response, speaker) = match outcome {
+            Some(llm::ChainOutcome { value: (response, speaker), served_by, failures }) => {
+                (chain_response(llm::ChainOutcome { value: response, served_by, failures }), speaker)
+            }
+            None => {
+                // Whatever the abandoned answer already said is cut off; the fallback's answer is
+                // spoken whole instead
+                if stream_lexicon.is_some() {
+                    app.state::<TTSState>().speech.stop();
+                }
+                let mut response = llm::OllamaClient::for_backend(&llm::ModelBackend::local(&fallback_model))
+                    .with_options(options)
+                    .with_pedagogy(pedagogy)
+                    .with_memory_order(memory_order)
+                    .generate_session_response(&userInput, &currentCode, &memory_context, &context_section, &history, &images, &fallback_model)
+                    .await?;
+                response.metadata.model = fallback_model.clone();
+                response.metadata.served_by = fallback_model.clone();
+                response.metadata.used_fallback = true;
+                (response, None)
+            }
+        };
+        response.metadata.latency_ms = started.elapsed().as_millis() as u64;
+        
+        // Test-run suggested code so failures surface before the user applies it
+        // (the self-check pass also relies on it)
+        if (dry_run_enabled || self_check_enabled) && !response.code_to_insert.trim().is_empty() {
+            let timeout = std::time::Duration::from_secs(5);
+            response.dry_run = Some(interactive_python::dry_run_python(&response.code_to_insert, timeout).await);
+        }
+        
+        if self_check_enabled {
+            self_check_response(&llm_state, &userInput, &session_context, &mut response).await;
+        }
+        Ok::<_, String>((response, speaker))
+    };
+    let (mut response, speaker) = llm_state.generations.run(requestId.as_deref(), generation).await?;
+    
+    if let Some(lexicon) = &speech_lexicon {
+        if let Some(speaker) = speaker {
+            response.metadata.utterance_ids = speaker.await.unwrap_or_default();
+        }
+        // Nothing is streamed for the fallback model's answer past the latency budget, or for a
+        // self-checked answer
+        if response.metadata.utterance_ids.is_empty() {
+            let utterance_id = queue_speech(&app, tts::prepare_for_speech(&response.conversation_response, lexicon), language.clone())?;
+            response.metadata.utterance_ids.push(utterance_id);
+        }
+    }
+    
+    // Save conversation history if sessionId is provided
+    if let Some(ref sessionId) = sessionId {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        
+        // Save user message
+        db.add_message(sessionId, "user", &userInput)
+            .map_err(|e| format!("Failed to save user message: {}", e))?;
+        
+        // Save AI conversation response (not the code part)
+        db.add_message(sessionId, "assistant", &response.conversation_response)
+            .map_err(|e| format!("Failed to save assistant message: {}", e))?;
+        
+        // After the first exchange, name a session still called "New Session" in the background
+        let first_exchange = db.get_session_messages(sessionId).map_err(|e| e.to_string())?.len() == 2;
+        let untitled = db.get_session_title(sessionId).map_err(|e| e.to_string())?
+            .is_some_and(|title| is_placeholder_title(&title));
+        if first_exchange && untitled && db.get_bool_setting("auto_session_titles", true).map_err(|e| e.to_string())? {
+            tauri::async_runtime::spawn(auto_title_session(app, sessionId.clone()));
+        }
+    }
+    
+    // Convert the response back to JSON string for the frontend
+    let json_response = serde_json::to_string(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+    
+    Ok(json_response)
+}
+
+// Sampling options for one LLM command: what the caller passed, then the user's defaults
+fn resolve_generation_options(db: &database::Database, requested: Option<llm::GenerationOptions>) -> Result<llm::GenerationOptions, String> {
+    let requested = requested.unwrap_or_default();
+    requested.validate()?;
+    Ok(requested.or(db.get_generation_options().map_err(|e| e.to_string())?))
+}
+
+#[command]
+async fn get_generation_options(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let options = db.get_generation_options().map_err(|e| e.to_string())?;
+    serde_json::to_string(&options).map_err(|e| e.to_string())
+}
+
+// Seeds are per request or come from the reproducibility settings, so a default seed isn't kept
+#[command]
+async fn set_generation_options(options: llm::GenerationOptions, state: State<'_, DatabaseState>) -> Result<(), String> {
+    options.validate()?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_generation_options(&llm::GenerationOptions { seed: None, ..options }).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_pedagogy_mode(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let mode = db.get_pedagogy_mode().map_err(|e| e.to_string())?;
+    serde_json::to_string(&mode).map_err(|e| e.to_string())
+}
+
+// "hints" makes the tutor answer with scaffolded hints and no code; "direct" is the default
+#[command]
+async fn set_pedagogy_mode(mode: llm::PedagogyMode, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_pedagogy_mode(mode).map_err(|e| e.to_string())
+}
+
+// Recent turns of the session for the tutor's chat history; none outside a session
+fn load_chat_history(db: &database::Database, session_id: Option<&str>) -> Result<Vec<llm::ChatMessage>, String> {
+    match session_id {
+        Some(session_id) => {
+            let messages = db.get_session_messages(session_id).map_err(|e| e.to_string())?;
+            Ok(llm::chat_history(&messages))
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+// Memory for a prompt about `query`: the indexed chunks closest to it when retrieval is on,
+// otherwise `fallback`, the whole rendered memory. Embedding failures (the model isn't pulled,
+// Ollama is down) fall back instead of failing the request.
+async fn retrieve_memory_context(
+    db: &Mutex<database::Database>,
+    query: &str,
+    exclude_session: Option<&str>,
+    fallback: String,
+) -> (String, llm::MemoryOrder) {
+    match retrieve_chunks(db, query, exclude_session).await {
+        Ok(Some(chunks)) => (embeddings::render_for_prompt(&chunks), llm::MemoryOrder::Ranked),
+        Ok(None) => (fallback, llm::MemoryOrder::Chronological),
+        Err(e) => {
+            eprintln!("Memory retrieval failed, using the whole memory instead: {}", e);
+            (fallback, llm::MemoryOrder::Chronological)
+        }
+    }
+}
+
+// None when retrieval is turned off. Embeds a batch of new memory and messages on the way, so
+// the index keeps up without a separate pass.
+async fn retrieve_chunks(db: &Mutex<database::Database>, query: &str, exclude_session: Option<&str>) -> Result<Option<Vec<embeddings::RetrievedChunk>>, String> {
+    let (user_id, settings, pending) = {
+        let db = db.lock().map_err(|e| e.to_string())?;
+        let settings = db.get_retrieval_settings().map_err(|e| e.to_string())?;
+        if !settings.enabled || query.trim().is_empty() {
+            return Ok(None);
+        }
+        db.prune_embeddings().map_err(|e| e.to_string())?;
+        let user_id = db.get_active_user_id().map_err(|e| e.to_string())?;
+        let pending = db.get_unindexed_sources(&user_id, &settings.model, embeddings::INDEX_BATCH).map_err(|e| e.to_string())?;
+        (user_id, settings, pending)
+    };
+
+    let client = embeddings::EmbeddingClient::new(settings.base_url.clone());
+    let query_vector = client.embed(&settings.model, query).await?;
+    if let Err(e) = index_sources(db, &client, &user_id, &settings.model, pending).await {
+        eprintln!("Failed to index memory for retrieval: {}", e);
+    }
+
+    let db = db.lock().map_err(|e| e.to_string())?;
+    let candidates = db.get_embeddings(&user_id, &settings.model, exclude_session).map_err(|e| e.to_string())?;
+    Ok(Some(embeddings::rank(&query_vector, &candidates, &settings)))
+}
+
+// Embed and store `sources` for `user_id`; returns how many were indexed
+async fn index_sources(
+    db: &Mutex<database::Database>,
+    client: &embeddings::EmbeddingClient,
+    user_id: &str,
+    model: &str,
+    sources: Vec<embeddings::PendingSource>,
+) -> Result<usize, String> {
+    let (embedded, error) = client.embed_sources(model, sources).await;
+    {
+        let db = db.lock().map_err(|e| e.to_string())?;
+        for (source, chunks) in &embedded {
+            db.save_embeddings(user_id, model, source, chunks).map_err(|e| e.to_string())?;
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(embedded.len()),
+    }
+}
+
+#[command]
+async fn get_retrieval_settings(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let settings = db.get_retrieval_settings().map_err(|e| e.to_string())?;
+    serde_json::to_string(&settings).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_retrieval_settings(settings: embeddings::RetrievalSettings, state: State<'_, DatabaseState>) -> Result<(), String> {
+    settings.validate()?;
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_retrieval_settings(&settings).map_err(|e| e.to_string())
+}
+
+// Drop the index and embed all memory and messages again, e.g. after changing the embedding
+// model. Returns how many memory entries and messages were indexed.
+#[command]
+async fn rebuild_embedding_index(state: State<'_, DatabaseState>) -> Result<usize, String> {
+    let (user_id, settings) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let user_id = db.get_active_user_id().map_err(|e| e.to_string())?;
+        db.clear_embeddings(&user_id).map_err(|e| e.to_string())?;
+        (user_id, db.get_retrieval_settings().map_err(|e| e.to_string())?)
+    };
+    let client = embeddings::EmbeddingClient::new(settings.base_url.clone());
+
+    let mut indexed = 0;
+    loop {
+        let pending = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_unindexed_sources(&user_id, &settings.model, embeddings::INDEX_BATCH).map_err(|e| e.to_string())?
+        };
+        if pending.is_empty() {
+            return Ok(indexed);
+        }
+        indexed += index_sources(&state.db, &client, &user_id, &settings.model, pending).await?;
+    }
+}
+
+// The memory and past messages retrieval would add to a prompt about `query`, with scores
+#[command]
+async fn search_memory(query: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let chunks = retrieve_chunks(&state.db, &query, None).await?
+        .ok_or_else(|| "Memory retrieval is turned off".to_string())?;
+    serde_json::to_string(&chunks).map_err(|e| e.to_string())
+}
+
+// The exact prompt generate_ai_response would send for this input, with a token estimate
+#[command]
+async fn preview_prompt(
+    sessionId: Option<String>,
+    userInput: Option<String>,
+    currentCode: Option<String>,
+    options: Option<llm::GenerationOptions>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (memory_context, chain, session_context, active_plan, history, options, pedagogy) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+        (
+            db.get_tutor_memory_context(user_id).map_err(|e| e.to_string())?,
+            db.get_feature_model_chain(llm::Feature::Tutor).map_err(|e| e.to_string())?,
+            match &sessionId {
+                Some(session_id) => db.get_session_context(session_id).map_err(|e| e.to_string())?,
+                None => session_context::SessionContext::default(),
+            },
+            db.get_active_project_plan(user_id).map_err(|e| e.to_string())?,
+            load_chat_history(&db, sessionId.as_deref())?,
+            resolve_generation_options(&db, options)?,
+            db.get_pedagogy_mode().map_err(|e| e.to_string())?,
+        )
+    };
+    let user_input = userInput.unwrap_or_default();
+    let (memory_context, memory_order) = retrieve_memory_context(&db_state.db, &user_input, sessionId.as_deref(), memory_context).await;
+    let backend = chain.first().cloned().unwrap_or_else(|| llm::ModelBackend::local(llm::DEFAULT_CHAT_MODEL));
+    
+    // Fitted to the context window of the model that would answer first
+    let preview = llm::OllamaClient::for_backend(&backend)
+        .with_options(options)
+        .with_pedagogy(pedagogy)
+        .with_memory_order(memory_order)
+        .preview_session_prompt(
+            &user_input,
+            currentCode.as_deref().unwrap_or_default(),
+            &memory_context,
+            &tutor_context_section(&session_context, active_plan.as_ref()),
+            &history,
+            &backend.model,
+        )
+        .await;
+    serde_json::to_string(&preview).map_err(|e| e.to_string())
+}
+
+// Stop a generate_ai_response, ask_about_selection or explain_python_error call started with this requestId; it fails
+// with "Generation cancelled". Returns false if it already finished.
+#[command]
+async fn cancel_generation(requestId: String, state: State<'_, LLMState>) -> Result<bool, String> {
+    Ok(state.generations.cancel(&requestId))
+}
+
+#[command]
+async fn ask_about_selection(
+    sessionId: Option<String>,
+    code: String,
+    selectionRange: llm::SelectionRange,
+    question: String,
+    requestId: Option<String>,
+    options: Option<llm::GenerationOptions>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (chain, context_section, options) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+        let context = match &sessionId {
+            Some(session_id) => db.get_session_context(session_id).map_err(|e| e.to_string())?,
+            None => session_context::SessionContext::default(),
+        };
+        (
+            db.get_feature_model_chain(llm::Feature::Tutor).map_err(|e| e.to_string())?,
+            context.render_for_prompt(),
+            resolve_generation_options(&db, options)?,
+        )
+    };
+    ensure_generation_ready(&llm_state, &chain).await?;
+    
+    let started = std::time::Instant::now();
+    let generation = llm::walk_model_chain(&chain, |backend| {
+        let client = llm::OllamaClient::for_backend(&backend).with_options(options);
+        let (question, code, selection_range, context_section) = (&question, &code, &selectionRange, &context_section);
+        async move {
+            client.generate_selection_response(question, code, selection_range, context_section, &backend.model).await
+        }
+    });
+    let outcome = llm_state.generations.run(requestId.as_deref(), generation).await?;
+    let mut response = chain_response(outcome);
+    response.metadata.latency_ms = started.elapsed().as_millis() as u64;
+    
+    // Save the exchange to the session, noting which lines were asked about
+    if let Some(ref sessionId) = sessionId {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let user_message = format!(
+            "{} (about lines {}-{})",
+            question, selectionRange.start_line, selectionRange.end_line
+        );
+        
+        db.add_message(sessionId, "user", &user_message)
+            .map_err(|e| format!("Failed to save user message: {}", e))?;
+        db.add_message(sessionId, "assistant", &response.conversation_response)
+            .map_err(|e| format!("Failed to save assistant message: {}", e))?;
+    }
+    
+    serde_json::to_string(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))
+}
+
+// Explain a failed run of the learner's code from the traceback the Python runner captured,
+// with a suggested fix as a diff against their code
+#[command]
+async fn explain_python_error(
+    code: String,
+    traceback: String,
+    sessionId: Option<String>,
+    requestId: Option<String>,
+    options: Option<llm::GenerationOptions>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    if traceback.trim().is_empty() {
+        return Err("No error output to explain".to_string());
+    }
+    let (chain, context_section, options) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+        let context = match &sessionId {
+            Some(session_id) => db.get_session_context(session_id).map_err(|e| e.to_string())?,
+            None => session_context::SessionContext::default(),
+        };
+        (
+            db.get_feature_model_chain(llm::Feature::Tutor).map_err(|e| e.to_string())?,
+            context.render_for_prompt(),
+            resolve_generation_options(&db, options)?,
+        )
+    };
+    ensure_generation_ready(&llm_state, &chain).await?;
+    
+    let started = std::time::Instant::now();
+    let generation = llm::walk_model_chain(&chain, |backend| {
+        let client = llm::OllamaClient::for_backend(&backend).with_options(options);
+        let (code, traceback, context_section) = (&code, &traceback, &context_section);
+        async move {
+            client.explain_error(code, traceback, context_section, &backend.model).await
+        }
+    });
+    let outcome = llm_state.generations.run(requestId.as_deref(), generation).await?;
+    let mut explanation = outcome.value;
+    explanation.metadata.model = outcome.served_by.model.clone();
+    explanation.metadata.served_by = outcome.served_by.label();
+    explanation.metadata.failed_backends = outcome.failures;
+    explanation.metadata.latency_ms = started.elapsed().as_millis() as u64;
+    
+    // Save the exchange to the session under the error's last line
+    if let Some(ref sessionId) = sessionId {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let error_line = traceback.trim_end().lines().last().unwrap_or_default();
+        db.add_message(sessionId, "user", &format!("Why does my code fail with {}?", error_line))
+            .map_err(|e| format!("Failed to save user message: {}", e))?;
+        db.add_message(sessionId, "assistant", &format!("{} {}", explanation.summary, explanation.cause))
+            .map_err(|e| format!("Failed to save assistant message: {}", e))?;
+    }
+    
+    serde_json::to_string(&explanation)
+        .map_err(|e| format!("Failed to serialize response: {}", e))
+}
+
+#[command]
+async fn test_tts() -> Result<String, String> {
+    tts::test_tts()
+}
+
+#[command]
+async fn initialize_tts(state: State<'_, TTSState>) -> Result<String, String> {
+    // Initializing TTS engine...
+    
+    let mut engine = state.engine.lock().map_err(|e| e.to_string())?;
+    engine.initialize()?;
+    
+    Ok("TTS engine initialized successfully".to_string())
+}
+
+// Queue `text` behind anything already being spoken and return its utterance id straight away;
+// tts-started and tts-finished events with that id follow as it's spoken
+#[command]
+async fn generate_and_play_speech(
+    text: String,
+    app: tauri::AppHandle,
+    state: State<'_, TTSState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    // Generating and playing speech for: {}
+    
+    // The text is already clean conversation text from structured output
+    let lexicon = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.get_pronunciation_lexicon().map_err(|e| e.to_string())?
+    };
+    let spoken_text = tts::prepare_for_speech(&text, &lexicon);
+    
+    ensure_tts_ready(&state)?;
+    queue_speech(&app, spoken_text, None)
+}
+
+// Write `text` as speech to a WAV in the TTS cache and return its path, for the frontend to play
+// with play_recording, show progress on and replay. Waits for any utterance being spoken.
+#[command]
+async fn synthesize_speech_to_file(
+    text: String,
+    app: tauri::AppHandle,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let lexicon = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_not_demo_mode(&db)?;
+        db.get_pronunciation_lexicon().map_err(|e| e.to_string())?
+    };
+    let spoken_text = tts::prepare_for_speech(&text, &lexicon);
+    let output = tts::new_synthesized_file()?;
+    
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<TTSState>();
+        let engine = state.engine.lock().map_err(|e| e.to_string())?;
+        if !engine.is_initialized() {
+            return Err(health::HealthIssue::tts_not_ready().into_error());
+        }
+        engine.synthesize_to_file(&spoken_text, &output)?;
+        Ok(output.to_string_lossy().into_owned())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// Delete cached Piper speech; returns the bytes freed
+#[command]
+async fn clear_tts_cache() -> Result<u64, String> {
+    tts::clear_speech_cache()
+}
+
+// The engine is only locked elsewhere while an utterance is spoken, which means it's ready
+fn ensure_tts_ready(state: &TTSState) -> Result<(), String> {
+    if let Ok(engine) = state.engine.try_lock() {
+        if !engine.is_initialized() {
+            return Err(health::HealthIssue::tts_not_ready().into_error());
+        }
+    }
+    Ok(())
+}
+
+// Speak a tutor answer while it streams in: each sentence of its conversation_response is queued
+// as soon as it's complete. Send the reply's text to the returned sender; the task ends once
+// every clone of it is dropped, with the ids of the queued utterances. Its sentences are all
+// dropped by a stop after the stream starts, including the ones still to come.
+fn stream_speech(
+    app: &tauri::AppHandle,
+    lexicon: Vec<(String, String)>,
+    language: Option<String>
+) -> (tokio::sync::mpsc::UnboundedSender<String>, tauri::async_runtime::JoinHandle<Vec<String>>) {
+    let (text_stream, mut chunks) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let app = app.clone();
+    let ticket = app.state::<TTSState>().speech.ticket();
+    let speaker = tauri::async_runtime::spawn(async move {
+        let mut spoken = speech_stream::SpokenTextStream::default();
+        let mut utterance_ids = Vec::new();
+        let mut speak = |sentence: String| match queue_speech_after(&app, tts::prepare_for_speech(&sentence, &lexicon), language.clone(), ticket) {
+            Ok(utterance_id) => utterance_ids.push(utterance_id),
+            Err(e) => eprintln!("Failed to queue speech: {}", e),
+        };
+        while let Some(chunk) = chunks.recv().await {
+            spoken.push(&chunk).into_iter().for_each(&mut speak);
+        }
+        spoken.finish().into_iter().for_each(&mut speak);
+        utterance_ids
+    });
+    (text_stream, speaker)
+}
+
+// Put an utterance on the speech queue. It's dropped if stop_speech is called before its turn.
+// With `language` it's spoken by a voice for that language when one is installed.
+fn queue_speech(app: &tauri::AppHandle, text: String, language: Option<String>) -> Result<String, String> {
+    let ticket = app.state::<TTSState>().speech.ticket();
+    queue_speech_after(app, text, language, ticket)
+}
+
+// queue_speech for an utterance that's dropped by any stop since `ticket` was taken
+fn queue_speech_after(app: &tauri::AppHandle, text: String, language: Option<String>, ticket: u64) -> Result<String, String> {
+    let state = app.state::<TTSState>();
+    let utterance_id = uuid::Uuid::new_v4().to_string();
+    
+    let worker_app = app.clone();
+    let id = utterance_id.clone();
+    state.queue.push(move || {
+        let (status, error) = match speak_utterance(&worker_app, &id, &text, language.as_deref(), ticket) {
+            Ok(true) => ("completed", None),
+            Ok(false) => ("stopped", None),
+            Err(e) => ("failed", Some(e)),
+        };
+        let _ = worker_app.emit("tts-finished", serde_json::json!({
+            "utterance_id": id,
+            "status": status,
+            "error": error,
+        }));
+    })?;
+    Ok(utterance_id)
+}
+
+// Speak one queued utterance on the queue's thread; false when it was stopped
+fn speak_utterance(app: &tauri::AppHandle, utterance_id: &str, text: &str, language: Option<&str>, ticket: u64) -> Result<bool, String> {
+    let state = app.state::<TTSState>();
+    if state.speech.stopped_since(ticket) {
+        return Ok(false);
+    }
+    let engine = state.engine.lock().map_err(|e| e.to_string())?;
+    if !engine.is_initialized() {
+        return Err(health::HealthIssue::tts_not_ready().into_error());
+    }
+    
+    let voice = match language {
+        Some(language) => {
+            let settings = {
+                let db_state = app.state::<DatabaseState>();
+                let db = db_state.db.lock().map_err(|e| e.to_string())?;
+                db.get_tts_settings().map_err(|e| e.to_string())?
+            };
+            // The engine's own voice speaks when the voices can't be listed
+            engine.list_voices().ok().and_then(|voices| settings.voice_for_language(&voices, language))
+        }
+        None => None,
+    };
+    
+    let _ = app.emit("tts-started", serde_json::json!({
+        "utterance_id": utterance_id,
+        "text": text,
+    }));
+    // Word by word, so the caption can highlight what's being said
+    let progress_app = app.clone();
+    let id = utterance_id.to_string();
+    state.speech.on_word(Some(Box::new(move |word| {
+        let _ = progress_app.emit("tts-progress", serde_json::json!({
+            "utterance_id": id,
+            "start": word.start,
+            "end": word.end,
+            "offset_ms": word.offset_ms,
+        }));
+    })));
+    state.echo_gate.begin_speaking();
+    let result = match &voice {
+        Some(voice) => engine.speak_with_voice(text, voice),
+        None => engine.generate_speech(text),
+    };
+    state.echo_gate.end_speaking();
+    state.speech.on_word(None);
+    result?;
+    
+    Ok(!state.speech.stopped_since(ticket))
+}
+
+// Cut off the utterance being spoken and drop the ones waiting, so a follow-up can be asked
+// straight away
+#[command]
+async fn stop_speech(state: State<'_, TTSState>) -> Result<(), String> {
+    state.speech.stop();
+    Ok(())
+}
+
+#[command]
+async fn get_tts_settings(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let settings = db.get_tts_settings().map_err(|e| e.to_string())?;
+    serde_json::to_string(&settings).map_err(|e| e.to_string())
+}
+
+// Save the settings and switch to a fresh engine for them, initializing it straight away so a
+// missing Piper binary or voice is reported here
+#[command]
+async fn set_tts_settings(
+    settings: tts::TtsSettings,
+    state: State<'_, TTSState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    settings.validate()?;
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.set_tts_settings(&settings).map_err(|e| e.to_string())?;
+    }
+    
+    let mut engine = state.engine.lock().map_err(|e| e.to_string())?;
+    *engine = tts::create_engine(&settings, state.speech.clone());
+    engine.initialize()
+}
+
+// Voices of the current TTS backend, for picking the tutor's accent and gender
+#[command]
+async fn list_tts_voices(state: State<'_, TTSState>) -> Result<String, String> {
+    let voices = state.engine.lock().map_err(|e| e.to_string())?.list_voices()?;
+    serde_json::to_string(&voices).map_err(|e| e.to_string())
+}
+
+// Speak with `voiceId` from list_tts_voices, on whichever backend is selected
+#[command]
+async fn set_tts_voice(
+    voiceId: String,
+    state: State<'_, TTSState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    let mut engine = state.engine.lock().map_err(|e| e.to_string())?;
+    if !engine.list_voices()?.iter().any(|voice| voice.id == voiceId) {
+        return Err(format!("Voice {} isn't available", voiceId));
+    }
+    
+    let settings = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let mut settings = db.get_tts_settings().map_err(|e| e.to_string())?;
+        match settings.backend {
+            tts::TtsBackendKind::System => settings.system_voice = Some(voiceId),
+            tts::TtsBackendKind::Piper => settings.piper_voice = Some(voiceId),
+        }
+        db.set_tts_settings(&settings).map_err(|e| e.to_string())?;
+        settings
+    };
+    *engine = tts::create_engine(&settings, state.speech.clone());
+    engine.initialize()
+}
+
+// Paths of the Piper voices installed in the voices folder
+#[command]
+async fn list_piper_voices() -> Result<Vec<String>, String> {
+    Ok(tts::list_piper_voices().iter().map(|voice| voice.to_string_lossy().into_owned()).collect())
+}
+
+// Pronunciation lexicon applied before TTS
+#[command]
+async fn get_pronunciation_lexicon(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let entries: Vec<serde_json::Value> = db.get_pronunciation_lexicon()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(word, spoken)| serde_json::json!({ "word": word, "spoken": spoken }))
+        .collect();
+    serde_json::to_string(&entries).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_pronunciation(word: String, spoken: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let word = word.trim();
+    if word.is_empty() || word.contains(char::is_whitespace) || spoken.trim().is_empty() {
+        return Err("Pronunciations map a single word to non-empty spoken text".to_string());
+    }
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_pronunciation(word, spoken.trim()).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn delete_pronunciation(word: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.delete_pronunciation(word.trim()).map_err(|e| e.to_string())
+}
+
+// Preview how text will be spoken, code rendering and lexicon included, without speaking it
+#[command]
+async fn preview_pronunciation(text: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let lexicon = db.get_pronunciation_lexicon().map_err(|e| e.to_string())?;
+    Ok(tts::prepare_for_speech(&text, &lexicon))
+}
+
+// Database commands
+#[command]
+async fn create_session(sessionId: String, title: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_within_usage_limit(&db)?;
+    db.create_session(&sessionId, &title).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_all_sessions(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let sessions = db.get_all_sessions().map_err(|e| e.to_string())?;
+    serde_json::to_string(&sessions).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_session_messages(sessionId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
     let messages = db.get_session_messages(&sessionId).map_err(|e| e.to_string())?;
     serde_json::to_string(&messages).map_err(|e| e.to_string())
 }
 
+// Find messages in one session containing `query`, with highlight offsets and snippets
+#[command]
+async fn search_in_session(sessionId: String, query: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let matches = db.search_session_messages(&sessionId, &query).map_err(|e| e.to_string())?;
+    serde_json::to_string(&matches).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_session_context(sessionId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let context = db.get_session_context(&sessionId).map_err(|e| e.to_string())?;
+    serde_json::to_string(&context).map_err(|e| e.to_string())
+}
+
+// Python version, allowed libraries and rules the tutor and self-check hold this session to
+#[command]
+async fn set_session_context(sessionId: String, context: session_context::SessionContext, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_session_context(&sessionId, &context).map_err(|e| e.to_string())
+}
+
+// Remember the last message the learner has seen in this session
+#[command]
+async fn mark_session_read(sessionId: String, messageId: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.mark_session_read(&sessionId, &messageId)
+        .map_err(|e| format!("Failed to mark session {} read: {}", sessionId, e))
+}
+
+// Unread count and the first message to scroll to when reopening a session
+#[command]
+async fn get_session_read_state(sessionId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let read_state = db.get_session_read_state(&sessionId).map_err(|e| e.to_string())?;
+    serde_json::to_string(&read_state).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn add_message(sessionId: String, role: String, content: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.add_message(&sessionId, &role, &content).map_err(|e| e.to_string())
+}
+
+// Titles the frontend gives sessions before they have one
+fn is_placeholder_title(title: &str) -> bool {
+    let title = title.trim();
+    title.is_empty() || title.eq_ignore_ascii_case("New Session")
+}
+
+// Title from the session's first question and answer, asked of the tutor chain
+async fn generate_title_for_session(chain: &[llm::ModelBackend], messages: &[database::Message]) -> Result<String, String> {
+    let question = messages.iter().find(|message| message.role == "user")
+        .map(|message| message.content.as_str())
+        .ok_or("The session has no messages to take a title from")?;
+    let answer = messages.iter().find(|message| message.role == "assistant")
+        .map(|message| message.content.as_str())
+        .unwrap_or_default();
+    let outcome = llm::walk_model_chain(chain, |backend| async move {
+        llm::OllamaClient::for_backend(&backend).generate_session_title(question, answer, &backend.model).await
+    }).await?;
+    Ok(outcome.value)
+}
+
+async fn auto_title_session(app: tauri::AppHandle, session_id: String) {
+    // Goes through the app's own connection, so sessions made in demo mode are titled too
+    let result = async {
+        let state = app.state::<DatabaseState>();
+        let (chain, messages) = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            (
+                db.get_feature_model_chain(llm::Feature::Tutor).map_err(|e| e.to_string())?,
+                db.get_session_messages(&session_id).map_err(|e| e.to_string())?,
+            )
+        };
+        let title = generate_title_for_session(&chain, &messages).await?;
+        
+        // The user may have renamed the session while the title was generated, or left demo mode
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let still_untitled = db.get_session_title(&session_id).map_err(|e| e.to_string())?
+            .is_some_and(|current| is_placeholder_title(&current));
+        if still_untitled {
+            db.update_session_title(&session_id, &title).map_err(|e| e.to_string())?;
+            let _ = app.emit("session-title-updated", serde_json::json!({
+                "sessionId": session_id,
+                "title": title,
+            }));
+        }
+        Ok::<_, String>(())
+    }.await;
+    if let Err(e) = result {
+        eprintln!("Failed to title session {}: {}", session_id, e);
+    }
+}
+
+// Name the session from its first exchange, replacing its current title; returns the new title
+#[command]
+async fn generate_session_title(
+    sessionId: String,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (chain, messages) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        (
+            db.get_feature_model_chain(llm::Feature::Tutor).map_err(|e| e.to_string())?,
+            db.get_session_messages(&sessionId).map_err(|e| e.to_string())?,
+        )
+    };
+    ensure_generation_ready(&llm_state, &chain).await?;
+    let title = generate_title_for_session(&chain, &messages).await?;
+    
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    db.update_session_title(&sessionId, &title).map_err(|e| e.to_string())?;
+    Ok(title)
+}
+
+#[command]
+async fn get_auto_session_titles_enabled(state: State<'_, DatabaseState>) -> Result<bool, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_bool_setting("auto_session_titles", true).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_auto_session_titles_enabled(enabled: bool, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("auto_session_titles", enabled).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn update_session_title(sessionId: String, title: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.update_session_title(&sessionId, &title).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn delete_session(sessionId: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_not_demo_mode(&db)?;
+    db.delete_session(&sessionId).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn delete_sessions(sessionIds: Vec<String>, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_not_demo_mode(&db)?;
+    db.delete_sessions(&sessionIds).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn archive_sessions(sessionIds: Vec<String>, archived: Option<bool>, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_not_demo_mode(&db)?;
+    db.archive_sessions(&sessionIds, archived.unwrap_or(true)).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn tag_sessions(sessionIds: Vec<String>, tag: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err("Tag cannot be empty".to_string());
+    }
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_not_demo_mode(&db)?;
+    db.tag_sessions(&sessionIds, tag).map_err(|e| e.to_string())
+}
+
+// Demo mode commands. The demo database has no usage limit, so while one is set entering demo
+// mode needs the guardian PIN.
+#[command]
+async fn set_demo_mode(enabled: bool, pin: Option<String>, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let mut db = state.db.lock().map_err(|e| e.to_string())?;
+    if db.is_demo_mode() == enabled {
+        return Ok(());
+    }
+    if let Some(limit) = db.get_usage_limit().map_err(|e| e.to_string())? {
+        if !limit.verify_pin(pin.as_deref().unwrap_or_default()) {
+            return Err("Incorrect guardian PIN".to_string());
+        }
+    }
+    
+    // Swap in a throwaway database (or back to the real one); demo data is discarded
+    *db = if enabled {
+        database::Database::new_demo()
+    } else {
+        database::Database::new()
+    }.map_err(|e| e.to_string())?;
+    
+    Ok(())
+}
+
+#[command]
+async fn is_demo_mode(state: State<'_, DatabaseState>) -> Result<bool, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    Ok(db.is_demo_mode())
+}
+
+// Forget every cached summary and quiz so the next ones are generated afresh; returns how many
+// were dropped
+#[command]
+async fn clear_response_cache(state: State<'_, DatabaseState>) -> Result<usize, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.clear_response_cache().map_err(|e| e.to_string())
+}
+
+// Every prompt template with its default and whether it has been customized
+#[command]
+async fn list_prompt_templates() -> Result<String, String> {
+    let templates: Vec<prompts::PromptTemplate> = PromptKind::ALL.into_iter().map(prompts::describe).collect();
+    serde_json::to_string(&templates).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_prompt_template(id: String) -> Result<String, String> {
+    let template = prompts::describe(PromptKind::from_id(&id)?);
+    serde_json::to_string(&template).map_err(|e| e.to_string())
+}
+
+// Saved templates apply from the next request; cached summaries and quizzes made with the old
+// one aren't reused since the prompt is part of the cache key
+#[command]
+async fn set_prompt_template(id: String, content: String) -> Result<(), String> {
+    prompts::set_override(PromptKind::from_id(&id)?, &content)
+}
+
+#[command]
+async fn reset_prompt_template(id: String) -> Result<(), String> {
+    prompts::reset(PromptKind::from_id(&id)?)
+}
+
+// Memory management commands
+#[command]
+async fn generate_session_summary(
+    sessionId: String, 
+    seed: Option<i64>,
+    options: Option<llm::GenerationOptions>,
+    skipCache: Option<bool>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    // Get session messages and seed settings (scope the lock)
+    let (messages, reproducibility, chain, options) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        (
+            db.get_session_messages(&sessionId).map_err(|e| e.to_string())?,
+            db.get_reproducibility_settings().map_err(|e| e.to_string())?,
+            db.get_feature_model_chain(llm::Feature::Summary).map_err(|e| e.to_string())?,
+            resolve_generation_options(&db, options)?,
+        )
+    };
+    ensure_generation_ready(&llm_state, &chain).await?;
+    
+    if messages.is_empty() {
+        return Err("No messages found for this session".to_string());
+    }
+    
+    // Format messages for LLM
+    let formatted_session = session_summary::format_session_for_summary(&messages);
+    
+    // Reuse the summary of an identical earlier request unless a fresh one is asked for
+    let requested_seed = seed.or(options.seed);
+    let prompt = prompts::render(PromptKind::Summary, &[("conversation", &formatted_session)]);
+    let cache_request = response_cache::CacheRequest {
+        feature: response_cache::CachedFeature::Summary,
+        prompt: &prompt,
+        fixed_seed: requested_seed.or(reproducibility.seed),
+        deterministic: reproducibility.deterministic,
+        options,
+    };
+    let cached = if skipCache.unwrap_or(false) {
+        None
+    } else {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        response_cache::lookup(&db, &chain, &cache_request)?
+    };
+    
+    let (summary, seed, backend) = match cached {
+        Some(cached) => (cached.response, cached.seed, cached.backend),
+        None => {
+            // Generate summary using LLM (an explicit seed replays an earlier generation)
+            let seed = requested_seed.unwrap_or_else(|| reproducibility.resolve_seed());
+            let outcome = generate_summary_with_chain(&chain, &formatted_session, seed, reproducibility.deterministic, options).await?;
+            let db = db_state.db.lock().map_err(|e| e.to_string())?;
+            if let Err(e) = response_cache::store(&db, &cache_request, &outcome.served_by, &outcome.value, seed) {
+                eprintln!("Failed to cache session summary: {}", e);
+            }
+            (outcome.value, seed, outcome.served_by.label())
+        }
+    };
+    
+    // Append summary to memory and record the seed and backend (scope the lock)
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        let user_id = &db.get_session_user_id(&sessionId).map_err(|e| e.to_string())?;
+        db.append_to_memory(user_id, &summary).map_err(|e| e.to_string())?;
+        db.set_session_summary_seed(&sessionId, seed).map_err(|e| e.to_string())?;
+        db.set_session_summary_backend(&sessionId, &backend).map_err(|e| e.to_string())?;
+    }
+    
+    Ok(summary)
+}
+
+async fn generate_summary_with_chain(
+    chain: &[llm::ModelBackend],
+    formatted_session: &str,
+    seed: i64,
+    deterministic: bool,
+    options: llm::GenerationOptions
+) -> Result<llm::ChainOutcome<String>, String> {
+    llm::walk_model_chain(chain, |backend| {
+        let client = session_summary::SummaryLLMClient::for_backend(&backend).with_options(options);
+        async move {
+            client.generate_session_summary(formatted_session, &backend.model, seed, deterministic).await
+        }
+    }).await
+}
+
+#[command]
+async fn get_memory_content(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    db.get_memory_content(user_id).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn append_to_memory(content: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_not_demo_mode(&db)?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    db.append_to_memory(user_id, &content).map_err(|e| e.to_string())
+}
+
+// Aggregate view across all learner profiles for classroom use
+#[command]
+async fn get_class_overview(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let overview = db.get_class_overview().map_err(|e| e.to_string())?;
+    serde_json::to_string(&overview).map_err(|e| e.to_string())
+}
+
+// The learner profile new sessions, memory and corrections belong to
+#[command]
+async fn get_active_learner(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_active_user_id().map_err(|e| e.to_string())
+}
+
+// Switch to a learner profile, creating it the first time it's used
+#[command]
+async fn set_active_learner(userId: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let user_id = userId.trim();
+    if user_id.is_empty() || user_id.len() > 64 {
+        return Err("A learner profile needs a name of 1 to 64 characters".to_string());
+    }
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_active_user_id(user_id).map_err(|e| e.to_string())
+}
+
+// Memory injection controls
+#[command]
+async fn get_memory_injection_settings(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let settings = db.get_memory_injection_settings().map_err(|e| e.to_string())?;
+    serde_json::to_string(&settings).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_memory_injection_settings(settings: memory::MemoryInjectionSettings, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_memory_injection_settings(&settings).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_memory_entries(category: Option<String>, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    let entries = db.get_memory_entries(user_id, category.as_deref()).map_err(|e| e.to_string())?;
+    serde_json::to_string(&entries).map_err(|e| e.to_string())
+}
+
+// Shows exactly what the tutor is told about the learner, category by category
+#[command]
+async fn get_tutor_memory_view(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    let structured = memory::parse_memory(&db.get_memory_content(user_id).map_err(|e| e.to_string())?);
+    let settings = db.get_memory_injection_settings().map_err(|e| e.to_string())?;
+    
+    let view = serde_json::json!({
+        "settings": settings,
+        "categories": structured,
+        "injected_text": memory::render_for_prompt(&structured, &settings),
+    });
+    Ok(view.to_string())
+}
+
+// Reproducibility settings for quiz and summary generation
+#[command]
+async fn get_reproducibility_settings(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let settings = db.get_reproducibility_settings().map_err(|e| e.to_string())?;
+    serde_json::to_string(&settings).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_reproducibility_settings(seed: Option<i64>, deterministic: bool, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_reproducibility_settings(&llm::ReproducibilitySettings { seed, deterministic })
+        .map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_self_check_enabled(state: State<'_, DatabaseState>) -> Result<bool, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_bool_setting("self_check_responses", false).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_self_check_enabled(enabled: bool, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("self_check_responses", enabled).map_err(|e| e.to_string())
+}
+
+// Ordered list of backends each generation call tries until one succeeds
+#[command]
+async fn get_model_chain(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let mut chain = db.get_model_chain().map_err(|e| e.to_string())?;
+    keychain::mark_api_keys(&mut chain);
+    serde_json::to_string(&chain).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_model_chain(chain: Vec<llm::ModelBackend>, state: State<'_, DatabaseState>) -> Result<(), String> {
+    validate_model_chain(&chain)?;
+    keychain::save_api_keys(&chain)?;
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_model_chain(&chain).map_err(|e| e.to_string())
+}
+
+fn validate_model_chain(chain: &[llm::ModelBackend]) -> Result<(), String> {
+    if chain.is_empty() {
+        return Err("Model chain needs at least one backend".to_string());
+    }
+    if chain.iter().any(|backend| backend.model.trim().is_empty()) {
+        return Err("Every backend in the model chain needs a model name".to_string());
+    }
+    Ok(())
+}
+
+// The chain the tutor, summaries or quizzes use; the shared chain unless the feature has its own
+#[command]
+async fn get_feature_model_chain(feature: llm::Feature, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let mut chain = db.get_feature_model_chain(feature).map_err(|e| e.to_string())?;
+    keychain::mark_api_keys(&mut chain);
+    serde_json::to_string(&chain).map_err(|e| e.to_string())
+}
+
+// Give a feature its own chain, e.g. a remote OpenAI-compatible model for the tutor on a weak
+// machine; no chain puts it back on the shared one
+#[command]
+async fn set_feature_model_chain(
+    feature: llm::Feature,
+    chain: Option<Vec<llm::ModelBackend>>,
+    state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    if let Some(chain) = &chain {
+        validate_model_chain(chain)?;
+        keychain::save_api_keys(chain)?;
+    }
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_feature_model_chain(feature, chain.as_deref()).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_dry_run_enabled(state: State<'_, DatabaseState>) -> Result<bool, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_bool_setting("dry_run_code", false).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_dry_run_enabled(enabled: bool, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_bool_setting("dry_run_code", enabled).map_err(|e| e.to_string())
+}
+
+// Per-turn latency budget settings
+#[command]
+async fn get_latency_budget(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let (budget_ms, fallback_model) = db.get_latency_budget().map_err(|e| e.to_string())?;
+    Ok(serde_json::json!({ "budget_ms": budget_ms, "fallback_model": fallback_model }).to_string())
+}
+
+#[command]
+async fn set_latency_budget(budgetMs: u64, fallbackModel: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    if fallbackModel.trim().is_empty() {
+        return Err("Fallback model cannot be empty".to_string());
+    }
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("turn_latency_budget_ms", &budgetMs.to_string()).map_err(|e| e.to_string())?;
+    db.set_setting("fallback_model", fallbackModel.trim()).map_err(|e| e.to_string())
+}
+
+// Idle auto-end settings (minutes of inactivity; 0 disables)
+#[command]
+async fn get_idle_timeout(state: State<'_, DatabaseState>) -> Result<i64, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_idle_timeout_minutes().map_err(|e| e.to_string())
+}
+
+#[command]
+async fn set_idle_timeout(minutes: i64, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_setting("idle_timeout_minutes", &minutes.max(0).to_string()).map_err(|e| e.to_string())
+}
+
+// Practice sheet commands
+#[command]
+async fn generate_practice_sheet_from_summary(
+    summary: String,
+    sessionId: String,
+    seed: Option<i64>,
+    options: Option<llm::GenerationOptions>,
+    skipCache: Option<bool>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (reproducibility, messages, chain, options) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+        (
+            db.get_reproducibility_settings().map_err(|e| e.to_string())?,
+            db.get_session_messages(&sessionId).map_err(|e| e.to_string())?,
+            db.get_feature_model_chain(llm::Feature::Quiz).map_err(|e| e.to_string())?,
+            resolve_generation_options(&db, options)?,
+        )
+    };
+    ensure_generation_ready(&llm_state, &chain).await?;
+    
+    // Reuse the quiz of an identical earlier request unless a fresh one is asked for
+    let requested_seed = seed.or(options.seed);
+    let prompt = practice_sheet::PracticeSheetLLMClient::create_practice_sheet_prompt(&summary);
+    let cache_request = response_cache::CacheRequest {
+        feature: response_cache::CachedFeature::Quiz,
+        prompt: &prompt,
+        fixed_seed: requested_seed.or(reproducibility.seed),
+        deterministic: reproducibility.deterministic,
+        options,
+    };
+    let cached = if skipCache.unwrap_or(false) {
+        None
+    } else {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        response_cache::lookup(&db, &chain, &cache_request)?
+    };
+    let cached = cached.and_then(|cached| {
+        let questions: Vec<practice_sheet::QuizQuestion> = serde_json::from_str(&cached.response).ok()?;
+        Some((questions, cached.seed, cached.backend))
+    });
+    
+    let (mut questions, seed, generation_backend) = match cached {
+        Some(cached) => cached,
+        None => {
+            // Generate quiz questions using LLM (an explicit seed replays an earlier generation)
+            let seed = requested_seed.unwrap_or_else(|| reproducibility.resolve_seed());
+            let outcome = llm::walk_model_chain(&chain, |backend| {
+                let client = practice_sheet::PracticeSheetLLMClient::for_backend(&backend).with_options(options);
+                let summary = &summary;
+                async move {
+                    client.generate_practice_sheet(summary, &backend.model, seed, reproducibility.deterministic).await
+                }
+            }).await?;
+            let response = serde_json::to_string(&outcome.value).map_err(|e| e.to_string())?;
+            let db = db_state.db.lock().map_err(|e| e.to_string())?;
+            if let Err(e) = response_cache::store(&db, &cache_request, &outcome.served_by, &response, seed) {
+                eprintln!("Failed to cache practice sheet: {}", e);
+            }
+            (outcome.value, seed, outcome.served_by.label())
+        }
+    };
+    
+    // Remember where each question came from so the UI can jump back to it
+    practice_sheet::link_question_sources(&mut questions, &messages, &summary);
+    
+    // Extract title from summary
+    let title = practice_sheet::extract_session_title_from_summary(&summary);
+    
+    // Save to database (scope the lock)
+    {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        
+        // Create practice sheet
+        let practice_sheet_id = db.create_practice_sheet(&sessionId, &title, Some(seed))
+            .map_err(|e| e.to_string())?;
+        db.set_practice_sheet_backend(&practice_sheet_id, &generation_backend)
+            .map_err(|e| e.to_string())?;
+        
+        // Add all questions
+        for (index, question) in questions.iter().enumerate() {
+            db.add_practice_question(&practice_sheet_id, question, (index + 1) as i32)
+                .map_err(|e| e.to_string())?;
+        }
+        
+        // Keep generated questions in the bank so they can be mixed with imported ones later
+        db.add_bank_questions(&questions, "generated", None)
+            .map_err(|e| e.to_string())?;
+        
+        Ok(practice_sheet_id)
+    }
+}
+
+// Project plans: multi-session projects broken into milestones the test runner verifies
+#[command]
+async fn create_project_plan(
+    goal: String,
+    sessionId: Option<String>,
+    options: Option<llm::GenerationOptions>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    if goal.trim().is_empty() {
+        return Err("Describe the project you want to build".to_string());
+    }
+    let (memory_context, user_id, chain, session_context, options) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+        let user_id = db.get_active_user_id().map_err(|e| e.to_string())?;
+        (
+            db.get_tutor_memory_context(&user_id).map_err(|e| e.to_string())?,
+            user_id,
+            db.get_feature_model_chain(llm::Feature::Tutor).map_err(|e| e.to_string())?,
+            match &sessionId {
+                Some(session_id) => db.get_session_context(session_id).map_err(|e| e.to_string())?,
+                None => session_context::SessionContext::default(),
+            },
+            resolve_generation_options(&db, options)?,
+        )
+    };
+    let (memory_context, _) = retrieve_memory_context(&db_state.db, &goal, None, memory_context).await;
+    ensure_generation_ready(&llm_state, &chain).await?;
+    
+    let context_section = session_context.render_for_prompt();
+    let outcome = llm::walk_model_chain(&chain, |backend| {
+        let client = llm::OllamaClient::for_backend(&backend).with_options(options);
+        let (goal, memory_context, context_section) = (&goal, &memory_context, &context_section);
+        async move {
+            client.generate_project_plan(goal, memory_context, context_section, &backend.model).await
+        }
+    }).await?;
+    
+    let db = db_state.db.lock().map_err(|e| e.to_string())?;
+    let plan_id = db.create_project_plan(&user_id, sessionId.as_deref(), goal.trim(), &outcome.value)
+        .map_err(|e| e.to_string())?;
+    let plan = db.get_project_plan(&plan_id).map_err(|e| e.to_string())?;
+    serde_json::to_string(&plan).map_err(|e| e.to_string())
+}
+
 #[command]
-async fn add_message(sessionId: String, role: String, content: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+async fn get_project_plans(state: State<'_, DatabaseState>) -> Result<String, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.add_message(&sessionId, &role, &content).map_err(|e| e.to_string())
+    let user_id = db.get_active_user_id().map_err(|e| e.to_string())?;
+    let plans = db.get_project_plans(&user_id).map_err(|e| e.to_string())?;
+    serde_json::to_string(&plans).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn update_session_title(sessionId: String, title: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+async fn get_project_plan(planId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.update_session_title(&sessionId, &title).map_err(|e| e.to_string())
+    let plan = db.get_project_plan(&planId).map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Project plan {} not found", planId))?;
+    serde_json::to_string(&plan).map_err(|e| e.to_string())
 }
 
+// Switch plans or set one aside; activating a plan pauses the one it replaces
 #[command]
-async fn delete_session(sessionId: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+async fn set_project_plan_status(planId: String, status: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    if !project_plan::PLAN_STATUSES.contains(&status.as_str()) {
+        return Err(format!("Unknown plan status '{}'", status));
+    }
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.delete_session(&sessionId).map_err(|e| e.to_string())
+    db.set_project_plan_status(&planId, &status).map_err(|e| e.to_string())
 }
 
-// Memory management commands
+// Run the milestone's check against the learner's code; returns the updated plan
 #[command]
-async fn generate_session_summary(
-    sessionId: String, 
-    db_state: State<'_, DatabaseState>,
-    summary_state: State<'_, SummaryState>
+async fn check_project_milestone(
+    planId: String,
+    milestoneId: String,
+    code: String,
+    state: State<'_, DatabaseState>
 ) -> Result<String, String> {
-    // Get session messages (scope the lock)
-    let messages = {
-        let db = db_state.db.lock().map_err(|e| e.to_string())?;
-        db.get_session_messages(&sessionId).map_err(|e| e.to_string())?
+    let milestone = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let plan = db.get_project_plan(&planId).map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Project plan {} not found", planId))?;
+        plan.milestones.into_iter()
+            .find(|milestone| milestone.id == milestoneId)
+            .ok_or_else(|| format!("Milestone {} is not part of this plan", milestoneId))?
     };
     
-    if messages.is_empty() {
-        return Err("No messages found for this session".to_string());
-    }
-    
-    // Format messages for LLM
-    let formatted_session = session_summary::format_session_for_summary(&messages);
-    
-    // Generate summary using LLM
-    let summary = summary_state.client
-        .generate_session_summary(&formatted_session, "gemma3n")
-        .await?;
-    
-    // Append summary to memory (scope the lock)
-    {
-        let db = db_state.db.lock().map_err(|e| e.to_string())?;
-        let user_id = "default_user"; // Single user system for now
-        db.append_to_memory(user_id, &summary).map_err(|e| e.to_string())?;
-    }
+    let check = project_plan::check_milestone(&milestone, &code).await;
     
-    Ok(summary)
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.record_milestone_check(&milestone.id, &check).map_err(|e| e.to_string())?;
+    let plan = db.get_project_plan(&planId).map_err(|e| e.to_string())?;
+    serde_json::to_string(&plan).map_err(|e| e.to_string())
 }
 
+// Question bank: import teacher-authored questions and assemble sheets from the bank
 #[command]
-async fn get_memory_content(state: State<'_, DatabaseState>) -> Result<String, String> {
+async fn import_questions(path: String, format: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let format = question_import::ImportFormat::parse(&format)?;
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let questions = question_import::parse_questions(&content, format)?;
+    
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    let user_id = "default_user"; // Single user system for now
-    db.get_memory_content(user_id).map_err(|e| e.to_string())
+    let ids = db.add_bank_questions(&questions, "imported", Some(&path))
+        .map_err(|e| e.to_string())?;
+    serde_json::to_string(&ids).map_err(|e| e.to_string())
 }
 
 #[command]
-async fn append_to_memory(content: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+async fn get_question_bank(source: Option<String>, state: State<'_, DatabaseState>) -> Result<String, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    let user_id = "default_user"; // Single user system for now
-    db.append_to_memory(user_id, &content).map_err(|e| e.to_string())
+    let questions = db.get_bank_questions(source.as_deref()).map_err(|e| e.to_string())?;
+    serde_json::to_string(&questions).map_err(|e| e.to_string())
 }
 
-// Practice sheet commands
 #[command]
-async fn generate_practice_sheet_from_summary(
-    summary: String,
+async fn assemble_practice_sheet(
     sessionId: String,
-    practice_state: State<'_, PracticeSheetState>,
-    db_state: State<'_, DatabaseState>
+    title: String,
+    questionIds: Vec<String>,
+    state: State<'_, DatabaseState>
 ) -> Result<String, String> {
-    // Generate quiz questions using LLM
-    let questions = practice_state.client
-        .generate_practice_sheet(&summary, "gemma3n")
-        .await?;
-    
-    // Extract title from summary
-    let title = practice_sheet::extract_session_title_from_summary(&summary);
-    
-    // Save to database (scope the lock)
-    {
-        let db = db_state.db.lock().map_err(|e| e.to_string())?;
-        
-        // Create practice sheet
-        let practice_sheet_id = db.create_practice_sheet(&sessionId, &title)
-            .map_err(|e| e.to_string())?;
-        
-        // Add all questions
-        for (index, question) in questions.iter().enumerate() {
-            db.add_practice_question(
-                &practice_sheet_id,
-                &question.question_text,
-                &question.options,
-                &question.correct_answer,
-                (index + 1) as i32,
-            ).map_err(|e| e.to_string())?;
-        }
-        
-        Ok(practice_sheet_id)
+    if questionIds.is_empty() {
+        return Err("Pick at least one question".to_string());
     }
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.assemble_practice_sheet(&sessionId, &title, &questionIds)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => "Question not found in bank".to_string(),
+            other => other.to_string(),
+        })
 }
 
 #[command]
@@ -355,6 +3397,24 @@ async fn get_all_practice_sheets(state: State<'_, DatabaseState>) -> Result<Stri
     serde_json::to_string(&sheets).map_err(|e| e.to_string())
 }
 
+// Plan a review of a practice sheet; `datetime` is RFC 3339
+#[command]
+async fn schedule_practice(sheetId: String, datetime: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let scheduled_for = chrono::DateTime::parse_from_rfc3339(&datetime)
+        .map_err(|e| format!("Invalid datetime '{}': {}", datetime, e))?
+        .with_timezone(&chrono::Utc);
+    
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.schedule_practice(&sheetId, scheduled_for).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_upcoming_schedule(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let schedule = db.get_upcoming_schedule(chrono::Utc::now()).map_err(|e| e.to_string())?;
+    serde_json::to_string(&schedule).map_err(|e| e.to_string())
+}
+
 #[command]
 async fn get_practice_sheet_questions(practiceSheetId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
@@ -362,6 +3422,47 @@ async fn get_practice_sheet_questions(practiceSheetId: String, state: State<'_,
     serde_json::to_string(&questions).map_err(|e| e.to_string())
 }
 
+#[command]
+async fn update_practice_question(
+    questionId: String,
+    fields: database::PracticeQuestionUpdate,
+    state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_not_demo_mode(&db)?;
+    let question = db.update_practice_question(&questionId, &fields).map_err(|e| e.to_string())?;
+    serde_json::to_string(&question).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn delete_practice_question(questionId: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    ensure_not_demo_mode(&db)?;
+    db.delete_practice_question(&questionId).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_practice_question_edits(practiceSheetId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let edits = db.get_practice_question_edits(&practiceSheetId).map_err(|e| e.to_string())?;
+    serde_json::to_string(&edits).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_question_sources(questionId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let question = db.get_practice_question(&questionId).map_err(|e| e.to_string())?;
+    let session_id = db.get_practice_sheet_session_id(&question.practice_sheet_id).map_err(|e| e.to_string())?;
+    let messages = db.get_messages_by_ids(&question.source_message_ids).map_err(|e| e.to_string())?;
+    
+    let sources = serde_json::json!({
+        "session_id": session_id,
+        "messages": messages,
+        "summary_lines": question.source_summary_lines,
+    });
+    Ok(sources.to_string())
+}
+
 
 #[command]
 async fn complete_practice_sheet(
@@ -369,13 +3470,15 @@ async fn complete_practice_sheet(
     userAnswers: Vec<String>,
     score: i32,
     totalQuestions: i32,
-    db_state: State<'_, DatabaseState>,
-    _practice_state: State<'_, PracticeSheetState>
+    responseTimesMs: Option<Vec<i64>>,
+    confidenceLevels: Option<Vec<i32>>,
+    app: tauri::AppHandle,
+    db_state: State<'_, DatabaseState>
 ) -> Result<String, String> {
     // Completing practice sheet: {} with score {}/{}
     
     // Store the practice attempt and mark as completed (scope the lock)
-    {
+    let (demo_mode, stall_seconds) = {
         let db = db_state.db.lock().map_err(|e| e.to_string())?;
         
         // Get practice sheet title for logging
@@ -384,8 +3487,21 @@ async fn complete_practice_sheet(
         
         // Processing completion for practice sheet '{}' (ID: {})
         
+        // Timings and confidence levels are optional, but when given there is one per question
+        let question_count = db.get_practice_sheet_questions(&practiceSheetId)
+            .map_err(|e| format!("Failed to get practice sheet questions: {}", e))?
+            .len();
+        if responseTimesMs.as_ref().is_some_and(|times| times.len() != question_count) {
+            return Err(format!("Expected {} response times, one per question", question_count));
+        }
+        if confidenceLevels.as_ref().is_some_and(|levels| levels.len() != question_count) {
+            return Err(format!("Expected {} confidence levels, one per question", question_count));
+        }
+        
         // Create practice attempt record
-        db.create_practice_attempt(&practiceSheetId, &userAnswers, score, totalQuestions)
+        let response_times_ms = responseTimesMs.unwrap_or_default();
+        let confidence_levels = confidenceLevels.unwrap_or_default();
+        db.create_practice_attempt(&practiceSheetId, &userAnswers, score, totalQuestions, &response_times_ms, &confidence_levels)
             .map_err(|e| format!("Failed to create practice attempt: {}", e))?;
         
         // Mark practice sheet as completed
@@ -393,88 +3509,257 @@ async fn complete_practice_sheet(
             .map_err(|e| format!("Failed to mark practice sheet as completed: {}", e))?;
         
         // Store results in memory
-        let user_id = "default_user";
+        let user_id = &db.get_practice_sheet_user_id(&practiceSheetId).map_err(|e| e.to_string())?;
         db.store_practice_results_to_memory(&practiceSheetId, user_id)
             .map_err(|e| format!("Failed to store results to memory: {}", e))?;
         
         // Successfully stored completion data for practice sheet: {}
+        (db.is_demo_mode(), db.get_task_stall_seconds().map_err(|e| e.to_string())?)
+    };
+    
+    // The background task works against the on-disk database, which demo sheets never reach
+    if demo_mode {
+        return Ok("Practice sheet completed successfully".to_string());
     }
     
-    // Check if a redo task is already running for this practice sheet
-    {
-        let running_tasks = RUNNING_REDO_TASKS.get_or_init(|| Mutex::new(HashSet::new()));
-        let mut tasks = running_tasks.lock().map_err(|e| e.to_string())?;
-        if tasks.contains(&practiceSheetId) {
-            // Redo generation already in progress for practice sheet: {}, skipping
-            return Ok("Practice sheet completed successfully".to_string());
+    // Hand the result to the configured gradebook hook, if any (don't wait for it)
+    let delivery = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        match db.get_results_hook() {
+            Ok(Some(hook)) => Some(db.get_practice_sheet_user_id(&practiceSheetId)
+                .and_then(|user_id| results_hook::PracticeResult::from_latest_attempt(&db, &practiceSheetId, &user_id))
+                .map(|result| result.map(|result| (hook, result)))),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
         }
-        tasks.insert(practiceSheetId.clone());
+    };
+    if let Some(delivery) = delivery {
+        spawn_results_delivery(app.clone(), practiceSheetId.clone(), delivery.map_err(|e| e.to_string()));
     }
     
-    // Start background redo generation (don't wait for it)
-    let practice_sheet_id_clone = practiceSheetId.clone();
+    // Start background redo generation (don't wait for it); skipped if one is already running
+    spawn_redo_task(app, practiceSheetId, stall_seconds);
+    
+    Ok("Practice sheet completed successfully".to_string())
+}
+
+// Explain every wrong answer of an attempt (the sheet's latest by default) with the quiz model and
+// store the explanations for the results screen. Answers already explained are kept, so a retry
+// only asks about the ones that failed.
+#[command]
+async fn grade_and_explain_attempt(
+    practiceSheetId: String,
+    attemptId: Option<String>,
+    options: Option<llm::GenerationOptions>,
+    llm_state: State<'_, LLMState>,
+    db_state: State<'_, DatabaseState>
+) -> Result<String, String> {
+    let (existing, attempt, chain, options) = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        ensure_within_usage_limit(&db)?;
+        let attempts = db.get_practice_attempts(&practiceSheetId).map_err(|e| e.to_string())?;
+        let attempt = match &attemptId {
+            Some(attempt_id) => attempts.into_iter().find(|attempt| &attempt.id == attempt_id),
+            None => attempts.into_iter().last(),
+        }.ok_or("No attempt found for this practice sheet")?;
+        (
+            db.get_attempt_feedback(&attempt.id).map_err(|e| e.to_string())?,
+            attempt,
+            db.get_feature_model_chain(llm::Feature::Quiz).map_err(|e| e.to_string())?,
+            resolve_generation_options(&db, options)?,
+        )
+    };
     
-    // Spawning background redo generation task for practice sheet: {}
+    if attempt.questions.is_empty() && !attempt.user_answers.is_empty() {
+        return Err("This attempt was made before answers were recorded per question, so it can't be explained".to_string());
+    }
+    let pending: Vec<_> = practice_sheet::wrong_answers(&attempt).into_iter()
+        .filter(|(index, _, _)| !existing.iter().any(|feedback| feedback.question_index == *index))
+        .collect();
+    if !pending.is_empty() {
+        ensure_generation_ready(&llm_state, &chain).await?;
+    }
     
-    tokio::spawn(async move {
-        // Add timeout to prevent indefinite running
-        let timeout_duration = std::time::Duration::from_secs(300); // 5 minutes timeout
-        let result = tokio::time::timeout(
-            timeout_duration,
-            generate_redo_questions_background_task(practice_sheet_id_clone.clone())
-        ).await;
-        
-        // Remove from running tasks when done (always execute this)
-        {
-            let running_tasks = RUNNING_REDO_TASKS.get_or_init(|| Mutex::new(HashSet::new()));
-            let mut tasks = running_tasks.lock().unwrap();
-            tasks.remove(&practice_sheet_id_clone);
+    // One request per question, so one failure doesn't lose the other explanations
+    let mut errors = Vec::new();
+    for (index, question, answer) in pending {
+        let explained = llm::walk_model_chain(&chain, |backend| async move {
+            practice_sheet::PracticeSheetLLMClient::for_backend(&backend)
+                .with_options(options)
+                .explain_wrong_answer(&question.question_text, answer, &question.correct_answer, &backend.model)
+                .await
+        }).await;
+        match explained {
+            Ok(outcome) => {
+                let feedback = database::AttemptFeedback {
+                    attempt_id: attempt.id.clone(),
+                    question_index: index,
+                    question_text: question.question_text.clone(),
+                    user_answer: answer.to_string(),
+                    correct_answer: question.correct_answer.clone(),
+                    explanation: outcome.value,
+                    backend: outcome.served_by.label(),
+                    created_at: chrono::Utc::now(),
+                };
+                let db = db_state.db.lock().map_err(|e| e.to_string())?;
+                db.save_attempt_feedback(&feedback).map_err(|e| e.to_string())?;
+            }
+            Err(e) => errors.push(format!("Question {}: {}", index + 1, e)),
         }
-        
-        match result {
-            Ok(Ok(_)) => {
-                // Background redo generation completed successfully for practice sheet: {}
-            },
-            Ok(Err(e)) => {
-                eprintln!("Background redo generation failed for practice sheet {}: {}", practice_sheet_id_clone, e);
-            },
-            Err(_) => {
-                eprintln!("Background redo generation timed out for practice sheet: {}", practice_sheet_id_clone);
+    }
+    
+    let feedback = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.get_attempt_feedback(&attempt.id).map_err(|e| e.to_string())?
+    };
+    let result = serde_json::json!({
+        "attempt_id": attempt.id,
+        "score": attempt.score,
+        "total_questions": attempt.total_questions,
+        "feedback": feedback,
+        "errors": errors,
+    });
+    Ok(result.to_string())
+}
+
+// Explanations stored for an attempt by grade_and_explain_attempt
+#[command]
+async fn get_attempt_feedback(attemptId: String, state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let feedback = db.get_attempt_feedback(&attemptId).map_err(|e| e.to_string())?;
+    serde_json::to_string(&feedback).map_err(|e| e.to_string())
+}
+
+// Emits "results-hook-delivered" or "results-hook-failed"; completion itself never fails on the hook
+fn spawn_results_delivery(
+    app: tauri::AppHandle,
+    practice_sheet_id: String,
+    delivery: Result<Option<(results_hook::ResultsHook, results_hook::PracticeResult)>, String>
+) {
+    tauri::async_runtime::spawn(async move {
+        let outcome = match delivery {
+            Ok(Some((hook, result))) => results_hook::deliver(&hook, &result).await,
+            Ok(None) => Err("No attempt recorded for this sheet".to_string()),
+            Err(e) => Err(e),
+        };
+        match outcome {
+            Ok(destination) => {
+                let _ = app.emit("results-hook-delivered", serde_json::json!({
+                    "practice_sheet_id": practice_sheet_id,
+                    "destination": destination,
+                }));
+            }
+            Err(error) => {
+                let _ = app.emit("results-hook-failed", serde_json::json!({
+                    "practice_sheet_id": practice_sheet_id,
+                    "error": error,
+                }));
             }
         }
     });
+}
+
+// Returns false when redo generation is already running for this sheet
+fn spawn_redo_task(app: tauri::AppHandle, practice_sheet_id: String, stall_seconds: u64) -> bool {
+    let Some(heartbeat) = background_tasks::try_start(background_tasks::TaskKind::Redo, &practice_sheet_id) else {
+        return false;
+    };
     
-    Ok("Practice sheet completed successfully".to_string())
+    tokio::spawn(async move {
+        let body = generate_redo_questions_background_task(&app, practice_sheet_id.clone(), heartbeat.clone());
+        let stall_after = std::time::Duration::from_secs(stall_seconds);
+        if let Err(e) = background_tasks::run_tracked(&app, heartbeat, stall_after, body).await {
+            eprintln!("Background redo generation failed for practice sheet {}: {}", practice_sheet_id, e);
+        }
+    });
+    true
 }
 
-async fn generate_redo_questions_background_task(practice_sheet_id: String) -> Result<(), String> {
+async fn generate_redo_questions_background_task(
+    app: &tauri::AppHandle,
+    practice_sheet_id: String,
+    heartbeat: background_tasks::Heartbeat
+) -> Result<(), String> {
     // Starting redo generation for practice sheet: {}
     
     // Create fresh database and LLM client connections for this background task
-    let db = database::Database::new().map_err(|e| e.to_string())?;
-    let llm_client = practice_sheet::PracticeSheetLLMClient::new(None);
+    let db = background_database(app)?.ok_or(DEMO_MODE_TASK_ERROR)?;
+    let chain = db.get_feature_model_chain(llm::Feature::Quiz).map_err(|e| e.to_string())?;
     
     // Get practice sheet specific memory content and sheet title
-    let user_id = "default_user";
+    let user_id = &db.get_practice_sheet_user_id(&practice_sheet_id).map_err(|e| e.to_string())?;
     let specific_memory_content = db.get_practice_sheet_specific_memory(&practice_sheet_id, user_id)
         .map_err(|e| format!("Failed to get specific memory for practice sheet {}: {}", practice_sheet_id, e))?;
     let sheet_title = db.get_practice_sheet_title(&practice_sheet_id)
         .map_err(|e| format!("Failed to get title for practice sheet {}: {}", practice_sheet_id, e))?;
     
+    // Weigh every attempt at the sheet, not just the latest memory block
+    let attempts = db.get_practice_attempts(&practice_sheet_id)
+        .map_err(|e| format!("Failed to get attempts for practice sheet {}: {}", practice_sheet_id, e))?;
+    let attempt_history = practice_sheet::render_attempt_history(
+        attempts.len(),
+        &practice_sheet::summarize_attempt_history(&attempts),
+    );
+    
+    // Add what earlier sessions said about the same topics, leaving out this sheet's own lines
+    heartbeat.beat("Retrieving related memory");
+    let retrieval_db = Mutex::new(background_database(app)?.ok_or(DEMO_MODE_TASK_ERROR)?);
+    let query = format!("{}\n{}", sheet_title, specific_memory_content);
+    let related = match retrieve_chunks(&retrieval_db, &query, None).await {
+        Ok(chunks) => chunks.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Memory retrieval failed for practice sheet {}: {}", practice_sheet_id, e);
+            Vec::new()
+        }
+    };
+    let related: Vec<_> = related.into_iter()
+        .filter(|chunk| !specific_memory_content.contains(chunk.content.as_str()))
+        .collect();
+    let specific_memory_content = if related.is_empty() {
+        specific_memory_content
+    } else {
+        format!("{}\n\nRelated notes from earlier sessions:\n{}", specific_memory_content, embeddings::render_for_prompt(&related))
+    };
+    
     // Using isolated memory content for practice sheet '{}' (ID: {})
     
     // Generate redo questions using LLM with isolated memory content
-    let new_questions = llm_client
-        .generate_redo_practice_sheet(&specific_memory_content, &sheet_title, "gemma3n")
-        .await
-        .map_err(|e| format!("Failed to generate redo questions for practice sheet {}: {}", practice_sheet_id, e))?;
+    heartbeat.beat("Generating redo questions");
+    let reproducibility = db.get_reproducibility_settings().map_err(|e| e.to_string())?;
+    let options = db.get_generation_options().map_err(|e| e.to_string())?;
+    let seed = reproducibility.resolve_seed();
+    let outcome = llm::walk_model_chain(&chain, |backend| {
+        let llm_client = practice_sheet::PracticeSheetLLMClient::for_backend(&backend).with_options(options);
+        let (specific_memory_content, attempt_history, sheet_title) = (&specific_memory_content, &attempt_history, &sheet_title);
+        async move {
+            llm_client
+                .generate_redo_practice_sheet(specific_memory_content, attempt_history, sheet_title, &backend.model, seed, reproducibility.deterministic)
+                .await
+        }
+    })
+    .await
+    .map_err(|e| format!("Failed to generate redo questions for practice sheet {}: {}", practice_sheet_id, e))?;
+    let generation_backend = outcome.served_by.label();
+    let new_questions = outcome.value;
     
     // Generated {} new questions for practice sheet: {}
     
+    // Link redo questions back to the original session's messages
+    let mut new_questions = new_questions;
+    let session_id = db.get_practice_sheet_session_id(&practice_sheet_id).map_err(|e| e.to_string())?;
+    let messages = db.get_session_messages(&session_id).map_err(|e| e.to_string())?;
+    practice_sheet::link_question_sources(&mut new_questions, &messages, "");
+    
     // Replace questions and mark as redo ready
+    heartbeat.beat("Saving redo questions");
     db.replace_practice_sheet_questions(&practice_sheet_id, &new_questions)
         .map_err(|e| format!("Failed to replace questions for practice sheet {}: {}", practice_sheet_id, e))?;
     
+    db.set_practice_sheet_seed(&practice_sheet_id, seed)
+        .map_err(|e| format!("Failed to record seed for practice sheet {}: {}", practice_sheet_id, e))?;
+    db.set_practice_sheet_backend(&practice_sheet_id, &generation_backend)
+        .map_err(|e| format!("Failed to record backend for practice sheet {}: {}", practice_sheet_id, e))?;
+    
     db.mark_practice_sheet_redo_ready(&practice_sheet_id)
         .map_err(|e| format!("Failed to mark practice sheet {} as redo ready: {}", practice_sheet_id, e))?;
     
@@ -482,32 +3767,401 @@ async fn generate_redo_questions_background_task(practice_sheet_id: String) -> R
     Ok(())
 }
 
+// Periodically fires "practice-due" for scheduled reviews whose time has come
+async fn watch_practice_schedule(app: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        if let Err(e) = notify_due_practice(&app) {
+            eprintln!("Practice schedule check failed: {}", e);
+        }
+    }
+}
+
+fn notify_due_practice(app: &tauri::AppHandle) -> Result<(), String> {
+    // Fresh connection for the background task, like the idle session watcher
+    let Some(db) = background_database(app)? else { return Ok(()) };
+    
+    for scheduled in db.get_due_practice(chrono::Utc::now()).map_err(|e| e.to_string())? {
+        // Completed sheets with no redo waiting have nothing left to take
+        if scheduled.is_completed && !scheduled.is_redo_ready {
+            db.mark_practice_notified(&scheduled.id).map_err(|e| e.to_string())?;
+            continue;
+        }
+        
+        let _ = app.emit("practice-due", &scheduled);
+        db.mark_practice_notified(&scheduled.id).map_err(|e| e.to_string())?;
+    }
+    
+    Ok(())
+}
+
+#[command]
+async fn get_usage_status(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let status = db.get_usage_status().map_err(|e| e.to_string())?;
+    serde_json::to_string(&status).map_err(|e| e.to_string())
+}
+
+// Set (or with no minutes, remove) the daily limit. Once a limit exists the same PIN is
+// needed to change it; the first call sets the PIN.
+#[command]
+async fn set_usage_limit(dailyMinutes: Option<u32>, pin: String, state: State<'_, DatabaseState>) -> Result<(), String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    if let Some(existing) = db.get_usage_limit().map_err(|e| e.to_string())? {
+        if !existing.verify_pin(&pin) {
+            return Err("Incorrect guardian PIN".to_string());
+        }
+    }
+    
+    let limit = match dailyMinutes {
+        Some(0) => return Err("Daily limit must be at least one minute".to_string()),
+        Some(minutes) => {
+            if pin.trim().is_empty() {
+                return Err("A guardian PIN is required".to_string());
+            }
+            Some(usage_limits::UsageLimit::new(minutes, &pin))
+        }
+        None => None,
+    };
+    db.set_usage_limit(limit.as_ref()).map_err(|e| e.to_string())
+}
+
+// Per-model counts of clean, repaired and failed JSON parses since startup
+#[command]
+async fn get_json_parse_stats() -> Result<String, String> {
+    serde_json::to_string(&json_repair::stats()).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn get_results_hook(state: State<'_, DatabaseState>) -> Result<String, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let hook = db.get_results_hook().map_err(|e| e.to_string())?;
+    serde_json::to_string(&hook).map_err(|e| e.to_string())
+}
+
+// Pass no hook to turn result export off
+#[command]
+async fn set_results_hook(hook: Option<results_hook::ResultsHook>, state: State<'_, DatabaseState>) -> Result<(), String> {
+    if let Some(hook) = &hook {
+        hook.validate()?;
+    }
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.set_results_hook(hook.as_ref()).map_err(|e| e.to_string())
+}
+
+// Warns once a day when the allowance is nearly used up and announces the lockout
+async fn watch_usage_limit(app: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    let mut warned_on = None;
+    let mut locked_on = None;
+    loop {
+        interval.tick().await;
+        if let Err(e) = check_usage_limit(&app, &mut warned_on, &mut locked_on) {
+            eprintln!("Usage limit check failed: {}", e);
+        }
+    }
+}
+
+fn check_usage_limit(
+    app: &tauri::AppHandle,
+    warned_on: &mut Option<chrono::NaiveDate>,
+    locked_on: &mut Option<chrono::NaiveDate>,
+) -> Result<(), String> {
+    // Fresh connection for the background task, like the idle session watcher
+    let Some(db) = background_database(app)? else { return Ok(()) };
+    let user_id = &db.get_active_user_id().map_err(|e| e.to_string())?;
+    
+    // A running recording is activity even when no other command comes in
+    let recording = app.state::<AudioState>().recorder.lock()
+        .map(|recorder| recorder.is_recording())
+        .unwrap_or(false);
+    let mut status = db.get_usage_status().map_err(|e| e.to_string())?;
+    if status.limit_minutes.is_none() {
+        return Ok(());
+    }
+    if recording && !status.locked {
+        db.log_activity(user_id, chrono::Local::now()).map_err(|e| e.to_string())?;
+        status = db.get_usage_status().map_err(|e| e.to_string())?;
+    }
+    
+    let today = chrono::Local::now().date_naive();
+    if status.should_warn() && *warned_on != Some(today) {
+        *warned_on = Some(today);
+        let _ = app.emit("usage-limit-warning", &status);
+        let remaining = status.remaining_minutes.unwrap_or(0);
+        speak_notice(app, format!("You have {} minutes left for today.", remaining));
+    }
+    if status.locked && *locked_on != Some(today) {
+        *locked_on = Some(today);
+        let _ = app.emit("usage-limit-reached", &status);
+    }
+    
+    Ok(())
+}
+
+// Best-effort spoken notice, queued like any other utterance; its tts-finished event reports a
+// failure when TTS hasn't been initialized
+fn speak_notice(app: &tauri::AppHandle, text: String) {
+    if let Err(e) = queue_speech(app, text, None) {
+        eprintln!("Failed to speak notice: {}", e);
+    }
+}
+
+// Periodically closes out sessions that have gone idle so their progress still reaches memory
+async fn watch_idle_sessions(app: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        if let Err(e) = end_idle_sessions(&app).await {
+            eprintln!("Idle session check failed: {}", e);
+        }
+    }
+}
+
+async fn end_idle_sessions(app: &tauri::AppHandle) -> Result<(), String> {
+    // Fresh connection for the background task, like the redo generation task
+    let Some(db) = background_database(app)? else { return Ok(()) };
+    
+    let timeout_minutes = db.get_idle_timeout_minutes().map_err(|e| e.to_string())?;
+    if timeout_minutes <= 0 {
+        return Ok(()); // Auto-end disabled
+    }
+    
+    let cutoff = chrono::Utc::now() - chrono::Duration::minutes(timeout_minutes);
+    let idle_session_ids = db.get_idle_session_ids(cutoff).map_err(|e| e.to_string())?;
+    let stall_seconds = db.get_task_stall_seconds().map_err(|e| e.to_string())?;
+    
+    for session_id in idle_session_ids {
+        // Mark ended first so a failing summary isn't retried every minute
+        db.mark_session_ended(&session_id).map_err(|e| e.to_string())?;
+        run_summary_task(app, &session_id, stall_seconds).await;
+    }
+    
+    Ok(())
+}
+
+// Summarize an ended session under stall detection; failures are reported through events
+async fn run_summary_task(app: &tauri::AppHandle, session_id: &str, stall_seconds: u64) {
+    let Some(heartbeat) = background_tasks::try_start(background_tasks::TaskKind::Summary, session_id) else {
+        return;
+    };
+    
+    let body = summarize_ended_session(app, session_id, heartbeat.clone());
+    let stall_after = std::time::Duration::from_secs(stall_seconds);
+    if let Err(e) = background_tasks::run_tracked(app, heartbeat, stall_after, body).await {
+        eprintln!("Auto-summary failed for idle session {}: {}", session_id, e);
+        let _ = app.emit("session-auto-ended", serde_json::json!({
+            "session_id": session_id,
+            "summary": null,
+            "error": e,
+        }));
+    }
+}
+
+async fn summarize_ended_session(
+    app: &tauri::AppHandle,
+    session_id: &str,
+    heartbeat: background_tasks::Heartbeat
+) -> Result<(), String> {
+    // Fresh connection for the background task, like the redo generation task
+    let db = background_database(app)?.ok_or(DEMO_MODE_TASK_ERROR)?;
+    let chain = db.get_feature_model_chain(llm::Feature::Summary).map_err(|e| e.to_string())?;
+    
+    let messages = db.get_session_messages(session_id).map_err(|e| e.to_string())?;
+    let reproducibility = db.get_reproducibility_settings().map_err(|e| e.to_string())?;
+    let options = db.get_generation_options().map_err(|e| e.to_string())?;
+    let formatted_session = session_summary::format_session_for_summary(&messages);
+    let prompt = prompts::render(PromptKind::Summary, &[("conversation", &formatted_session)]);
+    let cache_request = response_cache::CacheRequest {
+        feature: response_cache::CachedFeature::Summary,
+        prompt: &prompt,
+        fixed_seed: reproducibility.seed,
+        deterministic: reproducibility.deterministic,
+        options,
+    };
+    
+    let (summary, seed, backend) = match response_cache::lookup(&db, &chain, &cache_request)? {
+        Some(cached) => (cached.response, cached.seed, cached.backend),
+        None => {
+            heartbeat.beat("Generating session summary");
+            let seed = reproducibility.resolve_seed();
+            let outcome = generate_summary_with_chain(&chain, &formatted_session, seed, reproducibility.deterministic, options).await?;
+            if let Err(e) = response_cache::store(&db, &cache_request, &outcome.served_by, &outcome.value, seed) {
+                eprintln!("Failed to cache session summary: {}", e);
+            }
+            (outcome.value, seed, outcome.served_by.label())
+        }
+    };
+    
+    heartbeat.beat("Saving session summary");
+    let user_id = &db.get_session_user_id(session_id).map_err(|e| e.to_string())?;
+    db.append_to_memory(user_id, &summary).map_err(|e| e.to_string())?;
+    db.set_session_summary_seed(session_id, seed).map_err(|e| e.to_string())?;
+    db.set_session_summary_backend(session_id, &backend).map_err(|e| e.to_string())?;
+    
+    let _ = app.emit("session-auto-ended", serde_json::json!({
+        "session_id": session_id,
+        "summary": summary,
+        "error": null,
+    }));
+    Ok(())
+}
+
+// Background task status and retry for stalled or failed work
+#[command]
+async fn get_background_tasks() -> Result<String, String> {
+    serde_json::to_string(&background_tasks::list_tasks()).map_err(|e| e.to_string())
+}
+
+#[command]
+async fn retry_background_task(
+    taskId: String,
+    app: tauri::AppHandle,
+    db_state: State<'_, DatabaseState>
+) -> Result<(), String> {
+    let task = background_tasks::get_task(&taskId)
+        .ok_or_else(|| format!("Background task not found: {}", taskId))?;
+    if task.state == background_tasks::TaskState::Running || task.state == background_tasks::TaskState::Completed {
+        return Err("Only stalled or failed tasks can be retried".to_string());
+    }
+    
+    let stall_seconds = {
+        let db = db_state.db.lock().map_err(|e| e.to_string())?;
+        db.get_task_stall_seconds().map_err(|e| e.to_string())?
+    };
+    
+    match task.kind {
+        background_tasks::TaskKind::Redo => {
+            spawn_redo_task(app, task.target_id, stall_seconds);
+        }
+        background_tasks::TaskKind::Summary => {
+            tokio::spawn(async move {
+                run_summary_task(&app, &task.target_id, stall_seconds).await;
+            });
+        }
+    }
+    Ok(())
+}
+
 fn main() {
+    let recorder = audio::AudioRecorder::new();
+    let echo_gate = recorder.echo_gate.clone();
+
+    // Load the Ollama client settings before any client is built
+    let database = database::Database::new().expect("Failed to initialize database");
+    if let Ok(config) = database.get_ollama_client_config() {
+        ollama::set_config(config);
+    }
+    let mut tts_settings = database.get_tts_settings().unwrap_or_default();
+    if tts::migrate_windows_voices(&mut tts_settings) {
+        if let Err(e) = database.set_tts_settings(&tts_settings) {
+            eprintln!("Failed to save migrated Windows voices: {}", e);
+        }
+    }
+    // A deleted environment leaves Python on the system interpreter
+    if let Some(venv) = database.get_setting("python_venv").ok().flatten().filter(|venv| !venv.is_empty()) {
+        if let Err(e) = venv::select(Some(&venv)) {
+            eprintln!("Failed to select Python environment: {}", e);
+        }
+    }
+    let speech = tts::SpeechControl::default();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
+        .setup(|app| {
+            // Restore the saved microphone; a missing device falls back to the default
+            let (saved_device, saved_channel, saved_quality, saved_vad, saved_noise_suppression, saved_max_seconds, saved_resume, saved_live_streaming, saved_format, saved_echo_suppression, saved_live_transcription) = app.state::<DatabaseState>().db.lock()
+                .map(|db| (
+                    db.get_setting("input_device").ok().flatten(),
+                    db.get_setting("input_channel").ok().flatten(),
+                    db.get_setting("recording_quality").ok().flatten(),
+                    db.get_setting("recording_vad").ok().flatten(),
+                    db.get_bool_setting("noise_suppression", false).unwrap_or(false),
+                    db.get_setting("max_recording_seconds").ok().flatten(),
+                    db.get_bool_setting("resume_on_device_loss", false).unwrap_or(false),
+                    db.get_bool_setting("live_audio_streaming", false).unwrap_or(false),
+                    db.get_setting("recording_format").ok().flatten(),
+                    db.get_bool_setting("echo_suppression", true).unwrap_or(true),
+                    db.get_bool_setting("live_transcription", false).unwrap_or(false),
+                ))
+                .unwrap_or((None, None, None, None, false, None, false, false, None, true, false));
+            if let Ok(mut recorder) = app.state::<AudioState>().recorder.lock() {
+                if let Some(device_id) = saved_device {
+                    if let Err(e) = recorder.set_input_device(Some(device_id)) {
+                        eprintln!("Saved input device unavailable, using default: {}", e);
+                    }
+                }
+                if let Some(channel) = saved_channel.and_then(|value| serde_json::from_str(&value).ok()) {
+                    if let Err(e) = recorder.set_channel(channel) {
+                        eprintln!("Saved input channel unavailable, using the first channel: {}", e);
+                    }
+                }
+                if let Some(quality) = saved_quality.and_then(|value| serde_json::from_str(&value).ok()) {
+                    recorder.quality = quality;
+                }
+                if let Some(vad) = saved_vad.and_then(|value| serde_json::from_str(&value).ok()) {
+                    recorder.vad = vad;
+                }
+                recorder.noise_suppression = saved_noise_suppression;
+                if let Some(seconds) = saved_max_seconds.and_then(|value| value.parse().ok()) {
+                    recorder.max_duration_secs = seconds;
+                }
+                recorder.resume_on_device_loss = saved_resume;
+                recorder.live_streaming = saved_live_streaming;
+                if let Some(format) = saved_format.and_then(|value| serde_json::from_str(&value).ok()) {
+                    recorder.output_format = format;
+                }
+                recorder.echo_suppression = saved_echo_suppression;
+                recorder.live_transcription = saved_live_transcription;
+            }
+            
+            // Apply the recording retention policy once per launch; demo mode's empty job list would
+            // leave pending recordings unprotected
+            if let Ok(db) = app.state::<DatabaseState>().db.lock() {
+                if !db.is_demo_mode() {
+                    if let Err(e) = run_recording_cleanup(&db, None) {
+                        eprintln!("Recording cleanup failed: {}", e);
+                    }
+                }
+            }
+            
+            tauri::async_runtime::spawn(watch_idle_sessions(app.handle().clone()));
+            tauri::async_runtime::spawn(watch_practice_schedule(app.handle().clone()));
+            tauri::async_runtime::spawn(watch_health(app.handle().clone()));
+            tauri::async_runtime::spawn(watch_usage_limit(app.handle().clone()));
+            Ok(())
+        })
         .manage(AudioState {
-            recorder: Mutex::new(audio::AudioRecorder::new()),
+            recorder: Mutex::new(recorder),
         })
-        .manage(WhisperState {
-            transcriber: Mutex::new(whisper::WhisperTranscriber::new()),
+        .manage(PlaybackState {
+            player: Mutex::new(playback::AudioPlayer::new()),
+        })
+        .manage({
+            let transcriber = whisper::WhisperTranscriber::new();
+            WhisperState {
+                cancel: transcriber.cancel_handle(),
+                transcriber: Mutex::new(transcriber),
+                worker: transcription_worker::TranscriptionWorker::start(),
+            }
         })
         .manage(LLMState {
             client: llm::OllamaClient::new(None),
+            generations: llm::GenerationRegistry::default(),
+            pulls: llm::GenerationRegistry::default(),
         })
         .manage(TTSState {
-            engine: Mutex::new(tts::SystemTTSEngine::new()),
+            engine: Mutex::new(tts::create_engine(&tts_settings, speech.clone())),
+            speech,
+            queue: speech_queue::SpeechQueue::start(),
+            echo_gate,
         })
         .manage(PythonState {
             session_manager: interactive_python::PythonSessionManager::new(),
         })
         .manage(DatabaseState {
-            db: Mutex::new(database::Database::new().expect("Failed to initialize database")),
-        })
-        .manage(SummaryState {
-            client: session_summary::SummaryLLMClient::new(None),
-        })
-        .manage(PracticeSheetState {
-            client: practice_sheet::PracticeSheetLLMClient::new(None),
+            db: Mutex::new(database),
         })
         .invoke_handler(tauri::generate_handler![
             execute_python_code,
@@ -515,32 +4169,194 @@ fn main() {
             get_python_output,
             is_python_session_running,
             close_python_session,
+            start_python_repl,
+            evaluate_python_repl,
+            close_python_repl,
+            list_venvs,
+            create_venv,
+            select_venv,
+            get_python_executions,
+            replay_execution,
             test_microphone,
+            list_input_devices,
+            set_input_device,
+            get_input_device,
+            get_input_channel,
+            set_input_channel,
+            get_recording_quality,
+            set_recording_quality,
+            get_recording_format,
+            set_recording_format,
+            get_last_archive_recording,
+            get_vad_settings,
+            set_vad_settings,
+            get_noise_suppression,
+            set_noise_suppression,
+            get_max_recording_duration,
+            set_max_recording_duration,
+            get_resume_on_device_loss,
+            set_resume_on_device_loss,
+            get_live_audio_streaming,
+            set_live_audio_streaming,
+            get_live_transcription,
+            set_live_transcription,
+            get_echo_suppression,
+            set_echo_suppression,
+            get_usage_status,
+            set_usage_limit,
+            get_results_hook,
+            get_json_parse_stats,
+            set_results_hook,
             start_recording,
             stop_recording,
             is_recording,
+            list_recordings,
+            get_session_recordings,
+            get_recording,
+            get_recording_retention,
+            set_recording_retention,
+            cleanup_recordings,
+            play_recording,
+            stop_playback,
+            seek_playback,
+            get_playback_status,
             record_audio_sample,
             initialize_whisper,
             transcribe_audio,
+            transcribe_audio_detailed,
+            transcribe_audio_batch,
+            get_transcription_queue,
+            cancel_transcription,
+            get_transcription_jobs,
+            transcribe_imported_recording,
+            record_transcript_edit,
+            get_transcription_corrections,
+            add_transcription_correction,
+            delete_transcription_correction,
             test_ollama_connection,
             initialize_llm,
+            cancel_generation,
+            list_models,
+            get_chat_model,
+            set_chat_model,
+            get_ollama_client_config,
+            set_ollama_client_config,
+            get_health_status,
+            get_llm_status,
+            get_model_disk_usage,
+            pull_ollama_model,
+            cancel_model_pull,
+            list_installed_models,
+            list_model_pulls,
+            delete_ollama_model,
+            list_whisper_models,
+            download_whisper_model,
+            delete_whisper_model,
+            get_whisper_acceleration,
+            set_whisper_gpu,
+            get_transcription_language,
+            set_transcription_language,
+            get_vocabulary_hints,
+            set_vocabulary_hints,
+            preview_vocabulary_prompt,
+            get_model_details,
             generate_ai_response,
+            ask_about_selection,
+            explain_python_error,
+            preview_prompt,
             test_tts,
             initialize_tts,
+            get_pronunciation_lexicon,
+            set_pronunciation,
+            delete_pronunciation,
+            preview_pronunciation,
+            get_tts_settings,
+            set_tts_settings,
+            list_piper_voices,
+            list_tts_voices,
+            set_tts_voice,
+            stop_speech,
+            synthesize_speech_to_file,
+            clear_tts_cache,
             generate_and_play_speech,
             create_session,
             get_all_sessions,
             get_session_messages,
+            search_in_session,
+            get_session_context,
+            set_session_context,
+            create_project_plan,
+            get_project_plans,
+            get_project_plan,
+            set_project_plan_status,
+            check_project_milestone,
             add_message,
+            mark_session_read,
+            get_session_read_state,
             update_session_title,
+            generate_session_title,
+            get_auto_session_titles_enabled,
+            set_auto_session_titles_enabled,
             delete_session,
+            delete_sessions,
+            archive_sessions,
+            tag_sessions,
+            set_demo_mode,
+            is_demo_mode,
             generate_session_summary,
             get_memory_content,
             append_to_memory,
+            get_generation_options,
+            get_pedagogy_mode,
+            set_pedagogy_mode,
+            clear_response_cache,
+            list_prompt_templates,
+            get_prompt_template,
+            set_prompt_template,
+            reset_prompt_template,
+            set_generation_options,
+            get_retrieval_settings,
+            set_retrieval_settings,
+            rebuild_embedding_index,
+            search_memory,
+            get_class_overview,
+            get_active_learner,
+            set_active_learner,
+            get_memory_injection_settings,
+            set_memory_injection_settings,
+            get_tutor_memory_view,
+            get_memory_entries,
+            get_reproducibility_settings,
+            set_reproducibility_settings,
+            get_dry_run_enabled,
+            get_self_check_enabled,
+            get_model_chain,
+            get_feature_model_chain,
+            set_feature_model_chain,
+            set_model_chain,
+            set_self_check_enabled,
+            set_dry_run_enabled,
+            get_latency_budget,
+            set_latency_budget,
+            get_background_tasks,
+            retry_background_task,
+            get_idle_timeout,
+            set_idle_timeout,
             generate_practice_sheet_from_summary,
             get_all_practice_sheets,
+            schedule_practice,
+            get_upcoming_schedule,
+            import_questions,
+            get_question_bank,
+            assemble_practice_sheet,
             get_practice_sheet_questions,
-            complete_practice_sheet
+            update_practice_question,
+            delete_practice_question,
+            get_practice_question_edits,
+            get_question_sources,
+            complete_practice_sheet,
+            grade_and_explain_attempt,
+            get_attempt_feedback
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
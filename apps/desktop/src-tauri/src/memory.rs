@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+// Memory profile split into categories that can be injected into prompts independently
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StructuredMemory {
+    pub strengths: Vec<String>,
+    pub weaknesses: Vec<String>,
+    pub preferences: Vec<String>,
+    pub quiz_history: Vec<String>,
+    pub session_summaries: Vec<String>,
+}
+
+// Which memory categories the tutor is allowed to see
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MemoryInjectionSettings {
+    pub strengths: bool,
+    pub weaknesses: bool,
+    pub preferences: bool,
+    pub quiz_history: bool,
+    pub session_summaries: bool,
+}
+
+impl Default for MemoryInjectionSettings {
+    fn default() -> Self {
+        Self {
+            strengths: true,
+            weaknesses: true,
+            preferences: true,
+            quiz_history: true,
+            session_summaries: true,
+        }
+    }
+}
+
+// One categorized piece of memory plus where it came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub category: String, // "strengths", "weaknesses", "preferences", "quiz_history" or "session_summaries"
+    pub content: String,
+    pub source_kind: String, // "session", "practice_sheet" or "note"
+    pub source_label: Option<String>, // Session name or practice sheet title
+    pub source_line: Option<usize>, // 0-based line in the memory blob the entry was parsed from
+}
+
+fn entry(category: &str, content: String, source_kind: &str, source_label: Option<&str>, source_line: usize) -> MemoryEntry {
+    MemoryEntry {
+        category: category.to_string(),
+        content,
+        source_kind: source_kind.to_string(),
+        source_label: source_label.map(|label| label.to_string()),
+        source_line: Some(source_line),
+    }
+}
+
+// Parse the free-form memory blob ("Session name:" and "Practice Sheet:" blocks plus
+// anything the user appended by hand) into categories
+pub fn parse_memory(memory_content: &str) -> StructuredMemory {
+    let mut memory = StructuredMemory::default();
+    for entry in parse_memory_entries(memory_content) {
+        let bucket = match entry.category.as_str() {
+            "strengths" => &mut memory.strengths,
+            "weaknesses" => &mut memory.weaknesses,
+            "quiz_history" => &mut memory.quiz_history,
+            "session_summaries" => &mut memory.session_summaries,
+            _ => &mut memory.preferences,
+        };
+        bucket.push(entry.content);
+    }
+    memory
+}
+
+pub fn parse_memory_entries(memory_content: &str) -> Vec<MemoryEntry> {
+    let mut entries = Vec::new();
+    let lines: Vec<&str> = memory_content.lines().collect();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index].trim();
+
+        if let Some(session_name) = line.strip_prefix("Session name: ") {
+            let session_name = session_name.trim();
+            // "Session name: X" is followed by "Summary: Y"
+            let summary = lines.get(index + 1)
+                .and_then(|next| next.trim().strip_prefix("Summary: "))
+                .map(|summary| summary.trim().to_string());
+            let content = match &summary {
+                Some(summary) => format!("{}: {}", session_name, summary),
+                None => session_name.to_string(),
+            };
+            entries.push(entry("session_summaries", content, "session", Some(session_name), index));
+            index += if summary.is_some() { 2 } else { 1 };
+            continue;
+        }
+
+        if let Some(sheet_title) = line.strip_prefix("Practice Sheet: ") {
+            index = parse_practice_block(&lines, index, sheet_title.trim(), &mut entries);
+            continue;
+        }
+
+        if !line.is_empty() {
+            // Anything outside the generated blocks was written by the user or tutor on purpose
+            entries.push(entry("preferences", line.to_string(), "note", None, index));
+        }
+        index += 1;
+    }
+
+    entries
+}
+
+// Parse one practice sheet block starting at its title line; returns the index after the block
+fn parse_practice_block(lines: &[&str], title_line: usize, sheet_title: &str, entries: &mut Vec<MemoryEntry>) -> usize {
+    let mut index = title_line + 1;
+    let mut date = String::new();
+    let mut score: Option<(i32, i32)> = None;
+    let mut section = "";
+
+    while index < lines.len() {
+        let line = lines[index].trim();
+        if line.starts_with("Practice Sheet: ") || line.starts_with("Session name: ") {
+            break;
+        }
+        index += 1;
+
+        if let Some(value) = line.strip_prefix("Date: ") {
+            date = value.to_string();
+        } else if let Some(value) = line.strip_prefix("Score: ") {
+            let mut parts = value.split('/');
+            if let (Some(got), Some(total)) = (parts.next(), parts.next()) {
+                if let (Ok(got), Ok(total)) = (got.trim().parse(), total.trim().parse()) {
+                    score = Some((got, total));
+                }
+            }
+        } else if line == "Incorrect Answers:" {
+            section = "incorrect";
+        } else if line == "Correct But Unsure:" {
+            section = "unsure";
+        } else if let Some(question) = line.strip_prefix("- Question: ") {
+            let content = match section {
+                "incorrect" => format!("Missed: {} ({})", question, sheet_title),
+                "unsure" => format!("Unsure: {} ({})", question, sheet_title),
+                _ => continue,
+            };
+            entries.push(entry("weaknesses", content, "practice_sheet", Some(sheet_title), index - 1));
+        } else if line == "Redo Available: Yes" {
+            break;
+        }
+    }
+
+    if let Some((got, total)) = score {
+        let content = format!("{}: {}/{} on {}", sheet_title, got, total, date);
+        entries.push(entry("quiz_history", content, "practice_sheet", Some(sheet_title), title_line));
+        // Sheets scored at 80% or better count as strengths
+        if total > 0 && got * 5 >= total * 4 {
+            let content = format!("{} ({}/{})", sheet_title, got, total);
+            entries.push(entry("strengths", content, "practice_sheet", Some(sheet_title), title_line));
+        }
+    }
+
+    index
+}
+
+// Render the enabled categories as the block the tutor prompt includes. Memory only grows, so
+// each category is cut to its most recent entries (entries are appended oldest first); repeats,
+// such as a question missed on several sheets, are listed once.
+pub fn render_for_prompt(memory: &StructuredMemory, settings: &MemoryInjectionSettings) -> String {
+    let sections = [
+        (settings.strengths, "Strengths", &memory.strengths, 6),
+        (settings.weaknesses, "Weak spots to reinforce", &memory.weaknesses, 8),
+        (settings.preferences, "Notes and preferences", &memory.preferences, 8),
+        (settings.quiz_history, "Quiz history", &memory.quiz_history, 5),
+        (settings.session_summaries, "Previous sessions", &memory.session_summaries, 3),
+    ];
+
+    let mut rendered = String::new();
+    for (enabled, heading, items, limit) in sections {
+        if !enabled || items.is_empty() {
+            continue;
+        }
+
+        let mut recent: Vec<&String> = Vec::new();
+        for item in items.iter().rev() {
+            if !recent.contains(&item) {
+                recent.push(item);
+            }
+        }
+        let omitted = recent.len().saturating_sub(limit);
+        recent.truncate(limit);
+
+        rendered.push_str(&format!("{}:\n", heading));
+        for item in recent.iter().rev() {
+            rendered.push_str(&format!("- {}\n", item));
+        }
+        if omitted > 0 {
+            rendered.push_str(&format!("- (and {} earlier)\n", omitted));
+        }
+    }
+
+    rendered.trim_end().to_string()
+}
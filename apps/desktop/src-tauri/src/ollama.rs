@@ -0,0 +1,191 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+// Timeouts and retry behaviour shared by every Ollama call, stored as the "ollama_client" setting
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientConfig {
+    pub connect_timeout_secs: u64,
+    pub request_timeout_secs: u64,    // Listing, deleting and other quick calls
+    pub generation_timeout_secs: u64, // Generating text, which can take minutes on a slow machine
+    pub max_retries: u32,             // Extra attempts after a transient failure
+    pub initial_backoff_ms: u64,      // Doubles after each retry
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 5,
+            request_timeout_secs: 30,
+            generation_timeout_secs: 300,
+            max_retries: 2,
+            initial_backoff_ms: 500,
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.connect_timeout_secs == 0 || self.request_timeout_secs == 0 || self.generation_timeout_secs == 0 {
+            return Err("Ollama timeouts must be at least one second".to_string());
+        }
+        if self.max_retries > 5 {
+            return Err("Ollama retries can't exceed 5".to_string());
+        }
+        Ok(())
+    }
+}
+
+static CONFIG: OnceLock<RwLock<ClientConfig>> = OnceLock::new();
+
+fn config_slot() -> &'static RwLock<ClientConfig> {
+    CONFIG.get_or_init(|| RwLock::new(ClientConfig::default()))
+}
+
+// Timeouts and retries apply to the next call; the connect timeout only to clients created afterwards
+pub fn set_config(config: ClientConfig) {
+    if let Ok(mut current) = config_slot().write() {
+        *current = config;
+    }
+}
+
+pub fn config() -> ClientConfig {
+    config_slot().read().map(|config| *config).unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+pub enum OllamaError {
+    Unreachable(String), // Couldn't connect, even after retrying
+    Timeout,
+    Status { status: u16, body: String },
+    InvalidResponse(String),
+}
+
+impl std::fmt::Display for OllamaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OllamaError::Unreachable(e) => write!(f, "Failed to connect to Ollama: {}. Make sure Ollama is running.", e),
+            OllamaError::Timeout => write!(f, "Ollama took too long to respond"),
+            OllamaError::Status { status, body } if body.is_empty() => write!(f, "Ollama returned status {}", status),
+            OllamaError::Status { status, body } => write!(f, "Ollama returned status {}: {}", status, body),
+            OllamaError::InvalidResponse(e) => write!(f, "Failed to parse Ollama response: {}", e),
+        }
+    }
+}
+
+impl From<OllamaError> for String {
+    fn from(error: OllamaError) -> Self {
+        error.to_string()
+    }
+}
+
+// How long a call may run before it is abandoned
+#[derive(Debug, Clone, Copy)]
+pub enum CallKind {
+    Quick,
+    Generation,
+    Download, // Model pulls; no limit beyond the connect timeout
+}
+
+// HTTP access to one Ollama endpoint; the feature clients build their requests on top of it.
+// OpenAI-compatible servers reuse it with an API key.
+#[derive(Clone)]
+pub struct OllamaHttp {
+    base_url: String,
+    client: reqwest::Client,
+    api_key: Option<String>, // Sent as a bearer token
+}
+
+impl OllamaHttp {
+    pub fn new(base_url: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config().connect_timeout_secs))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        Self {
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            client,
+            api_key: None,
+        }
+    }
+
+    pub fn with_api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key.filter(|key| !key.trim().is_empty());
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str, kind: CallKind) -> Result<T, OllamaError> {
+        let response = self.send(kind, || self.client.get(self.url(path))).await?;
+        response.json().await.map_err(|e| OllamaError::InvalidResponse(e.to_string()))
+    }
+
+    pub async fn post_json<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B, kind: CallKind) -> Result<T, OllamaError> {
+        let response = self.send(kind, || self.client.post(self.url(path)).json(body)).await?;
+        response.json().await.map_err(|e| OllamaError::InvalidResponse(e.to_string()))
+    }
+
+    // For streamed replies the caller reads chunk by chunk
+    pub async fn post_stream<B: Serialize>(&self, path: &str, body: &B, kind: CallKind) -> Result<reqwest::Response, OllamaError> {
+        self.send(kind, || self.client.post(self.url(path)).json(body)).await
+    }
+
+    // For calls whose reply body doesn't matter
+    pub async fn send_json<B: Serialize>(&self, method: reqwest::Method, path: &str, body: &B, kind: CallKind) -> Result<(), OllamaError> {
+        self.send(kind, || self.client.request(method.clone(), self.url(path)).json(body)).await?;
+        Ok(())
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    // Retry with exponential backoff while Ollama can't be reached or says it's busy (429, 502-504).
+    // Timeouts and other errors are returned straight away: a slow generation isn't worth repeating.
+    async fn send(&self, kind: CallKind, build: impl Fn() -> reqwest::RequestBuilder) -> Result<reqwest::Response, OllamaError> {
+        let config = config();
+        let mut backoff = Duration::from_millis(config.initial_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            let request = match kind {
+                CallKind::Quick => build().timeout(Duration::from_secs(config.request_timeout_secs)),
+                CallKind::Generation => build().timeout(Duration::from_secs(config.generation_timeout_secs)),
+                CallKind::Download => build(),
+            };
+            let request = match &self.api_key {
+                Some(api_key) => request.bearer_auth(api_key),
+                None => request,
+            };
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    let body = response.text().await.unwrap_or_default();
+                    let error = OllamaError::Status { status, body: body.trim().to_string() };
+                    if !matches!(status, 429 | 502 | 503 | 504) {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) if e.is_timeout() => return Err(OllamaError::Timeout),
+                Err(e) if e.is_connect() => OllamaError::Unreachable(e.to_string()),
+                Err(e) => return Err(OllamaError::Unreachable(e.to_string())),
+            };
+
+            if attempt >= config.max_retries {
+                return Err(error);
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use crate::audio_codec;
+use rubato::{FftFixedIn, Resampler};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybackStatus {
+    pub file_path: String,
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub is_playing: bool,
+}
+
+// Decoded clip shared between the player and the output stream callback
+struct Playback {
+    file_path: String,
+    samples: Vec<f32>, // Mono, already at the output device's sample rate
+    sample_rate: u32,
+    position: usize,
+    stopped: bool,
+}
+
+impl Playback {
+    fn status(&self) -> PlaybackStatus {
+        PlaybackStatus {
+            file_path: self.file_path.clone(),
+            position_ms: self.position as u64 * 1000 / self.sample_rate as u64,
+            duration_ms: self.samples.len() as u64 * 1000 / self.sample_rate as u64,
+            is_playing: !self.stopped && self.position < self.samples.len(),
+        }
+    }
+}
+
+pub type PlaybackFinishedCallback = Box<dyn FnOnce(PlaybackStatus) + Send>;
+
+pub struct AudioPlayer {
+    current: Option<Arc<Mutex<Playback>>>,
+}
+
+impl AudioPlayer {
+    pub fn new() -> Self {
+        Self { current: None }
+    }
+
+    // Play a recording from `start_ms`; `on_finished` runs when it ends or is stopped
+    pub fn play(&mut self, file_path: &str, start_ms: u64, on_finished: PlaybackFinishedCallback) -> Result<PlaybackStatus, String> {
+        self.stop();
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| "No output device available".to_string())?;
+        let config = device.default_output_config().map_err(|e| e.to_string())?;
+        let output_sample_rate = config.sample_rate().0;
+
+        let samples = load_mono_samples(file_path, output_sample_rate)?;
+        let start = (start_ms * output_sample_rate as u64 / 1000) as usize;
+        let playback = Arc::new(Mutex::new(Playback {
+            file_path: file_path.to_string(),
+            position: start.min(samples.len()),
+            samples,
+            sample_rate: output_sample_rate,
+            stopped: false,
+        }));
+
+        let status = playback.lock().map_err(|e| e.to_string())?.status();
+        let playback_clone = playback.clone();
+        let sample_format = config.sample_format();
+        let stream_config: StreamConfig = config.into();
+
+        // cpal streams aren't Send, so the stream lives on its own thread for the clip's lifetime
+        thread::spawn(move || {
+            let stream = match sample_format {
+                SampleFormat::F32 => build_output_stream::<f32>(&device, &stream_config, playback_clone.clone()),
+                SampleFormat::I16 => build_output_stream::<i16>(&device, &stream_config, playback_clone.clone()),
+                SampleFormat::U16 => build_output_stream::<u16>(&device, &stream_config, playback_clone.clone()),
+                _ => Err("Unsupported output sample format".to_string()),
+            };
+            let stream = match stream.and_then(|stream| stream.play().map(|_| stream).map_err(|e| e.to_string())) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Playback error: {}", e);
+                    if let Ok(mut playback) = playback_clone.lock() {
+                        playback.stopped = true;
+                    }
+                    return;
+                }
+            };
+
+            while playback_clone.lock().map(|playback| playback.status().is_playing).unwrap_or(false) {
+                thread::sleep(Duration::from_millis(50));
+            }
+            drop(stream);
+
+            if let Ok(playback) = playback_clone.lock() {
+                on_finished(playback.status());
+            }
+        });
+
+        self.current = Some(playback);
+        Ok(status)
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(playback) = self.current.take() {
+            if let Ok(mut playback) = playback.lock() {
+                playback.stopped = true;
+            }
+        }
+    }
+
+    pub fn seek(&mut self, position_ms: u64) -> Result<PlaybackStatus, String> {
+        let playback = self.current.as_ref().ok_or("Nothing is playing")?;
+        let mut playback = playback.lock().map_err(|e| e.to_string())?;
+        let position = (position_ms * playback.sample_rate as u64 / 1000) as usize;
+        playback.position = position.min(playback.samples.len());
+        Ok(playback.status())
+    }
+
+    pub fn status(&self) -> Option<PlaybackStatus> {
+        let playback = self.current.as_ref()?;
+        playback.lock().ok().map(|playback| playback.status())
+    }
+}
+
+fn build_output_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    playback: Arc<Mutex<Playback>>,
+) -> Result<Stream, String>
+where
+    T: SizedSample + FromSample<f32> + Send + 'static,
+{
+    let channels = config.channels as usize;
+
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let Ok(mut playback) = playback.lock() else { return };
+                for frame in data.chunks_mut(channels) {
+                    let sample = if playback.stopped {
+                        0.0
+                    } else {
+                        let position = playback.position;
+                        match playback.samples.get(position) {
+                            Some(&sample) => {
+                                playback.position += 1;
+                                sample
+                            }
+                            None => 0.0,
+                        }
+                    };
+                    // Mono clip, so every output channel gets the same sample
+                    for out in frame.iter_mut() {
+                        *out = T::from_sample(sample);
+                    }
+                }
+            },
+            |err| eprintln!("Playback stream error: {}", err),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+// Read a recording (WAV, FLAC or Opus) as mono f32 at the requested sample rate
+fn load_mono_samples(file_path: &str, output_sample_rate: u32) -> Result<Vec<f32>, String> {
+    let audio = audio_codec::open_audio(file_path)
+        .map_err(|e| format!("Failed to open recording {}: {}", file_path, e))?;
+    let interleaved: Vec<f32> = audio.samples.collect::<Result<Vec<_>, _>>()?;
+
+    let channels = audio.channels.max(1) as usize;
+    let mono: Vec<f32> = interleaved.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+
+    if audio.sample_rate == output_sample_rate || mono.is_empty() {
+        return Ok(mono);
+    }
+
+    let mut resampler = FftFixedIn::<f32>::new(audio.sample_rate as usize, output_sample_rate as usize, 1024, 2, 1)
+        .map_err(|e| format!("Failed to create resampler: {}", e))?;
+    let mut output = Vec::new();
+    let mut chunks = mono.chunks(1024).peekable();
+    while let Some(chunk) = chunks.next() {
+        let resampled = if chunk.len() == 1024 && chunks.peek().is_some() {
+            resampler.process(&[chunk], None)
+        } else {
+            resampler.process_partial(Some(&[chunk]), None)
+        };
+        let mut resampled = resampled.map_err(|e| format!("Resampling error: {}", e))?;
+        output.append(&mut resampled[0]);
+    }
+    Ok(output)
+}
@@ -1,308 +1,317 @@
-use reqwest;
+use crate::llm::{ChatMessage, GenerationOptions, ModelBackend};
+use crate::prompts::{self, PromptKind};
+use crate::provider::{complete_with_continuation, CompletionRequest, Provider, ResponseFormat};
 use serde::{Deserialize, Serialize};
-use serde_json;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PracticeSheetRequest {
-    pub model: String,
-    pub prompt: String,
-    pub stream: bool,
-    pub format: String,
-    pub options: RequestOptions,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RequestOptions {
-    pub num_predict: i32,
-    pub temperature: f32,
-    pub top_p: f32,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PracticeSheetLLMResponse {
-    pub model: String,
-    pub created_at: String,
-    pub response: String,
-    pub done: bool,
-}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QuizQuestion {
     pub question_text: String,
     pub options: Vec<String>,
     pub correct_answer: String,
+    #[serde(default)]
+    pub source_message_ids: Vec<String>, // Filled in by link_question_sources, not by the LLM
+    #[serde(default)]
+    pub source_summary_lines: Vec<usize>,
+}
+
+// Five questions with four options each; the response format of quiz requests, checked again on
+// our side. The correct answer being one of the options is checked after parsing.
+pub fn quiz_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "array",
+        "minItems": 5,
+        "maxItems": 5,
+        "items": {
+            "type": "object",
+            "properties": {
+                "question_text": { "type": "string", "minLength": 1 },
+                "options": { "type": "array", "items": { "type": "string" }, "minItems": 4, "maxItems": 4 },
+                "correct_answer": { "type": "string" }
+            },
+            "required": ["question_text", "options", "correct_answer"]
+        }
+    })
 }
 
 pub struct PracticeSheetLLMClient {
-    base_url: String,
-    client: reqwest::Client,
+    provider: Provider,
+    options: GenerationOptions,
 }
 
 impl PracticeSheetLLMClient {
-    pub fn new(base_url: Option<String>) -> Self {
-        Self {
-            base_url: base_url.unwrap_or_else(|| "http://localhost:11434".to_string()),
-            client: reqwest::Client::new(),
-        }
+    pub fn for_backend(backend: &ModelBackend) -> Self {
+        Self { provider: Provider::for_backend(backend), options: GenerationOptions::default() }
     }
 
-    pub async fn generate_practice_sheet(&self, session_summary: &str, model: &str) -> Result<Vec<QuizQuestion>, String> {
-        let prompt = self.create_practice_sheet_prompt(session_summary);
-        
-        let request = PracticeSheetRequest {
-            model: model.to_string(),
-            prompt,
-            stream: false,
-            format: "json".to_string(),
-            options: RequestOptions {
-                num_predict: 2000,
-                temperature: 0.3,  // Lower temperature for more consistent quiz generation
-                top_p: 0.9,
-            },
-        };
+    pub fn with_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-        let url = format!("{}/api/generate", self.base_url);
-        
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send request to Ollama: {}", e))?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("Ollama request failed: {}", error_text));
-        }
+    pub async fn generate_practice_sheet(&self, session_summary: &str, model: &str, seed: i64, deterministic: bool) -> Result<Vec<QuizQuestion>, String> {
+        let prompt = Self::create_practice_sheet_prompt(session_summary);
+        self.request_quiz(prompt, model, seed, deterministic).await
+    }
 
-        let llm_response: PracticeSheetLLMResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse LLM response: {}", e))?;
+    pub async fn generate_redo_practice_sheet(&self, memory_content: &str, attempt_history: &str, sheet_title: &str, model: &str, seed: i64, deterministic: bool) -> Result<Vec<QuizQuestion>, String> {
+        let prompt = self.create_redo_practice_sheet_prompt(memory_content, attempt_history, sheet_title);
+        self.request_quiz(prompt, model, seed, deterministic).await
+    }
 
-        // Parse the JSON response from the LLM
-        let questions = self.parse_quiz_response(&llm_response.response)?;
-        Ok(questions)
+    async fn request_quiz(&self, prompt: String, model: &str, seed: i64, deterministic: bool) -> Result<Vec<QuizQuestion>, String> {
+        let messages = [ChatMessage::new("user", prompt)];
+        let schema = quiz_schema();
+        let options = if deterministic { self.options.without_sampling() } else { self.options };
+        let request = options.apply(CompletionRequest {
+            model,
+            messages: &messages,
+            format: ResponseFormat::Schema(&schema),
+            context_tokens: None,
+            max_tokens: 2000,
+            temperature: if deterministic { 0.0 } else { 0.3 },  // Lower temperature for more consistent quiz generation
+            top_p: 0.9,
+            seed: None,
+        });
+        // The seed passed in is the one stored with the sheet, so it wins over the options' seed
+        let request = CompletionRequest { seed: Some(seed), ..request };
+
+        // Let a cut-off quiz finish, then parse it
+        let output = complete_with_continuation(&self.provider, request).await?;
+        self.parse_quiz_response(&output, model)
     }
 
-    pub async fn generate_redo_practice_sheet(&self, memory_content: &str, sheet_title: &str, model: &str) -> Result<Vec<QuizQuestion>, String> {
-        let prompt = self.create_redo_practice_sheet_prompt(memory_content, sheet_title);
-        
-        let request = PracticeSheetRequest {
-            model: model.to_string(),
-            prompt,
-            stream: false,
-            format: "json".to_string(),
-            options: RequestOptions {
-                num_predict: 2000,
-                temperature: 0.3,  // Lower temperature for more consistent quiz generation
-                top_p: 0.9,
-            },
-        };
+    fn parse_quiz_response(&self, response: &str, model: &str) -> Result<Vec<QuizQuestion>, String> {
+        let questions: Vec<QuizQuestion> = crate::json_repair::parse_for_model_with_schema(model, response, &quiz_schema())
+            .map_err(|e| e.replacen("Failed to parse JSON response", "Failed to parse quiz JSON", 1))?;
 
-        let url = format!("{}/api/generate", self.base_url);
+        // The schema fixes the counts; correct_answer being one of the options is checked here
+        for (i, question) in questions.iter().enumerate() {
+            if !question.options.contains(&question.correct_answer) {
+                return Err(format!("Question {}: correct_answer '{}' is not in options", i + 1, question.correct_answer));
+            }
+        }
         
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send request to Ollama: {}", e))?;
-
-        if !response.status().is_success() {
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("Ollama request failed: {}", error_text));
+        Ok(questions)
+    }
+
+    // A short explanation of why the student's answer is wrong and the correct one is right
+    pub async fn explain_wrong_answer(&self, question: &str, student_answer: &str, correct_answer: &str, model: &str) -> Result<String, String> {
+        let prompt = prompts::render(PromptKind::AnswerFeedback, &[
+            ("question", question),
+            ("student_answer", student_answer),
+            ("correct_answer", correct_answer),
+        ]);
+        let messages = [ChatMessage::new("user", prompt)];
+        let request = self.options.apply(CompletionRequest {
+            model,
+            messages: &messages,
+            format: ResponseFormat::Text,
+            context_tokens: None,
+            max_tokens: 300,
+            temperature: 0.3,
+            top_p: 0.9,
+            seed: None,
+        });
+
+        let explanation = complete_with_continuation(&self.provider, request).await?;
+        let explanation = explanation.trim();
+        if explanation.is_empty() {
+            return Err(format!("{} returned an empty explanation", model));
         }
+        Ok(explanation.to_string())
+    }
 
-        let llm_response: PracticeSheetLLMResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse LLM response: {}", e))?;
+    pub fn create_practice_sheet_prompt(session_summary: &str) -> String {
+        prompts::render(PromptKind::Quiz, &[
+            ("summary", session_summary),
+        ])
+    }
 
-        // Parse the JSON response from the LLM
-        let questions = self.parse_quiz_response(&llm_response.response)?;
-        Ok(questions)
+    fn create_redo_practice_sheet_prompt(&self, memory_content: &str, attempt_history: &str, sheet_title: &str) -> String {
+        prompts::render(PromptKind::RedoQuiz, &[
+            ("sheet_title", sheet_title),
+            ("memory_content", memory_content),
+            ("attempt_history", attempt_history),
+        ])
     }
+}
 
-    fn parse_quiz_response(&self, response: &str) -> Result<Vec<QuizQuestion>, String> {
-        // First try normal JSON parsing
-        match serde_json::from_str::<Vec<QuizQuestion>>(response) {
-            Ok(questions) => {
-                // Validate we have exactly 5 questions
-                if questions.len() != 5 {
-                    return Err(format!("Expected 5 questions, got {}", questions.len()));
-                }
-                
-                // Validate each question has 4 options
-                for (i, question) in questions.iter().enumerate() {
-                    if question.options.len() != 4 {
-                        return Err(format!("Question {} has {} options, expected 4", i + 1, question.options.len()));
-                    }
-                    
-                    // Validate correct_answer is one of the options
-                    if !question.options.contains(&question.correct_answer) {
-                        return Err(format!("Question {}: correct_answer '{}' is not in options", i + 1, question.correct_answer));
-                    }
+// How the learner has done on one concept across every attempt at a sheet. Redo generation
+// rewords questions, so similar questions (by shared significant words) count as one concept.
+#[derive(Debug, Clone)]
+pub struct ConceptHistory {
+    pub question_text: String, // Most recent wording
+    pub correct_answer: String,
+    pub missed: u32,
+    pub answered_correctly: u32,
+}
+
+impl ConceptHistory {
+    // Answered correctly twice; redo sheets shouldn't ask it again
+    pub fn is_mastered(&self) -> bool {
+        self.answered_correctly >= 2
+    }
+}
+
+// Questions of an attempt answered wrongly (or left blank): index, question and the answer given.
+// Attempts made before question snapshots have nothing to grade.
+pub fn wrong_answers(attempt: &crate::database::PracticeAttempt) -> Vec<(usize, &crate::database::AttemptQuestion, &str)> {
+    attempt.questions.iter()
+        .enumerate()
+        .filter_map(|(index, question)| {
+            let answer = attempt.user_answers.get(index).map(String::as_str).unwrap_or_default();
+            (answer != question.correct_answer).then_some((index, question, answer))
+        })
+        .collect()
+}
+
+// Share of significant words two questions need in common to count as the same concept
+const SAME_CONCEPT_OVERLAP: f32 = 0.6;
+
+pub fn summarize_attempt_history(attempts: &[crate::database::PracticeAttempt]) -> Vec<ConceptHistory> {
+    let mut concepts: Vec<(Vec<String>, ConceptHistory)> = Vec::new();
+
+    for attempt in attempts {
+        // Attempts made before question snapshots can't be matched to their questions
+        for (index, question) in attempt.questions.iter().enumerate() {
+            let correct = attempt.user_answers.get(index) == Some(&question.correct_answer);
+            let words = significant_words(&format!("{} {}", question.question_text, question.correct_answer));
+
+            let existing = concepts.iter_mut().find(|(concept_words, _)| {
+                let shared = words.iter().filter(|word| concept_words.binary_search(word).is_ok()).count();
+                let smaller = words.len().min(concept_words.len()).max(1);
+                shared as f32 / smaller as f32 >= SAME_CONCEPT_OVERLAP
+            });
+            let concept = match existing {
+                Some((_, concept)) => {
+                    concept.question_text = question.question_text.clone();
+                    concept.correct_answer = question.correct_answer.clone();
+                    concept
                 }
-                
-                Ok(questions)
-            },
-            Err(e) => {
-                // Try to fix common JSON issues and retry
-                let fixed_response = self.fix_json_response(response);
-                match serde_json::from_str::<Vec<QuizQuestion>>(&fixed_response) {
-                    Ok(questions) => {
-                        if questions.len() != 5 {
-                            return Err(format!("Expected 5 questions, got {}", questions.len()));
-                        }
-                        Ok(questions)
-                    },
-                    Err(_) => Err(format!("Failed to parse quiz JSON: {}. Raw response: {}", e, response))
+                None => {
+                    concepts.push((words, ConceptHistory {
+                        question_text: question.question_text.clone(),
+                        correct_answer: question.correct_answer.clone(),
+                        missed: 0,
+                        answered_correctly: 0,
+                    }));
+                    &mut concepts.last_mut().unwrap().1
                 }
+            };
+            if correct {
+                concept.answered_correctly += 1;
+            } else {
+                concept.missed += 1;
             }
         }
     }
 
-    fn fix_json_response(&self, response: &str) -> String {
-        let mut fixed = response.to_string();
-        
-        // Remove any text before the first [
-        if let Some(start) = fixed.find('[') {
-            fixed = fixed[start..].to_string();
-        }
-        
-        // Remove any text after the last ]
-        if let Some(end) = fixed.rfind(']') {
-            fixed = fixed[..=end].to_string();
-        }
-        
-        // Try to close incomplete JSON if it ends abruptly
-        if !fixed.trim().ends_with(']') {
-            // Count brackets to see if we need to close
-            let open_brackets = fixed.matches('[').count();
-            let close_brackets = fixed.matches(']').count();
-            let open_braces = fixed.matches('{').count();
-            let close_braces = fixed.matches('}').count();
-            
-            // Close any open braces first
-            for _ in close_braces..open_braces {
-                fixed.push('}');
-            }
-            
-            // Close any open brackets
-            for _ in close_brackets..open_brackets {
-                fixed.push(']');
-            }
-        }
-        
-        fixed
-    }
+    let mut history: Vec<ConceptHistory> = concepts.into_iter().map(|(_, concept)| concept).collect();
+    history.sort_by(|a, b| b.missed.cmp(&a.missed));
+    history
+}
 
-    fn create_practice_sheet_prompt(&self, session_summary: &str) -> String {
-        format!(
-            r#"You are a Quiz Creator. Based on the following session summary, generate 5 multiple-choice questions in a valid JSON array format. Each question object should have keys: 'question_text', 'options' (an array of 4 strings), and 'correct_answer'.
-
-Session Summary:
-{}
-
-CRITICAL: You must respond with valid JSON in EXACTLY this format:
-[
-  {{
-    "question_text": "What is the main concept discussed in this session?",
-    "options": ["Option A", "Option B", "Option C", "Option D"],
-    "correct_answer": "Option A"
-  }},
-  {{
-    "question_text": "Which Python feature was demonstrated?",
-    "options": ["Feature 1", "Feature 2", "Feature 3", "Feature 4"],
-    "correct_answer": "Feature 2"
-  }},
-  {{
-    "question_text": "What was the key learning outcome?",
-    "options": ["Outcome A", "Outcome B", "Outcome C", "Outcome D"],
-    "correct_answer": "Outcome C"
-  }},
-  {{
-    "question_text": "Which programming technique was explained?",
-    "options": ["Technique 1", "Technique 2", "Technique 3", "Technique 4"],
-    "correct_answer": "Technique 4"
-  }},
-  {{
-    "question_text": "What was the practical application shown?",
-    "options": ["Application A", "Application B", "Application C", "Application D"],
-    "correct_answer": "Application B"
-  }}
-]
-
-IMPORTANT RULES:
-- Generate EXACTLY 5 questions
-- Each question must have EXACTLY 4 options
-- The correct_answer must be one of the 4 options (exact match)
-- Valid JSON syntax only
-- No additional text outside the JSON array
-- Base questions on the session content provided
-- Make questions educational and relevant to Python learning
-- Ensure correct_answer value exactly matches one of the options
-
-Remember: Respond ONLY with valid JSON array, no additional text."#,
-            session_summary
-        )
+// Attempt history block for the redo prompt, most-missed concepts first
+pub fn render_attempt_history(attempt_count: usize, history: &[ConceptHistory]) -> String {
+    if history.is_empty() {
+        return format!("{} attempt(s); no per-question detail recorded yet.", attempt_count);
     }
 
-    fn create_redo_practice_sheet_prompt(&self, memory_content: &str, sheet_title: &str) -> String {
+    let describe = |concept: &ConceptHistory| {
         format!(
-            r#"You are an Adaptive Learning Specialist. Analyze the user's memory profile provided below, specifically their past incorrect answers on the quiz titled '{}'. Generate 5 NEW, targeted multiple-choice questions that focus on those specific weak areas. Respond in a valid JSON array format.
-
-User's Memory Profile:
-{}
-
-CRITICAL: You must respond with valid JSON in EXACTLY this format:
-[
-  {{
-    "question_text": "Based on your previous mistakes, what is the correct approach to...?",
-    "options": ["Option A", "Option B", "Option C", "Option D"],
-    "correct_answer": "Option A"
-  }},
-  {{
-    "question_text": "You previously got this wrong - which Python concept is most important for...?",
-    "options": ["Concept 1", "Concept 2", "Concept 3", "Concept 4"],
-    "correct_answer": "Concept 2"
-  }},
-  {{
-    "question_text": "Let's reinforce this topic where you made an error - what happens when...?",
-    "options": ["Result A", "Result B", "Result C", "Result D"],
-    "correct_answer": "Result C"
-  }},
-  {{
-    "question_text": "This was a challenging area for you - which method should be used to...?",
-    "options": ["Method 1", "Method 2", "Method 3", "Method 4"],
-    "correct_answer": "Method 4"
-  }},
-  {{
-    "question_text": "Building on your previous attempt, what is the best practice for...?",
-    "options": ["Practice A", "Practice B", "Practice C", "Practice D"],
-    "correct_answer": "Practice B"
-  }}
-]
-
-IMPORTANT ADAPTIVE LEARNING RULES:
-- Generate EXACTLY 5 questions
-- Each question must have EXACTLY 4 options
-- The correct_answer must be one of the 4 options (exact match)
-- Focus on the topics where the user made mistakes in their previous attempt
-- If the user got everything right, create questions that deepen understanding of the same topics
-- Make questions MORE challenging and specific than the original practice sheet
-- Reference their learning journey subtly in question phrasing
-- Valid JSON syntax only
-- No additional text outside the JSON array
-
-Remember: These questions should help the user master the areas where they struggled. Respond ONLY with valid JSON array, no additional text."#,
-            sheet_title,
-            memory_content
+            "- {} (answer: {}) - missed {}x, correct {}x",
+            concept.question_text, concept.correct_answer, concept.missed, concept.answered_correctly
         )
+    };
+    let section = |heading: &str, concepts: Vec<&ConceptHistory>| -> String {
+        if concepts.is_empty() {
+            return String::new();
+        }
+        let lines: Vec<String> = concepts.into_iter().map(describe).collect();
+        format!("{}\n{}\n", heading, lines.join("\n"))
+    };
+
+    let mut rendered = format!("{} attempt(s) so far.\n", attempt_count);
+    rendered.push_str(&section(
+        "Repeatedly missed (highest priority):",
+        history.iter().filter(|concept| concept.missed >= 2 && !concept.is_mastered()).collect(),
+    ));
+    rendered.push_str(&section(
+        "Missed once:",
+        history.iter().filter(|concept| concept.missed == 1 && !concept.is_mastered()).collect(),
+    ));
+    rendered.push_str(&section(
+        "Mastered (correct at least twice; do not ask again):",
+        history.iter().filter(|concept| concept.is_mastered()).collect(),
+    ));
+    rendered.push_str(&section(
+        "Correct once:",
+        history.iter().filter(|concept| concept.missed == 0 && concept.answered_correctly == 1).collect(),
+    ));
+    rendered
+}
+
+// Link each question to the session messages and summary lines it most likely came from,
+// scored by overlap of significant words with the question and its correct answer
+pub fn link_question_sources(
+    questions: &mut [QuizQuestion],
+    messages: &[crate::database::Message],
+    summary: &str,
+) {
+    const MAX_SOURCES: usize = 2;
+
+    let summary_lines: Vec<&str> = summary.lines().collect();
+
+    for question in questions.iter_mut() {
+        let question_words = significant_words(&format!("{} {}", question.question_text, question.correct_answer));
+        if question_words.is_empty() {
+            continue;
+        }
+
+        let mut message_scores: Vec<(usize, &crate::database::Message)> = messages.iter()
+            .map(|message| (overlap_score(&question_words, &message.content), message))
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        message_scores.sort_by(|a, b| b.0.cmp(&a.0));
+        question.source_message_ids = message_scores.iter()
+            .take(MAX_SOURCES)
+            .map(|(_, message)| message.id.clone())
+            .collect();
+
+        let mut line_scores: Vec<(usize, usize)> = summary_lines.iter()
+            .enumerate()
+            .map(|(index, line)| (overlap_score(&question_words, line), index))
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        line_scores.sort_by(|a, b| b.0.cmp(&a.0));
+        question.source_summary_lines = line_scores.iter()
+            .take(MAX_SOURCES)
+            .map(|(_, index)| *index)
+            .collect();
     }
 }
 
+fn significant_words(text: &str) -> Vec<String> {
+    const STOP_WORDS: [&str; 16] = [
+        "the", "and", "for", "that", "this", "with", "what", "which", "when", "how",
+        "are", "was", "you", "your", "from", "does",
+    ];
+
+    let mut words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.len() >= 3 && !STOP_WORDS.contains(&word.as_str()))
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+fn overlap_score(question_words: &[String], text: &str) -> usize {
+    let text_words = significant_words(text);
+    question_words.iter().filter(|word| text_words.binary_search(word).is_ok()).count()
+}
+
 // Helper function to extract session title from summary
 pub fn extract_session_title_from_summary(summary: &str) -> String {
     // Look for "Session name: " pattern
@@ -0,0 +1,189 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// A project the tutor guides the learner through over several sessions, one milestone at a time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectPlan {
+    pub id: String,
+    pub user_id: String,
+    pub session_id: Option<String>, // Session the plan was started from
+    pub title: String,
+    pub goal: String,
+    pub status: String, // "active", "paused", "completed" or "abandoned"; one plan is active at a time
+    pub milestones: Vec<Milestone>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Milestone {
+    pub id: String,
+    pub plan_id: String,
+    pub position: i32,
+    pub title: String,
+    pub description: String,
+    pub acceptance_criteria: Vec<String>,
+    pub check_code: String, // Python asserts run after the learner's code; plans without them are rejected
+    pub completed_at: Option<DateTime<Utc>>,
+    pub last_check: Option<MilestoneCheck>,
+}
+
+// Outcome of running a milestone's check against the learner's code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MilestoneCheck {
+    pub passed: bool,
+    pub output: String,
+    pub error: String, // Final traceback line, e.g. "AssertionError: total() should return 6"
+    pub timed_out: bool,
+    pub checked_at: DateTime<Utc>,
+}
+
+pub const PLAN_STATUSES: [&str; 4] = ["active", "paused", "completed", "abandoned"];
+
+impl ProjectPlan {
+    // The first milestone not yet completed
+    pub fn current_milestone(&self) -> Option<&Milestone> {
+        self.milestones.iter().find(|milestone| milestone.completed_at.is_none())
+    }
+
+    // Block for the tutor prompt so answers build toward the current milestone
+    pub fn render_for_prompt(&self) -> String {
+        let completed = self.milestones.iter().filter(|milestone| milestone.completed_at.is_some()).count();
+        let mut rendered = format!(
+            "Project the student is working on: {} ({} of {} milestones done)\nGoal: {}\n",
+            self.title, completed, self.milestones.len(), self.goal
+        );
+
+        for milestone in &self.milestones {
+            let marker = if milestone.completed_at.is_some() { "[done]" } else { "[todo]" };
+            rendered.push_str(&format!("- {} {}\n", marker, milestone.title));
+        }
+
+        if let Some(current) = self.current_milestone() {
+            rendered.push_str(&format!("Current milestone: {}\n{}\n", current.title, current.description));
+            if !current.acceptance_criteria.is_empty() {
+                rendered.push_str("It is done when:\n");
+                for criterion in &current.acceptance_criteria {
+                    rendered.push_str(&format!("- {}\n", criterion));
+                }
+            }
+            if let Some(check) = current.last_check.as_ref().filter(|check| !check.passed) {
+                let reason = if check.timed_out { "timed out" } else { check.error.as_str() };
+                rendered.push_str(&format!("Last check failed: {}\n", reason));
+            }
+            rendered.push_str("Help with the current milestone; don't jump ahead or solve it outright.\n");
+        }
+        rendered.push('\n');
+        rendered
+    }
+}
+
+// Plan as returned by the model, before it is stored
+#[derive(Debug, Deserialize)]
+pub struct PlanDraft {
+    pub title: String,
+    #[serde(default)]
+    pub milestones: Vec<MilestoneDraft>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MilestoneDraft {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub acceptance_criteria: Vec<String>,
+    #[serde(default)]
+    pub check_code: String,
+}
+
+const MIN_MILESTONES: usize = 2;
+const MAX_MILESTONES: usize = 8;
+
+impl PlanDraft {
+    // Drop untitled milestones and ones without a check, which would pass any code that runs, and
+    // cap the count; too few left means the plan is unusable
+    pub fn validate(mut self) -> Result<Self, String> {
+        self.title = self.title.trim().to_string();
+        self.milestones.retain(|milestone| !milestone.title.trim().is_empty() && !milestone.check_code.trim().is_empty());
+        self.milestones.truncate(MAX_MILESTONES);
+        for milestone in &mut self.milestones {
+            milestone.acceptance_criteria.retain(|criterion| !criterion.trim().is_empty());
+        }
+
+        if self.title.is_empty() {
+            return Err("Generated plan has no title".to_string());
+        }
+        if self.milestones.len() < MIN_MILESTONES {
+            return Err(format!("Generated plan has {} usable milestones; at least {} are needed", self.milestones.len(), MIN_MILESTONES));
+        }
+        Ok(self)
+    }
+}
+
+// Run the milestone's check after the learner's code, the same way suggested code is dry-run.
+// The check only passes when a sentinel printed after its asserts shows up, so code that exits
+// early with sys.exit(0) doesn't skip them.
+pub async fn check_milestone(milestone: &Milestone, code: &str) -> MilestoneCheck {
+    if milestone.check_code.trim().is_empty() {
+        return MilestoneCheck {
+            passed: false,
+            output: String::new(),
+            error: "This milestone has no check to run".to_string(),
+            timed_out: false,
+            checked_at: Utc::now(),
+        };
+    }
+
+    let sentinel = format!("milestone-check-passed-{}", uuid::Uuid::new_v4());
+    let program = format!("{}\n\n# Milestone check\n{}\nprint({:?})\n", code, milestone.check_code, sentinel);
+    let result = crate::interactive_python::dry_run_python(&program, Duration::from_secs(10)).await;
+    let reached_end = result.output.lines().any(|line| line == sentinel);
+    let output = result.output.lines()
+        .filter(|line| *line != sentinel)
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let error = if result.needs_input {
+        // A program stopped at input() never reached the check
+        format!("Code waits for input(), so the check couldn't run ({})", result.error)
+    } else if result.success && !reached_end {
+        "Code exited before the check ran".to_string()
+    } else {
+        result.error
+    };
+    MilestoneCheck {
+        passed: result.success && reached_end,
+        output,
+        error,
+        timed_out: result.timed_out,
+        checked_at: Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draft(title: &str, check_code: &str) -> MilestoneDraft {
+        MilestoneDraft {
+            title: title.to_string(),
+            description: String::new(),
+            acceptance_criteria: Vec::new(),
+            check_code: check_code.to_string(),
+        }
+    }
+
+    #[test]
+    fn milestones_without_a_check_are_dropped() {
+        let plan = PlanDraft {
+            title: "Calculator".to_string(),
+            milestones: vec![draft("Add", "assert add(1, 2) == 3"), draft("Subtract", "  "), draft("Multiply", "assert mul(2, 3) == 6")],
+        };
+        let titles: Vec<String> = plan.validate().unwrap().milestones.into_iter().map(|milestone| milestone.title).collect();
+        assert_eq!(titles, ["Add", "Multiply"]);
+
+        let unchecked = PlanDraft { title: "Calculator".to_string(), milestones: vec![draft("Add", ""), draft("Subtract", "")] };
+        assert!(unchecked.validate().is_err());
+    }
+}
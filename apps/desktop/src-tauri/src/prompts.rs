@@ -0,0 +1,550 @@
+use serde::Serialize;
+use std::path::PathBuf;
+
+// Prompt templates for every LLM feature. The defaults are built in; a teacher can override any
+// of them (tone, age level, language) with a file in the prompts folder of the app's config
+// directory. Templates fill in "{{name}}" placeholders; other braces are sent as written.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    Tutor,
+    HintTutor,
+    Selection,
+    ExplainError,
+    SelfCheck,
+    ProjectPlan,
+    Summary,
+    Quiz,
+    RedoQuiz,
+    SessionTitle,
+    AnswerFeedback,
+}
+
+impl PromptKind {
+    pub const ALL: [PromptKind; 11] = [
+        PromptKind::Tutor,
+        PromptKind::HintTutor,
+        PromptKind::Selection,
+        PromptKind::ExplainError,
+        PromptKind::SelfCheck,
+        PromptKind::ProjectPlan,
+        PromptKind::Summary,
+        PromptKind::Quiz,
+        PromptKind::RedoQuiz,
+        PromptKind::SessionTitle,
+        PromptKind::AnswerFeedback,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            PromptKind::Tutor => "tutor",
+            PromptKind::HintTutor => "hint_tutor",
+            PromptKind::Selection => "selection",
+            PromptKind::ExplainError => "explain_error",
+            PromptKind::SelfCheck => "self_check",
+            PromptKind::ProjectPlan => "project_plan",
+            PromptKind::Summary => "summary",
+            PromptKind::Quiz => "quiz",
+            PromptKind::RedoQuiz => "redo_quiz",
+            PromptKind::SessionTitle => "session_title",
+            PromptKind::AnswerFeedback => "answer_feedback",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Result<Self, String> {
+        Self::ALL.into_iter()
+            .find(|kind| kind.id() == id)
+            .ok_or_else(|| format!("Unknown prompt template: {}", id))
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            PromptKind::Tutor => "System prompt for tutoring replies",
+            PromptKind::HintTutor => "System prompt for tutoring replies in hint mode",
+            PromptKind::Selection => "Questions about lines selected in the editor",
+            PromptKind::ExplainError => "Explaining a failed run and suggesting a fix",
+            PromptKind::SelfCheck => "Reviewing a tutor answer for mistakes",
+            PromptKind::ProjectPlan => "Breaking a project goal into milestones",
+            PromptKind::Summary => "Session summaries kept in memory",
+            PromptKind::Quiz => "Practice sheets from a session summary",
+            PromptKind::RedoQuiz => "Redo practice sheets targeting past mistakes",
+            PromptKind::SessionTitle => "Session titles from the first question and answer",
+            PromptKind::AnswerFeedback => "Explaining a wrong practice sheet answer",
+        }
+    }
+
+    // Every one must appear in a template, so no input is silently left out
+    pub fn placeholders(&self) -> &'static [&'static str] {
+        match self {
+            PromptKind::Tutor | PromptKind::HintTutor => &["memory_section", "session_context", "history_note"],
+            PromptKind::Selection => &["session_context", "context_before", "start_line", "end_line", "selected_lines", "context_after", "question"],
+            PromptKind::ExplainError => &["session_context", "code", "traceback"],
+            PromptKind::SelfCheck => &["session_context", "question", "explanation", "code"],
+            PromptKind::ProjectPlan => &["memory_section", "session_context", "goal"],
+            PromptKind::Summary => &["conversation"],
+            PromptKind::Quiz => &["summary"],
+            PromptKind::RedoQuiz => &["sheet_title", "memory_content", "attempt_history"],
+            PromptKind::SessionTitle => &["question", "answer"],
+            PromptKind::AnswerFeedback => &["question", "student_answer", "correct_answer"],
+        }
+    }
+
+    fn default_template(&self) -> &'static str {
+        match self {
+            PromptKind::Tutor => TUTOR,
+            PromptKind::HintTutor => HINT_TUTOR,
+            PromptKind::Selection => SELECTION,
+            PromptKind::ExplainError => EXPLAIN_ERROR,
+            PromptKind::SelfCheck => SELF_CHECK,
+            PromptKind::ProjectPlan => PROJECT_PLAN,
+            PromptKind::Summary => SUMMARY,
+            PromptKind::Quiz => QUIZ,
+            PromptKind::RedoQuiz => REDO_QUIZ,
+            PromptKind::SessionTitle => SESSION_TITLE,
+            PromptKind::AnswerFeedback => ANSWER_FEEDBACK,
+        }
+    }
+}
+
+// A template as the settings page shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTemplate {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub placeholders: &'static [&'static str],
+    pub content: String, // What is sent: the override if there is one, otherwise the default
+    pub default_content: &'static str,
+    pub customized: bool,
+}
+
+pub fn describe(kind: PromptKind) -> PromptTemplate {
+    let custom = read_override(kind);
+    PromptTemplate {
+        id: kind.id(),
+        description: kind.description(),
+        placeholders: kind.placeholders(),
+        customized: custom.is_some(),
+        content: custom.unwrap_or_else(|| kind.default_template().to_string()),
+        default_content: kind.default_template(),
+    }
+}
+
+// The template in effect, read on every use so edits apply to the next request
+pub fn template(kind: PromptKind) -> String {
+    read_override(kind).unwrap_or_else(|| kind.default_template().to_string())
+}
+
+pub fn render(kind: PromptKind, values: &[(&str, &str)]) -> String {
+    fill(&template(kind), values)
+}
+
+pub fn set_override(kind: PromptKind, content: &str) -> Result<(), String> {
+    validate(kind, content)?;
+    let path = override_path(kind).ok_or("Couldn't find the app's config directory")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(&path, content).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+// Back to the built-in template
+pub fn reset(kind: PromptKind) -> Result<(), String> {
+    match override_path(kind) {
+        Some(path) if path.exists() => {
+            std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn validate(kind: PromptKind, content: &str) -> Result<(), String> {
+    if content.trim().is_empty() {
+        return Err("Prompt template can't be empty".to_string());
+    }
+    let used = placeholders_in(content);
+    if let Some(unknown) = used.iter().find(|name| !kind.placeholders().contains(&name.as_str())) {
+        return Err(format!(
+            "Unknown placeholder {{{{{}}}}}; this template can use {}",
+            unknown,
+            kind.placeholders().iter().map(|name| format!("{{{{{}}}}}", name)).collect::<Vec<_>>().join(", ")
+        ));
+    }
+    if let Some(missing) = kind.placeholders().iter().find(|name| !used.iter().any(|used| used == *name)) {
+        return Err(format!("The template must include {{{{{}}}}}", missing));
+    }
+    Ok(())
+}
+
+fn override_path(kind: PromptKind) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("project-r").join("prompts").join(format!("{}.txt", kind.id())))
+}
+
+// An override that is missing, unreadable or no longer valid falls back to the default
+fn read_override(kind: PromptKind) -> Option<String> {
+    let content = std::fs::read_to_string(override_path(kind)?).ok()?;
+    match validate(kind, &content) {
+        Ok(()) => Some(content),
+        Err(e) => {
+            eprintln!("Ignoring the {} prompt override: {}", kind.id(), e);
+            None
+        }
+    }
+}
+
+// Replace each "{{name}}" with its value in one pass, so placeholders inside values (a student's
+// code can contain anything) are left alone
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let name = &after[..end];
+            values.iter().find(|(key, _)| *key == name).map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                filled.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+// Names of the "{{name}}" placeholders in a template
+fn placeholders_in(template: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) if after[..end].chars().all(|c| c.is_ascii_lowercase() || c == '_') && end > 0 => {
+                names.push(after[..end].to_string());
+                rest = &after[end + 2..];
+            }
+            _ => rest = after,
+        }
+    }
+    names
+}
+
+const TUTOR: &str = r#"You are an AI Python tutor for Project-R. You help students learn Python through conversation and code assistance. Earlier messages in this conversation are what the student and you already said; build on them rather than repeating yourself.
+
+{{memory_section}}{{session_context}}{{history_note}}CRITICAL: You must respond with valid JSON in EXACTLY this format:
+{
+  "conversation_response": "Your helpful response to the user as their Python tutor. Keep this conversational and friendly. Avoid code blocks in this field.",
+  "code_to_insert": "A whole new file for the editor, or empty string",
+  "edits": [{ "start_line": 3, "end_line": 4, "replacement": "code that replaces lines 3-4" }]
+}
+
+IMPORTANT JSON RULES:
+- Field names must be EXACTLY: "conversation_response", "code_to_insert" and "edits"
+- Valid JSON syntax only
+- No additional text outside the JSON
+- Keep conversation_response concise to avoid truncation
+- Escape quotes properly with \"
+
+Guidelines:
+- Be encouraging and educational in conversation_response
+- Explain concepts clearly but keep responses reasonably short
+- Provide working Python code when requested, and leave code_to_insert empty and edits as [] when no code changes are needed
+- To change code that is already in the editor (fixes, new features), use edits and leave code_to_insert empty. Each edit replaces lines start_line to end_line, as numbered in the editor code, with its replacement; to insert without removing anything, use end_line = start_line - 1. Keep edits as small as possible and never touch lines unrelated to the request
+- Use code_to_insert only when the editor is empty or the student asks for a complete rewrite
+- Only include runnable Python code in code_to_insert and replacements
+
+Remember: Respond ONLY with valid JSON, no additional text."#;
+
+const HINT_TUTOR: &str = r#"You are an AI Python tutor for Project-R in hint mode. The student learns by working the solution out themselves, so you never give a complete solution: no finished functions, no fixed versions of their code, no full answers to exercises. Earlier messages in this conversation are what the student and you already said; build on them rather than repeating yourself.
+
+{{memory_section}}{{session_context}}{{history_note}}CRITICAL: You must respond with valid JSON in EXACTLY this format:
+{
+  "conversation_response": "Your hint to the student. Keep this conversational and friendly.",
+  "hint_level": 1,
+  "code_to_insert": "",
+  "edits": []
+}
+
+IMPORTANT JSON RULES:
+- Field names must be EXACTLY: "conversation_response", "hint_level", "code_to_insert" and "edits"
+- code_to_insert is always an empty string and edits is always []
+- Valid JSON syntax only
+- No additional text outside the JSON
+- Keep conversation_response concise to avoid truncation
+- Escape quotes properly with "
+
+Hint levels:
+1. A guiding question or a nudge toward where to look (a line, a concept, what the error message says)
+2. The concept or approach they need, explained with a small example that is unrelated to their task
+3. An outline of the steps, or a skeleton of their code with the key parts left as ___ for them to fill in
+
+Guidelines:
+- Start at level 1 for a new problem and go up one level only when the student has tried and is still stuck
+- Ask the student to explain their thinking or predict what their code will do
+- Point out what they got right before what needs work
+- If the student asks for the answer, encourage them and give the next hint instead
+- Explaining Python concepts, syntax and error messages in general is fine; writing their solution is not
+
+Remember: Respond ONLY with valid JSON, no additional text."#;
+
+const SELECTION: &str = r#"You are an AI Python tutor for Project-R. The student selected a few lines in their editor and has a question about them.
+
+{{session_context}}Lines just before the selection (context only):
+```python
+{{context_before}}
+```
+
+SELECTED LINES {{start_line}}-{{end_line}}:
+```python
+{{selected_lines}}
+```
+
+Lines just after the selection (context only):
+```python
+{{context_after}}
+```
+
+Student's question about the selected lines: "{{question}}"
+
+CRITICAL: You must respond with valid JSON in EXACTLY this format:
+{
+  "conversation_response": "A focused answer about the selected lines only. Keep it short and friendly. Avoid code blocks in this field.",
+  "code_to_insert": "",
+  "edits": [{ "start_line": 1, "end_line": 1, "replacement": "new code for those lines" }]
+}
+
+IMPORTANT:
+- Answer about the SELECTED LINES; use the surrounding lines only to understand them
+- Leave "edits" empty unless the student asked for a change. Each edit replaces lines start_line to end_line (the numbers shown above) with its replacement; never rewrite the whole file
+- Field names must be EXACTLY: "conversation_response", "code_to_insert" and "edits"
+- Valid JSON syntax only, no additional text outside the JSON
+
+Remember: Respond ONLY with valid JSON, no additional text."#;
+
+const EXPLAIN_ERROR: &str = r#"You are an AI Python tutor for Project-R. The student's code failed when they ran it. Explain the error so a beginner understands it, then fix it.
+
+{{session_context}}Student's code (with line numbers):
+```python
+{{code}}
+```
+
+What Python printed:
+```
+{{traceback}}
+```
+
+Respond with JSON only:
+{
+  "summary": "What went wrong, in one or two plain sentences",
+  "cause": "Why it happened, pointing at the line and what is wrong there",
+  "line": line number in the student's code where the problem is, or 0 if unclear,
+  "fixed_code": "The student's whole file with the smallest change that fixes the error; keep everything else exactly as it was"
+}"#;
+
+const SELF_CHECK: &str = r#"You are reviewing a Python tutor's answer for mistakes before it is read aloud to a student.
+
+{{session_context}}Student asked:
+{{question}}
+
+Tutor's explanation:
+{{explanation}}
+
+Tutor's code:
+```python
+{{code}}
+```
+
+Check every factual claim about Python and every line of code. Anything that breaks the class rules
+counts as a mistake. Ignore style and tone.
+Respond with JSON only:
+{
+  "accurate": true or false,
+  "issues": ["one short sentence per concrete mistake"]
+}"#;
+
+const PROJECT_PLAN: &str = r#"You are an AI Python tutor planning a project a student will build over several sessions.
+
+{{memory_section}}{{session_context}}Project goal: "{{goal}}"
+
+Break the project into 3 to 6 milestones, smallest useful step first. Each milestone must:
+- Build on the previous ones and end with code the student can run
+- List concrete acceptance criteria the student can read
+- Have a check_code snippet of Python assert statements that is appended to the student's
+  finished code and passes only when the criteria are met (call their functions, don't redefine
+  them; give each assert a short message; no input() and no file or network access)
+
+Respond with JSON only:
+{
+  "title": "Short project title",
+  "milestones": [
+    {
+      "title": "Milestone title",
+      "description": "What to build in this step",
+      "acceptance_criteria": ["One checkable requirement per entry"],
+      "check_code": "assert add(2, 3) == 5, \"add() should return the sum\""
+    }
+  ]
+}"#;
+
+const SUMMARY: &str = r#"You are a session summary generator for an AI Python tutoring application. Your task is to create a concise summary of a tutoring session based on the conversation between a user and an AI tutor.
+
+Given the session conversation history, generate a summary in EXACTLY this format:
+
+Session name: [Generate a descriptive name for this session based on the main topics/concepts covered]
+Summary: [Write a concise 2-3 sentence summary of what was learned, discussed, or accomplished in this session. Focus on the key programming concepts, techniques, or problems that were covered.]
+
+Important guidelines:
+- The session name should be descriptive and specific (e.g., "Python List Comprehensions and Filtering", "Debugging IndexError in For Loops", "Introduction to Functions and Parameters")
+- The summary should focus on learning outcomes and key concepts
+- Keep the summary concise but informative
+- Use clear, educational language
+- Do not include any other text or formatting outside of the specified format
+
+Session conversation:
+{{conversation}}"#;
+
+const QUIZ: &str = r#"You are a Quiz Creator. Based on the following session summary, generate 5 multiple-choice questions in a valid JSON array format. Each question object should have keys: 'question_text', 'options' (an array of 4 strings), and 'correct_answer'.
+
+Session Summary:
+{{summary}}
+
+CRITICAL: You must respond with valid JSON in EXACTLY this format:
+[
+  {
+    "question_text": "What is the main concept discussed in this session?",
+    "options": ["Option A", "Option B", "Option C", "Option D"],
+    "correct_answer": "Option A"
+  },
+  {
+    "question_text": "Which Python feature was demonstrated?",
+    "options": ["Feature 1", "Feature 2", "Feature 3", "Feature 4"],
+    "correct_answer": "Feature 2"
+  },
+  {
+    "question_text": "What was the key learning outcome?",
+    "options": ["Outcome A", "Outcome B", "Outcome C", "Outcome D"],
+    "correct_answer": "Outcome C"
+  },
+  {
+    "question_text": "Which programming technique was explained?",
+    "options": ["Technique 1", "Technique 2", "Technique 3", "Technique 4"],
+    "correct_answer": "Technique 4"
+  },
+  {
+    "question_text": "What was the practical application shown?",
+    "options": ["Application A", "Application B", "Application C", "Application D"],
+    "correct_answer": "Application B"
+  }
+]
+
+IMPORTANT RULES:
+- Generate EXACTLY 5 questions
+- Each question must have EXACTLY 4 options
+- The correct_answer must be one of the 4 options (exact match)
+- Valid JSON syntax only
+- No additional text outside the JSON array
+- Base questions on the session content provided
+- Make questions educational and relevant to Python learning
+- Ensure correct_answer value exactly matches one of the options
+
+Remember: Respond ONLY with valid JSON array, no additional text."#;
+
+const REDO_QUIZ: &str = r#"You are an Adaptive Learning Specialist. Analyze the user's memory profile and full attempt history provided below, specifically their past incorrect answers on the quiz titled '{{sheet_title}}'. Generate 5 NEW, targeted multiple-choice questions that focus on those specific weak areas. Respond in a valid JSON array format.
+
+User's Memory Profile:
+{{memory_content}}
+
+Attempt History (every attempt at this quiz so far):
+{{attempt_history}}
+
+CRITICAL: You must respond with valid JSON in EXACTLY this format:
+[
+  {
+    "question_text": "Based on your previous mistakes, what is the correct approach to...?",
+    "options": ["Option A", "Option B", "Option C", "Option D"],
+    "correct_answer": "Option A"
+  },
+  {
+    "question_text": "You previously got this wrong - which Python concept is most important for...?",
+    "options": ["Concept 1", "Concept 2", "Concept 3", "Concept 4"],
+    "correct_answer": "Concept 2"
+  },
+  {
+    "question_text": "Let's reinforce this topic where you made an error - what happens when...?",
+    "options": ["Result A", "Result B", "Result C", "Result D"],
+    "correct_answer": "Result C"
+  },
+  {
+    "question_text": "This was a challenging area for you - which method should be used to...?",
+    "options": ["Method 1", "Method 2", "Method 3", "Method 4"],
+    "correct_answer": "Method 4"
+  },
+  {
+    "question_text": "Building on your previous attempt, what is the best practice for...?",
+    "options": ["Practice A", "Practice B", "Practice C", "Practice D"],
+    "correct_answer": "Practice B"
+  }
+]
+
+IMPORTANT ADAPTIVE LEARNING RULES:
+- Generate EXACTLY 5 questions
+- Each question must have EXACTLY 4 options
+- The correct_answer must be one of the 4 options (exact match)
+- Focus on the topics where the user made mistakes across ALL their attempts, not just the latest one
+- Concepts missed more than once matter most: give them at least 3 of the 5 questions when there are any
+- Never repeat or lightly reword a question listed under "Mastered"
+- Give extra weight to answers marked "confident" that were wrong, and to anything under "Correct But Unsure"
+- If the user got everything right, create questions that deepen understanding of the same topics
+- Make questions MORE challenging and specific than the original practice sheet
+- Reference their learning journey subtly in question phrasing
+- Valid JSON syntax only
+- No additional text outside the JSON array
+
+Remember: These questions should help the user master the areas where they struggled. Respond ONLY with valid JSON array, no additional text."#;
+
+const SESSION_TITLE: &str = r#"Write a title for a Python tutoring session that started with this exchange. The title names the topic in 3 to 6 words, like "Fixing a list index error" or "Reading CSV files".
+
+Student: {{question}}
+
+Tutor: {{answer}}
+
+Reply with the title only: no quotes, no label and no punctuation at the end."#;
+
+const ANSWER_FEEDBACK: &str = r#"You are an AI Python tutor going over a practice question a student got wrong.
+
+Question: {{question}}
+Student's answer: {{student_answer}}
+Correct answer: {{correct_answer}}
+
+In 2 to 4 sentences, explain why the correct answer is right and what misunderstanding likely led to the student's answer. If the student left it blank, just explain the correct answer. Be encouraging and speak to the student directly. Reply with the explanation only, as plain text."#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_filled_once() {
+        let filled = fill("Code:\n{{code}}\nAsked: {{question}} {not a placeholder}", &[("code", "x = '{{question}}'"), ("question", "why?")]);
+        assert_eq!(filled, "Code:\nx = '{{question}}'\nAsked: why? {not a placeholder}");
+    }
+
+    #[test]
+    fn overrides_must_use_exactly_the_known_placeholders() {
+        assert!(validate(PromptKind::Quiz, "Write a quiz in French about:\n{{summary}}").is_ok());
+        assert!(validate(PromptKind::Quiz, "Write a quiz").unwrap_err().contains("{{summary}}"));
+        assert!(validate(PromptKind::Quiz, "{{summary}} {{goal}}").unwrap_err().contains("Unknown placeholder {{goal}}"));
+    }
+
+    #[test]
+    fn built_in_templates_are_valid() {
+        for kind in PromptKind::ALL {
+            assert_eq!(validate(kind, kind.default_template()), Ok(()), "{}", kind.id());
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use crate::embedded_llm::EmbeddedProvider;
+use crate::llm::{ChatMessage, ModelBackend};
+use crate::ollama::{CallKind, OllamaError, OllamaHttp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+pub const OPENAI_DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+// Context window assumed when the model doesn't report one (Ollama's default num_ctx)
+pub const DEFAULT_CONTEXT_TOKENS: usize = 4096;
+
+// Larger windows cost memory the app can't assume is there, so reported lengths are capped
+const MAX_CONTEXT_TOKENS: usize = 8192;
+
+// Follow-ups allowed per response when output stops at the token limit
+const MAX_CONTINUATIONS: usize = 2;
+
+// API a backend speaks. "openai" covers any OpenAI-compatible server: LM Studio, vLLM,
+// llama.cpp's server or a hosted API with a key. "embedded" runs a GGUF file in-process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ProviderKind {
+    #[default]
+    #[serde(rename = "ollama")]
+    Ollama,
+    #[serde(rename = "openai")]
+    OpenAi,
+    #[serde(rename = "embedded")]
+    Embedded,
+}
+
+// Shape the reply is constrained to
+#[derive(Debug, Clone, Copy)]
+pub enum ResponseFormat<'a> {
+    Text,
+    Json,
+    Schema(&'a serde_json::Value), // JSON matching this schema
+}
+
+// One generation, independent of the API it is sent to
+#[derive(Debug, Clone, Copy)]
+pub struct CompletionRequest<'a> {
+    pub model: &'a str,
+    pub messages: &'a [ChatMessage],
+    pub format: ResponseFormat<'a>,
+    pub context_tokens: Option<usize>, // Window the prompt was fitted to; None leaves the server's default
+    pub max_tokens: i32,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub seed: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct Completion {
+    pub text: String,
+    pub truncated: bool, // Stopped at max_tokens
+}
+
+// Receives a reply's text piece by piece as it's generated
+pub type TextSink<'a> = dyn FnMut(&str) + Send + 'a;
+
+// What the tutor, summary and quiz clients need from a model server
+pub trait LlmProvider {
+    async fn complete(&self, request: &CompletionRequest<'_>) -> Result<Completion, String>;
+
+    // Like complete, handing the text to `on_text` as it arrives. Servers that aren't streamed
+    // from hand over the whole reply at once.
+    async fn complete_streaming(&self, request: &CompletionRequest<'_>, on_text: &mut TextSink<'_>) -> Result<Completion, String> {
+        let completion = self.complete(request).await?;
+        on_text(&completion.text);
+        Ok(completion)
+    }
+}
+
+// The provider a fallback chain entry points at
+pub enum Provider {
+    Ollama(OllamaHttp),
+    OpenAi(OpenAiProvider),
+    Embedded(EmbeddedProvider),
+}
+
+impl Provider {
+    pub fn for_backend(backend: &ModelBackend) -> Self {
+        match backend.provider {
+            ProviderKind::Ollama => Provider::Ollama(OllamaHttp::new(backend.base_url.clone())),
+            ProviderKind::OpenAi => {
+                let api_key = backend.api_key.clone().or_else(|| crate::keychain::api_key(backend));
+                Provider::OpenAi(OpenAiProvider::new(backend.base_url.clone(), api_key))
+            }
+            ProviderKind::Embedded => Provider::Embedded(EmbeddedProvider::new(&backend.model)),
+        }
+    }
+
+    // Tokens of prompt and reply the model can take at once. Only Ollama reports it; other
+    // servers are assumed to have at least the default.
+    pub async fn context_window(&self, model: &str) -> usize {
+        match self {
+            Provider::Ollama(http) => http.context_length(model).await
+                .map(|length| length.min(MAX_CONTEXT_TOKENS))
+                .unwrap_or(DEFAULT_CONTEXT_TOKENS),
+            Provider::OpenAi(_) | Provider::Embedded(_) => DEFAULT_CONTEXT_TOKENS,
+        }
+    }
+
+    // Whether the model takes image input; None when the server doesn't say, in which case the
+    // images are sent and the server decides
+    pub async fn supports_images(&self, model: &str) -> Option<bool> {
+        match self {
+            Provider::Ollama(http) => http.capabilities(model).await.map(|capabilities| capabilities.iter().any(|c| c == "vision")),
+            Provider::OpenAi(_) => None,
+            Provider::Embedded(_) => Some(false),
+        }
+    }
+}
+
+impl LlmProvider for Provider {
+    async fn complete(&self, request: &CompletionRequest<'_>) -> Result<Completion, String> {
+        match self {
+            Provider::Ollama(http) => http.complete(request).await,
+            Provider::OpenAi(provider) => provider.complete(request).await,
+            Provider::Embedded(provider) => provider.complete(request).await,
+        }
+    }
+
+    async fn complete_streaming(&self, request: &CompletionRequest<'_>, on_text: &mut TextSink<'_>) -> Result<Completion, String> {
+        match self {
+            Provider::Ollama(http) => http.complete_streaming(request, on_text).await,
+            Provider::OpenAi(provider) => provider.complete_streaming(request, on_text).await,
+            Provider::Embedded(provider) => provider.complete_streaming(request, on_text).await,
+        }
+    }
+}
+
+// Send the request, and while the reply stops at max_tokens send the partial reply back as an
+// assistant message followed by "continue", appending each continuation. Best effort: a failed
+// follow-up leaves what was generated so far.
+pub async fn complete_with_continuation(provider: &impl LlmProvider, request: CompletionRequest<'_>) -> Result<String, String> {
+    continue_completion(provider, request, None).await
+}
+
+// complete_with_continuation, with the text of the reply and its continuations handed to
+// `on_text` as it's generated
+pub async fn stream_with_continuation(provider: &impl LlmProvider, request: CompletionRequest<'_>, on_text: &mut TextSink<'_>) -> Result<String, String> {
+    continue_completion(provider, request, Some(on_text)).await
+}
+
+async fn continue_completion(provider: &impl LlmProvider, request: CompletionRequest<'_>, mut on_text: Option<&mut TextSink<'_>>) -> Result<String, String> {
+    let first = complete_once(provider, &request, on_text.as_deref_mut()).await?;
+    let mut output = first.text;
+    let mut truncated = first.truncated;
+
+    for _ in 0..MAX_CONTINUATIONS {
+        if !truncated {
+            break;
+        }
+        let mut messages = request.messages.to_vec();
+        messages.push(ChatMessage::new("assistant", output.clone()));
+        messages.push(ChatMessage::new("user", "continue"));
+
+        let Ok(next) = complete_once(provider, &CompletionRequest { messages: &messages, ..request }, on_text.as_deref_mut()).await else { break };
+
+        crate::json_repair::record_continuation(request.model);
+        output.push_str(&next.text);
+        truncated = next.truncated;
+    }
+    Ok(output)
+}
+
+async fn complete_once(provider: &impl LlmProvider, request: &CompletionRequest<'_>, on_text: Option<&mut TextSink<'_>>) -> Result<Completion, String> {
+    match on_text {
+        Some(on_text) => provider.complete_streaming(request, on_text).await,
+        None => provider.complete(request).await,
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>, // "json", or a JSON schema the output is constrained to
+    options: OllamaOptions,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    num_predict: i32,
+    temperature: f32,
+    top_p: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: ChatMessage,
+    #[serde(default)]
+    done_reason: Option<String>, // "length" when generation stopped at num_predict
+}
+
+// One line of a streamed /api/chat reply; the last has done_reason, and a failure mid-stream
+// comes as an error line
+#[derive(Debug, Deserialize)]
+struct OllamaChatChunk {
+    #[serde(default)]
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+fn ollama_chat_request<'a>(request: &CompletionRequest<'a>, stream: bool) -> OllamaChatRequest<'a> {
+    OllamaChatRequest {
+        model: request.model,
+        messages: request.messages,
+        stream,
+        format: match request.format {
+            ResponseFormat::Text => None,
+            ResponseFormat::Json => Some(serde_json::Value::from("json")),
+            ResponseFormat::Schema(schema) => Some(schema.clone()),
+        },
+        options: OllamaOptions {
+            num_predict: request.max_tokens,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            seed: request.seed,
+            num_ctx: request.context_tokens,
+        },
+    }
+}
+
+impl LlmProvider for OllamaHttp {
+    async fn complete(&self, request: &CompletionRequest<'_>) -> Result<Completion, String> {
+        let body = ollama_chat_request(request, false);
+        let reply: OllamaChatResponse = self.post_json("/api/chat", &body, CallKind::Generation).await?;
+        Ok(Completion {
+            text: reply.message.content,
+            truncated: reply.done_reason.as_deref() == Some("length"),
+        })
+    }
+
+    // Ollama streams one JSON object per line, each with the next piece of the message
+    async fn complete_streaming(&self, request: &CompletionRequest<'_>, on_text: &mut TextSink<'_>) -> Result<Completion, String> {
+        let body = ollama_chat_request(request, true);
+        let mut response = self.post_stream("/api/chat", &body, CallKind::Generation).await?;
+
+        let mut text = String::new();
+        let mut done_reason = None;
+        let mut buffer = Vec::new();
+        let mut finished = false;
+        while !finished {
+            match response.chunk().await.map_err(|e| format!("Ollama reply was interrupted: {}", e))? {
+                Some(chunk) => buffer.extend_from_slice(&chunk),
+                None => {
+                    // The last line may come without a newline
+                    buffer.push(b'\n');
+                    finished = true;
+                }
+            }
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let Ok(chunk) = serde_json::from_slice::<OllamaChatChunk>(&line) else { continue };
+                if let Some(error) = chunk.error {
+                    return Err(format!("Ollama failed mid-reply: {}", error));
+                }
+                if let Some(message) = chunk.message.filter(|message| !message.content.is_empty()) {
+                    on_text(&message.content);
+                    text.push_str(&message.content);
+                }
+                done_reason = chunk.done_reason.or(done_reason);
+            }
+        }
+        Ok(Completion {
+            text,
+            truncated: done_reason.as_deref() == Some("length"),
+        })
+    }
+}
+
+// Context lengths from /api/show by base URL and model; they only change when a model is re-pulled
+static CONTEXT_LENGTHS: OnceLock<Mutex<HashMap<String, usize>>> = OnceLock::new();
+
+impl OllamaHttp {
+    // The model's trained context length, from the "<architecture>.context_length" entry of its
+    // model info
+    async fn context_length(&self, model: &str) -> Option<usize> {
+        let cache = CONTEXT_LENGTHS.get_or_init(|| Mutex::new(HashMap::new()));
+        let key = format!("{}|{}", self.base_url(), model);
+        if let Some(length) = cache.lock().ok().and_then(|lengths| lengths.get(&key).copied()) {
+            return Some(length);
+        }
+
+        let request = serde_json::json!({ "model": model });
+        let details: serde_json::Value = self.post_json("/api/show", &request, CallKind::Quick).await.ok()?;
+        let length = details.get("model_info")?.as_object()?
+            .iter()
+            .find(|(name, _)| name.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64())? as usize;
+
+        if let Ok(mut lengths) = cache.lock() {
+            lengths.insert(key, length);
+        }
+        Some(length)
+    }
+
+    // What the model can do ("completion", "vision", "tools"...), as /api/show reports it; Ollama
+    // releases before capabilities were added report nothing
+    async fn capabilities(&self, model: &str) -> Option<Vec<String>> {
+        let request = serde_json::json!({ "model": model });
+        let details: serde_json::Value = self.post_json("/api/show", &request, CallKind::Quick).await.ok()?;
+        serde_json::from_value(details.get("capabilities")?.clone()).ok()
+    }
+}
+
+// Chat completions on an OpenAI-compatible server; the base URL includes the version
+// prefix, e.g. "http://localhost:1234/v1"
+pub struct OpenAiProvider {
+    http: OllamaHttp,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<serde_json::Value>,
+    stream: bool,
+    max_tokens: i32,
+    temperature: f32,
+    top_p: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiChatResponse {
+    choices: Vec<OpenAiChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiChoice {
+    message: ChatMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+impl OpenAiProvider {
+    pub fn new(base_url: Option<String>, api_key: Option<String>) -> Self {
+        let base_url = base_url.unwrap_or_else(|| OPENAI_DEFAULT_BASE_URL.to_string());
+        Self { http: OllamaHttp::new(Some(base_url.trim_end_matches('/').to_string())).with_api_key(api_key) }
+    }
+
+    fn describe(&self, error: OllamaError) -> String {
+        let base_url = self.http.base_url();
+        match error {
+            OllamaError::Unreachable(e) => format!("Failed to connect to {}: {}", base_url, e),
+            OllamaError::Timeout => format!("{} took too long to respond", base_url),
+            OllamaError::Status { status, body } => format!("{} returned status {}: {}", base_url, status, body),
+            OllamaError::InvalidResponse(e) => format!("Failed to parse response from {}: {}", base_url, e),
+        }
+    }
+}
+
+impl LlmProvider for OpenAiProvider {
+    // Plain JSON mode isn't requested: several compatible servers reject "json_object", and
+    // callers already ask for JSON in the prompt. Schemas are only sent for objects, the one
+    // root type OpenAI accepts.
+    async fn complete(&self, request: &CompletionRequest<'_>) -> Result<Completion, String> {
+        let body = OpenAiChatRequest {
+            model: request.model,
+            messages: request.messages.iter().map(openai_message).collect(),
+            stream: false,
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            top_p: request.top_p,
+            seed: request.seed,
+            response_format: match request.format {
+                ResponseFormat::Schema(schema) if schema.get("type").and_then(|kind| kind.as_str()) == Some("object") => {
+                    Some(serde_json::json!({
+                        "type": "json_schema",
+                        "json_schema": { "name": "response", "schema": schema },
+                    }))
+                }
+                _ => None,
+            },
+        };
+        let reply: OpenAiChatResponse = self.http.post_json("/chat/completions", &body, CallKind::Generation).await
+            .map_err(|e| self.describe(e))?;
+        let choice = reply.choices.into_iter().next()
+            .ok_or_else(|| format!("{} returned no completion", self.http.base_url()))?;
+        Ok(Completion {
+            text: choice.message.content,
+            truncated: choice.finish_reason.as_deref() == Some("length"),
+        })
+    }
+}
+
+// Messages with images become content parts, the images as data URLs
+fn openai_message(message: &ChatMessage) -> serde_json::Value {
+    if message.images.is_empty() {
+        return serde_json::json!({ "role": message.role, "content": message.content });
+    }
+    let mut parts = vec![serde_json::json!({ "type": "text", "text": message.content })];
+    parts.extend(message.images.iter().map(|image| {
+        let mime = crate::llm::image_mime_type(image).unwrap_or("image/png");
+        serde_json::json!({ "type": "image_url", "image_url": { "url": format!("data:{};base64,{}", mime, image) } })
+    }));
+    serde_json::json!({ "role": message.role, "content": parts })
+}
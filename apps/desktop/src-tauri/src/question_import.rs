@@ -0,0 +1,238 @@
+use crate::practice_sheet::QuizQuestion;
+
+// Teacher-authored question bank formats
+//
+// CSV: one question per row, `question,option 1,option 2,...,answer`. The answer may be the
+// option text, its letter (A, B, ...) or its 1-based number. A header row starting with
+// "question" is skipped.
+//
+// Markdown: each question is a heading followed by checkbox options, the correct one ticked:
+//
+//     ## What does len([1, 2]) return?
+//     - [ ] 1
+//     - [x] 2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    Csv,
+    Markdown,
+}
+
+impl ImportFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(ImportFormat::Csv),
+            "md" | "markdown" => Ok(ImportFormat::Markdown),
+            other => Err(format!("Unsupported question format: {} (expected csv or markdown)", other)),
+        }
+    }
+}
+
+pub fn parse_questions(content: &str, format: ImportFormat) -> Result<Vec<QuizQuestion>, String> {
+    let questions = match format {
+        ImportFormat::Csv => parse_csv(content)?,
+        ImportFormat::Markdown => parse_markdown(content)?,
+    };
+
+    if questions.is_empty() {
+        return Err("No questions found in file".to_string());
+    }
+    Ok(questions)
+}
+
+fn parse_csv(content: &str) -> Result<Vec<QuizQuestion>, String> {
+    let mut questions = Vec::new();
+
+    for (line_index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = split_csv_line(line);
+        if line_index == 0 && fields.first().map(|f| f.to_lowercase().starts_with("question")).unwrap_or(false) {
+            continue;
+        }
+
+        // Question, at least two options, answer
+        if fields.len() < 4 {
+            return Err(format!("Line {}: expected a question, at least two options and an answer", line_index + 1));
+        }
+
+        let question_text = fields[0].clone();
+        let options: Vec<String> = fields[1..fields.len() - 1].iter()
+            .filter(|option| !option.is_empty())
+            .cloned()
+            .collect();
+        let answer = &fields[fields.len() - 1];
+
+        let correct_answer = resolve_answer(answer, &options)
+            .ok_or_else(|| format!("Line {}: answer '{}' does not match any option", line_index + 1, answer))?;
+
+        questions.push(imported_question(question_text, options, correct_answer));
+    }
+
+    Ok(questions)
+}
+
+// Split one CSV row, honouring double-quoted fields and "" escapes
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+// Accept the option text, a letter or a 1-based index
+fn resolve_answer(answer: &str, options: &[String]) -> Option<String> {
+    if let Some(option) = options.iter().find(|option| option.eq_ignore_ascii_case(answer)) {
+        return Some(option.clone());
+    }
+
+    let index = if answer.len() == 1 && answer.chars().all(|c| c.is_ascii_alphabetic()) {
+        (answer.to_ascii_uppercase().as_bytes()[0] - b'A') as usize
+    } else {
+        answer.parse::<usize>().ok()?.checked_sub(1)?
+    };
+    options.get(index).cloned()
+}
+
+fn parse_markdown(content: &str) -> Result<Vec<QuizQuestion>, String> {
+    let mut questions = Vec::new();
+    let mut current: Option<(String, Vec<String>, Option<String>)> = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.starts_with('#') {
+            if let Some(question) = current.take() {
+                questions.push(finish_markdown_question(question)?);
+            }
+            let question_text = line.trim_start_matches('#').trim().to_string();
+            current = Some((question_text, Vec::new(), None));
+        } else if let Some((_, options, correct)) = current.as_mut() {
+            let checkbox = line.strip_prefix("- ").or_else(|| line.strip_prefix("* "));
+            let Some(checkbox) = checkbox else { continue };
+
+            if let Some(option) = checkbox.strip_prefix("[x] ").or_else(|| checkbox.strip_prefix("[X] ")) {
+                options.push(option.trim().to_string());
+                *correct = Some(option.trim().to_string());
+            } else if let Some(option) = checkbox.strip_prefix("[ ] ") {
+                options.push(option.trim().to_string());
+            }
+        }
+    }
+
+    if let Some(question) = current.take() {
+        questions.push(finish_markdown_question(question)?);
+    }
+    Ok(questions)
+}
+
+fn finish_markdown_question((question_text, options, correct): (String, Vec<String>, Option<String>)) -> Result<QuizQuestion, String> {
+    if options.len() < 2 {
+        return Err(format!("Question '{}' needs at least two options", question_text));
+    }
+    let correct_answer = correct
+        .ok_or_else(|| format!("Question '{}' has no option marked [x]", question_text))?;
+    Ok(imported_question(question_text, options, correct_answer))
+}
+
+fn imported_question(question_text: String, options: Vec<String>, correct_answer: String) -> QuizQuestion {
+    QuizQuestion {
+        question_text,
+        options,
+        correct_answer,
+        source_message_ids: Vec::new(),
+        source_summary_lines: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(content: &str, format: ImportFormat) -> String {
+        parse_questions(content, format).unwrap_err()
+    }
+
+    #[test]
+    fn csv_answers_can_be_the_text_a_letter_or_a_number() {
+        let csv = "question,option a,option b,option c,answer\n\
+                   \"What does len(\"\"ab\"\") return?\",1,2,3,2\n\
+                   Which is a list?,\"[1, 2]\",\"(1, 2)\",,a\n\
+                   Which keyword defines a function?,def,fun,lambda,DEF\n";
+        let questions = parse_questions(csv, ImportFormat::Csv).unwrap();
+        assert_eq!(questions.len(), 3);
+        assert_eq!(questions[0].question_text, "What does len(\"ab\") return?");
+        assert_eq!(questions[0].correct_answer, "2");
+        assert_eq!(questions[1].options, ["[1, 2]", "(1, 2)"]);
+        assert_eq!(questions[1].correct_answer, "[1, 2]");
+        assert_eq!(questions[2].correct_answer, "def");
+    }
+
+    #[test]
+    fn csv_rows_without_two_options_and_an_answer_are_rejected() {
+        let message = error("What is 1 + 1?,2,2\n", ImportFormat::Csv);
+        assert!(message.starts_with("Line 1:"), "{}", message);
+        let message = error("What is 1 + 1?,1,2,2\nWhat is 2 + 2?,4\n", ImportFormat::Csv);
+        assert!(message.starts_with("Line 2:"), "{}", message);
+    }
+
+    #[test]
+    fn csv_rows_without_a_correct_answer_are_rejected() {
+        let message = error("What is 1 + 1?,1,2,\n", ImportFormat::Csv);
+        assert_eq!(message, "Line 1: answer '' does not match any option");
+    }
+
+    #[test]
+    fn csv_answers_outside_the_options_are_rejected() {
+        for answer in ["three", "C", "3", "0"] {
+            let message = error(&format!("What is 1 + 1?,1,2,{}\n", answer), ImportFormat::Csv);
+            assert!(message.contains("does not match any option"), "{}: {}", answer, message);
+        }
+    }
+
+    #[test]
+    fn markdown_questions_take_the_ticked_option() {
+        let markdown = "## What does len([1, 2]) return?\n- [ ] 1\n- [x] 2\n\nNotes under a question are ignored.\n\n### Which is immutable?\n* [X] tuple\n* [ ] list\n";
+        let questions = parse_questions(markdown, ImportFormat::Markdown).unwrap();
+        assert_eq!(questions.len(), 2);
+        assert_eq!(questions[0].options, ["1", "2"]);
+        assert_eq!(questions[0].correct_answer, "2");
+        assert_eq!(questions[1].question_text, "Which is immutable?");
+        assert_eq!(questions[1].correct_answer, "tuple");
+    }
+
+    #[test]
+    fn markdown_questions_without_a_ticked_option_are_rejected() {
+        let message = error("## What is 1 + 1?\n- [ ] 1\n- [ ] 2\n", ImportFormat::Markdown);
+        assert_eq!(message, "Question 'What is 1 + 1?' has no option marked [x]");
+    }
+
+    #[test]
+    fn markdown_questions_with_one_option_are_rejected() {
+        let message = error("## What is 1 + 1?\n- [x] 2\n", ImportFormat::Markdown);
+        assert_eq!(message, "Question 'What is 1 + 1?' needs at least two options");
+    }
+
+    #[test]
+    fn files_without_questions_are_rejected() {
+        assert_eq!(error("question,a,b,answer\n\n", ImportFormat::Csv), "No questions found in file");
+        assert_eq!(error("Just some notes\n", ImportFormat::Markdown), "No questions found in file");
+        assert!(ImportFormat::parse("xlsx").is_err());
+        assert_eq!(ImportFormat::parse("MD"), Ok(ImportFormat::Markdown));
+    }
+}
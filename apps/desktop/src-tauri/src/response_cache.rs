@@ -0,0 +1,164 @@
+use crate::llm::{GenerationOptions, ModelBackend};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// Summaries and quizzes generated for the same prompt, model and options are reused instead of
+// spending another minute of inference. Entries are keyed by a hash of everything that shapes
+// the output; the seed only counts when it was fixed, since a random one is picked per run.
+
+// Entries kept; the least recently used go first
+pub const MAX_CACHED_RESPONSES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CachedFeature {
+    Summary,
+    Quiz,
+}
+
+impl CachedFeature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CachedFeature::Summary => "summary",
+            CachedFeature::Quiz => "quiz",
+        }
+    }
+}
+
+// A stored generation and how it was made, so artifacts built from it record the right seed
+// and backend
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub response: String,
+    pub seed: i64,
+    pub backend: String,
+}
+
+// What a request is keyed on, besides the backend it's sent to
+#[derive(Debug, Clone, Copy)]
+pub struct CacheRequest<'a> {
+    pub feature: CachedFeature,
+    pub prompt: &'a str,
+    pub fixed_seed: Option<i64>, // None when the seed is picked at random
+    pub deterministic: bool,
+    pub options: GenerationOptions,
+}
+
+#[derive(Serialize)]
+struct KeyParts<'a> {
+    feature: &'static str,
+    provider: crate::provider::ProviderKind,
+    base_url: &'a Option<String>,
+    model: &'a str,
+    prompt: &'a str,
+    seed: Option<i64>,
+    deterministic: bool,
+    options: GenerationOptions,
+}
+
+// Hex SHA-256 of the request as sent to `backend`; the API key isn't part of it
+pub fn cache_key(request: &CacheRequest<'_>, backend: &ModelBackend) -> String {
+    let parts = KeyParts {
+        feature: request.feature.as_str(),
+        provider: backend.provider,
+        base_url: &backend.base_url,
+        model: &backend.model,
+        prompt: request.prompt,
+        seed: request.fixed_seed,
+        deterministic: request.deterministic,
+        options: GenerationOptions { seed: None, ..request.options },
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_string(&parts).unwrap_or_default().as_bytes());
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The first backend in the chain with a cached response for this request
+pub fn lookup(db: &crate::database::Database, chain: &[ModelBackend], request: &CacheRequest<'_>) -> Result<Option<CachedResponse>, String> {
+    for backend in chain {
+        if let Some(cached) = db.get_cached_response(&cache_key(request, backend)).map_err(|e| e.to_string())? {
+            return Ok(Some(cached));
+        }
+    }
+    Ok(None)
+}
+
+pub fn store(
+    db: &crate::database::Database,
+    request: &CacheRequest<'_>,
+    backend: &ModelBackend,
+    response: &str,
+    seed: i64,
+) -> Result<(), String> {
+    let cached = CachedResponse { response: response.to_string(), seed, backend: backend.label() };
+    db.put_cached_response(&cache_key(request, backend), request.feature.as_str(), &cached)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ProviderKind;
+
+    fn request(prompt: &str) -> CacheRequest<'_> {
+        CacheRequest {
+            feature: CachedFeature::Quiz,
+            prompt,
+            fixed_seed: None,
+            deterministic: false,
+            options: GenerationOptions::default(),
+        }
+    }
+
+    fn remote(api_key: Option<&str>) -> ModelBackend {
+        ModelBackend {
+            base_url: Some("https://llm.example.com/v1".to_string()),
+            provider: ProviderKind::OpenAi,
+            api_key: api_key.map(str::to_string),
+            ..ModelBackend::local("gpt-test")
+        }
+    }
+
+    #[test]
+    fn key_ignores_the_api_key_and_a_random_seed() {
+        let key = cache_key(&request("Lists"), &remote(Some("sk-one")));
+        assert_eq!(cache_key(&request("Lists"), &remote(Some("sk-two"))), key);
+        assert_eq!(cache_key(&request("Lists"), &remote(None)), key);
+
+        let random_seed = CacheRequest { options: GenerationOptions { seed: Some(981), ..Default::default() }, ..request("Lists") };
+        assert_eq!(cache_key(&random_seed, &remote(None)), key);
+    }
+
+    #[test]
+    fn key_changes_with_the_fixed_seed_options_and_prompt() {
+        let key = cache_key(&request("Lists"), &remote(None));
+        let keys = [
+            cache_key(&CacheRequest { fixed_seed: Some(7), ..request("Lists") }, &remote(None)),
+            cache_key(&CacheRequest { fixed_seed: Some(8), ..request("Lists") }, &remote(None)),
+            cache_key(&CacheRequest { options: GenerationOptions { temperature: Some(0.2), ..Default::default() }, ..request("Lists") }, &remote(None)),
+            cache_key(&CacheRequest { deterministic: true, ..request("Lists") }, &remote(None)),
+            cache_key(&CacheRequest { feature: CachedFeature::Summary, ..request("Lists") }, &remote(None)),
+            cache_key(&request("Dicts"), &remote(None)),
+            cache_key(&request("Lists"), &ModelBackend::local("gpt-test")),
+        ];
+        for (index, other) in keys.iter().enumerate() {
+            assert_ne!(*other, key, "variation {}", index);
+            assert!(keys[index + 1..].iter().all(|later| later != other), "variation {} repeats", index);
+        }
+    }
+
+    #[test]
+    fn lookup_prefers_the_first_backend_in_the_chain() {
+        let db = crate::database::Database::new_demo().unwrap();
+        let (first, second) = (ModelBackend::local("gemma3n"), remote(None));
+        let chain = [first.clone(), second.clone()];
+        assert!(lookup(&db, &chain, &request("Lists")).unwrap().is_none());
+
+        store(&db, &request("Lists"), &second, "from the fallback", 2).unwrap();
+        assert_eq!(lookup(&db, &chain, &request("Lists")).unwrap().unwrap().response, "from the fallback");
+
+        store(&db, &request("Lists"), &first, "from the primary", 1).unwrap();
+        let cached = lookup(&db, &chain, &request("Lists")).unwrap().unwrap();
+        assert_eq!((cached.response.as_str(), cached.seed, cached.backend), ("from the primary", 1, first.label()));
+        assert!(lookup(&db, &chain, &request("Dicts")).unwrap().is_none());
+    }
+}
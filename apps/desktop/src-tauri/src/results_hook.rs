@@ -0,0 +1,134 @@
+use crate::database::Database;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Where completed practice sheets are reported, e.g. {"kind":"directory","path":"/home/me/gradebook"}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ResultsHook {
+    Directory { path: String },
+    Http { url: String }, // POSTed as JSON; must point at this machine
+}
+
+impl ResultsHook {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ResultsHook::Directory { path } => {
+                if path.trim().is_empty() {
+                    return Err("Results directory can't be empty".to_string());
+                }
+                Ok(())
+            }
+            ResultsHook::Http { url } => {
+                let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid results URL: {}", e))?;
+                if !matches!(parsed.scheme(), "http" | "https") {
+                    return Err("Results URL must use http or https".to_string());
+                }
+                // Results never leave the machine
+                let host = parsed.host_str().unwrap_or("").trim_matches(|c| c == '[' || c == ']');
+                let is_local = host.eq_ignore_ascii_case("localhost")
+                    || host.parse::<std::net::IpAddr>().map(|ip| ip.is_loopback()).unwrap_or(false);
+                if !is_local {
+                    return Err("Results URL must be a local endpoint (localhost or 127.0.0.1)".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuestionResult {
+    pub question_text: String,
+    pub user_answer: Option<String>,
+    pub correct_answer: String,
+    pub is_correct: bool,
+    pub response_time_ms: Option<i64>,
+    pub confidence: Option<i32>,
+}
+
+// Structured record of one completed sheet, as handed to the hook
+#[derive(Debug, Clone, Serialize)]
+pub struct PracticeResult {
+    pub schema_version: u32,
+    pub user_id: String,
+    pub session_id: String,
+    pub practice_sheet_id: String,
+    pub title: String,
+    pub attempt_id: String,
+    pub score: i32,
+    pub total_questions: i32,
+    pub questions: Vec<QuestionResult>,
+    pub completed_at: DateTime<Utc>,
+}
+
+impl PracticeResult {
+    // Build the record from the sheet's latest attempt; None if it has no attempt yet
+    pub fn from_latest_attempt(db: &Database, practice_sheet_id: &str, user_id: &str) -> rusqlite::Result<Option<Self>> {
+        let Some(attempt) = db.get_practice_attempt(practice_sheet_id)? else {
+            return Ok(None);
+        };
+        let questions = db.get_practice_sheet_questions(practice_sheet_id)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, question)| {
+                let user_answer = attempt.user_answers.get(index).cloned();
+                QuestionResult {
+                    is_correct: user_answer.as_deref() == Some(question.correct_answer.as_str()),
+                    question_text: question.question_text,
+                    user_answer,
+                    correct_answer: question.correct_answer,
+                    response_time_ms: attempt.response_times_ms.get(index).copied(),
+                    confidence: attempt.confidence_levels.get(index).copied(),
+                }
+            })
+            .collect();
+
+        Ok(Some(Self {
+            schema_version: 1,
+            user_id: user_id.to_string(),
+            session_id: db.get_practice_sheet_session_id(practice_sheet_id)?,
+            practice_sheet_id: practice_sheet_id.to_string(),
+            title: db.get_practice_sheet_title(practice_sheet_id)?,
+            attempt_id: attempt.id,
+            score: attempt.score,
+            total_questions: attempt.total_questions,
+            questions,
+            completed_at: attempt.completed_at,
+        }))
+    }
+}
+
+// Write or POST the record; returns where it went
+pub async fn deliver(hook: &ResultsHook, result: &PracticeResult) -> Result<String, String> {
+    let body = serde_json::to_string_pretty(result).map_err(|e| e.to_string())?;
+    match hook {
+        ResultsHook::Directory { path } => {
+            let dir = PathBuf::from(path);
+            std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create results directory: {}", e))?;
+
+            let file_name = format!("{}_{}.json", result.completed_at.format("%Y%m%dT%H%M%SZ"), result.attempt_id);
+            let target = dir.join(&file_name);
+            // Write then rename so scripts watching the folder never read a half-written file
+            let partial = dir.join(format!(".{}.partial", file_name));
+            std::fs::write(&partial, body).map_err(|e| format!("Failed to write result: {}", e))?;
+            std::fs::rename(&partial, &target).map_err(|e| format!("Failed to write result: {}", e))?;
+            Ok(target.to_string_lossy().to_string())
+        }
+        ResultsHook::Http { url } => {
+            let response = reqwest::Client::new()
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body)
+                .timeout(std::time::Duration::from_secs(10))
+                .send()
+                .await
+                .map_err(|e| format!("Failed to post result: {}", e))?;
+            if !response.status().is_success() {
+                return Err(format!("Results endpoint returned {}", response.status()));
+            }
+            Ok(url.clone())
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+// What the class has covered, attached to a session so the tutor stays within it
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SessionContext {
+    pub python_version: Option<String>, // e.g. "3.8"
+    pub allowed_libraries: Vec<String>, // Empty allows any import
+    pub constraints: Vec<String>, // Free-form rules such as "no f-strings yet"
+}
+
+impl SessionContext {
+    pub fn is_empty(&self) -> bool {
+        self.python_version.is_none() && self.allowed_libraries.is_empty() && self.constraints.is_empty()
+    }
+
+    // Block for the tutor and reviewer prompts; empty when nothing is set
+    pub fn render_for_prompt(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        let mut rendered = String::from("Class rules (what the class has covered; never go beyond them):\n");
+        if let Some(version) = &self.python_version {
+            rendered.push_str(&format!("- Python version: {} (don't use newer syntax or features)\n", version));
+        }
+        if !self.allowed_libraries.is_empty() {
+            rendered.push_str(&format!("- Only these libraries may be imported: {}\n", self.allowed_libraries.join(", ")));
+        }
+        for constraint in &self.constraints {
+            rendered.push_str(&format!("- {}\n", constraint));
+        }
+        rendered.push('\n');
+        rendered
+    }
+}
+
+// Top-level modules `code` imports that the allowed library list doesn't cover
+pub fn disallowed_imports(code: &str, context: &SessionContext) -> Vec<String> {
+    if context.allowed_libraries.is_empty() {
+        return Vec::new();
+    }
+
+    let mut disallowed: Vec<String> = Vec::new();
+    for line in code.lines() {
+        let line = line.trim();
+        let modules: Vec<&str> = if let Some(rest) = line.strip_prefix("import ") {
+            // "import a.b as c, d"
+            rest.split(',')
+                .filter_map(|part| part.split_whitespace().next())
+                .collect()
+        } else if let Some(rest) = line.strip_prefix("from ") {
+            // Relative imports stay inside the student's own code
+            rest.split_whitespace().next().filter(|module| !module.starts_with('.')).into_iter().collect()
+        } else {
+            Vec::new()
+        };
+
+        for module in modules {
+            let top_level = module.split('.').next().unwrap_or(module);
+            let allowed = context.allowed_libraries.iter().any(|library| library == top_level);
+            if !allowed && !disallowed.iter().any(|seen| seen == top_level) {
+                disallowed.push(top_level.to_string());
+            }
+        }
+    }
+    disallowed
+}
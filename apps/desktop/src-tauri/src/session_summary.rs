@@ -1,105 +1,52 @@
-use reqwest;
-use serde::{Deserialize, Serialize};
+use crate::llm::{ChatMessage, GenerationOptions, ModelBackend};
+use crate::prompts::{self, PromptKind};
+use crate::provider::{CompletionRequest, LlmProvider, Provider, ResponseFormat};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SessionSummaryRequest {
-    pub model: String,
-    pub prompt: String,
-    pub stream: bool,
-    pub options: RequestOptions,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RequestOptions {
-    pub num_predict: i32,    // Maximum tokens to generate
-    pub temperature: f32,    // Randomness (0.0 to 1.0)
-    pub top_p: f32,         // Nucleus sampling
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SessionSummaryResponse {
-    pub model: String,
-    pub created_at: String,
-    pub response: String,
-    pub done: bool,
-}
+const SUMMARY_MAX_TOKENS: i32 = 200; // Limit tokens for concise summary
 
 pub struct SummaryLLMClient {
-    base_url: String,
-    client: reqwest::Client,
+    provider: Provider,
+    options: GenerationOptions,
 }
 
 impl SummaryLLMClient {
-    pub fn new(base_url: Option<String>) -> Self {
-        Self {
-            base_url: base_url.unwrap_or_else(|| "http://localhost:11434".to_string()),
-            client: reqwest::Client::new(),
-        }
+    pub fn for_backend(backend: &ModelBackend) -> Self {
+        Self { provider: Provider::for_backend(backend), options: GenerationOptions::default() }
     }
 
-    pub async fn generate_session_summary(&self, session_messages: &str, model: &str) -> Result<String, String> {
-        let system_prompt = r#"You are a session summary generator for an AI Python tutoring application. Your task is to create a concise summary of a tutoring session based on the conversation between a user and an AI tutor.
-
-Given the session conversation history, generate a summary in EXACTLY this format:
-
-Session name: [Generate a descriptive name for this session based on the main topics/concepts covered]
-Summary: [Write a concise 2-3 sentence summary of what was learned, discussed, or accomplished in this session. Focus on the key programming concepts, techniques, or problems that were covered.]
-
-Important guidelines:
-- The session name should be descriptive and specific (e.g., "Python List Comprehensions and Filtering", "Debugging IndexError in For Loops", "Introduction to Functions and Parameters")
-- The summary should focus on learning outcomes and key concepts
-- Keep the summary concise but informative
-- Use clear, educational language
-- Do not include any other text or formatting outside of the specified format"#;
-
-        let full_prompt = format!("{}\n\nSession conversation:\n{}", system_prompt, session_messages);
-
-        let request = SessionSummaryRequest {
-            model: model.to_string(),
-            prompt: full_prompt,
-            stream: false,
-            options: RequestOptions {
-                num_predict: 200,  // Limit tokens for concise summary
-                temperature: 0.1,  // Low temperature for consistent formatting
-                top_p: 0.9,
-            },
-        };
-
-        let url = format!("{}/api/generate", self.base_url);
-        
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send request: {}", e))?;
-
-        if !response.status().is_success() {
-            return Err(format!("API request failed with status: {}", response.status()));
-        }
-
-        let summary_response: SessionSummaryResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-        Ok(summary_response.response.trim().to_string())
+    pub fn with_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
     }
 
-    pub async fn check_connection(&self) -> Result<(), String> {
-        let url = format!("{}/api/tags", self.base_url);
-        
-        let response = self.client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to connect to Ollama: {}", e))?;
-
-        if response.status().is_success() {
-            Ok(())
-        } else {
-            Err(format!("Ollama connection failed with status: {}", response.status()))
-        }
+    pub async fn generate_session_summary(&self, session_messages: &str, model: &str, seed: i64, deterministic: bool) -> Result<String, String> {
+        let options = if deterministic { self.options.without_sampling() } else { self.options };
+        let max_tokens = options.num_predict.unwrap_or(SUMMARY_MAX_TOKENS);
+
+        // Long sessions keep their most recent part, leaving room for the summary itself
+        let context_tokens = self.provider.context_window(model).await;
+        let template = prompts::template(PromptKind::Summary);
+        let budget = context_tokens.saturating_sub(max_tokens as usize + crate::llm::estimate_tokens(&template));
+        let session_messages = crate::llm::keep_latest_tokens(session_messages, budget);
+        let full_prompt = prompts::render(PromptKind::Summary, &[("conversation", &session_messages)]);
+
+        let messages = [ChatMessage::new("user", full_prompt)];
+        let request = options.apply(CompletionRequest {
+            model,
+            messages: &messages,
+            format: ResponseFormat::Text,
+            context_tokens: Some(context_tokens),
+            max_tokens,
+            temperature: if deterministic { 0.0 } else { 0.1 },  // Low temperature for consistent formatting
+            top_p: 0.9,
+            seed: None,
+        });
+        // The seed passed in is the one stored with the summary, so it wins over the options' seed
+        let request = CompletionRequest { seed: Some(seed), ..request };
+
+        let completion = self.provider.complete(&request).await?;
+
+        Ok(completion.text.trim().to_string())
     }
 }
 
@@ -0,0 +1,34 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Speaks queued utterances one at a time on a dedicated thread, in the order they were queued,
+// so the commands that queue speech return straight away instead of waiting for it to finish
+pub struct SpeechQueue {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl SpeechQueue {
+    pub fn start() -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name("speech-queue".to_string())
+            .spawn(move || {
+                for job in queue {
+                    // A panicking utterance is dropped; the ones behind it are still spoken
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        eprintln!("Speech job panicked");
+                    }
+                }
+            })
+            .expect("Failed to start the speech queue thread");
+
+        Self { jobs }
+    }
+
+    // Queue `job` behind any utterances already waiting
+    pub fn push(&self, job: impl FnOnce() + Send + 'static) -> Result<(), String> {
+        self.jobs.send(Box::new(job)).map_err(|_| "Speech queue has stopped".to_string())
+    }
+}
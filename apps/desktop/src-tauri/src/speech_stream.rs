@@ -0,0 +1,192 @@
+// The tutor's spoken answer pulled out of its reply while the reply is still streaming in, and
+// cut into sentences so TTS can start on the first one before the rest has been generated. The
+// reply is the JSON object of session_response_schema; only conversation_response is spoken.
+
+const SPOKEN_FIELD: &str = "\"conversation_response\"";
+
+// Shorter sentences are joined with the next, so "Sure." isn't an utterance of its own
+const MIN_SENTENCE_CHARS: usize = 20;
+
+// Words ending in a period that don't end the sentence
+const ABBREVIATIONS: &[&str] = &["e.g.", "i.e.", "etc.", "vs.", "mr.", "mrs.", "dr.", "approx."];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum FieldState {
+    #[default]
+    Searching,   // Looking for the field's key
+    BeforeValue, // Past the key, waiting for the opening quote
+    InValue,
+    Escape,
+    Unicode, // Reading the four hex digits of a \u escape
+    Done,
+}
+
+#[derive(Debug, Default)]
+pub struct SpokenTextStream {
+    state: FieldState,
+    search: String,   // Reply text that may still hold the start of the key
+    unicode: String,  // Hex digits of the \u escape being read
+    sentence: String, // Spoken text since the last sentence break
+}
+
+impl SpokenTextStream {
+    // Feed the next piece of the reply; returns the sentences it completed
+    pub fn push(&mut self, chunk: &str) -> Vec<String> {
+        let mut sentences = Vec::new();
+        let text = if self.state == FieldState::Searching {
+            self.search.push_str(chunk);
+            let Some(at) = self.search.find(SPOKEN_FIELD) else {
+                // Keep enough of the end to match a key split across chunks
+                let mut keep_from = self.search.len().saturating_sub(SPOKEN_FIELD.len());
+                while !self.search.is_char_boundary(keep_from) {
+                    keep_from += 1;
+                }
+                self.search.drain(..keep_from);
+                return sentences;
+            };
+            self.state = FieldState::BeforeValue;
+            let rest = self.search[at + SPOKEN_FIELD.len()..].to_string();
+            self.search.clear();
+            rest
+        } else {
+            chunk.to_string()
+        };
+
+        for c in text.chars() {
+            match self.state {
+                FieldState::Searching | FieldState::Done => break,
+                FieldState::BeforeValue => {
+                    if c == '"' {
+                        self.state = FieldState::InValue;
+                    }
+                }
+                FieldState::InValue => match c {
+                    '\\' => self.state = FieldState::Escape,
+                    '"' => {
+                        // The rest of the reply is code and metadata, so the last sentence goes now
+                        self.state = FieldState::Done;
+                        sentences.extend(self.finish());
+                    }
+                    c => self.push_char(c, &mut sentences),
+                },
+                FieldState::Escape => {
+                    self.state = FieldState::InValue;
+                    match c {
+                        'n' => self.push_char('\n', &mut sentences),
+                        't' => self.push_char(' ', &mut sentences),
+                        'u' => {
+                            self.unicode.clear();
+                            self.state = FieldState::Unicode;
+                        }
+                        'b' | 'f' | 'r' => {}
+                        c => self.push_char(c, &mut sentences),
+                    }
+                }
+                FieldState::Unicode => {
+                    self.unicode.push(c);
+                    if self.unicode.len() == 4 {
+                        self.state = FieldState::InValue;
+                        // Surrogate pairs (emoji) don't decode on their own; they aren't spoken anyway
+                        if let Some(decoded) = u32::from_str_radix(&self.unicode, 16).ok().and_then(char::from_u32) {
+                            self.push_char(decoded, &mut sentences);
+                        }
+                    }
+                }
+            }
+        }
+        sentences
+    }
+
+    // Whatever is left once the reply has ended
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.sentence);
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+
+    // Sentences end at whitespace after ., ! or ?, and at line breaks. Either way a sentence
+    // shorter than MIN_SENTENCE_CHARS runs on into the next, so short list items are read
+    // together. A ``` code block is kept whole, so it can be read out as code.
+    fn push_char(&mut self, c: char, sentences: &mut Vec<String>) {
+        let in_code_block = self.sentence.matches("```").count() % 2 == 1;
+        let ends = !in_code_block
+            && (c == '\n' || (c.is_whitespace() && ends_sentence(&self.sentence)))
+            && self.sentence.trim().chars().count() >= MIN_SENTENCE_CHARS;
+        if ends {
+            sentences.extend(self.finish());
+        } else {
+            self.sentence.push(c);
+        }
+    }
+}
+
+fn ends_sentence(text: &str) -> bool {
+    let text = text.trim_end_matches(['"', '\'', ')']);
+    let Some(last) = text.chars().last() else { return false };
+    if last == '!' || last == '?' {
+        return true;
+    }
+    if last != '.' {
+        return false;
+    }
+    // "e.g." and list numbers like "2." don't end anything
+    let word = text.rsplit(char::is_whitespace).next().unwrap_or_default();
+    let number = &word[..word.len() - 1];
+    !ABBREVIATIONS.contains(&word.to_lowercase().as_str()) && !(!number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speak(chunks: &[&str]) -> Vec<String> {
+        let mut stream = SpokenTextStream::default();
+        let mut sentences: Vec<String> = chunks.iter().flat_map(|chunk| stream.push(chunk)).collect();
+        sentences.extend(stream.finish());
+        sentences
+    }
+
+    #[test]
+    fn sentences_come_out_as_the_field_streams_in() {
+        let mut stream = SpokenTextStream::default();
+        assert!(stream.push("{\"conversation_res").is_empty());
+        assert_eq!(stream.push("ponse\": \"A for loop repeats code. It goes"), ["A for loop repeats code."]);
+        assert_eq!(stream.push(" over each item in a list. Tr"), ["It goes over each item in a list."]);
+        assert_eq!(stream.push("y it!\", \"code_to_insert\": \"print('Done. Really.')\"}"), ["Try it!"]);
+        assert_eq!(stream.finish(), None);
+    }
+
+    #[test]
+    fn abbreviations_numbers_and_short_sentences_stay_together() {
+        let reply = r#"{"conversation_response": "OK. Call a function, e.g. len(x), to count items. Pi is about 3.14 here.\n1. Make a list\n2. Call len on it"}"#;
+        assert_eq!(speak(&[reply]), [
+            "OK. Call a function, e.g. len(x), to count items.",
+            "Pi is about 3.14 here.",
+            "1. Make a list\n2. Call len on it",
+        ]);
+    }
+
+    #[test]
+    fn code_blocks_are_not_split() {
+        let reply = r#"{"conversation_response": "Here is a loop for you:
+```python
+for i in range(3):
+    print(i)
+```
+It prints three numbers."}"#;
+        assert_eq!(speak(&[reply]), [
+            "Here is a loop for you:",
+            "```python
+for i in range(3):
+    print(i)
+```",
+            "It prints three numbers.",
+        ]);
+    }
+
+    #[test]
+    fn escapes_are_decoded_and_other_fields_skipped() {
+        let reply = r#"{"code_to_insert": "x = \"hi\"", "conversation_response": "The \"x\" variable holds caf\u00e9 \\o/"}"#;
+        assert_eq!(speak(&[reply]), ["The \"x\" variable holds café \\o/"]);
+    }
+}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Runs Whisper jobs one at a time on a dedicated thread. Commands queue a job and await its
+// result, so inference never blocks an async command thread and requests are served in order.
+pub struct TranscriptionWorker {
+    jobs: mpsc::Sender<Job>,
+    status: Arc<WorkerStatus>,
+}
+
+#[derive(Default)]
+struct WorkerStatus {
+    waiting: AtomicUsize,
+    busy: AtomicBool,
+}
+
+// Snapshot of the queue for the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueStatus {
+    pub waiting: usize, // Jobs queued behind the running one
+    pub busy: bool,
+}
+
+impl TranscriptionWorker {
+    pub fn start() -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let status = Arc::new(WorkerStatus::default());
+
+        let worker_status = Arc::clone(&status);
+        std::thread::Builder::new()
+            .name("whisper-worker".to_string())
+            .spawn(move || {
+                for job in queue {
+                    worker_status.waiting.fetch_sub(1, Ordering::SeqCst);
+                    worker_status.busy.store(true, Ordering::SeqCst);
+                    // A panicking job drops its reply, which fails that caller; the worker carries on
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        eprintln!("Whisper job panicked");
+                    }
+                    worker_status.busy.store(false, Ordering::SeqCst);
+                }
+            })
+            .expect("Failed to start the Whisper worker thread");
+
+        Self { jobs, status }
+    }
+
+    // Queue `job` behind any already waiting and wait for its result
+    pub async fn run<T, F>(&self, job: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, String> + Send + 'static,
+    {
+        let (reply, result) = tokio::sync::oneshot::channel();
+        self.status.waiting.fetch_add(1, Ordering::SeqCst);
+        let queued = self.jobs.send(Box::new(move || {
+            let _ = reply.send(job());
+        }));
+        if queued.is_err() {
+            self.status.waiting.fetch_sub(1, Ordering::SeqCst);
+            return Err("Whisper worker has stopped".to_string());
+        }
+
+        result.await.map_err(|_| "Whisper job failed unexpectedly".to_string())?
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        QueueStatus {
+            waiting: self.status.waiting.load(Ordering::SeqCst),
+            busy: self.status.busy.load(Ordering::SeqCst),
+        }
+    }
+}
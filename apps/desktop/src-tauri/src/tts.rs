@@ -1,18 +1,565 @@
-use std::path::PathBuf;
-use std::process::Command;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-pub struct SystemTTSEngine {
-    is_initialized: bool,
+// Built-in pronunciations for terms every system voice gets wrong; user entries override these
+const DEFAULT_PRONUNCIATIONS: &[(&str, &str)] = &[
+    ("pypi", "pie pee eye"),
+    ("numpy", "num pie"),
+    ("scipy", "sigh pie"),
+    ("stdin", "standard in"),
+    ("stdout", "standard out"),
+    ("stderr", "standard error"),
+    ("kwargs", "keyword args"),
+    ("repl", "repple"),
+];
+
+// Replace whole words (case-insensitive) with their spoken form before handing text to the voice
+pub fn apply_pronunciations(text: &str, user_lexicon: &[(String, String)]) -> String {
+    let mut lexicon: HashMap<String, String> = DEFAULT_PRONUNCIATIONS.iter()
+        .map(|(word, spoken)| (word.to_string(), spoken.to_string()))
+        .collect();
+    for (word, spoken) in user_lexicon {
+        lexicon.insert(word.to_lowercase(), spoken.clone());
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut word = String::new();
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            match lexicon.get(&word.to_lowercase()) {
+                Some(spoken) => output.push_str(spoken),
+                None => output.push_str(&word),
+            }
+            word.clear();
+        }
+        output.push(c);
+    }
+    output.pop(); // The sentinel space
+    output
 }
 
-impl SystemTTSEngine {
-    pub fn new() -> Self {
+// The speech process or playback in progress, shared between the engine and stop_speech. The
+// engine lock is held for a whole utterance, so stopping goes through this instead.
+#[derive(Clone, Default)]
+pub struct SpeechControl {
+    process: Arc<Mutex<Option<Child>>>,
+    player: Arc<Mutex<Option<crate::playback::AudioPlayer>>>,
+    stops: Arc<AtomicU64>,
+    progress: Arc<Mutex<Option<ProgressSink>>>,
+}
+
+// Called as each word of the current utterance starts being spoken
+pub type ProgressSink = Box<dyn Fn(&WordBoundary) + Send>;
+
+// A word being spoken: its range in the utterance's text, in UTF-16 units as the frontend
+// indexes strings, and how far into the utterance it starts
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WordBoundary {
+    pub start: usize,
+    pub end: usize,
+    pub offset_ms: u64,
+}
+
+// Pauses after punctuation, counted as this many characters of speech
+const CLAUSE_PAUSE_CHARS: usize = 3;
+const SENTENCE_PAUSE_CHARS: usize = 6;
+
+// Where each word falls in speech of the given length, for backends that don't report word
+// timings: time is shared out by word length, plus pauses at punctuation
+pub fn estimate_word_boundaries(text: &str, duration_ms: u64) -> Vec<WordBoundary> {
+    let mut words = Vec::new(); // (start, end, weight)
+    let mut word_start = None;
+    let mut word_chars = 0;
+    let mut position = 0;
+    let mut last = ' ';
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if c.is_whitespace() {
+            if let Some(start) = word_start.take() {
+                let pause = match last {
+                    ',' | ';' | ':' => CLAUSE_PAUSE_CHARS,
+                    '.' | '!' | '?' => SENTENCE_PAUSE_CHARS,
+                    _ => 0,
+                };
+                words.push((start, position, word_chars + pause));
+                word_chars = 0;
+            }
+        } else {
+            word_start.get_or_insert(position);
+            word_chars += 1;
+        }
+        last = c;
+        position += c.len_utf16();
+    }
+
+    let total: usize = words.iter().map(|(_, _, weight)| weight).sum();
+    let mut spoken = 0;
+    words.into_iter()
+        .map(|(start, end, weight)| {
+            let offset_ms = duration_ms * spoken as u64 / total.max(1) as u64;
+            spoken += weight;
+            WordBoundary { start, end, offset_ms }
+        })
+        .collect()
+}
+
+impl SpeechControl {
+    // Taken before waiting for the engine; an utterance whose ticket predates a stop is dropped
+    pub fn ticket(&self) -> u64 {
+        self.stops.load(Ordering::SeqCst)
+    }
+
+    pub fn stopped_since(&self, ticket: u64) -> bool {
+        self.stops.load(Ordering::SeqCst) != ticket
+    }
+
+    // Where word boundaries of the utterances spoken from now on go; None stops reporting them
+    pub fn on_word(&self, sink: Option<ProgressSink>) {
+        if let Ok(mut progress) = self.progress.lock() {
+            *progress = sink;
+        }
+    }
+
+    // Report the words from `next` that have started by `elapsed_ms`
+    fn report_words(&self, words: &[WordBoundary], next: &mut usize, elapsed_ms: u64) {
+        let Ok(progress) = self.progress.lock() else { return };
+        while let Some(word) = words.get(*next).filter(|word| word.offset_ms <= elapsed_ms) {
+            if let Some(sink) = progress.as_ref() {
+                sink(word);
+            }
+            *next += 1;
+        }
+    }
+
+    // Kill the speaking process, stop playback and drop the utterances waiting their turn
+    pub fn stop(&self) {
+        self.stops.fetch_add(1, Ordering::SeqCst);
+        if let Some(mut child) = self.process.lock().ok().and_then(|mut process| process.take()) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if let Some(mut player) = self.player.lock().ok().and_then(|mut player| player.take()) {
+            player.stop();
+        }
+    }
+
+    // Wait for a spawned speech process, which stop() may kill meanwhile, reporting `words` as
+    // their time comes. Returns its exit status and stderr, or None when it was stopped.
+    fn wait(&self, child: Child, words: &[WordBoundary]) -> Result<Option<(ExitStatus, String)>, String> {
+        *self.process.lock().map_err(|e| e.to_string())? = Some(child);
+        let started = std::time::Instant::now();
+        let mut next_word = 0;
+        loop {
+            self.report_words(words, &mut next_word, started.elapsed().as_millis() as u64);
+            {
+                let mut process = self.process.lock().map_err(|e| e.to_string())?;
+                let Some(child) = process.as_mut() else { return Ok(None) };
+                if let Some(status) = child.try_wait().map_err(|e| format!("Error waiting for TTS process: {}", e))? {
+                    let mut stderr = String::new();
+                    if let Some(mut stderr_handle) = child.stderr.take() {
+                        let _ = stderr_handle.read_to_string(&mut stderr);
+                    }
+                    *process = None;
+                    return Ok(Some((status, stderr)));
+                }
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    // Play a WAV through the output device until it ends, reporting the words `words` places
+    // in a clip of its duration as playback reaches them; false when stop() cut it off
+    fn play(&self, path: &Path, words: impl FnOnce(u64) -> Vec<WordBoundary>) -> Result<bool, String> {
+        let (finished, wait) = std::sync::mpsc::channel();
+        let mut player = crate::playback::AudioPlayer::new();
+        let started = player.play(&path.to_string_lossy(), 0, Box::new(move |status| {
+            let _ = finished.send(status);
+        }))?;
+        *self.player.lock().map_err(|e| e.to_string())? = Some(player);
+
+        let words = words(started.duration_ms);
+        let mut next_word = 0;
+        let status = loop {
+            let position_ms = self.player.lock().ok()
+                .and_then(|player| player.as_ref().and_then(|player| player.status()))
+                .map(|status| status.position_ms);
+            if let Some(position_ms) = position_ms {
+                self.report_words(&words, &mut next_word, position_ms);
+            }
+            match wait.recv_timeout(Duration::from_millis(50)) {
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                status => break status.ok(),
+            }
+        };
+        if let Ok(mut player) = self.player.lock() {
+            *player = None;
+        }
+        let status = status.ok_or_else(|| "Playback of synthesized speech failed".to_string())?;
+        Ok(status.position_ms >= status.duration_ms)
+    }
+}
+
+// What a voice is given to say: code read out as words, then the pronunciation lexicon
+pub fn prepare_for_speech(text: &str, user_lexicon: &[(String, String)]) -> String {
+    apply_pronunciations(&render_code_for_speech(text), user_lexicon)
+}
+
+// Code in `backticks` or ``` fences is read as words ("for i in range(10):" becomes "for i in
+// range of ten, colon"); voices spell symbols out or skip them, which can't be followed.
+// Prose around it is left alone.
+pub fn render_code_for_speech(text: &str) -> String {
+    text.split("```")
+        .enumerate()
+        .map(|(index, part)| if index % 2 == 1 { speak_code_block(part) } else { speak_inline_code(part) })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn speak_inline_code(text: &str) -> String {
+    text.split('`')
+        .enumerate()
+        .map(|(index, part)| if index % 2 == 1 { speak_code_line(part) } else { part.to_string() })
+        .collect()
+}
+
+// One sentence per line; a language tag after the opening fence isn't read
+fn speak_code_block(block: &str) -> String {
+    let mut lines: Vec<&str> = block.lines().collect();
+    if lines.len() > 1 && lines[0].trim().chars().all(|c| c.is_alphanumeric()) {
+        lines.remove(0);
+    }
+    let spoken: Vec<String> = lines.iter()
+        .map(|line| speak_code_line(line))
+        .filter(|line| !line.is_empty())
+        .map(|line| format!("{}.", line))
+        .collect();
+    spoken.join("\n")
+}
+
+// Words read in place of Python names
+const SPOKEN_NAMES: &[(&str, &str)] = &[
+    ("def", "define"),
+    ("elif", "else if"),
+    ("len", "length"),
+    ("str", "string"),
+    ("int", "integer"),
+    ("bool", "boolean"),
+    ("dict", "dictionary"),
+];
+
+// Longest first, so "**=" isn't read as "**" then "="
+const SPOKEN_OPERATORS: &[(&str, &str)] = &[
+    ("**=", "power equals"),
+    ("//=", "floor divide equals"),
+    ("==", "is equal to"),
+    ("!=", "is not equal to"),
+    ("<=", "is less than or equal to"),
+    (">=", "is greater than or equal to"),
+    ("+=", "plus equals"),
+    ("-=", "minus equals"),
+    ("*=", "times equals"),
+    ("/=", "divide equals"),
+    ("->", "returns"),
+    ("**", "to the power of"),
+    ("//", "floor divided by"),
+    ("=", "equals"),
+    ("<", "is less than"),
+    (">", "is greater than"),
+    ("+", "plus"),
+    ("-", "minus"),
+    ("*", "times"),
+    ("/", "divided by"),
+    ("%", "modulo"),
+    ("[]", "an empty list"),
+    ("{}", "an empty dictionary"),
+    ("()", "an empty tuple"),
+    ("[", "a list of"),
+    ("{", "a dictionary of"),
+    ("(", ""),
+    (")", ""),
+    ("]", ""),
+    ("}", ""),
+    (".", "dot"),
+    ("@", "at"),
+];
+
+fn speak_code_line(line: &str) -> String {
+    let chars: Vec<char> = line.trim().chars().collect();
+    let mut words: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c == '#' {
+            let comment: String = chars[index + 1..].iter().collect();
+            words.push(format!("comment, {}", comment.trim()));
+            break;
+        } else if c == ',' || c == ':' || c == ';' {
+            let spoken = match c {
+                ',' => ",",
+                ':' => ", colon",
+                _ => ", semicolon",
+            };
+            match words.last_mut() {
+                Some(word) => word.push_str(spoken),
+                None => words.push(spoken.trim_start_matches(", ").to_string()),
+            }
+            index += 1;
+        } else if c == '"' || c == '\'' {
+            let end = (index + 1..chars.len()).find(|&end| chars[end] == c && chars[end - 1] != '\\').unwrap_or(chars.len());
+            let content: String = chars[index + 1..end].iter().collect();
+            words.push(if content.is_empty() { "an empty string".to_string() } else { format!("the string {}", content) });
+            index = end + 1;
+        } else if c.is_ascii_digit() {
+            let end = (index..chars.len()).find(|&end| !(chars[end].is_ascii_digit() || chars[end] == '.' || chars[end] == '_')).unwrap_or(chars.len());
+            let number: String = chars[index..end].iter().filter(|c| **c != '_').collect();
+            words.push(speak_number(&number));
+            index = end;
+        } else if c.is_alphabetic() || c == '_' {
+            let end = (index..chars.len()).find(|&end| !(chars[end].is_alphanumeric() || chars[end] == '_')).unwrap_or(chars.len());
+            let name: String = chars[index..end].iter().collect();
+            let name = SPOKEN_NAMES.iter()
+                .find(|(python, _)| *python == name)
+                .map(|(_, spoken)| spoken.to_string())
+                .unwrap_or_else(|| name.replace('_', " ").trim().to_string());
+            // A call reads "range of ten"; an empty one is just the name
+            index = end;
+            match (chars.get(index), chars.get(index + 1)) {
+                (Some('('), Some(')')) => {
+                    words.push(name);
+                    index += 2;
+                }
+                (Some('('), _) => {
+                    words.push(format!("{} of", name));
+                    index += 1;
+                }
+                (Some('['), Some(c)) if *c != ']' => {
+                    words.push(format!("{} at", name));
+                    index += 1;
+                }
+                _ => words.push(name),
+            }
+        } else {
+            let rest: String = chars[index..].iter().collect();
+            match SPOKEN_OPERATORS.iter().find(|(operator, _)| rest.starts_with(operator)) {
+                Some((operator, spoken)) => {
+                    if !spoken.is_empty() {
+                        words.push(spoken.to_string());
+                    }
+                    index += operator.chars().count();
+                }
+                None => {
+                    words.push(c.to_string());
+                    index += 1;
+                }
+            }
+        }
+    }
+    words.retain(|word| !word.is_empty());
+    words.join(" ")
+}
+
+// "10" as "ten" and "3.14" as "three point one four"; very long numbers are left as digits
+fn speak_number(number: &str) -> String {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let Some(whole_words) = whole.parse::<u64>().ok().filter(|whole| *whole < 1_000_000).map(number_words) else {
+        return number.to_string();
+    };
+    let digits: Vec<&str> = fraction.chars()
+        .filter_map(|digit| digit.to_digit(10))
+        .map(|digit| ONES[digit as usize])
+        .collect();
+    if digits.is_empty() {
+        whole_words
+    } else {
+        format!("{} point {}", whole_words, digits.join(" "))
+    }
+}
+
+const ONES: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+
+fn number_words(number: u64) -> String {
+    match number {
+        0..=19 => ONES[number as usize].to_string(),
+        20..=99 if number % 10 == 0 => TENS[number as usize / 10].to_string(),
+        20..=99 => format!("{} {}", TENS[number as usize / 10], ONES[number as usize % 10]),
+        100..=999 if number % 100 == 0 => format!("{} hundred", ONES[number as usize / 100]),
+        100..=999 => format!("{} hundred {}", ONES[number as usize / 100], number_words(number % 100)),
+        _ if number % 1000 == 0 => format!("{} thousand", number_words(number / 1000)),
+        _ => format!("{} thousand {}", number_words(number / 1000), number_words(number % 1000)),
+    }
+}
+
+// A voice that speaks text aloud; generate_speech blocks until it has finished speaking, so the
+// recorder's echo gate covers the whole utterance
+pub trait TtsBackend: Send {
+    fn initialize(&mut self) -> Result<(), String>;
+    fn is_initialized(&self) -> bool;
+    fn generate_speech(&self, text: &str) -> Result<(), String>;
+    // Speak with another of list_voices' voices than the engine's own, e.g. one for the reply's
+    // language
+    fn speak_with_voice(&self, text: &str, voice: &str) -> Result<(), String>;
+    fn list_voices(&self) -> Result<Vec<TtsVoice>, String>;
+    // Write the speech to `output` as a WAV instead of playing it
+    fn synthesize_to_file(&self, text: &str, output: &Path) -> Result<(), String>;
+}
+
+// A voice the backend can speak with; `id` is what the voice settings store
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TtsVoice {
+    pub id: String,
+    pub name: String,
+    pub language: Option<String>, // e.g. "en_US" or "en-gb", as the platform reports it
+    pub gender: Option<String>,   // "male" or "female", when the platform says
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtsBackendKind {
+    #[default]
+    System, // say, espeak or the Windows speech synthesizer
+    Piper,  // Offline neural voices, the same on every OS
+}
+
+// Stored as the "tts" setting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TtsSettings {
+    pub backend: TtsBackendKind,
+    pub system_voice: Option<String>, // Voice id for say, espeak or Windows; the system default when unset
+    pub piper_binary: Option<String>, // Path to the piper executable; looked up when unset
+    pub piper_voice: Option<String>,  // Path to a voice's .onnx file; the first installed voice when unset
+    pub length_scale: f32,            // Speaking pace for every backend: above 1 is slower, below 1 faster
+    pub language_voices: HashMap<String, String>, // Voice id per reply language, by Whisper code such as "es"
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
         Self {
-            is_initialized: false,
+            backend: TtsBackendKind::System,
+            system_voice: None,
+            piper_binary: None,
+            piper_voice: None,
+            length_scale: 1.0,
+            language_voices: HashMap::new(),
+        }
+    }
+}
+
+impl TtsSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.5..=2.0).contains(&self.length_scale) {
+            return Err("Speaking pace must be between 0.5 and 2".to_string());
+        }
+        if let Some(voice) = &self.piper_voice {
+            if !voice.ends_with(".onnx") {
+                return Err("Piper voices are .onnx files".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // The voice to speak `language` (a Whisper code) with: the one mapped to it, or else the
+    // first installed voice for it. None keeps the engine's own voice, which is expected to speak
+    // English, so English only switches when it's mapped.
+    pub fn voice_for_language(&self, voices: &[TtsVoice], language: &str) -> Option<String> {
+        let mapped = self.language_voices.get(language)
+            .filter(|mapped| voices.iter().any(|voice| &voice.id == *mapped));
+        if let Some(mapped) = mapped {
+            return Some(mapped.clone());
+        }
+        if language == "en" {
+            return None;
+        }
+        voices.iter()
+            .find(|voice| voice.language.as_deref().is_some_and(|tag| primary_language(tag) == language))
+            .map(|voice| voice.id.clone())
+    }
+
+    // Windows voices used to be picked through SAPI by name ("Microsoft Zira Desktop"); point
+    // voices saved that way at the same installed voice's id. Returns whether any changed.
+    #[cfg(any(target_os = "windows", test))]
+    pub fn migrate_sapi_voices(&mut self, voices: &[TtsVoice]) -> bool {
+        let mut changed = false;
+        for saved in self.system_voice.iter_mut().chain(self.language_voices.values_mut()) {
+            if let Some(id) = sapi_voice_id(voices, saved) {
+                *saved = id;
+                changed = true;
+            }
         }
+        changed
     }
+}
+
+// The id of the voice a SAPI voice name refers to; None when `saved` is already an installed
+// voice's id or matches no voice. SAPI adds " Desktop" to the names of the same voices.
+#[cfg(any(target_os = "windows", test))]
+fn sapi_voice_id(voices: &[TtsVoice], saved: &str) -> Option<String> {
+    if voices.iter().any(|voice| voice.id == saved) {
+        return None;
+    }
+    let name = saved.strip_suffix(" Desktop").unwrap_or(saved);
+    voices.iter()
+        .find(|voice| voice.name == saved || voice.name == name)
+        .map(|voice| voice.id.clone())
+}
 
-    pub fn initialize(&mut self) -> Result<(), String> {
+// Move voice settings saved for SAPI over to the WinRT voices; nothing to do elsewhere
+#[cfg(target_os = "windows")]
+pub fn migrate_windows_voices(settings: &mut TtsSettings) -> bool {
+    match winrt_voices() {
+        Ok(voices) => settings.migrate_sapi_voices(&voices),
+        Err(e) => {
+            eprintln!("Failed to list Windows voices: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn migrate_windows_voices(_settings: &mut TtsSettings) -> bool {
+    false
+}
+
+// "es" from the "es-ES", "es_ES" and "es" tags the backends give their voices
+fn primary_language(tag: &str) -> String {
+    tag.split(['-', '_']).next().unwrap_or_default().to_lowercase()
+}
+
+// An uninitialized engine for the chosen backend
+pub fn create_engine(settings: &TtsSettings, speech: SpeechControl) -> Box<dyn TtsBackend> {
+    match settings.backend {
+        TtsBackendKind::System => Box::new(SystemTTSEngine::new(speech)
+            .with_voice(settings.system_voice.clone())
+            .with_length_scale(settings.length_scale)),
+        TtsBackendKind::Piper => Box::new(PiperTTSEngine::new(settings.clone(), speech)),
+    }
+}
+
+#[derive(Clone)]
+pub struct SystemTTSEngine {
+    is_initialized: bool,
+    voice: Option<String>,
+    length_scale: f32,
+    speech: SpeechControl,
+}
+
+impl TtsBackend for SystemTTSEngine {
+    fn initialize(&mut self) -> Result<(), String> {
         if self.is_initialized {
             return Ok(());
         }
@@ -25,6 +572,99 @@ impl SystemTTSEngine {
         Ok(())
     }
 
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn generate_speech(&self, text: &str) -> Result<(), String> {
+        if !self.is_initialized {
+            return Err("TTS engine not initialized. Call initialize() first.".to_string());
+        }
+
+        if text.trim().is_empty() {
+            return Err("Text cannot be empty".to_string());
+        }
+
+        self.speak_text(text)
+    }
+
+    fn speak_with_voice(&self, text: &str, voice: &str) -> Result<(), String> {
+        let mut engine = self.clone();
+        engine.voice = Some(voice.to_string());
+        engine.generate_speech(text)
+    }
+
+    fn synthesize_to_file(&self, text: &str, output: &Path) -> Result<(), String> {
+        if !self.is_initialized {
+            return Err("TTS engine not initialized. Call initialize() first.".to_string());
+        }
+        if text.trim().is_empty() {
+            return Err("Text cannot be empty".to_string());
+        }
+
+        if !self.run_speech_command(text, Some(output))? {
+            let _ = std::fs::remove_file(output);
+            return Err("Speech synthesis was stopped".to_string());
+        }
+        Ok(())
+    }
+
+    fn list_voices(&self) -> Result<Vec<TtsVoice>, String> {
+        #[cfg(target_os = "macos")]
+        {
+            let output = Command::new("say").args(["-v", "?"]).output()
+                .map_err(|e| format!("Failed to list macOS voices: {}", e))?;
+            return Ok(parse_say_voices(&String::from_utf8_lossy(&output.stdout)));
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let output = Command::new("espeak").arg("--voices").output()
+                .map_err(|e| format!("Failed to list espeak voices: {}", e))?;
+            return Ok(parse_espeak_voices(&String::from_utf8_lossy(&output.stdout)));
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            return winrt_voices().map_err(|e| format!("Failed to list Windows voices: {}", e));
+        }
+
+        #[allow(unreachable_code)]
+        Ok(Vec::new())
+    }
+}
+
+impl SystemTTSEngine {
+    pub fn new(speech: SpeechControl) -> Self {
+        Self {
+            is_initialized: false,
+            voice: None,
+            length_scale: 1.0,
+            speech,
+        }
+    }
+
+    pub fn with_voice(mut self, voice: Option<String>) -> Self {
+        self.voice = voice.filter(|voice| !voice.trim().is_empty());
+        self
+    }
+
+    pub fn with_length_scale(mut self, length_scale: f32) -> Self {
+        self.length_scale = length_scale;
+        self
+    }
+
+    // say and espeak both default to 175 words per minute; None keeps their default
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    fn rate_arg(&self) -> Option<String> {
+        (self.length_scale != 1.0).then(|| (self.words_per_minute().round() as u32).to_string())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn words_per_minute(&self) -> f32 {
+        175.0 / self.length_scale
+    }
+
     fn test_system_tts(&self) -> Result<(), String> {
         #[cfg(target_os = "macos")]
         {
@@ -57,138 +697,643 @@ impl SystemTTSEngine {
 
         #[cfg(target_os = "windows")]
         {
-            let output = Command::new("powershell")
-                .args(&["-Command", "Add-Type -AssemblyName System.Speech"])
-                .output()
+            winrt_init();
+            windows::Media::SpeechSynthesis::SpeechSynthesizer::new()
                 .map_err(|e| format!("Windows TTS not available: {}", e))?;
-
-            if !output.status.success() {
-                return Err("Windows TTS initialization failed".to_string());
-            }
-            println!("Windows TTS (SAPI) is available");
+            println!("Windows TTS (WinRT) is available");
         }
 
         Ok(())
     }
 
-    pub fn generate_speech(&self, text: &str) -> Result<(), String> {
-        if !self.is_initialized {
-            return Err("TTS engine not initialized. Call initialize() first.".to_string());
+    fn speak_text(&self, text: &str) -> Result<(), String> {
+        println!("Speaking: {}", text.chars().take(50).collect::<String>());
+        if self.run_speech_command(text, None)? {
+            println!("System TTS completed successfully");
+        }
+        Ok(())
+    }
+
+    // Run the platform's speech command, spawned and tracked so stop_speech can kill this
+    // utterance and nothing else. With `output` the speech is written there as a WAV instead of
+    // played. Returns false when it was stopped.
+    #[cfg(not(target_os = "windows"))]
+    fn run_speech_command(&self, text: &str, output: Option<&Path>) -> Result<bool, String> {
+        let child = self.speech_command(text, output)?
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", SYSTEM_TTS_NAME, e))?;
+
+        // The commands don't report progress, so words are timed from the speaking rate
+        let words = match output {
+            Some(_) => Vec::new(),
+            None => {
+                let word_count = text.split_whitespace().count() as f32;
+                estimate_word_boundaries(text, (word_count * 60_000.0 / self.words_per_minute()) as u64)
+            }
+        };
+        let Some((status, stderr)) = self.speech.wait(child, &words)? else { return Ok(false) };
+        if !status.success() {
+            return Err(format!("{} failed with status {:?}: {}", SYSTEM_TTS_NAME, status, stderr.trim()));
         }
+        Ok(true)
+    }
 
-        if text.trim().is_empty() {
-            return Err("Text cannot be empty".to_string());
+    // Windows speaks in-process: the synthesizer renders a WAV, which is played like Piper's so
+    // stop_speech can cut it off. Returns false when it was stopped.
+    #[cfg(target_os = "windows")]
+    fn run_speech_command(&self, text: &str, output: Option<&Path>) -> Result<bool, String> {
+        let ticket = self.speech.ticket();
+        let (wav, words) = winrt_synthesize(text, self.voice.as_deref(), self.length_scale)
+            .map_err(|e| format!("{} failed: {}", SYSTEM_TTS_NAME, e))?;
+        if self.speech.stopped_since(ticket) {
+            return Ok(false);
         }
 
-        self.speak_text(text)
+        if let Some(output) = output {
+            std::fs::write(output, wav).map_err(|e| format!("Failed to write synthesized speech: {}", e))?;
+            return Ok(true);
+        }
+        let path = get_tts_output_dir()?.join(format!("speech-{}.wav", uuid::Uuid::new_v4()));
+        std::fs::write(&path, wav).map_err(|e| format!("Failed to write synthesized speech: {}", e))?;
+        let played = self.speech.play(&path, |_| words);
+        let _ = std::fs::remove_file(&path);
+        played
     }
 
-    fn speak_text(&self, text: &str) -> Result<(), String> {
+    #[cfg(not(target_os = "windows"))]
+    #[allow(unused_variables)]
+    fn speech_command(&self, text: &str, output: Option<&Path>) -> Result<Command, String> {
         #[cfg(target_os = "macos")]
         {
-            // First, kill any hanging speech processes to prevent conflicts
-            println!("Cleaning up any hanging speech processes...");
-            let _ = Command::new("pkill")
-                .args(&["-f", "speechsynthesisd"])
-                .output();
-            let _ = Command::new("pkill")
-                .args(&["-f", "say"])
-                .output();
-            
-            // Wait a moment for cleanup
-            std::thread::sleep(std::time::Duration::from_millis(300));
-            
-            println!("Speaking: {}", &text[..std::cmp::min(50, text.len())]);
-            
-            // Use spawn and wait for completion without timeout
-            use std::process::{Stdio};
-            
-            let mut child = Command::new("say")
-                .arg(text)
-                .stdin(Stdio::null())
-                .stdout(Stdio::null())
-                .stderr(Stdio::piped())
-                .spawn()
-                .map_err(|e| format!("Failed to spawn 'say' command: {}", e))?;
-
-            // Wait for completion without timeout
-            match child.wait() {
-                Ok(status) => {
-                    if status.success() {
-                        println!("macOS TTS completed successfully");
-                    } else {
-                        let mut stderr = String::new();
-                        if let Some(mut stderr_handle) = child.stderr.take() {
-                            use std::io::Read;
-                            let _ = stderr_handle.read_to_string(&mut stderr);
-                        }
-                        return Err(format!("macOS TTS failed with status: {:?}, stderr: {}", status, stderr));
-                    }
-                }
-                Err(e) => {
-                    let _ = child.kill();
-                    return Err(format!("Error waiting for TTS process: {}", e));
-                }
+            let mut say = Command::new("say");
+            if let Some(voice) = &self.voice {
+                say.arg("-v").arg(voice);
+            }
+            if let Some(rate) = self.rate_arg() {
+                say.arg("-r").arg(rate);
             }
+            if let Some(output) = output {
+                say.arg("-o").arg(output).args(["--file-format=WAVE", "--data-format=LEI16@22050"]);
+            }
+            say.arg(text);
+            return Ok(say);
         }
 
         #[cfg(target_os = "linux")]
         {
-            let output = Command::new("espeak")
-                .arg(text)
-                .output()
-                .map_err(|e| format!("Failed to execute 'espeak' command: {}", e))?;
-
-            if !output.status.success() {
-                let error = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Linux TTS failed: {}", error));
+            let mut espeak = Command::new("espeak");
+            if let Some(voice) = &self.voice {
+                espeak.arg("-v").arg(voice);
+            }
+            if let Some(rate) = self.rate_arg() {
+                espeak.arg("-s").arg(rate);
+            }
+            if let Some(output) = output {
+                espeak.arg("-w").arg(output);
             }
-            
-            println!("Linux TTS completed successfully");
+            espeak.arg(text);
+            return Ok(espeak);
         }
 
-        #[cfg(target_os = "windows")]
-        {
-            let script = format!(
-                r#"Add-Type -AssemblyName System.Speech; $synth = New-Object System.Speech.Synthesis.SpeechSynthesizer; $synth.Speak("{}")"#,
-                text.replace('"', '\"')
-            );
+        #[allow(unreachable_code)]
+        Err("System TTS isn't available on this platform".to_string())
+    }
+}
 
-            let output = Command::new("powershell")
-                .args(&["-Command", &script])
-                .output()
-                .map_err(|e| format!("Failed to execute PowerShell TTS: {}", e))?;
+#[cfg(target_os = "macos")]
+const SYSTEM_TTS_NAME: &str = "macOS TTS (say)";
+#[cfg(target_os = "linux")]
+const SYSTEM_TTS_NAME: &str = "Linux TTS (espeak)";
+#[cfg(target_os = "windows")]
+const SYSTEM_TTS_NAME: &str = "Windows TTS (WinRT)";
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+const SYSTEM_TTS_NAME: &str = "System TTS";
 
-            if !output.status.success() {
-                let error = String::from_utf8_lossy(&output.stderr);
-                return Err(format!("Windows TTS failed: {}", error));
-            }
-            
-            println!("Windows TTS completed successfully");
+// `say -v ?` lines: "Daniel              en_GB    # Hello, my name is Daniel." Names can contain spaces.
+#[cfg(any(target_os = "macos", test))]
+fn parse_say_voices(output: &str) -> Vec<TtsVoice> {
+    output.lines()
+        .filter_map(|line| {
+            let description = line.split('#').next()?.trim_end();
+            let (name, language) = description.rsplit_once(char::is_whitespace)?;
+            let name = name.trim();
+            (!name.is_empty()).then(|| TtsVoice {
+                id: name.to_string(),
+                name: name.to_string(),
+                language: Some(language.to_string()),
+                gender: None,
+            })
+        })
+        .collect()
+}
+
+// `espeak --voices` table: "Pty Language Age/Gender VoiceName File Other Languages". Voices are
+// selected by language, so the first voice of each language is kept.
+#[cfg(any(target_os = "linux", test))]
+fn parse_espeak_voices(output: &str) -> Vec<TtsVoice> {
+    let mut voices: Vec<TtsVoice> = Vec::new();
+    for line in output.lines().skip(1) {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        let [_, language, age_gender, name, ..] = columns[..] else { continue };
+        if voices.iter().any(|voice| voice.id == language) {
+            continue;
+        }
+        let gender = match age_gender.rsplit('/').next() {
+            Some("M") => Some("male".to_string()),
+            Some("F") => Some("female".to_string()),
+            _ => None,
+        };
+        voices.push(TtsVoice {
+            id: language.to_string(),
+            name: name.replace('_', " "),
+            language: Some(language.to_string()),
+            gender,
+        });
+    }
+    voices
+}
+
+// WinRT objects need the calling thread in an apartment; speech runs on the queue's thread and
+// blocking workers, which nothing else initializes. Already-initialized threads keep theirs.
+#[cfg(target_os = "windows")]
+fn winrt_init() {
+    use windows::Win32::System::WinRT::{RoInitialize, RO_INIT_MULTITHREADED};
+    let _ = unsafe { RoInitialize(RO_INIT_MULTITHREADED) };
+}
+
+#[cfg(target_os = "windows")]
+fn winrt_voices() -> windows::core::Result<Vec<TtsVoice>> {
+    use windows::Media::SpeechSynthesis::{SpeechSynthesizer, VoiceGender};
+    winrt_init();
+    SpeechSynthesizer::AllVoices()?
+        .into_iter()
+        .map(|voice| {
+            // The id is the voice's registry token, which stays the same across releases and
+            // display languages, unlike its name
+            let id = voice.Id()?.to_string_lossy();
+            let name = voice.DisplayName()?.to_string_lossy();
+            let language = voice.Language()?.to_string_lossy();
+            let gender = match voice.Gender()? {
+                VoiceGender::Female => Some("female".to_string()),
+                VoiceGender::Male => Some("male".to_string()),
+                _ => None,
+            };
+            Ok(TtsVoice { id, name, language: (!language.is_empty()).then_some(language), gender })
+        })
+        .collect()
+}
+
+// The text spoken as WAV bytes, with the synthesizer's own word timings. It takes the text as a
+// string, so quotes, $ and backticks need no escaping. Its speaking rate is the inverse of the
+// length scale.
+#[cfg(target_os = "windows")]
+fn winrt_synthesize(text: &str, voice: Option<&str>, length_scale: f32) -> windows::core::Result<(Vec<u8>, Vec<WordBoundary>)> {
+    use windows::core::{Interface, HSTRING};
+    use windows::Media::Core::SpeechCue;
+    use windows::Media::SpeechSynthesis::SpeechSynthesizer;
+    use windows::Storage::Streams::DataReader;
+    winrt_init();
+
+    let synthesizer = SpeechSynthesizer::new()?;
+    if let Some(voice) = voice {
+        // An uninstalled voice falls back to the default rather than failing the utterance
+        let installed = SpeechSynthesizer::AllVoices()?
+            .into_iter()
+            .find(|info| info.Id().map(|id| id == voice).unwrap_or(false));
+        if let Some(info) = installed {
+            synthesizer.SetVoice(&info)?;
+        }
+    }
+    let options = synthesizer.Options()?;
+    options.SetSpeakingRate(1.0 / length_scale as f64)?;
+    options.SetIncludeWordBoundaryMetadata(true)?;
+
+    let stream = synthesizer.SynthesizeTextToStreamAsync(&HSTRING::from(text))?.join()?;
+    let size = stream.Size()? as u32;
+    let reader = DataReader::CreateDataReader(&stream.GetInputStreamAt(0)?)?;
+    reader.LoadAsync(size)?.join()?;
+    let mut wav = vec![0u8; size as usize];
+    reader.ReadBytes(&mut wav)?;
+
+    // Cue positions are UTF-16 indexes into the text, the end one inclusive; times are in 100ns
+    let mut words = Vec::new();
+    for track in stream.TimedMetadataTracks()? {
+        for cue in track.Cues()? {
+            let cue: SpeechCue = cue.cast()?;
+            words.push(WordBoundary {
+                start: cue.StartPositionInInput()?.Value()? as usize,
+                end: cue.EndPositionInInput()?.Value()? as usize + 1,
+                offset_ms: (cue.StartTime()?.Duration / 10_000) as u64,
+            });
+        }
+    }
+    words.sort_by_key(|word| word.offset_ms);
+    Ok((wav, words))
+}
+
+pub fn get_tts_output_dir() -> Result<PathBuf, String> {
+    let tts_dir = dirs::cache_dir()
+        .ok_or("Failed to get cache directory")?
+        .join("project-r")
+        .join("tts");
+    
+    std::fs::create_dir_all(&tts_dir)
+        .map_err(|e| format!("Failed to create TTS directory: {}", e))?;
+    
+    Ok(tts_dir)
+}
+
+// Synthesized answers kept for replay; older ones are deleted as new ones are written
+const MAX_SYNTHESIZED_FILES: usize = 20;
+
+// A new file in the TTS output dir for synthesize_to_file, after pruning the oldest synthesized
+// files beyond MAX_SYNTHESIZED_FILES
+pub fn new_synthesized_file() -> Result<PathBuf, String> {
+    let dir = get_tts_output_dir()?;
+    let mut existing: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read TTS directory: {}", e))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("synthesized-"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    existing.sort();
+    let excess = (existing.len() + 1).saturating_sub(MAX_SYNTHESIZED_FILES);
+    for (_, path) in existing.into_iter().take(excess) {
+        let _ = std::fs::remove_file(path);
+    }
+
+    let name = format!("synthesized-{}-{}.wav", chrono::Local::now().format("%Y%m%d-%H%M%S"), uuid::Uuid::new_v4());
+    Ok(dir.join(name))
+}
+
+// Where downloaded Piper voices go: a voice is a .onnx model with its .onnx.json config beside it
+pub fn piper_voices_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("project-r").join("piper").join("voices"))
+}
+
+// Installed voices, by path, sorted by name
+pub fn list_piper_voices() -> Vec<PathBuf> {
+    let Some(entries) = piper_voices_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut voices: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "onnx") && piper_voice_config(path).is_file())
+        .collect();
+    voices.sort();
+    voices
+}
+
+fn piper_voice_config(voice: &Path) -> PathBuf {
+    let mut config = voice.as_os_str().to_owned();
+    config.push(".json");
+    PathBuf::from(config)
+}
+
+// Piper (https://github.com/rhasspy/piper) run as a separate process: it synthesizes each
+// utterance to a WAV file, which is then played through the output device
+#[derive(Clone)]
+pub struct PiperTTSEngine {
+    settings: TtsSettings,
+    binary: Option<PathBuf>,
+    voice: Option<PathBuf>,
+    speech: SpeechControl,
+}
+
+impl PiperTTSEngine {
+    pub fn new(settings: TtsSettings, speech: SpeechControl) -> Self {
+        Self { settings, binary: None, voice: None, speech }
+    }
+
+    // The configured binary, else one bundled next to the app or in the piper folder, else PATH
+    fn find_binary(&self) -> PathBuf {
+        if let Some(binary) = &self.settings.piper_binary {
+            return PathBuf::from(binary);
         }
+        let name = if cfg!(target_os = "windows") { "piper.exe" } else { "piper" };
+        let bundled = std::env::current_exe().ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(name)));
+        let downloaded = dirs::config_dir()
+            .map(|dir| dir.join("project-r").join("piper").join(name));
+        [bundled, downloaded].into_iter()
+            .flatten()
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| PathBuf::from(name))
+    }
 
+    fn find_voice(&self) -> Result<PathBuf, String> {
+        let voice = match &self.settings.piper_voice {
+            Some(voice) => PathBuf::from(voice),
+            None => list_piper_voices().into_iter().next().ok_or_else(|| match piper_voices_dir() {
+                Some(dir) => format!("No Piper voice installed; put a voice's .onnx and .onnx.json files in {}", dir.display()),
+                None => "No Piper voice installed".to_string(),
+            })?,
+        };
+        if !voice.is_file() {
+            return Err(format!("Piper voice {} not found", voice.display()));
+        }
+        if !piper_voice_config(&voice).is_file() {
+            return Err(format!("Piper voice {} is missing its .onnx.json config", voice.display()));
+        }
+        Ok(voice)
+    }
+
+    // False when stop_speech killed Piper before it finished
+    fn synthesize(&self, binary: &Path, voice: &Path, text: &str, output: &Path) -> Result<bool, String> {
+        let mut child = Command::new(binary)
+            .arg("--model").arg(voice)
+            .arg("--output_file").arg(output)
+            .arg("--length_scale").arg(self.settings.length_scale.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start Piper: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).map_err(|e| format!("Failed to send text to Piper: {}", e))?;
+        }
+        let Some((status, stderr)) = self.speech.wait(child, &[])? else { return Ok(false) };
+        if !status.success() {
+            return Err(format!("Piper failed: {}", stderr.trim()));
+        }
+        Ok(true)
+    }
+}
+
+impl TtsBackend for PiperTTSEngine {
+    fn initialize(&mut self) -> Result<(), String> {
+        if self.is_initialized() {
+            return Ok(());
+        }
+
+        let binary = self.find_binary();
+        let output = Command::new(&binary)
+            .arg("--help")
+            .output()
+            .map_err(|e| format!("Piper not available at {}: {}", binary.display(), e))?;
+        if !output.status.success() {
+            return Err(format!("Piper at {} failed to start", binary.display()));
+        }
+        let voice = self.find_voice()?;
+
+        println!("Piper TTS initialized with voice {}", voice.display());
+        self.binary = Some(binary);
+        self.voice = Some(voice);
         Ok(())
     }
 
-    pub fn get_tts_output_dir() -> Result<PathBuf, String> {
-        let tts_dir = dirs::cache_dir()
-            .ok_or("Failed to get cache directory")?
-            .join("project-r")
-            .join("tts");
-        
-        std::fs::create_dir_all(&tts_dir)
-            .map_err(|e| format!("Failed to create TTS directory: {}", e))?;
-        
-        Ok(tts_dir)
+    fn is_initialized(&self) -> bool {
+        self.binary.is_some() && self.voice.is_some()
+    }
+
+    // Voices are named after their file, e.g. "en_GB-alan-medium"; the language is its first part
+    fn list_voices(&self) -> Result<Vec<TtsVoice>, String> {
+        Ok(list_piper_voices().into_iter()
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                Some(TtsVoice {
+                    id: path.to_string_lossy().into_owned(),
+                    language: name.split('-').next().map(str::to_string),
+                    name,
+                    gender: None,
+                })
+            })
+            .collect())
+    }
+
+    fn generate_speech(&self, text: &str) -> Result<(), String> {
+        let (Some(binary), Some(voice)) = (&self.binary, &self.voice) else {
+            return Err("TTS engine not initialized. Call initialize() first.".to_string());
+        };
+        if text.trim().is_empty() {
+            return Err("Text cannot be empty".to_string());
+        }
+
+        // Wait for playback to end, like the system voices do. Piper doesn't time its words, so
+        // they're placed by the clip's length.
+        if let Some(speech) = self.cached_synthesis(binary, voice, text)? {
+            self.speech.play(&speech, |duration_ms| estimate_word_boundaries(text, duration_ms))?;
+        }
+        Ok(())
+    }
+
+    fn speak_with_voice(&self, text: &str, voice: &str) -> Result<(), String> {
+        let mut engine = self.clone();
+        engine.voice = Some(PathBuf::from(voice));
+        engine.generate_speech(text)
+    }
+
+    fn synthesize_to_file(&self, text: &str, output: &Path) -> Result<(), String> {
+        let (Some(binary), Some(voice)) = (&self.binary, &self.voice) else {
+            return Err("TTS engine not initialized. Call initialize() first.".to_string());
+        };
+        if text.trim().is_empty() {
+            return Err("Text cannot be empty".to_string());
+        }
+
+        let speech = self.cached_synthesis(binary, voice, text)?
+            .ok_or_else(|| "Speech synthesis was stopped".to_string())?;
+        std::fs::copy(&speech, output).map_err(|e| format!("Failed to write synthesized speech: {}", e))?;
+        Ok(())
+    }
+}
+
+// Synthesized Piper utterances, by a hash of the text, voice and pace, so repeated phrases like
+// "Great job!" play straight away instead of being synthesized again. Past
+// MAX_SPEECH_CACHE_BYTES the least recently used are deleted.
+const MAX_SPEECH_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+fn speech_cache_dir() -> Result<PathBuf, String> {
+    let dir = get_tts_output_dir()?.join("cache");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create TTS cache directory: {}", e))?;
+    Ok(dir)
+}
+
+fn speech_cache_key(text: &str, voice: &Path, length_scale: f32) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n{}\n{}", voice.display(), length_scale, text).as_bytes());
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Delete the least recently used entries until the cache fits. Use is tracked by modification
+// time, which a cache hit bumps.
+fn prune_speech_cache(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    let mut files: Vec<(std::time::SystemTime, u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .filter(|(_, _, path)| path.extension().is_some_and(|extension| extension == "wav"))
+        .collect();
+    files.sort();
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    for (_, size, path) in files {
+        if total <= MAX_SPEECH_CACHE_BYTES {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= size;
+        }
+    }
+}
+
+// Empty the speech cache; returns the bytes freed
+pub fn clear_speech_cache() -> Result<u64, String> {
+    let dir = speech_cache_dir()?;
+    let mut freed = 0;
+    for entry in std::fs::read_dir(&dir).map_err(|e| format!("Failed to read TTS cache directory: {}", e))?.flatten() {
+        let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if std::fs::remove_file(entry.path()).is_ok() {
+            freed += size;
+        }
+    }
+    Ok(freed)
+}
+
+impl PiperTTSEngine {
+    // The utterance's WAV from the cache, synthesized into it first on a miss; None when
+    // stop_speech cut the synthesis off
+    fn cached_synthesis(&self, binary: &Path, voice: &Path, text: &str) -> Result<Option<PathBuf>, String> {
+        let dir = speech_cache_dir()?;
+        let cached = dir.join(format!("{}.wav", speech_cache_key(text, voice, self.settings.length_scale)));
+        if cached.is_file() {
+            if let Ok(file) = std::fs::File::options().write(true).open(&cached) {
+                let _ = file.set_modified(std::time::SystemTime::now());
+            }
+            return Ok(Some(cached));
+        }
+
+        // Written beside the entry and renamed once complete, so a stopped or failed synthesis
+        // never leaves a partial file in the cache
+        let partial = dir.join(format!("partial-{}", uuid::Uuid::new_v4()));
+        let synthesized = self.synthesize(binary, voice, text, &partial);
+        if !matches!(synthesized, Ok(true)) {
+            let _ = std::fs::remove_file(&partial);
+            return synthesized.map(|_| None);
+        }
+        std::fs::rename(&partial, &cached).map_err(|e| format!("Failed to cache synthesized speech: {}", e))?;
+        prune_speech_cache(&dir);
+        Ok(Some(cached))
     }
 }
 
 // Test function for System TTS
 pub fn test_tts() -> Result<String, String> {
-    let mut engine = SystemTTSEngine::new();
+    let mut engine = SystemTTSEngine::new(SpeechControl::default());
     engine.initialize()?;
     
     engine.generate_speech("Hello! This is a test of the Project-R text to speech system.")?;
     
     Ok("System TTS test completed successfully".to_string())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_boundaries_share_time_by_length_and_pauses() {
+        let words = estimate_word_boundaries("Hi, café ok.", 1000);
+        assert_eq!(words, [
+            WordBoundary { start: 0, end: 3, offset_ms: 0 },
+            WordBoundary { start: 4, end: 8, offset_ms: 315 },
+            WordBoundary { start: 9, end: 12, offset_ms: 526 },
+        ]);
+        assert!(estimate_word_boundaries("  ", 1000).is_empty());
+    }
+
+    #[test]
+    fn reply_languages_use_the_mapped_or_first_matching_voice() {
+        let voice = |id: &str, language: &str| TtsVoice {
+            id: id.to_string(),
+            name: id.to_string(),
+            language: Some(language.to_string()),
+            gender: None,
+        };
+        let voices = [voice("Daniel", "en_GB"), voice("Monica", "es_ES"), voice("Paulina", "es_MX"), voice("Lekha", "hi-IN")];
+        let mut settings = TtsSettings::default();
+        assert_eq!(settings.voice_for_language(&voices, "es").as_deref(), Some("Monica"));
+        assert_eq!(settings.voice_for_language(&voices, "hi").as_deref(), Some("Lekha"));
+        assert_eq!(settings.voice_for_language(&voices, "en"), None);
+        assert_eq!(settings.voice_for_language(&voices, "fr"), None);
+
+        settings.language_voices.insert("es".to_string(), "Paulina".to_string());
+        settings.language_voices.insert("en".to_string(), "Daniel".to_string());
+        settings.language_voices.insert("hi".to_string(), "Uninstalled".to_string());
+        assert_eq!(settings.voice_for_language(&voices, "es").as_deref(), Some("Paulina"));
+        assert_eq!(settings.voice_for_language(&voices, "en").as_deref(), Some("Daniel"));
+        assert_eq!(settings.voice_for_language(&voices, "hi").as_deref(), Some("Lekha"));
+    }
+
+    #[test]
+    fn sapi_voice_names_migrate_to_voice_ids() {
+        let voice = |id: &str, name: &str| TtsVoice {
+            id: id.to_string(),
+            name: name.to_string(),
+            language: Some("en-US".to_string()),
+            gender: None,
+        };
+        let tokens = r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Speech_OneCore\Voices\Tokens";
+        let zira = format!(r"{}\MSTTS_V110_enUS_ZiraM", tokens);
+        let david = format!(r"{}\MSTTS_V110_enUS_DavidM", tokens);
+        let voices = [voice(&zira, "Microsoft Zira"), voice(&david, "Microsoft David")];
+
+        let mut settings = TtsSettings {
+            system_voice: Some("Microsoft Zira Desktop".to_string()),
+            ..TtsSettings::default()
+        };
+        settings.language_voices.insert("en".to_string(), "Microsoft David".to_string());
+        settings.language_voices.insert("fr".to_string(), "Microsoft Hortense Desktop".to_string());
+        assert!(settings.migrate_sapi_voices(&voices));
+        assert_eq!(settings.system_voice.as_deref(), Some(zira.as_str()));
+        assert_eq!(settings.language_voices["en"], david);
+        assert_eq!(settings.language_voices["fr"], "Microsoft Hortense Desktop");
+
+        assert!(!settings.migrate_sapi_voices(&voices));
+    }
+
+    #[test]
+    fn inline_code_is_read_as_words() {
+        assert_eq!(
+            render_code_for_speech("Write `for i in range(10):` and then `print(i)`."),
+            "Write for i in range of ten, colon and then print of i."
+        );
+        assert_eq!(
+            render_code_for_speech("Check `if total >= 2.5 and items == []:`"),
+            "Check if total is greater than or equal to two point five and items is equal to an empty list, colon"
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_read_line_by_line() {
+        let text = "Like this:\n```python\ndef add(a, b):\n    return a + b  # sum\nmy_list.append(\"hi\")\n```\nTry it.";
+        assert_eq!(
+            render_code_for_speech(text),
+            "Like this:\n define add of a, b, colon.\nreturn a plus b comment, sum.\nmy list dot append of the string hi. \nTry it."
+        );
+    }
+
+    #[test]
+    fn say_voices_keep_names_with_spaces() {
+        let output = "Alex                en_US    # Most people recognize me by my voice.\nBad News            en_US    # The light you see at the end of the tunnel...\n";
+        let voices = parse_say_voices(output);
+        assert_eq!(voices.len(), 2);
+        assert_eq!(voices[1].id, "Bad News");
+        assert_eq!(voices[1].language.as_deref(), Some("en_US"));
+    }
+
+    #[test]
+    fn espeak_voices_are_one_per_language_with_gender() {
+        let output = "Pty Language Age/Gender VoiceName          File          Other Languages\n 5  en-gb          M  english              default       \n 5  en-gb          M  english_rp           other/en-rp   \n 2  en-us          F  english-us           en-us         (en 3)\n";
+        let voices = parse_espeak_voices(output);
+        let ids: Vec<&str> = voices.iter().map(|voice| voice.id.as_str()).collect();
+        assert_eq!(ids, ["en-gb", "en-us"]);
+        assert_eq!(voices[1].gender.as_deref(), Some("female"));
+    }
+}
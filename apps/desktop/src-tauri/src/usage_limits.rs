@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Warn the learner when this many minutes of today's allowance are left
+pub const WARNING_MINUTES: u32 = 10;
+
+// Daily allowance set by a guardian; changing or removing it needs the guardian PIN
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageLimit {
+    pub daily_minutes: u32,
+    pin_salt: String,
+    pin_hash: String,
+}
+
+impl UsageLimit {
+    pub fn new(daily_minutes: u32, pin: &str) -> Self {
+        let pin_salt = uuid::Uuid::new_v4().to_string();
+        let pin_hash = hash_pin(pin, &pin_salt);
+        Self { daily_minutes, pin_salt, pin_hash }
+    }
+
+    pub fn verify_pin(&self, pin: &str) -> bool {
+        hash_pin(pin, &self.pin_salt) == self.pin_hash
+    }
+}
+
+fn hash_pin(pin: &str, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(pin.as_bytes());
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageStatus {
+    pub limit_minutes: Option<u32>, // None when no limit is configured
+    pub used_minutes: u32, // Active minutes logged today (local time)
+    pub remaining_minutes: Option<u32>,
+    pub locked: bool, // New activity is refused; past material stays readable and exportable
+}
+
+impl UsageStatus {
+    pub fn new(limit: Option<&UsageLimit>, used_minutes: u32) -> Self {
+        let limit_minutes = limit.map(|limit| limit.daily_minutes);
+        let remaining_minutes = limit_minutes.map(|limit| limit.saturating_sub(used_minutes));
+        Self {
+            limit_minutes,
+            used_minutes,
+            remaining_minutes,
+            locked: remaining_minutes == Some(0),
+        }
+    }
+
+    pub fn should_warn(&self) -> bool {
+        matches!(self.remaining_minutes, Some(remaining) if remaining > 0 && remaining <= WARNING_MINUTES)
+    }
+
+    // Error string for commands refused during lockout, e.g. {"kind":"UsageLimitReached",...}
+    pub fn into_error(self) -> String {
+        serde_json::json!({
+            "kind": "UsageLimitReached",
+            "limit_minutes": self.limit_minutes,
+            "used_minutes": self.used_minutes,
+        }).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn minutes_count_once_across_learner_profiles() {
+        let db = Database::new_demo().unwrap();
+        let minute = Local.with_ymd_and_hms(2026, 3, 2, 16, 5, 0).unwrap();
+        db.log_activity("default_user", minute).unwrap();
+        db.log_activity("x", minute).unwrap();
+        db.log_activity("x", minute + chrono::Duration::minutes(1)).unwrap();
+        assert_eq!(db.get_usage_minutes(minute.date_naive()).unwrap(), 2);
+    }
+
+    #[test]
+    fn status_locks_once_the_allowance_is_used() {
+        let limit = UsageLimit::new(30, "1234");
+        assert!(limit.verify_pin("1234"));
+        assert!(!limit.verify_pin("0000"));
+
+        let status = UsageStatus::new(Some(&limit), 22);
+        assert_eq!(status.remaining_minutes, Some(8));
+        assert!(status.should_warn() && !status.locked);
+        assert!(UsageStatus::new(Some(&limit), 31).locked);
+        assert!(!UsageStatus::new(None, 500).locked);
+    }
+}
@@ -0,0 +1,172 @@
+use serde::Serialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+// Virtual environments Project-R creates and manages, so packages installed for lessons stay out
+// of the system Python. The selected one runs every Python session; with none selected they run
+// on the system's python3.
+
+const SYSTEM_PYTHON: &str = "python3";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Venv {
+    pub name: String,
+    pub path: String,
+    pub python_version: Option<String>, // From pyvenv.cfg
+    pub selected: bool,
+}
+
+// Where the managed environments live, one folder per environment named after it
+pub fn venvs_dir() -> Result<PathBuf, String> {
+    dirs::config_dir()
+        .map(|dir| dir.join("project-r").join("venvs"))
+        .ok_or_else(|| "Failed to get config directory".to_string())
+}
+
+// Names become folder names, so they're kept to letters, digits, '-', '_' and '.'
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let allowed = name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || name.len() > 64 || !allowed || name.starts_with('.') {
+        return Err(format!("'{}' isn't a valid environment name; use letters, digits, '-', '_' and '.'", name));
+    }
+    Ok(())
+}
+
+fn venv_python(dir: &Path) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        dir.join("Scripts").join("python.exe")
+    } else {
+        dir.join("bin").join("python")
+    }
+}
+
+// The "version" line venv writes, or the "version_info" one uv writes, of a pyvenv.cfg
+fn pyvenv_version(config: &str) -> Option<String> {
+    config.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| matches!(key.trim(), "version" | "version_info"))
+        .map(|(_, value)| value.trim().split(".final").next().unwrap_or_default().to_string())
+        .filter(|version| !version.is_empty())
+}
+
+// Environments in the venvs folder with an interpreter, by name
+pub fn list_venvs() -> Result<Vec<Venv>, String> {
+    let dir = venvs_dir()?;
+    let Ok(entries) = std::fs::read_dir(&dir) else { return Ok(Vec::new()) };
+    let selected = selected_name();
+    let mut venvs: Vec<Venv> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| venv_python(path).exists())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let python_version = std::fs::read_to_string(path.join("pyvenv.cfg")).ok()
+                .and_then(|config| pyvenv_version(&config));
+            Some(Venv {
+                selected: selected.as_deref() == Some(name.as_str()),
+                path: path.to_string_lossy().into_owned(),
+                name,
+                python_version,
+            })
+        })
+        .collect();
+    venvs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(venvs)
+}
+
+// Create an environment with the system Python's venv module. A half-made one is removed.
+pub async fn create_venv(name: &str) -> Result<Venv, String> {
+    validate_name(name)?;
+    let dir = venvs_dir()?.join(name);
+    if dir.exists() {
+        return Err(format!("An environment named '{}' already exists", name));
+    }
+    std::fs::create_dir_all(venvs_dir()?).map_err(|e| format!("Failed to create venvs directory: {}", e))?;
+
+    let output = tokio::process::Command::new(SYSTEM_PYTHON)
+        .args(["-m", "venv"])
+        .arg(&dir)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to start Python: {}", e))?;
+    if !output.status.success() {
+        let _ = std::fs::remove_dir_all(&dir);
+        return Err(format!("Failed to create the environment: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    list_venvs()?
+        .into_iter()
+        .find(|venv| venv.name == name)
+        .ok_or_else(|| format!("The environment '{}' has no Python interpreter", name))
+}
+
+static SELECTED: OnceLock<RwLock<Option<String>>> = OnceLock::new();
+
+fn selected_slot() -> &'static RwLock<Option<String>> {
+    SELECTED.get_or_init(|| RwLock::new(None))
+}
+
+pub fn selected_name() -> Option<String> {
+    selected_slot().read().ok().and_then(|selected| selected.clone())
+}
+
+// Run Python sessions started from now on in `name`, or on the system Python with None
+pub fn select(name: Option<&str>) -> Result<(), String> {
+    if let Some(name) = name {
+        validate_name(name)?;
+        if !venv_python(&venvs_dir()?.join(name)).exists() {
+            return Err(format!("No environment named '{}'", name));
+        }
+    }
+    if let Ok(mut selected) = selected_slot().write() {
+        *selected = name.map(str::to_string);
+    }
+    Ok(())
+}
+
+fn selected_dir() -> Option<PathBuf> {
+    let dir = venvs_dir().ok()?.join(selected_name()?);
+    venv_python(&dir).exists().then_some(dir)
+}
+
+// The interpreter Python sessions run on
+pub fn python_program() -> PathBuf {
+    selected_dir().map(|dir| venv_python(&dir)).unwrap_or_else(|| PathBuf::from(SYSTEM_PYTHON))
+}
+
+// What activating the selected environment sets, so programs the code starts (pip, python)
+// resolve to it too; nothing for the system Python
+pub fn activation_env() -> Vec<(&'static str, OsString)> {
+    let Some(dir) = selected_dir() else { return Vec::new() };
+    let bin = venv_python(&dir).parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut paths = vec![bin];
+    if let Some(path) = std::env::var_os("PATH") {
+        paths.extend(std::env::split_paths(&path));
+    }
+    let path = std::env::join_paths(paths).unwrap_or_default();
+    vec![("VIRTUAL_ENV", dir.into_os_string()), ("PATH", path)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_plain_folder_names() {
+        assert!(validate_name("lesson-3_numpy.v2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../system").is_err());
+        assert!(validate_name(".hidden").is_err());
+        assert!(validate_name("my env").is_err());
+    }
+
+    #[test]
+    fn python_version_comes_from_pyvenv_cfg() {
+        let venv = "home = /usr/bin\ninclude-system-site-packages = false\nversion = 3.11.7\n";
+        assert_eq!(pyvenv_version(venv).as_deref(), Some("3.11.7"));
+        let uv = "home = /usr/bin\nimplementation = CPython\nversion_info = 3.12.1.final.0\n";
+        assert_eq!(pyvenv_version(uv).as_deref(), Some("3.12.1"));
+        assert_eq!(pyvenv_version("home = /usr/bin\n"), None);
+    }
+}
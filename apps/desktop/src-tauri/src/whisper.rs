@@ -1,153 +1,1404 @@
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
-use hound;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState, WhisperToken};
+use crate::audio_codec::{self, DecodedAudio};
+use serde::{Deserialize, Serialize};
+use rubato::{FftFixedIn, Resampler};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    pub speaker: Option<String>, // Set when speakers are labeled in imported recordings
+    #[serde(default)]
+    pub confidence: f32, // Mean probability of the segment's text tokens, 0 to 1
+}
+
+// Text of a recording with the language Whisper transcribed it in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcription {
+    pub text: String,
+    pub language: String, // Whisper language code, detected when transcribing with "auto"
+    pub confidence: f32,
+    pub low_confidence: bool, // Likely misheard; ask the user to repeat rather than act on the text
+}
+
+// Below this overall confidence a transcript is mostly guesses (mumbling, noise, far-off speech)
+pub const LOW_CONFIDENCE_THRESHOLD: f32 = 0.5;
+
+// Overall confidence of a transcript, weighting each segment by how long it lasts.
+// Returns 0 when nothing was transcribed.
+pub fn transcript_confidence(segments: impl IntoIterator<Item = (i64, i64, f32)>) -> f32 {
+    let (weighted, total) = segments.into_iter()
+        .fold((0.0f64, 0.0f64), |(weighted, total), (start_ms, end_ms, confidence)| {
+            let weight = (end_ms - start_ms).max(1) as f64;
+            (weighted + confidence as f64 * weight, total + weight)
+        });
+    if total > 0.0 { (weighted / total) as f32 } else { 0.0 }
+}
+
+// Empty transcripts aren't flagged; there is nothing to repeat
+pub fn is_low_confidence(text: &str, confidence: f32) -> bool {
+    !text.trim().is_empty() && confidence < LOW_CONFIDENCE_THRESHOLD
+}
+
+const TRANSCRIPTION_CANCELLED: &str = "Transcription cancelled";
+
+pub fn is_cancellation(error: &str) -> bool {
+    error == TRANSCRIPTION_CANCELLED
+}
+
+// Outcome for one file of a batch; exactly one of `transcription` and `error` is set
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchFileResult {
+    pub file_path: String,
+    pub transcription: Option<Transcription>,
+    pub error: Option<String>,
+}
+
+// A word and when it is spoken, for highlighting during playback
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimedWord {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    pub probability: f32, // Lowest token probability in the word; low values are likely mishearings
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetailedSegment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    pub confidence: f32,
+    pub words: Vec<TimedWord>,
+}
+
+// Transcription with segment and word timings, returned by transcribe_audio_detailed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetailedTranscription {
+    pub text: String,
+    pub language: String,
+    pub duration_ms: i64,
+    pub confidence: f32,
+    pub low_confidence: bool,
+    pub segments: Vec<DetailedSegment>,
+}
+
+// Transcription language setting that has Whisper detect the spoken language
+pub const AUTO_LANGUAGE: &str = "auto";
+
+// Accept "auto" or a Whisper language code such as "en", "es" or "hi"
+pub fn validate_language(language: &str) -> Result<(), String> {
+    let well_formed = !language.is_empty() && language.chars().all(|c| c.is_ascii_lowercase());
+    if language == AUTO_LANGUAGE || (well_formed && whisper_rs::get_lang_id(language).is_some()) {
+        Ok(())
+    } else {
+        Err(format!("Unknown transcription language '{}'", language))
+    }
+}
+
+// English-only models can neither transcribe nor detect other languages
+pub fn needs_multilingual_model(language: &str) -> bool {
+    language != "en"
+}
+
+// One silence-bounded piece of a long recording, with its segments already on the file's timeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscribedChunk {
+    pub chunk_index: usize,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub total_ms: i64,
+    pub language: String,
+    pub segments: Vec<TranscriptSegment>,
+}
+
+// Chunking for long files: cut at the first pause once a chunk reaches CHUNK_MIN_SECS,
+// or force a cut at CHUNK_MAX_SECS if the speaker never pauses
+const CHUNK_MIN_SECS: usize = 30;
+const CHUNK_MAX_SECS: usize = 90;
+const CHUNK_FRAME_SAMPLES: usize = 480; // 30ms at 16kHz
+const CHUNK_SILENCE_FRAMES: usize = 10; // 300ms of quiet counts as a pause
+const CHUNK_SILENCE_RMS: f32 = 0.01;
+
+// Live transcription while recording: re-run Whisper over the audio since the last pause every
+// LIVE_STEP_SAMPLES, and settle that text once the speaker pauses or the window gets too long
+const LIVE_STEP_SAMPLES: usize = 24000; // 1.5s at 16kHz
+const LIVE_MIN_SECS: usize = 3;
+const LIVE_MAX_SECS: usize = 12;
+
+// Emitted as "transcription-partial" while recording with live transcription on
+#[derive(Debug, Clone, Serialize)]
+pub struct PartialTranscript {
+    pub recording_id: String,
+    pub committed_text: String, // Settled; later events only add to it
+    pub tentative_text: String, // Best guess for the audio since the last pause; may still change
+    pub language: Option<String>, // Known once Whisper has transcribed some speech
+    pub is_final: bool, // The recording has stopped and this is its last event
+}
+
+// Sliding window over the live Whisper audio
+#[derive(Default)]
+pub struct LiveTranscription {
+    committed: Vec<String>,
+    tentative: String,
+    window: Vec<f32>,
+    new_samples: usize,
+    language: Option<String>, // Pinned after the first settled text so "auto" doesn't flip mid-recording
+}
+
+impl LiveTranscription {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add 16kHz mono audio; true once enough has arrived for another pass
+    pub fn push(&mut self, samples: &[f32]) -> bool {
+        self.window.extend_from_slice(samples);
+        self.new_samples += samples.len();
+        self.new_samples >= LIVE_STEP_SAMPLES
+    }
+
+    // Transcribe the current window, settling it at a pause, at LIVE_MAX_SECS or when `finish`ing
+    pub fn step(&mut self, transcriber: &WhisperTranscriber, initial_prompt: Option<&str>, language: &str, finish: bool) -> Result<(), String> {
+        self.new_samples = 0;
+        if self.window.is_empty() {
+            return Ok(());
+        }
+
+        let language = self.language.as_deref().unwrap_or(language);
+        let (segments, detected) = transcriber.transcribe_samples(&self.window, initial_prompt, language)?;
+        let text = segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim()
+            .to_string();
+
+        let settle = finish
+            || self.window.len() >= LIVE_MAX_SECS * 16000
+            || (self.window.len() >= LIVE_MIN_SECS * 16000 && self.ends_in_pause());
+        if settle {
+            if !text.is_empty() {
+                self.committed.push(text);
+                self.language.get_or_insert(detected);
+            }
+            self.tentative.clear();
+            self.window.clear();
+        } else {
+            self.tentative = text;
+        }
+        Ok(())
+    }
+
+    fn ends_in_pause(&self) -> bool {
+        let tail = CHUNK_SILENCE_FRAMES * CHUNK_FRAME_SAMPLES;
+        if self.window.len() < tail {
+            return false;
+        }
+        self.window[self.window.len() - tail..]
+            .chunks(CHUNK_FRAME_SAMPLES)
+            .all(|frame| (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt() < CHUNK_SILENCE_RMS)
+    }
+
+    pub fn snapshot(&self, recording_id: &str, is_final: bool) -> PartialTranscript {
+        PartialTranscript {
+            recording_id: recording_id.to_string(),
+            committed_text: self.committed.join(" "),
+            tentative_text: self.tentative.clone(),
+            language: self.language.clone(),
+            is_final,
+        }
+    }
+}
+
+// A Whisper model file on disk, for the disk usage advisor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WhisperModelFile {
+    pub name: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub bundled: bool, // Shipped next to the executable; can't be deleted from the app
+}
+
+// Emitted as "whisper-download-progress" while the model downloads
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub stage: &'static str, // "downloading", "verifying" or "done"
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>, // None when the server doesn't report a length
+    pub resumed: bool, // Picked up from an earlier partial download
+}
+
+// A downloadable model; the English-only one is used unless another language is chosen
+struct ModelDownload {
+    file_name: &'static str,
+    url: &'static str,
+    sha256: &'static str,
+    multilingual: bool,
+}
+
+// A model the app can download, for the settings screen
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadableWhisperModel {
+    pub name: String,
+    pub multilingual: bool,
+    pub downloaded: bool, // Already in the models directory (or bundled)
+}
+
+const ENGLISH_MODEL: ModelDownload = ModelDownload {
+    file_name: "ggml-tiny.en.bin",
+    url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.en.bin",
+    sha256: "921e4cf8686fdd993dcd081a5da5b6c365bfde1162e72b08d75ac75289920b1f",
+    multilingual: false,
+};
+const MULTILINGUAL_MODEL: ModelDownload = ModelDownload {
+    file_name: "ggml-tiny.bin",
+    url: "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.bin",
+    sha256: "be07e048e1e599ad46341c8d2a135645097a538221678b7acdd1b1919c6e1b21",
+    multilingual: true,
+};
+const DOWNLOADABLE_MODELS: [&ModelDownload; 2] = [&ENGLISH_MODEL, &MULTILINGUAL_MODEL];
+const PROGRESS_STEP_BYTES: u64 = 512 * 1024;
+
+// GPU backend this build's whisper.cpp was compiled with, if any
+pub const GPU_BACKEND: Option<&str> = if cfg!(target_os = "macos") {
+    Some("metal")
+} else if cfg!(feature = "whisper-cuda") {
+    Some("cuda")
+} else if cfg!(feature = "whisper-hipblas") {
+    Some("hipblas")
+} else {
+    None
+};
+
+// What Whisper is running on, for the settings UI
+#[derive(Debug, Clone, Serialize)]
+pub struct WhisperAcceleration {
+    pub gpu_enabled: bool, // The user's setting
+    pub gpu_backend: Option<&'static str>, // None when this build has no GPU support
+    pub active: &'static str, // "gpu", "cpu" or "not_loaded"
+    pub fallback_reason: Option<String>, // Why the GPU was requested but the CPU is in use
+}
+
+// Lets another thread stop a file transcription in progress. Only transcriptions started
+// after `begin` can be cancelled, so a stray cancel never hits live transcription or the next file.
+#[derive(Default)]
+pub struct TranscriptionCancel {
+    running: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+impl TranscriptionCancel {
+    // Returns whether a transcription was running to cancel
+    pub fn cancel(&self) -> bool {
+        let running = self.running.load(Ordering::SeqCst);
+        if running {
+            self.cancelled.store(true, Ordering::SeqCst);
+        }
+        running
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn begin(self: &Arc<Self>) -> RunningTranscription {
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.running.store(true, Ordering::SeqCst);
+        RunningTranscription(Arc::clone(self))
+    }
+}
+
+// Marks the transcription finished when dropped, however it ends
+struct RunningTranscription(Arc<TranscriptionCancel>);
+
+impl Drop for RunningTranscription {
+    fn drop(&mut self) {
+        self.0.running.store(false, Ordering::SeqCst);
+        self.0.cancelled.store(false, Ordering::SeqCst);
+    }
+}
+
+// whisper.cpp polls this during encoding and decoding; user_data points at the `cancelled` flag
+unsafe extern "C" fn abort_requested(user_data: *mut std::ffi::c_void) -> bool {
+    (*(user_data as *const AtomicBool)).load(Ordering::SeqCst)
+}
 
 pub struct WhisperTranscriber {
     context: Option<WhisperContext>,
+    model_path: Option<String>,
+    using_gpu: bool,
+    gpu_fallback_reason: Option<String>,
+    cancel: Arc<TranscriptionCancel>,
 }
 
 impl WhisperTranscriber {
     pub fn new() -> Self {
-        Self { context: None }
+        Self {
+            context: None,
+            model_path: None,
+            using_gpu: false,
+            gpu_fallback_reason: None,
+            cancel: Arc::new(TranscriptionCancel::default()),
+        }
     }
 
-    pub fn initialize(&mut self, model_path: &str) -> Result<(), String> {
-        let ctx_params = WhisperContextParameters::default();
-        
+    // Handle for cancelling from outside the transcriber's lock
+    pub fn cancel_handle(&self) -> Arc<TranscriptionCancel> {
+        Arc::clone(&self.cancel)
+    }
+
+    pub fn model_path(&self) -> Option<&str> {
+        self.model_path.as_deref()
+    }
+
+    // Load the model on the GPU when requested and compiled in, falling back to the CPU if that fails
+    pub fn initialize(&mut self, model_path: &str, use_gpu: bool) -> Result<(), String> {
+        let mut gpu_fallback_reason = None;
+        if use_gpu && GPU_BACKEND.is_some() {
+            let mut ctx_params = WhisperContextParameters::default();
+            ctx_params.use_gpu(true);
+            match WhisperContext::new_with_params(model_path, ctx_params) {
+                Ok(context) => {
+                    self.set_context(context, model_path, true, None);
+                    return Ok(());
+                }
+                Err(e) => gpu_fallback_reason = Some(format!("GPU initialization failed: {}", e)),
+            }
+        } else if use_gpu {
+            gpu_fallback_reason = Some("This build has no GPU support for Whisper".to_string());
+        }
+
+        let mut ctx_params = WhisperContextParameters::default();
+        ctx_params.use_gpu(false);
         let context = WhisperContext::new_with_params(model_path, ctx_params)
             .map_err(|e| format!("Failed to load Whisper model: {}", e))?;
         
-        self.context = Some(context);
+        self.set_context(context, model_path, false, gpu_fallback_reason);
         Ok(())
     }
 
-    pub fn transcribe_audio_file(&self, audio_file_path: &str) -> Result<String, String> {
+    fn set_context(&mut self, context: WhisperContext, model_path: &str, using_gpu: bool, gpu_fallback_reason: Option<String>) {
+        self.context = Some(context);
+        self.model_path = Some(model_path.to_string());
+        self.using_gpu = using_gpu;
+        self.gpu_fallback_reason = gpu_fallback_reason;
+    }
+
+    pub fn acceleration(&self, gpu_enabled: bool) -> WhisperAcceleration {
+        WhisperAcceleration {
+            gpu_enabled,
+            gpu_backend: GPU_BACKEND,
+            active: match (&self.context, self.using_gpu) {
+                (None, _) => "not_loaded",
+                (Some(_), true) => "gpu",
+                (Some(_), false) => "cpu",
+            },
+            fallback_reason: self.gpu_fallback_reason.clone(),
+        }
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.context.is_some()
+    }
+
+    pub fn is_multilingual(&self) -> bool {
+        self.context.as_ref().map(|context| context.is_multilingual()).unwrap_or(false)
+    }
+
+    // `language` is a Whisper language code or AUTO_LANGUAGE
+    pub fn transcribe_audio_file(&self, audio_file_path: &str, initial_prompt: Option<&str>, language: &str) -> Result<Transcription, String> {
+        let _running = self.cancel.begin();
+        let (segments, language) = self.transcribe_segments(audio_file_path, initial_prompt, language)?;
+
+        let full_text = segments.iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let text = full_text.trim().to_string();
+        let confidence = transcript_confidence(segments.iter().map(|segment| (segment.start_ms, segment.end_ms, segment.confidence)));
+
+        Ok(Transcription { low_confidence: is_low_confidence(&text, confidence), text, language, confidence })
+    }
+
+    // Transcribe into timestamped segments, also returning the language they are in
+    pub fn transcribe_segments(&self, audio_file_path: &str, initial_prompt: Option<&str>, language: &str) -> Result<(Vec<TranscriptSegment>, String), String> {
+        // Load audio data from file
+        let audio_data = self.load_audio(audio_file_path)?;
+        self.transcribe_samples(&audio_data, initial_prompt, language)
+    }
+
+    // Transcribe a long file chunk by chunk, holding at most CHUNK_MAX_SECS of audio at once.
+    // `on_chunk` sees each chunk as soon as it is done (and may edit its segments) so callers
+    // can surface partial results. Speaker labels, if requested, are assigned per chunk for those
+    // early results, then reassigned across the whole file so a voice keeps one label throughout.
+    // With AUTO_LANGUAGE the language detected in the first chunk with speech is kept for the rest.
+    pub fn transcribe_chunked<F>(
+        &self,
+        audio_file_path: &str,
+        initial_prompt: Option<&str>,
+        language: &str,
+        speaker_labels: Option<[&str; 2]>,
+        mut on_chunk: F,
+    ) -> Result<(Vec<TranscriptSegment>, String), String>
+    where
+        F: FnMut(&mut TranscribedChunk),
+    {
+        let _running = self.cancel.begin();
+        let audio = Self::open_whisper_audio(audio_file_path)?;
+        let total_ms = match audio.total_frames {
+            Some(frames) => frames as i64 / 16,
+            None => audio_codec::duration_ms(audio_file_path).unwrap_or(0) as i64,
+        };
+        let mut samples = audio.samples;
+
+        let mut segments = Vec::new();
+        let mut features = Vec::new();
+        let mut language = language.to_string();
+        let mut buffer: Vec<f32> = Vec::with_capacity(CHUNK_MAX_SECS * 16000);
+        let mut chunk_start_sample = 0usize;
+        let mut silent_frames = 0usize;
+        let mut chunk_index = 0usize;
+        let mut frame = Vec::with_capacity(CHUNK_FRAME_SAMPLES);
+
+        loop {
+            frame.clear();
+            for sample in samples.by_ref().take(CHUNK_FRAME_SAMPLES) {
+                frame.push(sample.map_err(|e| format!("Failed to read audio samples: {}", e))?);
+            }
+            let at_end = frame.is_empty();
+
+            if !at_end {
+                buffer.extend_from_slice(&frame);
+                let rms = (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt();
+                silent_frames = if rms < CHUNK_SILENCE_RMS { silent_frames + 1 } else { 0 };
+            }
+
+            // Cut in the middle of the pause so neither side clips a word
+            let cut = if at_end || buffer.len() >= CHUNK_MAX_SECS * 16000 {
+                buffer.len()
+            } else if buffer.len() >= CHUNK_MIN_SECS * 16000 && silent_frames >= CHUNK_SILENCE_FRAMES {
+                buffer.len() - silent_frames * CHUNK_FRAME_SAMPLES / 2
+            } else {
+                continue;
+            };
+
+            if cut > 0 {
+                let (mut chunk_segments, chunk_language) = self.transcribe_samples(&buffer[..cut], initial_prompt, &language)?;
+                if language == AUTO_LANGUAGE && !chunk_segments.is_empty() {
+                    language = chunk_language.clone();
+                }
+                if let Some(labels) = speaker_labels {
+                    let chunk_features = speaker_features(&chunk_segments, &buffer[..cut]);
+                    assign_speakers(&mut chunk_segments, &chunk_features, labels);
+                    features.extend(chunk_features);
+                }
+
+                let offset_ms = chunk_start_sample as i64 / 16;
+                for segment in chunk_segments.iter_mut() {
+                    segment.start_ms += offset_ms;
+                    segment.end_ms += offset_ms;
+                }
+
+                let mut chunk = TranscribedChunk {
+                    chunk_index,
+                    start_ms: offset_ms,
+                    end_ms: (chunk_start_sample + cut) as i64 / 16,
+                    total_ms,
+                    language: chunk_language,
+                    segments: chunk_segments,
+                };
+                on_chunk(&mut chunk);
+                segments.append(&mut chunk.segments);
+
+                chunk_index += 1;
+                chunk_start_sample += cut;
+                buffer.drain(..cut);
+                silent_frames = 0;
+            }
+
+            if at_end {
+                break;
+            }
+        }
+
+        // Features no longer line up if on_chunk dropped segments; the per-chunk labels stand then
+        if let Some(labels) = speaker_labels {
+            if chunk_index > 1 && features.len() == segments.len() {
+                assign_speakers(&mut segments, &features, labels);
+            }
+        }
+
+        // Nothing was said, so nothing was detected; English-only models still mean English
+        if language == AUTO_LANGUAGE && !self.is_multilingual() {
+            language = "en".to_string();
+        }
+        Ok((segments, language))
+    }
+
+    // Returns the segments and the language Whisper used (detected when `language` is AUTO_LANGUAGE)
+    fn transcribe_samples(&self, audio_data: &[f32], initial_prompt: Option<&str>, language: &str) -> Result<(Vec<TranscriptSegment>, String), String> {
+        let (state, language) = self.run_whisper(audio_data, initial_prompt, language, false)?;
+        let eot = self.token_eot()?;
+
+        let num_segments = state.full_n_segments()
+            .map_err(|e| format!("Failed to get segment count: {}", e))?;
+        let mut segments = Vec::with_capacity(num_segments as usize);
+        for i in 0..num_segments {
+            segments.push(Self::segment_at(&state, i, eot)?);
+        }
+
+        Ok((segments, language))
+    }
+
+    // Transcribe with per-word timings so playback can highlight each word as it is spoken
+    pub fn transcribe_detailed(&self, audio_file_path: &str, initial_prompt: Option<&str>, language: &str) -> Result<DetailedTranscription, String> {
+        let _running = self.cancel.begin();
+        let audio_data = self.load_audio(audio_file_path)?;
+        let (state, language) = self.run_whisper(&audio_data, initial_prompt, language, true)?;
+        let eot = self.token_eot()?;
+
+        let num_segments = state.full_n_segments()
+            .map_err(|e| format!("Failed to get segment count: {}", e))?;
+        let mut segments = Vec::with_capacity(num_segments as usize);
+        for i in 0..num_segments {
+            let segment = Self::segment_at(&state, i, eot)?;
+            let words = Self::segment_words(&state, i, eot, &segment.text)?;
+            segments.push(DetailedSegment {
+                start_ms: segment.start_ms,
+                end_ms: segment.end_ms,
+                text: segment.text,
+                confidence: segment.confidence,
+                words,
+            });
+        }
+
+        let text = segments.iter()
+            .map(|segment| segment.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let text = text.trim().to_string();
+        let confidence = transcript_confidence(segments.iter().map(|segment| (segment.start_ms, segment.end_ms, segment.confidence)));
+        Ok(DetailedTranscription {
+            low_confidence: is_low_confidence(&text, confidence),
+            text,
+            language,
+            duration_ms: audio_data.len() as i64 / 16,
+            confidence,
+            segments,
+        })
+    }
+
+    // Tokens at or after end-of-text are timestamps, language tags and other special tokens
+    fn token_eot(&self) -> Result<WhisperToken, String> {
+        self.context.as_ref()
+            .map(|context| context.token_eot())
+            .ok_or_else(|| "Whisper context not initialized".to_string())
+    }
+
+    // Run Whisper over the samples, returning its state for reading results and the language it used
+    fn run_whisper(&self, audio_data: &[f32], initial_prompt: Option<&str>, language: &str, token_timestamps: bool) -> Result<(WhisperState, String), String> {
         let context = self.context.as_ref()
             .ok_or("Whisper context not initialized")?;
 
-        // Load audio data from file
-        let audio_data = self.load_audio_from_wav(audio_file_path)?;
+        // An English-only model has nothing to detect and can't transcribe anything else
+        let language = if context.is_multilingual() || language == "en" {
+            language
+        } else if language == AUTO_LANGUAGE {
+            "en"
+        } else {
+            return Err(format!(
+                "The loaded Whisper model is English-only; reinitialize Whisper to load the multilingual model for '{}'",
+                language
+            ));
+        };
 
-        // Set up transcription parameters
+        // Set up transcription parameters ("auto" makes Whisper detect the language first)
         let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-        params.set_language(Some("en"));
+        params.set_language(Some(language));
         params.set_translate(false);
         params.set_print_special(false);
         params.set_print_progress(false);
         params.set_print_realtime(false);
         params.set_print_timestamps(false);
+        params.set_token_timestamps(token_timestamps);
+        if let Some(prompt) = initial_prompt {
+            params.set_initial_prompt(prompt);
+        }
+        // Safety: the flag lives in self.cancel, which outlives this call
+        unsafe {
+            params.set_abort_callback(Some(abort_requested));
+            params.set_abort_callback_user_data(&self.cancel.cancelled as *const AtomicBool as *mut std::ffi::c_void);
+        }
 
         // Run transcription
         let mut state = context.create_state()
             .map_err(|e| format!("Failed to create Whisper state: {}", e))?;
         
-        state.full(params, &audio_data)
-            .map_err(|e| format!("Transcription failed: {}", e))?;
+        let result = state.full(params, audio_data);
+        if self.cancel.is_cancelled() {
+            return Err(TRANSCRIPTION_CANCELLED.to_string());
+        }
+        result.map_err(|e| format!("Transcription failed: {}", e))?;
 
-        // Extract transcription text
-        let num_segments = state.full_n_segments()
-            .map_err(|e| format!("Failed to get segment count: {}", e))?;
+        let detected = state.full_lang_id_from_state().ok()
+            .and_then(whisper_rs::get_lang_str)
+            .unwrap_or(language)
+            .to_string();
+        Ok((state, detected))
+    }
 
-        let mut full_text = String::new();
-        for i in 0..num_segments {
-            let segment_text = state.full_get_segment_text(i)
-                .map_err(|e| format!("Failed to get segment text: {}", e))?;
-            full_text.push_str(&segment_text);
-            if i < num_segments - 1 {
-                full_text.push(' ');
+    // Whisper timestamps are in centiseconds
+    fn segment_at(state: &WhisperState, i: i32, eot: WhisperToken) -> Result<TranscriptSegment, String> {
+        let segment_text = state.full_get_segment_text(i)
+            .map_err(|e| format!("Failed to get segment text: {}", e))?;
+        let start = state.full_get_segment_t0(i)
+            .map_err(|e| format!("Failed to get segment start: {}", e))?;
+        let end = state.full_get_segment_t1(i)
+            .map_err(|e| format!("Failed to get segment end: {}", e))?;
+
+        let num_tokens = state.full_n_tokens(i)
+            .map_err(|e| format!("Failed to get token count: {}", e))?;
+        let mut probability_sum = 0.0f32;
+        let mut text_tokens = 0usize;
+        for token in 0..num_tokens {
+            let data = state.full_get_token_data(i, token)
+                .map_err(|e| format!("Failed to get token data: {}", e))?;
+            if data.id < eot {
+                probability_sum += data.p;
+                text_tokens += 1;
             }
         }
 
-        Ok(full_text.trim().to_string())
+        Ok(TranscriptSegment {
+            start_ms: start * 10,
+            end_ms: end * 10,
+            text: segment_text.trim().to_string(),
+            speaker: None,
+            confidence: if text_tokens > 0 { probability_sum / text_tokens as f32 } else { 0.0 },
+        })
     }
 
-    fn load_audio_from_wav(&self, file_path: &str) -> Result<Vec<f32>, String> {
-        let mut reader = hound::WavReader::open(file_path)
-            .map_err(|e| format!("Failed to open WAV file: {}", e))?;
+    // Join the segment's tokens into words; a token starting with a space starts a new word
+    fn segment_words(state: &WhisperState, i: i32, eot: WhisperToken, segment_text: &str) -> Result<Vec<TimedWord>, String> {
+        let num_tokens = state.full_n_tokens(i)
+            .map_err(|e| format!("Failed to get token count: {}", e))?;
 
-        let spec = reader.spec();
-        
-        // Whisper expects 16kHz mono audio
-        if spec.sample_rate != 16000 {
-            return Err(format!("Audio must be 16kHz, got {}Hz", spec.sample_rate));
-        }
-        
-        if spec.channels != 1 {
-            return Err(format!("Audio must be mono, got {} channels", spec.channels));
-        }
-
-        // Convert samples to f32 in the range [-1.0, 1.0]
-        let samples: Result<Vec<f32>, _> = match spec.sample_format {
-            hound::SampleFormat::Int => {
-                match spec.bits_per_sample {
-                    16 => {
-                        reader.samples::<i16>()
-                            .map(|s| s.map(|sample| sample as f32 / i16::MAX as f32))
-                            .collect()
-                    }
-                    32 => {
-                        reader.samples::<i32>()
-                            .map(|s| s.map(|sample| sample as f32 / i32::MAX as f32))
-                            .collect()
-                    }
-                    _ => return Err(format!("Unsupported bit depth: {}", spec.bits_per_sample)),
+        let mut words: Vec<TimedWord> = Vec::new();
+        for token in 0..num_tokens {
+            let data = state.full_get_token_data(i, token)
+                .map_err(|e| format!("Failed to get token data: {}", e))?;
+            // Timestamps, language tags and other special tokens come after end-of-text
+            if data.id >= eot {
+                continue;
+            }
+            let text = state.full_get_token_text_lossy(i, token)
+                .map_err(|e| format!("Failed to get token text: {}", e))?;
+
+            match words.last_mut() {
+                Some(word) if !text.starts_with(' ') => {
+                    word.text.push_str(&text);
+                    word.end_ms = data.t1 * 10;
+                    word.probability = word.probability.min(data.p);
                 }
+                _ => words.push(TimedWord {
+                    start_ms: data.t0 * 10,
+                    end_ms: data.t1 * 10,
+                    text: text.trim_start().to_string(),
+                    probability: data.p,
+                }),
             }
-            hound::SampleFormat::Float => {
-                reader.samples::<f32>().collect()
+        }
+        words.retain(|word| !word.text.trim().is_empty());
+
+        // A token can end partway through a multi-byte character, so take the spelling
+        // from the segment text whenever the words line up with it
+        let spelled: Vec<&str> = segment_text.split_whitespace().collect();
+        if spelled.len() == words.len() {
+            for (word, spelling) in words.iter_mut().zip(spelled) {
+                word.text = spelling.to_string();
             }
-        };
+        }
+        Ok(words)
+    }
 
+    fn load_audio(&self, file_path: &str) -> Result<Vec<f32>, String> {
+        let audio = Self::open_whisper_audio(file_path)?;
+        let samples: Result<Vec<f32>, _> = audio.samples.collect();
         samples.map_err(|e| format!("Failed to read audio samples: {}", e))
     }
+
+    // Open a WAV, FLAC or Opus file as the 16kHz mono audio Whisper expects; files from other
+    // sources are downmixed and resampled on the fly
+    fn open_whisper_audio(file_path: &str) -> Result<DecodedAudio, String> {
+        let audio = audio_codec::open_audio(file_path)?;
+        if audio.sample_rate == WHISPER_SAMPLE_RATE && audio.channels == 1 {
+            return Ok(audio);
+        }
+        if audio.channels == 0 || audio.sample_rate == 0 {
+            return Err(format!("Unsupported audio: {}Hz with {} channels", audio.sample_rate, audio.channels));
+        }
+
+        let total_frames = audio.total_frames
+            .map(|frames| frames * WHISPER_SAMPLE_RATE as u64 / audio.sample_rate as u64);
+        let samples = WhisperFormatSamples::new(audio)?;
+        Ok(DecodedAudio {
+            sample_rate: WHISPER_SAMPLE_RATE,
+            channels: 1,
+            total_frames,
+            samples: Box::new(samples),
+        })
+    }
 }
 
-// Utility function to download Whisper model if needed
-pub async fn ensure_whisper_model() -> Result<String, String> {
-    use std::fs;
-    
-    // First try to use bundled model
+const WHISPER_SAMPLE_RATE: u32 = 16000;
+const CONVERT_CHUNK_FRAMES: usize = 1024;
+
+// Downmixes interleaved audio to mono and resamples it to 16kHz a chunk at a time,
+// so converted files stay as lazy as the decoders underneath
+struct WhisperFormatSamples {
+    source: Box<dyn Iterator<Item = Result<f32, String>>>,
+    channels: usize,
+    resampler: Option<FftFixedIn<f32>>, // None when only downmixing
+    skip: usize, // Resampler delay still to drop, so timestamps line up with the original
+    sample_rate: u32,
+    frames_read: u64,
+    frames_emitted: u64,
+    input: Vec<f32>,
+    output: VecDeque<f32>,
+    finished: bool,
+}
+
+impl WhisperFormatSamples {
+    fn new(audio: DecodedAudio) -> Result<Self, String> {
+        let resampler = if audio.sample_rate == WHISPER_SAMPLE_RATE {
+            None
+        } else {
+            Some(FftFixedIn::<f32>::new(audio.sample_rate as usize, WHISPER_SAMPLE_RATE as usize, CONVERT_CHUNK_FRAMES, 2, 1)
+                .map_err(|e| format!("Failed to create resampler: {}", e))?)
+        };
+        Ok(Self {
+            skip: resampler.as_ref().map(|resampler| resampler.output_delay()).unwrap_or(0),
+            source: audio.samples,
+            channels: audio.channels as usize,
+            sample_rate: audio.sample_rate,
+            frames_read: 0,
+            frames_emitted: 0,
+            resampler,
+            input: Vec::with_capacity(CONVERT_CHUNK_FRAMES),
+            output: VecDeque::new(),
+            finished: false,
+        })
+    }
+
+    // Read and convert the next chunk into `output`
+    fn fill(&mut self) -> Result<(), String> {
+        let needed = self.resampler.as_ref()
+            .map(|resampler| resampler.input_frames_next())
+            .unwrap_or(CONVERT_CHUNK_FRAMES);
+        while self.input.len() < needed {
+            let mut sum = 0.0;
+            let mut read = 0;
+            for sample in self.source.by_ref().take(self.channels) {
+                sum += sample?;
+                read += 1;
+            }
+            if read == 0 {
+                self.finished = true;
+                break;
+            }
+            self.input.push(sum / read as f32);
+            self.frames_read += 1;
+        }
+
+        let converted = match self.resampler.as_mut() {
+            None => std::mem::take(&mut self.input),
+            Some(resampler) if !self.finished => {
+                let mut resampled = resampler.process(&[&self.input], None)
+                    .map_err(|e| format!("Resampling error: {}", e))?;
+                self.input.clear();
+                resampled.swap_remove(0)
+            }
+            Some(resampler) => {
+                // Pad out the last partial chunk, then flush what the resampler is still holding
+                let mut tail = Vec::new();
+                if !self.input.is_empty() {
+                    let mut resampled = resampler.process_partial(Some(&[&self.input]), None)
+                        .map_err(|e| format!("Resampling error: {}", e))?;
+                    tail.append(&mut resampled[0]);
+                    self.input.clear();
+                }
+                let mut resampled = resampler.process_partial::<Vec<f32>>(None, None)
+                    .map_err(|e| format!("Resampling error: {}", e))?;
+                tail.append(&mut resampled[0]);
+                tail
+            }
+        };
+
+        let skipped = self.skip.min(converted.len());
+        self.skip -= skipped;
+        let mut converted = &converted[skipped..];
+        if self.finished {
+            // Drop the padding so the converted audio is as long as the original
+            let expected = self.frames_read * WHISPER_SAMPLE_RATE as u64 / self.sample_rate as u64;
+            let remaining = expected.saturating_sub(self.frames_emitted) as usize;
+            converted = &converted[..converted.len().min(remaining)];
+        }
+        self.frames_emitted += converted.len() as u64;
+        self.output.extend(converted);
+        Ok(())
+    }
+}
+
+impl Iterator for WhisperFormatSamples {
+    type Item = Result<f32, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(sample) = self.output.pop_front() {
+                return Some(Ok(sample));
+            }
+            if self.finished {
+                return None;
+            }
+            if let Err(e) = self.fill() {
+                self.finished = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+// Labels for recordings where neither speaker has a known role, e.g. study groups
+pub const NUMBERED_SPEAKERS: [&str; 2] = ["Speaker 1", "Speaker 2"];
+
+// Per-segment features: RMS energy in dB and zero-crossing rate. `audio` must start where the
+// segments' timestamps do.
+fn speaker_features(segments: &[TranscriptSegment], audio: &[f32]) -> Vec<[f64; 2]> {
+    const SAMPLES_PER_MS: i64 = 16; // 16kHz audio
+
+    segments.iter().map(|segment| {
+        let start = ((segment.start_ms * SAMPLES_PER_MS) as usize).min(audio.len());
+        let end = ((segment.end_ms * SAMPLES_PER_MS) as usize).clamp(start, audio.len());
+        let samples = &audio[start..end];
+        if samples.is_empty() {
+            return [0.0, 0.0];
+        }
+
+        let rms = (samples.iter().map(|s| (*s as f64) * (*s as f64)).sum::<f64>() / samples.len() as f64).sqrt();
+        let crossings = samples.windows(2).filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0)).count();
+        [20.0 * (rms + 1e-9).log10(), crossings as f64 / samples.len() as f64]
+    }).collect()
+}
+
+// Label segments with one of two speakers using simple acoustic heuristics.
+// Segments are clustered on loudness and zero-crossing rate (a rough pitch proxy), one feature
+// entry per segment; the speaker who talks longest gets the first label (e.g. the teacher in a lecture).
+fn assign_speakers(segments: &mut [TranscriptSegment], features: &[[f64; 2]], labels: [&str; 2]) {
+    const SHORT_TURN_MS: i64 = 1000;
+
+    if segments.is_empty() {
+        return;
+    }
+    if segments.len() == 1 {
+        segments[0].speaker = Some(labels[0].to_string());
+        return;
+    }
+
+    // Normalize each feature so neither dominates the distance
+    let mut normalized = features.to_vec();
+    for dim in 0..2 {
+        let mean = features.iter().map(|f| f[dim]).sum::<f64>() / features.len() as f64;
+        let variance = features.iter().map(|f| (f[dim] - mean).powi(2)).sum::<f64>() / features.len() as f64;
+        let std_dev = variance.sqrt().max(1e-9);
+        for (index, feature) in features.iter().enumerate() {
+            normalized[index][dim] = (feature[dim] - mean) / std_dev;
+        }
+    }
+
+    // Two-means clustering, seeded with the first segment and the one farthest from it
+    let distance = |a: &[f64; 2], b: &[f64; 2]| (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2);
+    let farthest = (0..normalized.len())
+        .max_by(|&a, &b| distance(&normalized[0], &normalized[a]).total_cmp(&distance(&normalized[0], &normalized[b])))
+        .unwrap_or(0);
+    let mut centroids = [normalized[0], normalized[farthest]];
+    let mut assignments = vec![0usize; normalized.len()];
+
+    for _ in 0..20 {
+        for (index, feature) in normalized.iter().enumerate() {
+            assignments[index] = if distance(feature, &centroids[0]) <= distance(feature, &centroids[1]) { 0 } else { 1 };
+        }
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&[f64; 2]> = normalized.iter()
+                .zip(&assignments)
+                .filter(|(_, assigned)| **assigned == cluster)
+                .map(|(feature, _)| feature)
+                .collect();
+            if !members.is_empty() {
+                *centroid = [
+                    members.iter().map(|f| f[0]).sum::<f64>() / members.len() as f64,
+                    members.iter().map(|f| f[1]).sum::<f64>() / members.len() as f64,
+                ];
+            }
+        }
+    }
+
+    // Turn heuristic: a short interjection between two turns of the same speaker is usually that speaker
+    for index in 1..segments.len() - 1 {
+        let duration = segments[index].end_ms - segments[index].start_ms;
+        if duration < SHORT_TURN_MS && assignments[index - 1] == assignments[index + 1] {
+            assignments[index] = assignments[index - 1];
+        }
+    }
+
+    // The speaker with more total talk time gets the first label
+    let mut talk_time = [0i64; 2];
+    for (segment, cluster) in segments.iter().zip(&assignments) {
+        talk_time[*cluster] += segment.end_ms - segment.start_ms;
+    }
+    let primary = if talk_time[0] >= talk_time[1] { 0 } else { 1 };
+
+    for (segment, cluster) in segments.iter_mut().zip(&assignments) {
+        let label = if *cluster == primary { labels[0] } else { labels[1] };
+        segment.speaker = Some(label.to_string());
+    }
+}
+
+// Merge consecutive segments from the same speaker into turns
+pub fn speaker_turns(segments: &[TranscriptSegment]) -> Vec<(Option<&str>, String)> {
+    let mut turns: Vec<(Option<&str>, String)> = Vec::new();
+
+    for segment in segments {
+        let speaker = segment.speaker.as_deref();
+        match turns.last_mut() {
+            Some((last_speaker, text)) if *last_speaker == speaker => {
+                text.push(' ');
+                text.push_str(&segment.text);
+            }
+            _ => turns.push((speaker, segment.text.clone())),
+        }
+    }
+    turns
+}
+
+// Render labeled segments as a transcript, one line per speaker turn
+pub fn format_labeled_transcript(segments: &[TranscriptSegment]) -> String {
+    speaker_turns(segments).iter()
+        .map(|(speaker, text)| match speaker {
+            Some(speaker) => format!("{}: {}", speaker, text),
+            None => text.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Learn misheard -> intended pairs from a user's edit of a transcript.
+// Words are aligned with an LCS diff; each replaced run of up to four words becomes a correction.
+pub fn learn_corrections(original: &str, edited: &str) -> Vec<(String, String)> {
+    const MAX_PHRASE_WORDS: usize = 4;
+
+    let original_words: Vec<&str> = original.split_whitespace().collect();
+    let edited_words: Vec<&str> = edited.split_whitespace().collect();
+    let normalize = |word: &str| trim_word(word).to_lowercase();
+
+    // LCS table over normalized words
+    let (n, m) = (original_words.len(), edited_words.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if normalize(original_words[i]) == normalize(edited_words[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // Walk the table, collecting runs where both sides changed
+    let mut corrections = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && normalize(original_words[i]) == normalize(edited_words[j]) {
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let (start_i, start_j) = (i, j);
+        while (i < n || j < m) && !(i < n && j < m && normalize(original_words[i]) == normalize(edited_words[j])) {
+            if j >= m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        let misheard = original_words[start_i..i].iter().map(|w| trim_word(w)).collect::<Vec<_>>().join(" ");
+        let intended = edited_words[start_j..j].iter().map(|w| trim_word(w)).collect::<Vec<_>>().join(" ");
+        let is_replacement = i > start_i && j > start_j;
+        let is_short = i - start_i <= MAX_PHRASE_WORDS && j - start_j <= MAX_PHRASE_WORDS;
+        if is_replacement && is_short && !misheard.is_empty() && !intended.is_empty()
+            && misheard.to_lowercase() != intended.to_lowercase() {
+            corrections.push((misheard.to_lowercase(), intended));
+        }
+    }
+
+    corrections
+}
+
+// Replace whole-word, case-insensitive occurrences of each misheard phrase with its intended form
+pub fn apply_corrections(text: &str, corrections: &[(String, String)]) -> String {
+    let mut result = text.to_string();
+
+    for (misheard, intended) in corrections {
+        if misheard.is_empty() {
+            continue;
+        }
+
+        // Lowercasing can change a character's length, so each character's offset in `lowered` is
+        // kept with its offset in `result`, and matches are only taken on character boundaries
+        let mut lowered = String::with_capacity(result.len());
+        let mut boundaries = Vec::new();
+        for (at, c) in result.char_indices() {
+            boundaries.push((lowered.len(), at));
+            lowered.extend(c.to_lowercase());
+        }
+        boundaries.push((lowered.len(), result.len()));
+        let original = |at: usize| boundaries.binary_search_by_key(&at, |&(lowered_at, _)| lowered_at).ok().map(|i| boundaries[i].1);
+
+        let needle = misheard.to_lowercase();
+        let mut output = String::with_capacity(result.len());
+        let mut copied = 0;
+        let mut search = 0;
+
+        while let Some(offset) = lowered[search..].find(&needle) {
+            let found = search + offset;
+            let whole_word = match (original(found), original(found + needle.len())) {
+                (Some(start), Some(end)) => {
+                    let before_ok = result[..start].chars().next_back().map_or(true, |c| !c.is_alphanumeric());
+                    let after_ok = result[end..].chars().next().map_or(true, |c| !c.is_alphanumeric());
+                    (before_ok && after_ok).then_some((start, end))
+                }
+                _ => None,
+            };
+
+            if let Some((start, end)) = whole_word {
+                output.push_str(&result[copied..start]);
+                output.push_str(intended);
+                copied = end;
+                search = found + needle.len();
+            } else {
+                search = found + lowered[found..].chars().next().map_or(1, char::len_utf8);
+            }
+        }
+
+        output.push_str(&result[copied..]);
+        result = output;
+    }
+
+    result
+}
+
+// Which vocabulary the Whisper initial prompt leans toward, on top of learned corrections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VocabularyHints {
+    pub python_terms: bool, // Built-in Python vocabulary such as "list comprehension" and "enumerate"
+    pub session_terms: bool, // Identifiers and code words from the current session's conversation
+    pub custom_terms: Vec<String>,
+}
+
+impl Default for VocabularyHints {
+    fn default() -> Self {
+        Self { python_terms: true, session_terms: true, custom_terms: Vec::new() }
+    }
+}
+
+// Terms learners say that Whisper tends to mishear without a hint
+pub const PYTHON_VOCABULARY: &[&str] = &[
+    "Python", "list comprehension", "enumerate", "dunder", "__init__", "self", "def", "elif",
+    "lambda", "f-string", "tuple", "dict", "range", "len", "append", "zip", "isinstance",
+    "try", "except", "IndexError", "KeyError", "TypeError", "ValueError", "NameError",
+    "None", "iterable", "generator", "yield", "decorator", "recursion", "slicing", "stdin",
+];
+
+// Whisper keeps only the last ~224 prompt tokens; staying under this keeps every term in
+const MAX_PROMPT_CHARS: usize = 600;
+const MAX_SESSION_TERMS: usize = 15;
+
+// Build a Whisper initial prompt that biases decoding toward the given terms, most important first;
+// duplicates are dropped and terms past the length cap left out
+pub fn build_vocabulary_prompt(terms: &[String]) -> Option<String> {
+    let mut kept: Vec<&str> = Vec::new();
+    let mut length = 0;
+    for term in terms {
+        let term = term.trim();
+        if term.is_empty() || kept.iter().any(|seen| seen.eq_ignore_ascii_case(term)) {
+            continue;
+        }
+        if length + term.len() + 2 > MAX_PROMPT_CHARS {
+            break;
+        }
+        length += term.len() + 2;
+        kept.push(term);
+    }
+
+    if kept.is_empty() {
+        return None;
+    }
+    Some(format!("Glossary: {}.", kept.join(", ")))
+}
+
+// Identifiers and code words from the conversation (snake_case names, calls like `enumerate()`,
+// attributes like `self.total`), most recent first
+pub fn session_vocabulary(messages: &[&str]) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for message in messages.iter().rev() {
+        for raw in message.split_whitespace() {
+            let word = raw.trim_matches(|c: char| !c.is_alphanumeric() && c != '_');
+            let has_letters = word.chars().any(|c| c.is_alphabetic());
+            let camel_case = word.chars().zip(word.chars().skip(1)).any(|(a, b)| a.is_lowercase() && b.is_uppercase());
+            let dotted = word.contains('.') && word.split('.').all(|part| part.len() >= 2); // Not "e.g"
+            let code_like = word.contains('_') || dotted || camel_case || raw.contains("()");
+            if !has_letters || !code_like || word.len() < 3 {
+                continue;
+            }
+            if !terms.iter().any(|seen| seen == word) {
+                terms.push(word.to_string());
+                if terms.len() == MAX_SESSION_TERMS {
+                    return terms;
+                }
+            }
+        }
+    }
+    terms
+}
+
+fn trim_word(word: &str) -> &str {
+    word.trim_matches(|c: char| !c.is_alphanumeric() && c != '_' && c != '\'')
+}
+
+// Utility function to download Whisper model if needed; the multilingual model is only
+// fetched once a language other than English is chosen
+pub async fn ensure_whisper_model(multilingual: bool, mut on_progress: impl FnMut(&DownloadProgress)) -> Result<String, String> {
+    // First try to use bundled model (English-only)
     let bundled_model_path = get_bundled_model_path().await?;
-    if bundled_model_path.exists() {
+    if !multilingual && bundled_model_path.exists() {
         return Ok(bundled_model_path.to_string_lossy().to_string());
     }
+    let model = if multilingual { &MULTILINGUAL_MODEL } else { &ENGLISH_MODEL };
     
     // Fallback to user directory for downloaded model
-    let model_dir = dirs::config_dir()
-        .ok_or("Failed to get config directory")?
-        .join("project-r")
-        .join("models");
+    download_model(model, &mut on_progress).await
+}
+
+// Download one of the models the app knows about ahead of time, e.g. from the settings screen,
+// so initialize_whisper doesn't have to. Returns the model's path; a verified copy is reused.
+pub async fn download_whisper_model(name: &str, mut on_progress: impl FnMut(&DownloadProgress)) -> Result<String, String> {
+    let model = DOWNLOADABLE_MODELS.iter()
+        .find(|model| model.file_name == name)
+        .ok_or_else(|| format!("Unknown Whisper model: {}", name))?;
+    download_model(model, &mut on_progress).await
+}
+
+// The models download_whisper_model accepts and whether each is already on disk
+pub async fn downloadable_whisper_models() -> Result<Vec<DownloadableWhisperModel>, String> {
+    let model_dir = whisper_models_dir()?;
+    let bundled = get_bundled_model_path().await?.exists();
+    Ok(DOWNLOADABLE_MODELS.iter().map(|model| DownloadableWhisperModel {
+        name: model.file_name.to_string(),
+        multilingual: model.multilingual,
+        downloaded: model_dir.join(model.file_name).exists() || (bundled && model.file_name == ENGLISH_MODEL.file_name),
+    }).collect())
+}
+
+// Fetch `model` into the user's models directory unless a verified copy is already there
+async fn download_model(model: &ModelDownload, on_progress: &mut impl FnMut(&DownloadProgress)) -> Result<String, String> {
+    use std::fs;
+    
+    let model_dir = whisper_models_dir()?;
     
     fs::create_dir_all(&model_dir)
         .map_err(|e| format!("Failed to create model directory: {}", e))?;
     
-    let model_path = model_dir.join("ggml-tiny.en.bin");
+    let model_path = model_dir.join(model.file_name);
     
-    // Check if model already exists in user directory
+    // Check if model already exists in user directory; a corrupt copy is downloaded again
     if model_path.exists() {
-        return Ok(model_path.to_string_lossy().to_string());
+        if sha256_file(&model_path).await? == model.sha256 {
+            return Ok(model_path.to_string_lossy().to_string());
+        }
+        fs::remove_file(&model_path)
+            .map_err(|e| format!("Failed to remove corrupt model: {}", e))?;
     }
     
-    // Download the smaller tiny.en model as fallback
-    let model_url = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.en.bin";
+    // Download into a .part file that survives interruptions
+    let partial_path = model_dir.join(format!("{}.part", model.file_name));
+    let mut progress = download_with_resume(model.url, &partial_path, on_progress).await?;
+    
+    progress.stage = "verifying";
+    on_progress(&progress);
+    let checksum = sha256_file(&partial_path).await?;
+    if checksum != model.sha256 {
+        let _ = fs::remove_file(&partial_path);
+        return Err(format!("Downloaded model failed checksum verification (expected {}, got {})", model.sha256, checksum));
+    }
+    fs::rename(&partial_path, &model_path)
+        .map_err(|e| format!("Failed to move model into place: {}", e))?;
     
-    let response = reqwest::get(model_url).await
+    progress.stage = "done";
+    on_progress(&progress);
+    Ok(model_path.to_string_lossy().to_string())
+}
+
+// Stream `url` into `path`, continuing from the bytes already there via an HTTP range request
+async fn download_with_resume(
+    url: &str,
+    path: &std::path::Path,
+    on_progress: &mut impl FnMut(&DownloadProgress),
+) -> Result<DownloadProgress, String> {
+    use tokio::io::AsyncWriteExt;
+    
+    let existing_bytes = tokio::fs::metadata(path).await.map(|metadata| metadata.len()).unwrap_or(0);
+    let client = reqwest::Client::new();
+    let mut request = client.get(url);
+    if existing_bytes > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", existing_bytes));
+    }
+    let mut response = request.send().await
         .map_err(|e| format!("Failed to download model: {}", e))?;
     
+    // 416: the partial file already holds everything the server has
+    if existing_bytes > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(DownloadProgress {
+            stage: "downloading",
+            downloaded_bytes: existing_bytes,
+            total_bytes: Some(existing_bytes),
+            resumed: true,
+        });
+    }
     if !response.status().is_success() {
         return Err(format!("Failed to download model: HTTP {}", response.status()));
     }
     
-    use tokio::io::AsyncWriteExt;
-    let bytes = response.bytes().await
-        .map_err(|e| format!("Failed to read model data: {}", e))?;
+    // A plain 200 means the server ignored the range, so start over
+    let resumed = existing_bytes > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut file = if resumed {
+        tokio::fs::OpenOptions::new().append(true).open(path).await
+    } else {
+        tokio::fs::File::create(path).await
+    }.map_err(|e| format!("Failed to create model file: {}", e))?;
     
-    let mut file = tokio::fs::File::create(&model_path).await
-        .map_err(|e| format!("Failed to create model file: {}", e))?;
+    let mut progress = DownloadProgress {
+        stage: "downloading",
+        downloaded_bytes: if resumed { existing_bytes } else { 0 },
+        total_bytes: response.content_length().map(|length| length + if resumed { existing_bytes } else { 0 }),
+        resumed,
+    };
+    on_progress(&progress);
     
-    file.write_all(&bytes).await
+    let mut last_reported = progress.downloaded_bytes;
+    while let Some(chunk) = response.chunk().await
+        .map_err(|e| format!("Model download interrupted: {}", e))? {
+        file.write_all(&chunk).await
+            .map_err(|e| format!("Failed to write model file: {}", e))?;
+        progress.downloaded_bytes += chunk.len() as u64;
+        if progress.downloaded_bytes - last_reported >= PROGRESS_STEP_BYTES {
+            last_reported = progress.downloaded_bytes;
+            on_progress(&progress);
+        }
+    }
+    file.flush().await
         .map_err(|e| format!("Failed to write model file: {}", e))?;
+    on_progress(&progress);
     
-    Ok(model_path.to_string_lossy().to_string())
+    Ok(progress)
+}
+
+async fn sha256_file(path: &std::path::Path) -> Result<String, String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        use sha2::{Digest, Sha256};
+        use std::io::Read;
+        
+        let mut file = std::fs::File::open(&path)
+            .map_err(|e| format!("Failed to open model for verification: {}", e))?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        loop {
+            let read = file.read(&mut buffer)
+                .map_err(|e| format!("Failed to read model for verification: {}", e))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn whisper_models_dir() -> Result<std::path::PathBuf, String> {
+    Ok(dirs::config_dir()
+        .ok_or("Failed to get config directory")?
+        .join("project-r")
+        .join("models"))
+}
+
+// Downloaded models in the user directory plus the bundled one, if present
+pub async fn list_whisper_models() -> Result<Vec<WhisperModelFile>, String> {
+    let mut models = Vec::new();
+
+    let bundled_model_path = get_bundled_model_path().await?;
+    if let Ok(metadata) = std::fs::metadata(&bundled_model_path) {
+        models.push(WhisperModelFile {
+            name: "ggml-tiny.en.bin".to_string(),
+            path: bundled_model_path.to_string_lossy().to_string(),
+            size_bytes: metadata.len(),
+            bundled: true,
+        });
+    }
+
+    let model_dir = whisper_models_dir()?;
+    let Ok(entries) = std::fs::read_dir(&model_dir) else {
+        return Ok(models); // Nothing downloaded yet
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("bin") {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else { continue };
+        models.push(WhisperModelFile {
+            name: entry.file_name().to_string_lossy().to_string(),
+            path: path.to_string_lossy().to_string(),
+            size_bytes: metadata.len(),
+            bundled: false,
+        });
+    }
+
+    Ok(models)
+}
+
+// Delete a downloaded model by file name; returns the bytes freed
+pub fn delete_whisper_model(name: &str) -> Result<u64, String> {
+    // Only bare file names, so a crafted name can't reach outside the models directory
+    if name.contains(['/', '\\']) || name.starts_with('.') || !name.ends_with(".bin") {
+        return Err(format!("Invalid Whisper model name: {}", name));
+    }
+
+    let path = whisper_models_dir()?.join(name);
+    let size_bytes = std::fs::metadata(&path)
+        .map_err(|_| format!("Whisper model not found: {}", name))?
+        .len();
+    std::fs::remove_file(&path)
+        .map_err(|e| format!("Failed to delete Whisper model {}: {}", name, e))?;
+    Ok(size_bytes)
 }
 
 async fn get_bundled_model_path() -> Result<std::path::PathBuf, String> {
@@ -161,7 +1412,27 @@ async fn get_bundled_model_path() -> Result<std::path::PathBuf, String> {
         .ok_or("Failed to get executable directory")?;
     
     // Look for the model in the resources directory relative to the executable
-    let resource_path = exe_dir.join("ggml-tiny.en.bin");
+    let resource_path = exe_dir.join(ENGLISH_MODEL.file_name);
     
     Ok(resource_path)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrections_match_whole_words_in_any_case() {
+        let corrections = vec![("pie thon".to_string(), "Python".to_string())];
+        assert_eq!(apply_corrections("I love Pie Thon and PIE THON", &corrections), "I love Python and Python");
+        assert_eq!(apply_corrections("pie thons", &corrections), "pie thons");
+    }
+
+    #[test]
+    fn corrections_match_non_ascii_text() {
+        let corrections = vec![("café".to_string(), "Kaffee".to_string())];
+        assert_eq!(apply_corrections("İ said CAFÉ, then café", &corrections), "İ said Kaffee, then Kaffee");
+        let corrections = vec![("i̇x".to_string(), "ix".to_string())];
+        assert_eq!(apply_corrections("İx and İxy", &corrections), "ix and İxy");
+    }
+}
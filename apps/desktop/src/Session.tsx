@@ -17,8 +17,34 @@ const AnimatedDots = () => (
   </span>
 )
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import { useNavigate } from 'react-router-dom'
 
+// Speech is queued and played in the background. `finished` resolves once an utterance has been
+// spoken, stopped or has failed, including ones that finished before it was asked about.
+const listenForSpeech = async () => {
+  const finishedIds = new Set<string>()
+  const waiting = new Map<string, () => void>()
+  const unlisten = await listen<{ utterance_id: string }>('tts-finished', event => {
+    finishedIds.add(event.payload.utterance_id)
+    waiting.get(event.payload.utterance_id)?.()
+  })
+  const finished = (utteranceId: string) => new Promise<void>(resolve => {
+    if (finishedIds.has(utteranceId)) resolve()
+    else waiting.set(utteranceId, resolve)
+  })
+  return { finished, unlisten }
+}
+
+const speakAndWait = async (text: string) => {
+  const speech = await listenForSpeech()
+  try {
+    await speech.finished(await invoke<string>('generate_and_play_speech', { text }))
+  } finally {
+    speech.unlisten()
+  }
+}
+
 // Helper function to clean markdown formatting from code
 function cleanCodeForEditor(code: string): string {
   return code
@@ -211,14 +237,24 @@ function Session() {
         // Transcribing speech with Whisper...
         
         // Step 2: Transcribe the audio using Whisper
-        const transcription = await invoke<string>('transcribe_audio', { 
-          audioFilePath: audioFilePath 
+        const transcriptionJson = await invoke<string>('transcribe_audio', { 
+          audioFilePath: audioFilePath,
+          sessionId: conversationSessionId
         })
+        const { text: transcription, language, low_confidence: lowConfidence } = JSON.parse(transcriptionJson)
         
         if (!transcription.trim()) {
           // No speech detected
           return
         }
+        
+        // Whisper was mostly guessing; ask again instead of sending garbage to the tutor
+        if (lowConfidence) {
+          setIsSpeaking(true)
+          setIsProcessing(false)
+          await speakAndWait("Sorry, I didn't catch that clearly. Could you say it again?")
+          return
+        }
 
         // AI is thinking...
         
@@ -228,12 +264,22 @@ function Session() {
           sessionIdToUse = await createConversationSession(transcription)
         }
         
-        // Step 3: Send transcription + current code to Session LLM (Ollama + Gemma)
-        const aiResponseJson = await invoke<string>('generate_ai_response', {
-          userInput: transcription,
-          currentCode: code,
-          sessionId: sessionIdToUse
-        })
+        // Step 3: Send transcription + current code to Session LLM (Ollama, using the chosen chat model);
+        // its sentences are spoken as they're generated, by a voice for the language the question was asked in
+        const speech = await listenForSpeech()
+        let aiResponseJson: string
+        try {
+          aiResponseJson = await invoke<string>('generate_ai_response', {
+            userInput: transcription,
+            currentCode: code,
+            sessionId: sessionIdToUse,
+            speak: true,
+            language
+          })
+        } catch (error) {
+          speech.unlisten()
+          throw error
+        }
         
         // Parse the AI response
         const aiResponse = JSON.parse(aiResponseJson)
@@ -246,13 +292,18 @@ function Session() {
           // Code has been updated
         }
         
-        // Step 5: Generate and play TTS response to complete audio-audio pipeline
+        // Step 5: Wait for the rest of the spoken answer to complete the audio-audio pipeline
         setIsSpeaking(true)
         setIsProcessing(false)
         
-        await invoke<string>('generate_and_play_speech', {
-          text: aiResponse.conversation_response
-        })
+        try {
+          const utteranceIds: string[] = aiResponse.metadata?.utterance_ids ?? []
+          if (utteranceIds.length > 0) {
+            await speech.finished(utteranceIds[utteranceIds.length - 1])
+          }
+        } finally {
+          speech.unlisten()
+        }
         
         setIsSpeaking(false)
         // Complete audio-audio conversation cycle completed
@@ -271,7 +322,7 @@ function Session() {
         console.log('Microphone test:', micTest)
         
         // Start recording
-        await invoke<string>('start_recording')
+        await invoke<string>('start_recording', { sessionId: conversationSessionId })
         setIsRecording(true)
         // Listening...
         